@@ -0,0 +1,180 @@
+//! Breakdown of the comment lines added by the weekday and hour of their commits, in the local
+//! time of the committer, to see when documentation actually gets written.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, FixedOffset, Timelike, Weekday};
+use serde_json::{json, Value};
+
+use crate::stats_file::StatsFile;
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+#[derive(Default)]
+pub struct Activity {
+    weekdays: [Slot; 7],
+    hours: [Slot; 24],
+}
+
+/// Commits within one weekday or hour, and the comment lines they added.
+#[derive(Clone, Copy, Default)]
+struct Slot {
+    commits: u64,
+    comments: u64,
+}
+
+impl Slot {
+    fn add(&mut self, comments: u64) {
+        self.commits += 1;
+        self.comments = self.comments.saturating_add(comments);
+    }
+
+    fn to_json(self) -> Value {
+        json!({
+            "commits": self.commits,
+            "comments_added": self.comments,
+        })
+    }
+}
+
+impl Activity {
+    /// Collect the activity of the first history of the stats file. Comment lines that were
+    /// removed aren't subtracted, and the first entry counts all of its comments as added.
+    pub fn load(file: &StatsFile) -> Result<Self> {
+        let mut activity = Self::default();
+        let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+            return Ok(activity);
+        };
+
+        let mut previous = 0;
+        for index in range {
+            file.read_chunk(index, |entry| {
+                // Failed commits have no data, and would count everything as added afterwards.
+                if entry.failed {
+                    return Ok(());
+                }
+
+                let comments = entry.total_stats()?.statistics.comments as u64;
+                activity.add(entry.timestamp, comments.saturating_sub(previous));
+                previous = comments;
+                Ok(())
+            })?;
+        }
+
+        Ok(activity)
+    }
+
+    fn add(&mut self, time: DateTime<FixedOffset>, comments: u64) {
+        self.weekdays[time.weekday().num_days_from_monday() as usize].add(comments);
+        self.hours[time.hour() as usize].add(comments);
+    }
+
+    /// Section of `report.json`.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "weekdays": WEEKDAYS
+                .iter()
+                .zip(self.weekdays)
+                .map(|(day, slot)| {
+                    let mut value = slot.to_json();
+                    value["day"] = json!(day.to_string());
+                    value
+                })
+                .collect::<Vec<_>>(),
+            "hours": self
+                .hours
+                .iter()
+                .enumerate()
+                .map(|(hour, slot)| {
+                    let mut value = slot.to_json();
+                    value["hour"] = json!(hour);
+                    value
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+}
+
+pub fn run(input: &Path) -> Result<()> {
+    let file = StatsFile::open(input)?;
+    if file.manifest().entries == 0 {
+        bail!("{} contains no entries", input.display());
+    }
+
+    let activity = Activity::load(&file)?;
+    let share = |slot: Slot| {
+        let total = activity.weekdays.iter().map(|s| s.comments).sum::<u64>();
+        if total == 0 {
+            0.0
+        } else {
+            slot.comments as f64 * 100.0 / total as f64
+        }
+    };
+
+    println!(
+        "{:<6}{:>10}{:>16}{:>8}",
+        "day", "commits", "comments added", "share"
+    );
+    for (day, slot) in WEEKDAYS.iter().zip(activity.weekdays) {
+        println!(
+            "{:<6}{:>10}{:>16}{:>7.1}%",
+            day.to_string(),
+            slot.commits,
+            slot.comments,
+            share(slot)
+        );
+    }
+
+    println!();
+    println!(
+        "{:<6}{:>10}{:>16}{:>8}",
+        "hour", "commits", "comments added", "share"
+    );
+    for (hour, slot) in activity.hours.iter().enumerate() {
+        println!(
+            "{:<6}{:>10}{:>16}{:>7.1}%",
+            format!("{hour:02}:00"),
+            slot.commits,
+            slot.comments,
+            share(*slot)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn activity_uses_local_time() {
+        let mut activity = Activity::default();
+        // Friday evening in Tokyo, but still Friday morning in UTC.
+        let friday = DateTime::parse_from_rfc3339("2024-03-01T18:30:00+09:00").unwrap();
+        let saturday = DateTime::parse_from_rfc3339("2024-03-02T01:00:00-05:00").unwrap();
+        activity.add(friday, 20);
+        activity.add(friday, 5);
+        activity.add(saturday, 0);
+
+        assert_eq!(2, activity.weekdays[4].commits);
+        assert_eq!(25, activity.weekdays[4].comments);
+        assert_eq!(1, activity.weekdays[5].commits);
+        assert_eq!(25, activity.hours[18].comments);
+        assert_eq!(1, activity.hours[1].commits);
+
+        let report = activity.to_json();
+        assert_eq!("Fri", report["weekdays"][4]["day"]);
+        assert_eq!(25, report["weekdays"][4]["comments_added"]);
+        assert_eq!(24, report["hours"].as_array().unwrap().len());
+    }
+}
@@ -0,0 +1,183 @@
+use syn::{Attribute, ImplItem, Item, TraitItem, Visibility};
+
+use crate::models::ApiDocs;
+
+/// Count the documented and undocumented public items of a Rust source file, including the
+/// public methods and associated items of `impl` blocks and the items of public traits.
+///
+/// Each file is looked at on its own, so items are counted by their `pub` keyword alone, even if
+/// the surrounding module isn't reachable from outside the crate. Items hidden with
+/// `#[doc(hidden)]` aren't counted. Returns `None` for files that can't be parsed.
+pub fn analyze(source: &[u8]) -> Option<ApiDocs> {
+    let source = std::str::from_utf8(source).ok()?;
+    let file = syn::parse_file(source).ok()?;
+
+    let mut docs = ApiDocs::default();
+    count_items(&file.items, &mut docs);
+
+    Some(docs)
+}
+
+fn count_items(items: &[Item], docs: &mut ApiDocs) {
+    for item in items {
+        let (vis, attrs) = match item {
+            Item::Const(item) => (&item.vis, &item.attrs),
+            Item::Enum(item) => (&item.vis, &item.attrs),
+            Item::Fn(item) => (&item.vis, &item.attrs),
+            Item::Static(item) => (&item.vis, &item.attrs),
+            Item::Struct(item) => (&item.vis, &item.attrs),
+            Item::Trait(item) => {
+                if is_public(&item.vis) && !is_hidden(&item.attrs) {
+                    count_trait_items(&item.items, docs);
+                }
+                (&item.vis, &item.attrs)
+            }
+            Item::Type(item) => (&item.vis, &item.attrs),
+            Item::Union(item) => (&item.vis, &item.attrs),
+            Item::Impl(item) => {
+                // Items of trait implementations are documented by the trait.
+                if item.trait_.is_none() && !is_hidden(&item.attrs) {
+                    count_impl_items(&item.items, docs);
+                }
+                continue;
+            }
+            Item::Mod(item) => {
+                // Modules in their own file are documented there, with inner doc comments.
+                let Some((_, items)) = &item.content else {
+                    continue;
+                };
+                if is_hidden(&item.attrs) {
+                    continue;
+                }
+                count_items(items, docs);
+                (&item.vis, &item.attrs)
+            }
+            _ => continue,
+        };
+
+        if is_public(vis) {
+            count(attrs, docs);
+        }
+    }
+}
+
+fn count_impl_items(items: &[ImplItem], docs: &mut ApiDocs) {
+    for item in items {
+        let (vis, attrs) = match item {
+            ImplItem::Const(item) => (&item.vis, &item.attrs),
+            ImplItem::Fn(item) => (&item.vis, &item.attrs),
+            ImplItem::Type(item) => (&item.vis, &item.attrs),
+            _ => continue,
+        };
+
+        if is_public(vis) {
+            count(attrs, docs);
+        }
+    }
+}
+
+fn count_trait_items(items: &[TraitItem], docs: &mut ApiDocs) {
+    for item in items {
+        let attrs = match item {
+            TraitItem::Const(item) => &item.attrs,
+            TraitItem::Fn(item) => &item.attrs,
+            TraitItem::Type(item) => &item.attrs,
+            _ => continue,
+        };
+
+        count(attrs, docs);
+    }
+}
+
+fn count(attrs: &[Attribute], docs: &mut ApiDocs) {
+    if is_hidden(attrs) {
+        return;
+    }
+
+    // Doc comments are turned into `#[doc = "..."]` attributes by the parser.
+    if attrs
+        .iter()
+        .any(|attr| attr.path().is_ident("doc") && attr.meta.require_name_value().is_ok())
+    {
+        docs.documented += 1;
+    } else {
+        docs.undocumented += 1;
+    }
+}
+
+/// Whether the item is public outside of its crate. Restricted visibility like `pub(crate)`
+/// isn't.
+fn is_public(vis: &Visibility) -> bool {
+    matches!(vis, Visibility::Public(_))
+}
+
+fn is_hidden(attrs: &[Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        let mut hidden = false;
+        if attr.path().is_ident("doc") && attr.meta.require_list().is_ok() {
+            // Other arguments like `alias` are irrelevant, so errors can be ignored.
+            let _ = attr.parse_nested_meta(|meta| {
+                hidden |= meta.path.is_ident("hidden");
+                Ok(())
+            });
+        }
+        hidden
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn public_items_are_counted() {
+        let docs = analyze(
+            br"
+            /// Documented.
+            pub struct Documented;
+
+            pub struct Undocumented;
+
+            struct Private;
+
+            pub(crate) fn restricted() {}
+
+            #[doc(hidden)]
+            pub fn hidden() {}
+
+            impl Documented {
+                /// Documented.
+                pub fn new() -> Self { Self }
+
+                pub fn undocumented(&self) {}
+
+                fn private(&self) {}
+            }
+
+            impl Default for Undocumented {
+                fn default() -> Self { Self }
+            }
+
+            /// Documented.
+            pub mod inline {
+                pub trait Trait {
+                    /// Documented.
+                    fn documented(&self);
+
+                    fn undocumented(&self);
+                }
+            }
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(4, docs.documented);
+        assert_eq!(4, docs.undocumented);
+    }
+
+    #[test]
+    fn invalid_source_is_ignored() {
+        assert!(analyze(b"pub fn broken(").is_none());
+        assert!(analyze(&[0xff, 0xfe]).is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! Minimal `.gitattributes` support for the linguist attributes that GitHub uses to exclude files
+//! from its language statistics, or to count them as another language.
+//!
+//! libgit2 only reads attributes from the working directory and index, so the files are parsed
+//! from each commit's tree instead. This keeps the exclusions accurate for the whole history, as
+//! the attributes of old commits may differ from the current ones.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    hash::Hasher,
+    path::{Component, Path},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use git2::{ObjectType, Oid, Repository, Tree};
+use globset::{GlobBuilder, GlobMatcher};
+use tokei::LanguageType;
+use twox_hash::XxHash3_64;
+
+use crate::languages;
+
+const FILE_NAME: &str = ".gitattributes";
+/// Attributes that exclude a file from the statistics when set.
+const EXCLUDING: [&str; 3] = [
+    "linguist-vendored",
+    "linguist-generated",
+    "linguist-documentation",
+];
+/// Attribute that overrides the language of a file.
+const LANGUAGE: &str = "linguist-language";
+
+/// Attribute rules of a whole tree, with one node per directory that contains rules.
+pub struct Rules {
+    node: Arc<Node>,
+}
+
+impl Rules {
+    /// Fingerprint of all `.gitattributes` files in the tree. Equal fingerprints mean the same
+    /// files are excluded from both trees.
+    pub fn fingerprint(&self) -> u64 {
+        self.node.fingerprint
+    }
+
+    /// Whether any of the linguist attributes is set for the file at the given path.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        let mut state = [None; EXCLUDING.len()];
+        self.apply(path, |rule| {
+            for (i, value) in &rule.attributes {
+                state[*i] = *value;
+            }
+        });
+
+        state.contains(&Some(true))
+    }
+
+    /// Language that the file at the given path is counted as instead of the one of its
+    /// extension, if set with `linguist-language`.
+    pub fn language(&self, path: &Path) -> Option<LanguageType> {
+        let mut language = None;
+        self.apply(path, |rule| {
+            if let Some(value) = rule.language {
+                language = value;
+            }
+        });
+
+        language
+    }
+
+    /// Call `f` with each rule that matches the path, in the order they take effect.
+    fn apply(&self, path: &Path, mut f: impl FnMut(&Rule)) {
+        let mut node = Some(&self.node);
+        let mut components = path.components();
+
+        // Rules of deeper directories take precedence, so the nodes are applied top-down.
+        while let Some(current) = node {
+            let relative = components.as_path();
+            for rule in &current.rules {
+                if rule.matches(relative) {
+                    f(rule);
+                }
+            }
+
+            node = match components.next() {
+                Some(Component::Normal(name)) => current.children.get(name),
+                _ => None,
+            };
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    rules: Vec<Rule>,
+    children: HashMap<Box<OsStr>, Arc<Node>>,
+    fingerprint: u64,
+}
+
+struct Rule {
+    matcher: GlobMatcher,
+    /// Patterns without a slash match the file name in any directory below the attributes file.
+    basename: bool,
+    /// Index into [`EXCLUDING`], and whether the attribute was set (`Some(true)`), unset
+    /// (`Some(false)`) or reset to unspecified (`None`).
+    attributes: Vec<(usize, Option<bool>)>,
+    /// Language the file is set to (`Some(Some(_))`), or whether it was unset or reset
+    /// (`Some(None)`), if the rule touches `linguist-language` at all.
+    language: Option<Option<LanguageType>>,
+}
+
+impl Rule {
+    fn matches(&self, path: &Path) -> bool {
+        if self.basename {
+            path.file_name()
+                .is_some_and(|name| self.matcher.is_match(name))
+        } else {
+            self.matcher.is_match(path)
+        }
+    }
+}
+
+/// Loader for the attribute [`Rules`] of trees. Parsed directories are cached by their tree ID,
+/// so consecutive commits only need to look at the directories that changed.
+#[derive(Default)]
+pub struct Cache {
+    nodes: HashMap<Oid, Arc<Node>>,
+}
+
+impl Cache {
+    pub fn load(&mut self, repo: &Repository, tree: &Tree<'_>) -> Result<Rules> {
+        Ok(Rules {
+            node: self.node(repo, tree)?,
+        })
+    }
+
+    fn node(&mut self, repo: &Repository, tree: &Tree<'_>) -> Result<Arc<Node>> {
+        if let Some(node) = self.nodes.get(&tree.id()) {
+            return Ok(Arc::clone(node));
+        }
+
+        let mut node = Node::default();
+        let mut hasher = XxHash3_64::default();
+
+        for item in tree {
+            match item.kind() {
+                Some(ObjectType::Blob) if item.name_bytes() == FILE_NAME.as_bytes() => {
+                    let blob = repo.find_blob(item.id())?;
+                    node.rules = parse(&String::from_utf8_lossy(blob.content()));
+                    hasher.write(item.id().as_bytes());
+                }
+                Some(ObjectType::Tree) => {
+                    let child = self.node(repo, &repo.find_tree(item.id())?)?;
+                    if child.fingerprint != 0 {
+                        hasher.write(item.name_bytes());
+                        hasher.write_u64(child.fingerprint);
+                        node.children.insert(name(item.name_bytes()), child);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Directories without any attributes get a zero fingerprint, so they can be left out.
+        if !node.rules.is_empty() || !node.children.is_empty() {
+            node.fingerprint = hasher.finish().max(1);
+        }
+
+        let node = Arc::new(node);
+        self.nodes.insert(tree.id(), Arc::clone(&node));
+
+        Ok(node)
+    }
+}
+
+#[cfg(unix)]
+fn name(bytes: &[u8]) -> Box<OsStr> {
+    use std::os::unix::ffi::OsStrExt;
+    OsStr::from_bytes(bytes).into()
+}
+
+#[cfg(not(unix))]
+fn name(bytes: &[u8]) -> Box<OsStr> {
+    OsStr::new(&*String::from_utf8_lossy(bytes)).into()
+}
+
+/// Parse the content of a `.gitattributes` file, keeping only the lines that touch any of the
+/// linguist attributes. Invalid patterns are ignored, like git does.
+fn parse(content: &str) -> Vec<Rule> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let mut fields = line.split_whitespace();
+            let pattern = fields.next()?;
+
+            let mut language = None;
+            let attributes = fields
+                .filter_map(|field| {
+                    let (name, value) = match field.split_once('=') {
+                        Some((name, value)) => (name, Some(value)),
+                        None => match field.strip_prefix('-') {
+                            Some(name) => (name, Some("false")),
+                            None => match field.strip_prefix('!') {
+                                Some(name) => (name, None),
+                                None => (field, Some("true")),
+                            },
+                        },
+                    };
+
+                    // Unknown languages are ignored, like linguist does.
+                    if name == LANGUAGE {
+                        language = match value {
+                            Some(value) => Some(parse_language(value)?),
+                            None => Some(None),
+                        };
+                        return None;
+                    }
+
+                    let value = value.map(|value| value != "false");
+                    Some((EXCLUDING.iter().position(|&a| a == name)?, value))
+                })
+                .collect::<Vec<_>>();
+
+            if attributes.is_empty() && language.is_none() {
+                return None;
+            }
+
+            let basename = !pattern.contains('/');
+            let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+
+            let matcher = GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .backslash_escape(true)
+                .build()
+                .ok()?
+                .compile_matcher();
+
+            Some(Rule {
+                matcher,
+                basename,
+                attributes,
+                language,
+            })
+        })
+        .collect()
+}
+
+/// Resolve the language name of a `linguist-language` value, where spaces are written as
+/// dashes, like `Visual-Basic`. Unsetting it with `false` counts the file by its extension.
+fn parse_language(value: &str) -> Option<Option<LanguageType>> {
+    if value == "false" {
+        return Some(None);
+    }
+
+    languages::parse(value)
+        .or_else(|_| languages::parse(&value.replace('-', " ")))
+        .ok()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rules of a tree with attributes at its root, and in the given directories below it.
+    fn rules(root: &str, dirs: &[(&str, &str)]) -> Rules {
+        let node = |content: &str, children| {
+            Arc::new(Node {
+                rules: parse(content),
+                children,
+                fingerprint: 1,
+            })
+        };
+        let children = dirs
+            .iter()
+            .map(|(dir, content)| (OsStr::new(dir).into(), node(content, HashMap::new())))
+            .collect();
+
+        Rules {
+            node: node(root, children),
+        }
+    }
+
+    #[test]
+    fn linguist_attributes_exclude_files() {
+        let rules = rules(
+            "# Generated assets\n\
+             *.min.js linguist-generated\n\
+             /third_party/** linguist-vendored\n\
+             docs/** linguist-documentation=true\n\
+             *.txt text eol=lf\n",
+            &[],
+        );
+        let excluded = |path| rules.is_excluded(Path::new(path));
+
+        assert!(excluded("app.min.js"));
+        assert!(excluded("web/static/app.min.js"));
+        assert!(excluded("third_party/zlib/inflate.c"));
+        assert!(excluded("docs/guide.md"));
+
+        // Patterns with a slash are relative to the attributes file.
+        assert!(!excluded("src/third_party/inflate.c"));
+        assert!(!excluded("src/docs/guide.md"));
+        assert!(!excluded("app.js"));
+        assert!(!excluded("notes.txt"));
+    }
+
+    #[test]
+    fn later_and_deeper_rules_take_precedence() {
+        let rules = rules(
+            "*.rs linguist-generated\n\
+             *.js linguist-vendored\n\
+             app.js linguist-vendored=false\n",
+            &[(
+                "src",
+                "keep.rs -linguist-generated\nreset.rs !linguist-generated\n",
+            )],
+        );
+        let excluded = |path| rules.is_excluded(Path::new(path));
+
+        assert!(excluded("build.rs"));
+        assert!(excluded("src/lib.rs"));
+        assert!(!excluded("src/keep.rs"));
+        assert!(!excluded("src/reset.rs"));
+        assert!(excluded("lib.js"));
+        assert!(!excluded("web/app.js"));
+    }
+
+    #[test]
+    fn unrelated_lines_are_ignored() {
+        let rules = parse(
+            "# Comment\n\
+             \n\
+             *.sh text eol=lf\n\
+             *.pl linguist-language=Klingon\n\
+             [ linguist-generated\n\
+             *.h linguist-language=C++\n",
+        );
+
+        assert_eq!(1, rules.len());
+        assert_eq!(Some(Some(LanguageType::Cpp)), rules[0].language);
+    }
+}
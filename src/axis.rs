@@ -0,0 +1,251 @@
+//! Labels of the value axis, which get hard to read with the plain numbers that poloto prints for
+//! large repositories, and the formats of numbers and dates of the user's locale.
+
+use std::{env, str::FromStr};
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+
+/// How the values on the y-axis are labeled.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum YUnit {
+    /// Shorten large values with a `k` or `M` suffix, like `1.2M`.
+    Auto,
+    /// Print the full values with thousands separators, like `1,234,567`.
+    Lines,
+    /// Print the values in thousands, like `1,234.5` for 1,234,500 lines.
+    Kloc,
+}
+
+impl YUnit {
+    /// Axis label for values of the given unit, like `Lines`.
+    pub fn label(self, unit: &str) -> String {
+        match self {
+            Self::Auto | Self::Lines => unit.to_owned(),
+            Self::Kloc => format!("{unit} (thousands)"),
+        }
+    }
+
+    /// Format a single tick value.
+    pub fn format(self, value: f64, locale: &Locale) -> String {
+        match self {
+            Self::Auto => {
+                let (value, suffix) = if value.abs() >= 1_000_000.0 {
+                    (value / 1_000_000.0, "M")
+                } else if value.abs() >= 1_000.0 {
+                    (value / 1_000.0, "k")
+                } else {
+                    (value, "")
+                };
+                format!("{}{suffix}", locale.format(value))
+            }
+            Self::Lines => locale.format(value),
+            Self::Kloc => locale.format(value / 1_000.0),
+        }
+    }
+}
+
+/// Separators for formatting numbers and patterns for formatting dates, as used by a locale.
+#[derive(Clone)]
+pub struct Locale {
+    thousands: char,
+    decimal: char,
+    /// Date patterns, or `None` for ISO 8601 dates like `2024-01-31`.
+    dates: Option<Dates>,
+}
+
+/// Patterns of dates, in the syntax of [`chrono::format::strftime`].
+#[derive(Clone, Copy)]
+struct Dates {
+    day: &'static str,
+    month: &'static str,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            thousands: ',',
+            decimal: '.',
+            dates: None,
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let language = language(s);
+        if language.len() < 2
+            || language.len() > 3
+            || !language.bytes().all(|b| b.is_ascii_alphabetic())
+        {
+            return Err("expected a locale like `de-DE` or `fr_FR.UTF-8`".to_owned());
+        }
+
+        Ok(Self::from_locale(s))
+    }
+}
+
+impl Locale {
+    /// Pick the separators from the `LC_ALL`, `LC_NUMERIC` or `LANG` environment variable and the
+    /// date patterns from the `LC_ALL`, `LC_TIME` or `LANG` environment variable, in that order.
+    /// Only the language part of the locale is looked at, and unknown languages fall back to the
+    /// English separators and ISO 8601 dates.
+    pub fn from_env() -> Self {
+        let from_env = |category| {
+            ["LC_ALL", category, "LANG"]
+                .into_iter()
+                .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+                .map_or_else(Self::default, |locale| Self::from_locale(&locale))
+        };
+
+        Self {
+            dates: from_env("LC_TIME").dates,
+            ..from_env("LC_NUMERIC")
+        }
+    }
+
+    fn from_locale(locale: &str) -> Self {
+        let (thousands, decimal) = match language(locale) {
+            "de" | "da" | "el" | "es" | "id" | "it" | "nl" | "pt" | "ro" | "sl" | "tr" => {
+                ('.', ',')
+            }
+            "cs" | "fi" | "fr" | "hu" | "nb" | "nn" | "no" | "pl" | "ru" | "sk" | "sv" | "uk" => {
+                ('\u{a0}', ',')
+            }
+            _ => (',', '.'),
+        };
+
+        let dates = match language(locale) {
+            "cs" | "da" | "de" | "fi" | "nb" | "nn" | "no" | "pl" | "ro" | "ru" | "sk" | "sl"
+            | "tr" | "uk" => Some(Dates {
+                day: "%d.%m.%Y",
+                month: "%m.%Y",
+            }),
+            "el" | "es" | "fr" | "id" | "it" | "pt" => Some(Dates {
+                day: "%d/%m/%Y",
+                month: "%m/%Y",
+            }),
+            "nl" => Some(Dates {
+                day: "%d-%m-%Y",
+                month: "%m-%Y",
+            }),
+            _ => None,
+        };
+
+        Self {
+            thousands,
+            decimal,
+            dates,
+        }
+    }
+
+    /// Separators of thousands and decimals.
+    pub fn separators(&self) -> (char, char) {
+        (self.thousands, self.decimal)
+    }
+
+    /// Pattern of a single day, in the syntax of [`chrono::format::strftime`], which matches the
+    /// one of d3 for the used specifiers.
+    pub fn date_pattern(&self) -> &'static str {
+        self.dates.map_or("%Y-%m-%d", |dates| dates.day)
+    }
+
+    /// Whether dates are written in ISO 8601 format.
+    pub fn is_iso(&self) -> bool {
+        self.dates.is_none()
+    }
+
+    /// Format a single day, like `31.01.2024`.
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        date.format(self.date_pattern()).to_string()
+    }
+
+    /// Format a month without its day, like `01.2024`.
+    pub fn format_month(&self, date: NaiveDate) -> String {
+        date.format(self.dates.map_or("%Y-%m", |dates| dates.month))
+            .to_string()
+    }
+
+    /// Format the value with thousands separators and up to two decimals, leaving out trailing
+    /// zeros.
+    pub fn format(&self, value: f64) -> String {
+        let rounded = format!("{:.2}", value.abs());
+        let (integer, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+        let fraction = fraction.trim_end_matches('0');
+
+        let mut out = String::new();
+        if value < 0.0 && rounded.bytes().any(|b| b.is_ascii_digit() && b != b'0') {
+            out.push('-');
+        }
+
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                out.push(self.thousands);
+            }
+            out.push(digit);
+        }
+
+        if !fraction.is_empty() {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+
+        out
+    }
+}
+
+/// Language part of a locale, like `de` for `de_DE.UTF-8`.
+fn language(locale: &str) -> &str {
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_separated() {
+        let locale = Locale::default();
+        assert_eq!("0", locale.format(0.0));
+        assert_eq!("999", locale.format(999.0));
+        assert_eq!("1,234,567", locale.format(1_234_567.0));
+        assert_eq!("-12,345.5", locale.format(-12_345.5));
+        assert_eq!("0.3", locale.format(0.1 + 0.2));
+
+        let locale = Locale::from_locale("de_DE.UTF-8");
+        assert_eq!("1.234.567,25", locale.format(1_234_567.25));
+    }
+
+    #[test]
+    fn dates_follow_locale() {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+
+        let locale = Locale::default();
+        assert_eq!("2024-01-31", locale.format_date(date));
+        assert_eq!("2024-01", locale.format_month(date));
+
+        let locale = "de-DE".parse::<Locale>().unwrap();
+        assert_eq!("31.01.2024", locale.format_date(date));
+        assert_eq!("01.2024", locale.format_month(date));
+
+        let locale = "fr_FR.UTF-8".parse::<Locale>().unwrap();
+        assert_eq!("31/01/2024", locale.format_date(date));
+
+        assert!("1234".parse::<Locale>().is_err());
+    }
+
+    #[test]
+    fn units_are_scaled() {
+        let locale = Locale::default();
+        assert_eq!("500", YUnit::Auto.format(500.0, &locale));
+        assert_eq!("12.5k", YUnit::Auto.format(12_500.0, &locale));
+        assert_eq!("1.23M", YUnit::Auto.format(1_234_567.0, &locale));
+        assert_eq!("1,234,567", YUnit::Lines.format(1_234_567.0, &locale));
+        assert_eq!("1,234.57", YUnit::Kloc.format(1_234_567.0, &locale));
+    }
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use git2::{Commit, FileMode, Oid, Repository, Signature, Time};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{config::Config, render, scan};
+
+/// Amount of files grouped into a single directory of the synthetic repository.
+const FILES_PER_DIR: usize = 100;
+/// Start of the synthetic history (2020-01-01T00:00:00Z), so generated commits are spread out
+/// over a realistic time range.
+const START_TIME: i64 = 1_577_836_800;
+/// Time between two synthetic commits.
+const COMMIT_INTERVAL: i64 = 60 * 60;
+/// Length of each directory name when nesting the synthetic files deeply.
+const DEEP_NAME_LENGTH: usize = 200;
+
+/// Shape of the synthetic repository, given as `<commits>x<files>` on the command line.
+#[derive(Clone, Copy)]
+pub struct Synthetic {
+    commits: usize,
+    files: usize,
+}
+
+impl FromStr for Synthetic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (commits, files) = s
+            .split_once('x')
+            .ok_or_else(|| "expected format `<commits>x<files>`".to_owned())?;
+
+        let commits = commits
+            .parse()
+            .map_err(|e| format!("invalid commit count: {e}"))?;
+        let files = files
+            .parse()
+            .map_err(|e| format!("invalid file count: {e}"))?;
+
+        if commits == 0 || files == 0 {
+            return Err("commit and file count must be greater than zero".to_owned());
+        }
+
+        Ok(Self { commits, files })
+    }
+}
+
+/// Run the benchmark. With a `depth`, all files are nested that many directories deep with long
+/// names, which stresses path handling (over 32k characters per path at a depth of 160).
+pub fn run(synthetic: Synthetic, depth: usize) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let repo_path = dir.path().join("repo");
+    let stats_path = dir.path().join("stats.stats");
+    let svg_path = dir.path().join("stats.svg");
+    let config = Config::default();
+
+    println!(
+        "generating synthetic repository ({} commits, {} files)...",
+        synthetic.commits, synthetic.files
+    );
+
+    let start = Instant::now();
+    generate(&repo_path, synthetic, depth)?;
+    let generate_time = start.elapsed();
+
+    let start = Instant::now();
+    scan::run(repo_path, &stats_path, &scan::Options::default(), &config)?;
+    let scan_time = start.elapsed();
+
+    let start = Instant::now();
+    render::run(
+        stats_path.clone(),
+        &svg_path,
+        &render::Options::default(),
+        &config,
+    )?;
+    let render_time = start.elapsed();
+
+    let stats_size = std::fs::metadata(&stats_path)?.len();
+
+    println!();
+    println!("generate: {}", format_duration(generate_time));
+    println!(
+        "scan:     {} ({:.1} commits/s)",
+        format_duration(scan_time),
+        per_second(synthetic.commits, scan_time)
+    );
+    println!(
+        "render:   {} ({:.1} commits/s)",
+        format_duration(render_time),
+        per_second(synthetic.commits, render_time)
+    );
+    println!("size:     {stats_size} bytes");
+
+    Ok(())
+}
+
+/// Create a new repository at the given path and fill it with a linear history. Each commit
+/// rewrites a small part of the files, so the scanner has to diff and re-parse a realistic
+/// amount of content per commit.
+fn generate(path: &Path, synthetic: Synthetic, depth: usize) -> Result<()> {
+    let repo = Repository::init_bare(path)?;
+    // A fixed seed keeps the content the same between runs, so results stay comparable.
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+
+    let mut blobs = (0..synthetic.files)
+        .map(|i| repo.blob(source_file(&mut rng, i).as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut dirs = BTreeMap::new();
+    let mut parent: Option<Commit<'_>> = None;
+
+    let changes_per_commit = (synthetic.files / 20).max(1);
+
+    for commit in 0..synthetic.commits {
+        if commit > 0 {
+            for _ in 0..changes_per_commit {
+                let i = rng.gen_range(0..synthetic.files);
+                blobs[i] = repo.blob(source_file(&mut rng, i).as_bytes())?;
+                dirs.remove(&(i / FILES_PER_DIR));
+            }
+        }
+
+        let tree = write_tree(&repo, &blobs, &mut dirs)?;
+        let tree = repo.find_tree(nest_tree(&repo, tree, depth)?)?;
+        let sig = Signature::new(
+            "Synthetic",
+            "synthetic@example.com",
+            &Time::new(START_TIME + commit as i64 * COMMIT_INTERVAL, 0),
+        )?;
+
+        let oid = repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            &format!("commit {commit}"),
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )?;
+
+        parent = Some(repo.find_commit(oid)?);
+    }
+
+    Ok(())
+}
+
+/// Write the root tree for the given blobs. Directory trees are cached in `dirs` and only
+/// rebuilt when one of their files changed.
+fn write_tree(repo: &Repository, blobs: &[Oid], dirs: &mut BTreeMap<usize, Oid>) -> Result<Oid> {
+    let mut root = repo.treebuilder(None)?;
+
+    for (d, chunk) in blobs.chunks(FILES_PER_DIR).enumerate() {
+        let oid = match dirs.get(&d) {
+            Some(&oid) => oid,
+            None => {
+                let mut dir = repo.treebuilder(None)?;
+                for (i, &blob) in chunk.iter().enumerate() {
+                    dir.insert(
+                        format!("file_{:05}.rs", d * FILES_PER_DIR + i),
+                        blob,
+                        FileMode::Blob.into(),
+                    )?;
+                }
+
+                let oid = dir.write()?;
+                dirs.insert(d, oid);
+                oid
+            }
+        };
+
+        root.insert(format!("mod_{d:04}"), oid, FileMode::Tree.into())?;
+    }
+
+    root.write().map_err(Into::into)
+}
+
+/// Wrap the tree into `depth` levels of directories.
+fn nest_tree(repo: &Repository, mut tree: Oid, depth: usize) -> Result<Oid> {
+    for level in (0..depth).rev() {
+        let mut dir = repo.treebuilder(None)?;
+        dir.insert(
+            format!("{level:03}_{}", "d".repeat(DEEP_NAME_LENGTH)),
+            tree,
+            FileMode::Tree.into(),
+        )?;
+        tree = dir.write()?;
+    }
+
+    Ok(tree)
+}
+
+/// Generate a Rust source file with a random mix of code, comment and blank lines.
+fn source_file(rng: &mut StdRng, index: usize) -> String {
+    let mut content = format!("//! Synthetic module {index}.\n\n");
+    let functions = rng.gen_range(5..25);
+
+    for f in 0..functions {
+        for _ in 0..rng.gen_range(0..4) {
+            content.push_str("/// Lorem ipsum dolor sit amet, consectetur adipiscing elit.\n");
+        }
+
+        writeln!(content, "pub fn function_{f}(value: u64) -> u64 {{").ok();
+        for l in 0..rng.gen_range(1..16) {
+            if rng.gen_ratio(1, 5) {
+                content.push_str("    // Sed do eiusmod tempor incididunt ut labore.\n");
+            }
+            writeln!(
+                content,
+                "    let value = value.wrapping_mul({l}) + {};",
+                rng.gen::<u32>()
+            )
+            .ok();
+        }
+        content.push_str("    value\n}\n\n");
+    }
+
+    content
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}s", duration.as_secs_f64())
+}
+
+fn per_second(count: usize, duration: Duration) -> f64 {
+    count as f64 / duration.as_secs_f64().max(f64::EPSILON)
+}
@@ -0,0 +1,224 @@
+//! Segmentation of a series into periods with distinct trends, to tell apart the phases of a
+//! long history, like a steady start, a rewrite and a quiet maintenance period.
+//!
+//! The changepoints are found with PELT (Killick et al., 2012), which minimizes the squared
+//! deviations from a straight line within each segment, plus a penalty for each additional
+//! segment. The penalty follows the BIC and scales with the noise of the series, so small
+//! wiggles don't start a new segment.
+
+use std::ops::Range;
+
+/// Fewest points of a segment, so single jumps don't become segments of their own.
+const MIN_POINTS: usize = 5;
+
+const DAY: f64 = (24 * 60 * 60) as f64;
+
+/// Period of the series that follows a single trend.
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    /// Indices of the points within the segment.
+    pub points: Range<usize>,
+    /// Values of the fitted line at the first and last point.
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Sums over the points of the series up to each index, to get the cost of any segment in
+/// constant time.
+struct Sums {
+    n: Vec<f64>,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    xx: Vec<f64>,
+    xy: Vec<f64>,
+    yy: Vec<f64>,
+}
+
+/// Straight line fitted through a segment, with its sum of squared residuals.
+struct Fit {
+    slope: f64,
+    intercept: f64,
+    residuals: f64,
+}
+
+impl Sums {
+    fn new(points: &[(f64, f64)]) -> Self {
+        let mut sums = Self {
+            n: vec![0.0],
+            x: vec![0.0],
+            y: vec![0.0],
+            xx: vec![0.0],
+            xy: vec![0.0],
+            yy: vec![0.0],
+        };
+
+        for (i, &(x, y)) in points.iter().enumerate() {
+            sums.n.push(sums.n[i] + 1.0);
+            sums.x.push(sums.x[i] + x);
+            sums.y.push(sums.y[i] + y);
+            sums.xx.push(sums.xx[i] + x * x);
+            sums.xy.push(sums.xy[i] + x * y);
+            sums.yy.push(sums.yy[i] + y * y);
+        }
+
+        sums
+    }
+
+    /// Fit a line through the points `start..end` by least squares.
+    fn fit(&self, start: usize, end: usize) -> Fit {
+        let sum = |values: &[f64]| values[end] - values[start];
+        let n = sum(&self.n);
+        let (x, y) = (sum(&self.x), sum(&self.y));
+        let xx = sum(&self.xx) - x * x / n;
+        let xy = sum(&self.xy) - x * y / n;
+        let yy = sum(&self.yy) - y * y / n;
+
+        let slope = if xx > f64::EPSILON { xy / xx } else { 0.0 };
+        Fit {
+            slope,
+            intercept: (y - slope * x) / n,
+            residuals: (yy - slope * xy).max(0.0),
+        }
+    }
+}
+
+/// Split the points, given as Unix timestamps and values ordered by time, into segments with
+/// their own trend. Series that are too short to split come back as a single segment.
+pub fn segments(points: &[(i64, f64)]) -> Vec<Segment> {
+    let Some(&(first, _)) = points.first() else {
+        return Vec::new();
+    };
+
+    // Center the values, so the sums don't lose precision for large line counts.
+    let mean = points.iter().map(|&(_, y)| y).sum::<f64>() / points.len() as f64;
+    let points = points
+        .iter()
+        .map(|&(time, y)| ((time - first) as f64 / DAY, y - mean))
+        .collect::<Vec<_>>();
+    let sums = Sums::new(&points);
+
+    let n = points.len();
+    let noise = noise(&points);
+    let penalty = 3.0 * (n as f64).ln();
+    let cost = |start, end| sums.fit(start, end).residuals / noise;
+
+    // Lowest total cost of the points up to each index, and where its last segment starts.
+    let mut best = vec![f64::INFINITY; n + 1];
+    let mut last = vec![0; n + 1];
+    let mut candidates = Vec::new();
+    best[0] = -penalty;
+
+    for end in MIN_POINTS..=n {
+        candidates.push(end - MIN_POINTS);
+
+        let (total, start) = candidates
+            .iter()
+            .filter(|&&start| best[start].is_finite())
+            .map(|&start| (best[start] + cost(start, end) + penalty, start))
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+            .unwrap_or((f64::INFINITY, 0));
+        best[end] = total;
+        last[end] = start;
+
+        // Splitting a segment never increases its cost, so starts that are already worse than
+        // the best one can never become the best one later.
+        candidates.retain(|&start| best[start] + cost(start, end) <= total);
+    }
+
+    if !best[n].is_finite() {
+        last[n] = 0;
+    }
+
+    let mut bounds = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let start = last[end];
+        bounds.push(start..end);
+        end = start;
+    }
+
+    bounds
+        .into_iter()
+        .rev()
+        .map(|range| {
+            let fit = sums.fit(range.start, range.end);
+            let at = |i: usize| fit.intercept + fit.slope * points[i].0 + mean;
+            Segment {
+                start: at(range.start),
+                end: at(range.end - 1),
+                points: range,
+            }
+        })
+        .collect()
+}
+
+/// Variance of the noise around the trend, estimated from the median change between
+/// consecutive points, which isn't thrown off by the jumps at the changepoints. Never less than
+/// a single line, so perfectly straight series aren't split at every bend.
+fn noise(points: &[(f64, f64)]) -> f64 {
+    let mut diffs = points
+        .windows(2)
+        .map(|pair| pair[1].1 - pair[0].1)
+        .collect::<Vec<_>>();
+    if diffs.is_empty() {
+        return 1.0;
+    }
+
+    let median = |values: &mut [f64]| {
+        values.sort_unstable_by(f64::total_cmp);
+        values[values.len() / 2]
+    };
+    let center = median(&mut diffs);
+    let mut deviations = diffs.iter().map(|d| (d - center).abs()).collect::<Vec<_>>();
+    // Differences of two noisy values have twice the variance of the noise itself.
+    let sigma = 1.4826 * median(&mut deviations) / 2f64.sqrt();
+
+    (sigma * sigma).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = 24 * 60 * 60;
+
+    #[test]
+    fn trends_are_separated() {
+        // Growth of 10 lines per day, a flat period and a decline, with a bit of noise.
+        let points = (0..90)
+            .map(|day| {
+                let value = match day {
+                    0..30 => 100.0 + day as f64 * 10.0,
+                    30..60 => 400.0,
+                    _ => 400.0 - (day - 60) as f64 * 5.0,
+                };
+                let noise = [2.0, -1.0, 0.0, 1.0, -2.0][day as usize % 5];
+                (day * DAY, value + noise)
+            })
+            .collect::<Vec<_>>();
+
+        let segments = segments(&points);
+        let bounds = segments
+            .iter()
+            .map(|s| s.points.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(3, bounds.len(), "{bounds:?}");
+        assert!((28..=32).contains(&bounds[1].start), "{bounds:?}");
+        assert!((58..=62).contains(&bounds[2].start), "{bounds:?}");
+        assert!((segments[0].start - 100.0).abs() < 10.0);
+        assert!((segments[2].end - 255.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn straight_series_stay_whole() {
+        let points = (0..50)
+            .map(|day| (day * DAY, 1000.0 + day as f64 * 3.0))
+            .collect::<Vec<_>>();
+        let segments = segments(&points);
+        assert_eq!(1, segments.len());
+        assert_eq!(0..50, segments[0].points);
+
+        assert_eq!(1, super::segments(&points[..3]).len());
+        assert!(super::segments(&[]).is_empty());
+    }
+}
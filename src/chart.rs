@@ -0,0 +1,89 @@
+//! Description of a rendered chart that doesn't depend on the library that draws it.
+//!
+//! The render command collects its series into a [`Chart`], which is then turned into the
+//! requested output [`Format`] by one of the backends.
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{
+    axis::{Locale, YUnit},
+    legend::Placement,
+    palette::Palette,
+};
+
+mod svg;
+pub mod template;
+mod vega_lite;
+
+/// Output format of the rendered chart.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    /// SVG image, drawn with poloto.
+    Svg,
+    /// Vega-Lite spec in JSON, with the data inlined, for use in existing dashboards.
+    VegaLite,
+}
+
+impl Format {
+    /// File extension of the output.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Svg => "svg",
+            Self::VegaLite => "vl.json",
+        }
+    }
+
+    /// Create the output file content for the chart.
+    pub fn render(self, chart: &Chart) -> Result<String> {
+        match self {
+            Self::Svg => svg::render(chart),
+            Self::VegaLite => vega_lite::render(chart),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    /// How to label the values on the y-axis.
+    #[serde(skip)]
+    pub y_unit: YUnit,
+    /// Formats of numbers and dates, or `None` to follow the environment, or the viewer for
+    /// formats that are displayed elsewhere.
+    #[serde(skip)]
+    pub locale: Option<Locale>,
+    /// Size of the image, for formats that have one.
+    pub width: u32,
+    pub height: u32,
+    #[serde(skip)]
+    pub legend: Placement,
+    #[serde(skip)]
+    pub palette: Palette,
+    pub series: Vec<Series>,
+}
+
+/// Single series of the chart.
+#[derive(Serialize)]
+pub struct Series {
+    pub label: String,
+    pub shape: Shape,
+}
+
+/// How a series is drawn, together with its points. Times are Unix timestamps, and points are
+/// ordered by time.
+#[derive(Serialize)]
+#[serde(tag = "type", content = "points", rename_all = "kebab-case")]
+pub enum Shape {
+    /// Points connected by a line.
+    Line(Vec<(i64, f64)>),
+    /// Points connected by a dashed line, for values that are projected instead of measured.
+    Dashed(Vec<(i64, f64)>),
+    /// A marker for each point, highlighting individual commits.
+    Markers(Vec<(i64, f64)>),
+    /// Shaded ranges of the chart, each made of points with a lower and upper value.
+    Area(Vec<Vec<(i64, f64, f64)>>),
+}
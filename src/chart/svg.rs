@@ -0,0 +1,141 @@
+use anyhow::Result;
+use chrono::Utc;
+use poloto::ticks::{self, TickDist};
+use poloto_chrono::{StepUnit, UnixTime};
+
+use super::{Chart, Shape};
+use crate::{
+    axis::Locale,
+    legend::{self, Placement},
+};
+
+pub fn render(chart: &Chart) -> Result<String> {
+    let labels = chart
+        .series
+        .iter()
+        .map(|series| legend::escape(&series.label))
+        .collect::<Vec<_>>();
+
+    // Positions other than the right side are drawn separately, so hide poloto's own legend.
+    let poloto_label = |i: usize| match chart.legend {
+        Placement::Right => labels[i].as_str(),
+        _ => "",
+    };
+
+    let plots = chart.series.iter().enumerate().map(|(i, series)| {
+        let plot = poloto::build::plot(poloto_label(i));
+        let points = |points: &[(i64, f64)]| {
+            points
+                .iter()
+                .map(|&(t, value)| (UnixTime(t), value))
+                .collect::<Vec<_>>()
+        };
+
+        match &series.shape {
+            Shape::Line(list) | Shape::Dashed(list) => plot.line(points(list)),
+            Shape::Markers(list) => plot.scatter(points(list)),
+            Shape::Area(ranges) => plot.line_fill_raw(outline(ranges)),
+        }
+    });
+
+    let svg = poloto::header()
+        .with_viewbox_width(1600.0)
+        .with_dim([chart.width as f64, chart.height as f64]);
+
+    let locale = chart.locale.clone().unwrap_or_else(Locale::from_env);
+
+    let mut buf = poloto::frame()
+        .with_tick_lines([true, true])
+        .with_viewbox(svg.get_viewbox())
+        .build()
+        .data(poloto::plots!(poloto::build::markers([], [0.0]), plots))
+        .map_xticks(|default| {
+            ticks::from_closure(|data, canvas, req| {
+                let ticks = ticks::gen_ticks(default, data, canvas, req);
+                let footnote = ticks.fmt.footnote();
+                let step = *ticks.fmt.step();
+                let start = *ticks.fmt.start();
+                let locale = &locale;
+
+                ticks
+                    .with_tick_fmt(move |&time| {
+                        let label = date_tick(time, step, locale);
+                        if time == start {
+                            format!("{label}{footnote}")
+                        } else {
+                            label
+                        }
+                    })
+                    .with_where_fmt(move || {
+                        let date = if locale.is_iso() {
+                            start.dynamic_where_format(&Utc, &step).to_string()
+                        } else {
+                            locale.format_date(start.datetime(&Utc).date_naive())
+                        };
+                        format!("{footnote}{date} in {step} in TZ:{Utc}")
+                    })
+            })
+        })
+        .map_yticks(|default| {
+            ticks::from_closure(|data, canvas, req| {
+                ticks::gen_ticks(default, data, canvas, req)
+                    .unwrap()
+                    .with_tick_fmt(|&value| chart.y_unit.format(value, &locale))
+            })
+        })
+        .build_and_label((&chart.title, &chart.x_label, &chart.y_label))
+        .append_to(svg.light_theme())
+        .render_string()?;
+
+    legend::draw(&mut buf, chart.legend, &labels, svg.get_viewbox())?;
+
+    // Rules of later style elements win, so the palette overrides the colors of the theme, and
+    // dashed series override the dash patterns of the palette.
+    let style = chart.palette.style().unwrap_or_default() + &dashes(chart);
+    if !style.is_empty() {
+        if let Some(end) = buf.rfind("</svg>") {
+            buf.insert_str(end, &format!("<style>\n{style}</style>\n"));
+        }
+    }
+
+    Ok(buf)
+}
+
+/// CSS rules that draw the dashed series with a dash pattern.
+fn dashes(chart: &Chart) -> String {
+    chart
+        .series
+        .iter()
+        .enumerate()
+        .filter(|(_, series)| matches!(series.shape, Shape::Dashed(_)))
+        .map(|(i, _)| format!(".poloto_line.poloto{i}.poloto_stroke{{stroke-dasharray:12 8;}}\n"))
+        .collect()
+}
+
+/// Label of a tick on the time axis. Years and smaller steps than days look the same everywhere,
+/// and poloto's own labels are kept for ISO dates.
+fn date_tick(time: UnixTime, step: StepUnit, locale: &Locale) -> String {
+    let date = time.datetime(&Utc).date_naive();
+
+    match step {
+        _ if locale.is_iso() => time.dynamic_format(&Utc, &step).to_string(),
+        StepUnit::MO => locale.format_month(date),
+        StepUnit::DY => locale.format_date(date),
+        _ => time.dynamic_format(&Utc, &step).to_string(),
+    }
+}
+
+/// Outline of the shaded ranges, going along the upper values and back along the lower ones, so
+/// they can be drawn as a single filled shape. Each range starts and ends at its first lower
+/// value, connecting them along the bottom for areas that start at zero.
+fn outline(ranges: &[Vec<(i64, f64, f64)>]) -> Vec<(UnixTime, f64)> {
+    ranges
+        .iter()
+        .flat_map(|range| {
+            let start = range.first().map(|&(t, min, _)| (UnixTime(t), min));
+            let upper = range.iter().map(|&(t, _, max)| (UnixTime(t), max));
+            let lower = range.iter().rev().map(|&(t, min, _)| (UnixTime(t), min));
+            start.into_iter().chain(upper).chain(lower)
+        })
+        .collect()
+}
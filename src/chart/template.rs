@@ -0,0 +1,31 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use tera::Tera;
+
+use super::{svg, vega_lite, Chart};
+
+/// Render a user provided [Tera](https://keats.github.io/tera/) template, to embed the chart into
+/// custom reports.
+///
+/// The template gets the `chart` itself with its `title`, axis labels and `series`, each with a
+/// `label` and a `shape` that has a `type` of `line`, `markers` or `area` and the `points`. The
+/// finished chart is available as `svg` and as `vega_lite` spec, which need the `safe` filter to
+/// be inserted as is. Templates ending in `.html` or `.xml` are escaped automatically.
+pub fn render(path: &Path, chart: &Chart) -> Result<String> {
+    let template = fs::read_to_string(path)
+        .with_context(|| format!("failed reading template {}", path.display()))?;
+
+    let mut context = tera::Context::new();
+    context.insert("chart", chart);
+    context.insert("svg", &svg::render(chart)?);
+    context.insert("vega_lite", &vega_lite::render(chart)?);
+
+    let autoescape = matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("html" | "htm" | "xml")
+    );
+
+    Tera::one_off(&template, &context, autoescape)
+        .with_context(|| format!("failed rendering template {}", path.display()))
+}
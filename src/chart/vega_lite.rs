@@ -0,0 +1,216 @@
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use super::{Chart, Shape};
+use crate::{axis::YUnit, legend::Placement};
+
+/// Version of the Vega-Lite schema that the spec is written for.
+const SCHEMA: &str = "https://vega.github.io/schema/vega-lite/v5.json";
+
+/// Create a Vega-Lite spec with the data of all series inlined, with one layer for each kind of
+/// shape. Times are given in milliseconds, as expected by Vega.
+pub fn render(chart: &Chart) -> Result<String> {
+    let mut lines = Vec::new();
+    let mut dashed = Vec::new();
+    let mut markers = Vec::new();
+    let mut areas = Vec::new();
+
+    for series in &chart.series {
+        match &series.shape {
+            Shape::Line(points) => lines.extend(values(&series.label, points)),
+            Shape::Dashed(points) => dashed.extend(values(&series.label, points)),
+            Shape::Markers(points) => markers.extend(values(&series.label, points)),
+            Shape::Area(ranges) => {
+                for (i, range) in ranges.iter().enumerate() {
+                    areas.extend(range.iter().map(|&(time, min, max)| {
+                        json!({
+                            "series": series.label,
+                            "shape": i,
+                            "time": time * 1000,
+                            "min": min,
+                            "max": max,
+                        })
+                    }));
+                }
+            }
+        }
+    }
+
+    let mut x = json!({ "field": "time", "type": "temporal", "title": chart.x_label });
+    if let Some(locale) = chart.locale.as_ref().filter(|locale| !locale.is_iso()) {
+        x["axis"] = json!({ "format": locale.date_pattern() });
+    }
+    let y = |field| {
+        json!({
+            "field": field,
+            "type": "quantitative",
+            "title": chart.y_label,
+            "axis": axis(chart.y_unit),
+        })
+    };
+    let legend = match chart.legend {
+        Placement::Top => json!({ "orient": "top" }),
+        Placement::Bottom => json!({ "orient": "bottom" }),
+        Placement::Right => json!({ "orient": "right" }),
+        Placement::None => Value::Null,
+    };
+    let mut color = json!({
+        "field": "series",
+        "type": "nominal",
+        "title": null,
+        "legend": legend,
+        // Keep the order of the series, instead of sorting them by name.
+        "sort": chart.series.iter().map(|s| &s.label).collect::<Vec<_>>(),
+    });
+    if let Some(colors) = chart.palette.colors() {
+        color["scale"] = json!({ "range": colors });
+    }
+
+    let mut layers = Vec::new();
+
+    if !areas.is_empty() {
+        layers.push(json!({
+            "data": { "values": areas },
+            "mark": { "type": "area", "opacity": 0.3 },
+            "encoding": {
+                "x": x,
+                "y": y("max"),
+                "y2": { "field": "min" },
+                "color": color,
+                "detail": { "field": "shape" },
+            },
+        }));
+    }
+
+    if !lines.is_empty() {
+        let mut encoding = json!({ "x": x, "y": y("value"), "color": color });
+        if let Some(dashes) = chart.palette.dashes() {
+            // Vega-Lite wants the dash patterns as arrays, with an empty one for solid lines.
+            let dashes = dashes
+                .iter()
+                .map(|dash| {
+                    dash.split(' ')
+                        .filter_map(|len| len.parse::<u32>().ok())
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            encoding["strokeDash"] = json!({
+                "field": "series",
+                "type": "nominal",
+                "legend": null,
+                "sort": color["sort"],
+                "scale": { "range": dashes },
+            });
+        }
+
+        layers.push(json!({
+            "data": { "values": lines },
+            "mark": "line",
+            "encoding": encoding,
+        }));
+    }
+
+    // Projected values keep their own dash pattern, regardless of the palette.
+    if !dashed.is_empty() {
+        layers.push(json!({
+            "data": { "values": dashed },
+            "mark": { "type": "line", "strokeDash": [12, 8] },
+            "encoding": { "x": x, "y": y("value"), "color": color },
+        }));
+    }
+
+    if !markers.is_empty() {
+        layers.push(json!({
+            "data": { "values": markers },
+            "mark": "point",
+            "encoding": { "x": x, "y": y("value"), "color": color },
+        }));
+    }
+
+    let mut spec = json!({
+        "$schema": SCHEMA,
+        "title": chart.title,
+        "width": chart.width,
+        "height": chart.height,
+        "layer": layers,
+    });
+
+    // Without a locale, the separators are left to the viewer.
+    if let Some(locale) = &chart.locale {
+        let (thousands, decimal) = locale.separators();
+        spec["config"] = json!({
+            "locale": {
+                "number": {
+                    "decimal": decimal.to_string(),
+                    "thousands": thousands.to_string(),
+                    "grouping": [3],
+                    "currency": ["", ""],
+                },
+            },
+        });
+    }
+
+    Ok(serde_json::to_string_pretty(&spec)?)
+}
+
+fn values<'a>(label: &'a str, points: &'a [(i64, f64)]) -> impl Iterator<Item = Value> + 'a {
+    points.iter().map(move |&(time, value)| {
+        json!({
+            "series": label,
+            "time": time * 1000,
+            "value": value,
+        })
+    })
+}
+
+/// Label format of the value axis, using the number formats of d3. Separators follow the locale
+/// of the spec, or the one that it is displayed with.
+fn axis(unit: YUnit) -> Value {
+    match unit {
+        YUnit::Auto => json!({ "format": "~s" }),
+        YUnit::Lines => json!({ "format": ",~f" }),
+        YUnit::Kloc => json!({ "labelExpr": "format(datum.value / 1000, ',~f')" }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chart::Series, palette::Palette};
+
+    #[test]
+    fn data_is_inlined() {
+        let chart = Chart {
+            title: "Title".to_owned(),
+            x_label: "Date".to_owned(),
+            y_label: "Lines".to_owned(),
+            y_unit: YUnit::Auto,
+            locale: None,
+            width: 800,
+            height: 600,
+            legend: Placement::Right,
+            palette: Palette::Default,
+            series: vec![
+                Series {
+                    label: "Code".to_owned(),
+                    shape: Shape::Line(vec![(1, 10.0), (2, 20.0)]),
+                },
+                Series {
+                    label: "Range".to_owned(),
+                    shape: Shape::Area(vec![vec![(1, 5.0, 15.0)]]),
+                },
+            ],
+        };
+
+        let spec = serde_json::from_str::<Value>(&render(&chart).unwrap()).unwrap();
+        let layers = spec["layer"].as_array().unwrap();
+
+        assert_eq!(2, layers.len());
+        assert_eq!("area", layers[0]["mark"]["type"]);
+        assert_eq!(15.0, layers[0]["data"]["values"][0]["max"]);
+        assert_eq!("line", layers[1]["mark"]);
+        assert_eq!(2000, layers[1]["data"]["values"][1]["time"]);
+        assert_eq!("Code", layers[1]["data"]["values"][1]["series"]);
+        assert!(spec.get("config").is_none());
+    }
+}
@@ -0,0 +1,310 @@
+//! Gate for CI pipelines, which fails with its own exit code if the comment ratio (comment lines
+//! per code line) or density (comment lines per 1000 code lines) of the latest entry is too low,
+//! or the ratio dropped too much compared to a baseline, like the stats file of the last release
+//! or of last week.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use clap::{Args, ValueHint};
+use serde_json::{json, Value};
+use tokei::LanguageType;
+
+use crate::{exit::Failure, languages, models::Summary, stats_file::StatsFile};
+
+#[derive(Args)]
+pub struct Options {
+    /// Lowest allowed comment ratio of the latest entry, like `0.15`, or of a single language,
+    /// like `Rust=0.20`. Can be given several times, and languages without code lines always
+    /// pass.
+    #[arg(long, value_name = "[LANGUAGE=]RATIO")]
+    pub min_ratio: Vec<MinRatio>,
+    /// Lowest allowed comment density of the latest entry, in comment lines per 1000 code lines
+    /// like `render --metric density`, for example `150`.
+    #[arg(long, value_name = "DENSITY")]
+    pub min_density: Option<f64>,
+    /// Stats file to compare against, like the one of the last release.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub baseline: Option<PathBuf>,
+    /// Largest allowed drop of the comment ratio compared to the baseline, like `0.01`. Defaults
+    /// to no drop at all.
+    #[arg(long, value_name = "RATIO", requires = "baseline")]
+    pub max_drop: Option<f64>,
+}
+
+/// Lowest allowed comment ratio, either of all code or of a single language.
+#[derive(Clone, Copy)]
+pub struct MinRatio {
+    pub language: Option<LanguageType>,
+    pub ratio: f64,
+}
+
+impl FromStr for MinRatio {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (language, ratio) = match s.split_once('=') {
+            Some((language, ratio)) => (Some(languages::parse(language)?), ratio),
+            None => (None, s),
+        };
+        let ratio = ratio
+            .parse()
+            .map_err(|e| format!("invalid ratio `{ratio}`: {e}"))?;
+
+        Ok(Self { language, ratio })
+    }
+}
+
+/// Line counts of the latest entry of a stats file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Totals {
+    pub code: usize,
+    pub comments: usize,
+}
+
+impl From<&Summary> for Totals {
+    fn from(summary: &Summary) -> Self {
+        Self {
+            code: summary.statistics.code,
+            comments: summary.statistics.comments,
+        }
+    }
+}
+
+impl Totals {
+    /// Read the totals of the latest entry.
+    pub fn latest(path: &Path) -> Result<Self> {
+        let file = StatsFile::open(path)?;
+        let Some(latest) = file.last_entry()? else {
+            bail!("{} contains no entries", path.display());
+        };
+
+        Ok(Self::from(&latest.total_stats()?))
+    }
+
+    pub fn ratio(self) -> f64 {
+        if self.code == 0 {
+            0.0
+        } else {
+            self.comments as f64 / self.code as f64
+        }
+    }
+
+    /// Comment lines per 1000 code lines, zero without any code.
+    pub fn density(self) -> f64 {
+        self.ratio() * 1000.0
+    }
+
+    /// Changes since the baseline, for reports.
+    pub fn delta(self, baseline: Self) -> Value {
+        json!({
+            "code": self.code as i64 - baseline.code as i64,
+            "comments": self.comments as i64 - baseline.comments as i64,
+            "comment_ratio": self.ratio() - baseline.ratio(),
+        })
+    }
+}
+
+pub fn run(input: &Path, options: &Options) -> Result<()> {
+    let file = StatsFile::open(input)?;
+    let Some(entry) = file.last_entry()? else {
+        bail!("{} contains no entries", input.display());
+    };
+
+    let latest = Totals::from(&entry.total_stats()?);
+    let languages = entry
+        .language_stats()?
+        .iter()
+        .map(|(&lang, summary)| (lang, Totals::from(summary)))
+        .collect::<HashMap<_, _>>();
+    let baseline = options
+        .baseline
+        .as_deref()
+        .map(Totals::latest)
+        .transpose()?;
+
+    println!("comment ratio: {:.4}", latest.ratio());
+    if options.min_density.is_some() {
+        println!("density:       {:.1}", latest.density());
+    }
+    if let Some(baseline) = baseline {
+        println!(
+            "baseline:      {:.4} ({:+.4})",
+            baseline.ratio(),
+            latest.ratio() - baseline.ratio()
+        );
+    }
+
+    for lang in options.min_ratio.iter().filter_map(|min| min.language) {
+        if let Some(totals) = languages.get(&lang) {
+            println!("{:<15}{:.4}", format!("{}:", lang.name()), totals.ratio());
+        }
+    }
+
+    let violations = violations(latest, &languages, baseline, options);
+    for violation in &violations {
+        eprintln!("violation: {violation}");
+    }
+
+    if !violations.is_empty() {
+        return Err(
+            anyhow!("{} of the thresholds were violated", violations.len())
+                .context(Failure::Threshold),
+        );
+    }
+
+    println!("all checks passed");
+
+    Ok(())
+}
+
+fn violations(
+    latest: Totals,
+    languages: &HashMap<LanguageType, Totals>,
+    baseline: Option<Totals>,
+    options: &Options,
+) -> Vec<String> {
+    let mut violations = Vec::new();
+
+    for min in &options.min_ratio {
+        let (totals, subject) = match min.language {
+            None => (latest, "comment ratio".to_owned()),
+            Some(lang) => match languages.get(&lang) {
+                Some(&totals) if totals.code > 0 => {
+                    (totals, format!("comment ratio of {}", lang.name()))
+                }
+                _ => continue,
+            },
+        };
+
+        if totals.ratio() < min.ratio {
+            violations.push(format!(
+                "{subject} {:.4} is below the minimum of {:.4}",
+                totals.ratio(),
+                min.ratio
+            ));
+        }
+    }
+
+    if let Some(min) = options.min_density {
+        if latest.density() < min {
+            violations.push(format!(
+                "comment density {:.1} is below the minimum of {min:.1}",
+                latest.density()
+            ));
+        }
+    }
+
+    if let Some(baseline) = baseline {
+        let drop = baseline.ratio() - latest.ratio();
+        let max = options.max_drop.unwrap_or_default();
+        if drop > max {
+            violations.push(format!(
+                "comment ratio dropped by {drop:.4} since the baseline, more than the allowed \
+                 {max:.4}"
+            ));
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds_are_checked() {
+        let options = Options {
+            min_ratio: vec!["0.2".parse().unwrap()],
+            min_density: None,
+            baseline: None,
+            max_drop: Some(0.05),
+        };
+        let totals = |code, comments| Totals { code, comments };
+        let none = HashMap::new();
+
+        assert!(violations(totals(100, 25), &none, None, &options).is_empty());
+        assert_eq!(1, violations(totals(100, 10), &none, None, &options).len());
+
+        // Dropping from 0.3 to 0.26 is within the allowed 0.05, to 0.22 it isn't.
+        let baseline = Some(totals(100, 30));
+        assert!(violations(totals(100, 26), &none, baseline, &options).is_empty());
+        assert_eq!(
+            1,
+            violations(totals(100, 22), &none, baseline, &options).len()
+        );
+        assert_eq!(
+            2,
+            violations(totals(100, 10), &none, baseline, &options).len()
+        );
+    }
+
+    #[test]
+    fn thresholds_apply_per_language() {
+        let options = Options {
+            min_ratio: vec![
+                "rust=0.2".parse().unwrap(),
+                "Shell=0.05".parse().unwrap(),
+                "Python=0.5".parse().unwrap(),
+            ],
+            min_density: None,
+            baseline: None,
+            max_drop: None,
+        };
+        let languages = HashMap::from([
+            (
+                LanguageType::Rust,
+                Totals {
+                    code: 100,
+                    comments: 10,
+                },
+            ),
+            (
+                LanguageType::Sh,
+                Totals {
+                    code: 100,
+                    comments: 10,
+                },
+            ),
+        ]);
+        let latest = Totals {
+            code: 200,
+            comments: 20,
+        };
+
+        // Rust is below its minimum, Shell isn't and Python has no code at all.
+        let violations = violations(latest, &languages, None, &options);
+        assert_eq!(1, violations.len());
+        assert!(violations[0].contains("Rust"));
+
+        assert!("Klingon=0.1".parse::<MinRatio>().is_err());
+        assert!("Rust=lots".parse::<MinRatio>().is_err());
+    }
+
+    #[test]
+    fn density_is_checked() {
+        let options = Options {
+            min_ratio: Vec::new(),
+            min_density: Some(150.0),
+            baseline: None,
+            max_drop: None,
+        };
+        let totals = |code, comments| Totals { code, comments };
+        let none = HashMap::new();
+
+        assert_eq!(250.0, totals(200, 50).density());
+        assert_eq!(0.0, totals(0, 50).density());
+
+        assert!(violations(totals(200, 30), &none, None, &options).is_empty());
+        let violations = violations(totals(200, 29), &none, None, &options);
+        assert_eq!(
+            vec!["comment density 145.0 is below the minimum of 150.0"],
+            violations
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Ownership of files as declared in a `CODEOWNERS` file, to split the statistics by the teams
+//! that are responsible for them.
+//!
+//! Patterns follow the rules of GitHub: they work like `.gitignore` patterns, and the last
+//! matching line decides the owners of a file. Lines without owners leave the matched files
+//! unowned.
+
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+
+use crate::scan;
+
+/// Locations of the `CODEOWNERS` file within a repository, in the order GitHub looks for them.
+const LOCATIONS: &[&str] = &[
+    ".github/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+];
+
+pub struct CodeOwners {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    pattern: Regex,
+    owners: Vec<String>,
+}
+
+impl CodeOwners {
+    /// Load the rules from a `CODEOWNERS` file, or from the one of a Git repository at `HEAD`.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_dir() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("failed reading {}", path.display()))?;
+            return Self::parse(&content);
+        }
+
+        match Self::find(path)? {
+            Some(owners) => Ok(owners),
+            None => bail!(
+                "{} contains no CODEOWNERS file at {}",
+                path.display(),
+                LOCATIONS.join(", ")
+            ),
+        }
+    }
+
+    /// Load the `CODEOWNERS` file of a Git repository at `HEAD`, if it has one.
+    pub fn find(repo: &Path) -> Result<Option<Self>> {
+        let repo = scan::open_repository(repo)?;
+        let tree = repo.head()?.peel_to_tree()?;
+
+        for location in LOCATIONS {
+            let Ok(entry) = tree.get_path(Path::new(location)) else {
+                continue;
+            };
+            let blob = entry.to_object(&repo)?.peel_to_blob()?;
+            let content = String::from_utf8_lossy(blob.content());
+
+            return Self::parse(&content)
+                .map(Some)
+                .with_context(|| format!("failed parsing {location}"));
+        }
+
+        Ok(None)
+    }
+
+    pub fn parse(content: &str) -> Result<Self> {
+        let rules = content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    return None;
+                }
+
+                let mut parts = line.split_whitespace();
+                let pattern = parts.next()?;
+                let owners = parts
+                    .take_while(|part| !part.starts_with('#'))
+                    .map(str::to_owned)
+                    .collect();
+
+                Some(
+                    glob(pattern)
+                        .map(|pattern| Rule { pattern, owners })
+                        .with_context(|| format!("invalid pattern in line {}", i + 1)),
+                )
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { rules })
+    }
+
+    /// Owners of a file, given by its path relative to the repository root. Empty for unowned
+    /// files.
+    pub fn owners(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.pattern.is_match(path))
+            .map_or(&[], |rule| &rule.owners)
+    }
+
+    /// Whether the file belongs to the owner, or is unowned for `None`.
+    pub fn owns(&self, path: &str, owner: Option<&str>) -> bool {
+        let owners = self.owners(path);
+        match owner {
+            Some(owner) => owners.iter().any(|o| o == owner),
+            None => owners.is_empty(),
+        }
+    }
+}
+
+/// Translate a pattern to a regular expression. Patterns that contain a slash anywhere but at
+/// the end are relative to the root, others match at any depth. A match of a directory applies
+/// to all files below it.
+fn glob(pattern: &str) -> Result<Regex> {
+    let anchored = pattern.trim_end_matches('/').contains('/');
+    let dir_only = pattern.ends_with('/');
+    let pattern = pattern.trim_start_matches('/').trim_end_matches('/');
+
+    let mut regex = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut rest = pattern;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(tail) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = tail;
+        } else {
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                _ => regex.push_str(&regex::escape(&c.to_string())),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    regex.push_str(if dir_only { "/.*$" } else { "(?:/.*)?$" });
+
+    Regex::new(&regex).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let owners = CodeOwners::parse(
+            "# Default owners\n\
+             * @org/everyone\n\
+             *.rs @org/rust\n\
+             /docs/ @org/docs @alice\n\
+             src/**/generated.rs\n\
+             build/ @org/ci # release tooling\n",
+        )
+        .unwrap();
+
+        assert_eq!(["@org/everyone"], owners.owners("README.md"));
+        assert_eq!(["@org/rust"], owners.owners("src/main.rs"));
+        assert_eq!(
+            ["@org/docs", "@alice"],
+            owners.owners("docs/guide/intro.md")
+        );
+        assert_eq!(["@org/everyone"], owners.owners("src/docs/notes.md"));
+        assert!(owners.owners("src/api/generated.rs").is_empty());
+        assert_eq!(["@org/ci"], owners.owners("tools/build/run.sh"));
+
+        assert!(owners.owns("docs/index.md", Some("@alice")));
+        assert!(!owners.owns("docs/index.md", None));
+        assert!(owners.owns("src/api/generated.rs", None));
+    }
+}
@@ -0,0 +1,255 @@
+use serde::Deserialize;
+use tokei::LanguageType;
+
+use crate::models::CommentKinds;
+
+/// Characters that are common in code, but rare in natural language.
+const SYMBOLS: &[char] = &['(', ')', '{', '}', '[', ']', ';', '=', '<', '>', '&', '|'];
+/// Tag that declares the license of a file, as defined by the SPDX specification.
+const SPDX_TAG: &str = "SPDX-License-Identifier:";
+/// Amount of lines at the start of a file that are searched for the [`SPDX_TAG`].
+const SPDX_HEADER_LINES: usize = 20;
+/// Markers of comments at the start of files that are required by tools rather than written for
+/// readers, besides shebangs.
+const BOILERPLATE: &[&str] = &[
+    "-*- coding",
+    "coding:",
+    "coding=",
+    "frozen_string_literal:",
+    SPDX_TAG,
+    "SPDX-FileCopyrightText:",
+];
+
+/// Settings of the heuristics that tell commented-out code apart from natural language, loaded
+/// from the `comment-heuristics` table of the configuration file.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Heuristics {
+    /// Comment lines ending with any of these are considered code.
+    pub code_endings: Vec<String>,
+    /// Comment lines starting with any of these are considered code, like keywords.
+    pub code_prefixes: Vec<String>,
+    /// Share of symbols typical for code (like braces and operators) among all characters that
+    /// aren't whitespace, from which on a comment line is considered code.
+    pub symbol_share: f64,
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self {
+            code_endings: [";", "{", "}", "=>", "(", "["].map(str::to_owned).to_vec(),
+            code_prefixes: [
+                "}", "#include", "import ", "return ", "let ", "const ", "var ", "fn ", "def ",
+                "if (", "for (", "while (",
+            ]
+            .map(str::to_owned)
+            .to_vec(),
+            symbol_share: 0.2,
+        }
+    }
+}
+
+impl Heuristics {
+    /// Count the comment lines of a file that look like commented-out code and those that look
+    /// like natural language.
+    ///
+    /// Only lines that consist of a comment alone are looked at, like the comment lines of tokei.
+    /// Comment markers inside strings and nested comments aren't detected, and lines without any
+    /// letters or digits (like separators) are neither counted as code nor as prose.
+    pub fn classify(&self, lang: LanguageType, source: &[u8]) -> CommentKinds {
+        let source = String::from_utf8_lossy(source);
+        let mut kinds = CommentKinds::default();
+        // End marker of the block comment that the current line is in.
+        let mut block = None::<&str>;
+
+        for line in source.lines() {
+            let line = line.trim();
+
+            let text = if let Some(end) = block {
+                match line.split_once(end) {
+                    Some((text, rest)) => {
+                        block = None;
+                        // Code follows after the comment ended.
+                        if !rest.trim().is_empty() {
+                            continue;
+                        }
+                        text
+                    }
+                    None => line,
+                }
+            } else if let Some(text) = lang
+                .line_comments()
+                .iter()
+                .find_map(|start| line.strip_prefix(start))
+            {
+                text
+            } else if let Some((text, end)) = lang
+                .multi_line_comments()
+                .iter()
+                .find_map(|&(start, end)| Some((line.strip_prefix(start)?, end)))
+            {
+                match text.split_once(end) {
+                    Some((text, rest)) => {
+                        if !rest.trim().is_empty() {
+                            continue;
+                        }
+                        text
+                    }
+                    None => {
+                        block = Some(end);
+                        text
+                    }
+                }
+            } else {
+                continue;
+            };
+
+            // Strip decorations like the extra slash of doc comments or the stars of block
+            // comments.
+            let text = text
+                .trim_start_matches(['/', '!', '*', '#', '-', ';', '%'])
+                .trim();
+
+            if !text.chars().any(char::is_alphanumeric) {
+                continue;
+            }
+
+            if self.is_code(text) {
+                kinds.code += 1;
+            } else {
+                kinds.prose += 1;
+            }
+        }
+
+        kinds
+    }
+
+    fn is_code(&self, text: &str) -> bool {
+        if self
+            .code_endings
+            .iter()
+            .any(|end| text.ends_with(end.as_str()))
+            || self
+                .code_prefixes
+                .iter()
+                .any(|prefix| text.starts_with(prefix.as_str()))
+        {
+            return true;
+        }
+
+        let chars = text.chars().filter(|c| !c.is_whitespace());
+        let total = chars.clone().count();
+        let symbols = chars.filter(|c| SYMBOLS.contains(c)).count();
+
+        symbols as f64 >= total as f64 * self.symbol_share
+    }
+}
+
+/// Split off the comment lines at the start of a file that are boilerplate, like shebangs,
+/// encoding declarations and SPDX tags. Returns the amount of these lines, together with the
+/// content following them.
+///
+/// Only lines that tokei counts as comments are taken into account, so a shebang in a language
+/// without `#` comments stays code.
+pub fn strip_boilerplate(lang: LanguageType, source: &[u8]) -> (usize, &[u8]) {
+    let mut lines = 0;
+    let mut offset = 0;
+    let mut end = 0;
+
+    for line in source.split_inclusive(|&b| b == b'\n') {
+        let start = offset;
+        offset += line.len();
+
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+
+        let comment = lang
+            .line_comments()
+            .iter()
+            .chain(lang.multi_line_comments().iter().map(|(open, _)| open))
+            .any(|open| text.starts_with(open));
+        let boilerplate = (start == 0 && text.starts_with("#!"))
+            || BOILERPLATE.iter().any(|marker| text.contains(marker));
+
+        if !comment || !boilerplate {
+            break;
+        }
+
+        lines += 1;
+        end = offset;
+    }
+
+    (lines, &source[end..])
+}
+
+/// Whether the file declares its license with an SPDX tag in its header.
+pub fn has_spdx_tag(source: &[u8]) -> bool {
+    source
+        .split(|&b| b == b'\n')
+        .take(SPDX_HEADER_LINES)
+        .any(|line| {
+            line.windows(SPDX_TAG.len())
+                .any(|window| window == SPDX_TAG.as_bytes())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commented_out_code_is_detected() {
+        let kinds = Heuristics::default().classify(
+            LanguageType::Rust,
+            br"
+            /// Add two numbers.
+            fn add(a: u32, b: u32) -> u32 {
+                // let sum = a + b;
+                // if sum > 10 {
+                //     return 0;
+                // }
+                a + b // Natural language behind code isn't counted.
+            }
+
+            /*
+             * Block comments are detected as well,
+             * total = (a + b) * (c + d)
+             */
+            // ----------
+            ",
+        );
+
+        assert_eq!(4, kinds.code);
+        assert_eq!(2, kinds.prose);
+    }
+
+    #[test]
+    fn boilerplate_is_stripped() {
+        let source = b"#!/usr/bin/env python
+# -*- coding: utf-8 -*-
+
+# SPDX-License-Identifier: MIT
+# Actual comment.
+";
+        let (lines, rest) = strip_boilerplate(LanguageType::Python, source);
+        assert_eq!(3, lines);
+        assert_eq!(b"# Actual comment.\n", rest);
+
+        let (lines, _) = strip_boilerplate(LanguageType::JavaScript, b"#!/usr/bin/env node\n");
+        assert_eq!(0, lines);
+    }
+
+    #[test]
+    fn spdx_tag_is_found_in_header() {
+        assert!(has_spdx_tag(
+            b"// Copyright\n// SPDX-License-Identifier: MIT\n"
+        ));
+        assert!(!has_spdx_tag(b"// Copyright\n"));
+
+        let late = format!("{}// SPDX-License-Identifier: MIT\n", "\n".repeat(20));
+        assert!(!has_spdx_tag(late.as_bytes()));
+    }
+}
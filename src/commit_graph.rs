@@ -0,0 +1,84 @@
+//! Commit-graph files, which hold the parents and generation numbers of all commits, so walking a
+//! history doesn't need to load every commit object.
+//!
+//! libgit2 reads them on its own, but only the single `objects/info/commit-graph` file and not
+//! the split chains that `git fetch` and `git maintenance` write. If requested, a single file is
+//! written with the `git` command line tool, next to any existing chain.
+
+use std::{fs, path::PathBuf, process::Command};
+
+use anyhow::{ensure, Context, Result};
+use git2::Repository;
+
+/// Location of the commit-graph file that libgit2 reads. Worktrees share the objects of the
+/// main repository, which they point to in their `commondir` file.
+fn path(repo: &Repository) -> PathBuf {
+    let dir = repo.path();
+    let common = match fs::read_to_string(dir.join("commondir")) {
+        Ok(common) => dir.join(common.trim_end()),
+        Err(_) => dir.to_owned(),
+    };
+
+    common.join("objects/info/commit-graph")
+}
+
+/// Whether the repository has a commit-graph that libgit2 can use.
+pub fn exists(repo: &Repository) -> bool {
+    path(repo).is_file()
+}
+
+/// Write the commit-graph of all commits reachable from any reference.
+pub fn write(repo: &Repository) -> Result<()> {
+    let status = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["commit-graph", "write", "--reachable", "--no-progress"])
+        .status()
+        .context("failed running git, is it installed?")?;
+    ensure!(status.success(), "git exited with {status}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use git2::{Signature, Sort};
+
+    use super::*;
+
+    #[test]
+    fn graph_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+
+        let signature = Signature::now("test", "test@example.com").unwrap();
+        let tree = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree).unwrap();
+        let mut parents = Vec::new();
+        for message in ["first", "second", "third"] {
+            let parent = parents.last().map(|&oid| repo.find_commit(oid).unwrap());
+            let oid = repo
+                .commit(
+                    Some("HEAD"),
+                    &signature,
+                    &signature,
+                    message,
+                    &tree,
+                    parent.as_slice().iter().collect::<Vec<_>>().as_slice(),
+                )
+                .unwrap();
+            parents.push(oid);
+        }
+
+        assert!(!exists(&repo));
+        write(&repo).unwrap();
+        assert!(exists(&repo));
+
+        // Walks read the parents from the graph now, which must give the same history.
+        let repo = Repository::open(dir.path()).unwrap();
+        let mut walk = repo.revwalk().unwrap();
+        walk.push_head().unwrap();
+        walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE).unwrap();
+        assert_eq!(parents, walk.collect::<Result<Vec<_>, _>>().unwrap());
+    }
+}
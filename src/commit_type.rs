@@ -0,0 +1,97 @@
+//! Classification of commits by the type of their [Conventional Commits](https://www.conventionalcommits.org)
+//! message, like `feat(api): add endpoint` or `docs!: rewrite guide`, to see which kinds of
+//! changes actually add comments.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum CommitType {
+    Feat,
+    Fix,
+    Docs,
+    Style,
+    Refactor,
+    Perf,
+    Test,
+    Build,
+    Ci,
+    Chore,
+    Revert,
+    /// Messages that don't follow the convention, or use a type outside of it.
+    Other,
+}
+
+impl CommitType {
+    /// Classify a commit by the first line of its message.
+    pub fn classify(summary: &str) -> Self {
+        let Some((prefix, _)) = summary.split_once(':') else {
+            return Self::Other;
+        };
+
+        // Strip the optional scope and breaking change marker, as in `feat(api)!`.
+        let prefix = prefix.trim_end_matches('!');
+        let kind = match prefix.split_once('(') {
+            Some((kind, scope)) if scope.ends_with(')') => kind,
+            Some(_) => return Self::Other,
+            None => prefix,
+        };
+
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "feat" | "feature" => Self::Feat,
+            "fix" => Self::Fix,
+            "docs" | "doc" => Self::Docs,
+            "style" => Self::Style,
+            "refactor" => Self::Refactor,
+            "perf" => Self::Perf,
+            "test" | "tests" => Self::Test,
+            "build" => Self::Build,
+            "ci" => Self::Ci,
+            "chore" => Self::Chore,
+            "revert" => Self::Revert,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Feat => "feat",
+            Self::Fix => "fix",
+            Self::Docs => "docs",
+            Self::Style => "style",
+            Self::Refactor => "refactor",
+            Self::Perf => "perf",
+            Self::Test => "test",
+            Self::Build => "build",
+            Self::Ci => "ci",
+            Self::Chore => "chore",
+            Self::Revert => "revert",
+            Self::Other => "other",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_classified() {
+        assert_eq!(CommitType::Feat, CommitType::classify("feat: add charts"));
+        assert_eq!(CommitType::Fix, CommitType::classify("fix(scan): skip LFS"));
+        assert_eq!(
+            CommitType::Docs,
+            CommitType::classify("docs!: rewrite guide")
+        );
+        assert_eq!(
+            CommitType::Refactor,
+            CommitType::classify("Refactor(render)!: split module")
+        );
+        assert_eq!(CommitType::Other, CommitType::classify("Update README"));
+        assert_eq!(CommitType::Other, CommitType::classify("wip: half done"));
+        assert_eq!(CommitType::Other, CommitType::classify("fix(scan: typo"));
+        assert_eq!(
+            CommitType::Other,
+            CommitType::classify("Merge branch 'fix: x'")
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::comments::Heuristics;
+
+/// Configuration file that is loaded from the current directory, if present.
+const DEFAULT_CONFIG_FILE: &str = "commentstats.toml";
+
+/// Optional settings that are loaded from the configuration file.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Custom language groups that can be used with `--filter-group`, on top of the built-in
+    /// ones. Each group maps to a list of language names.
+    pub filter_groups: HashMap<String, Vec<String>>,
+    /// Heuristics for `scan --comment-quality`, to tell commented-out code apart from natural
+    /// language.
+    pub comment_heuristics: Heuristics,
+    /// Rules to hide parts of paths and names, like customer names in directories, in everything
+    /// that leaves the stats file, like charts, the site and the server's answers. They're
+    /// applied in order.
+    pub redact: Vec<Redaction>,
+    /// Charts that `replay` regenerates from their stats files, like after upgrading the tool.
+    pub charts: Vec<ChartEntry>,
+}
+
+/// Chart that is rendered from a stats file with a fixed set of `render` arguments. Paths are
+/// relative to the current directory.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChartEntry {
+    /// Name to select the chart with.
+    pub name: String,
+    /// Location of the statistics file.
+    pub input: PathBuf,
+    /// Location of the rendered chart.
+    pub output: PathBuf,
+    /// Arguments of the `render` command, like `["--group-by", "language"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Replacement of all matches of a regular expression, like `customer-[a-z]+` to `customer-*`.
+/// The replacement can refer to capture groups, like `$1`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Redaction {
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl Config {
+    /// Apply all redaction rules to the text.
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for redaction in &self.redact {
+            if let Cow::Owned(replaced) = redaction
+                .pattern
+                .replace_all(&text, redaction.replacement.as_str())
+            {
+                text = Cow::Owned(replaced);
+            }
+        }
+
+        text
+    }
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(de::Error::custom)
+}
+
+/// Load the configuration from the given file, or the default location if none is given. A
+/// missing default configuration is not an error and simply results in default settings.
+pub fn load(path: Option<PathBuf>) -> Result<Config> {
+    let (path, required) = match path {
+        Some(path) => (path, true),
+        None => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+    };
+
+    if !required && !path.exists() {
+        return Ok(Config::default());
+    }
+
+    read(&path).with_context(|| format!("failed loading config from {}", path.display()))
+}
+
+fn read(path: &Path) -> Result<Config> {
+    let content = fs::read_to_string(path)?;
+    toml::from_str(&content).map_err(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redactions_are_applied_in_order() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[redact]]
+            pattern = "customer-([a-z]+)"
+            replacement = "customer-*"
+
+            [[redact]]
+            pattern = "\\*/internal"
+            replacement = "*/..."
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            "src/customer-*/...",
+            config.redact("src/customer-acme/internal")
+        );
+        assert!(matches!(config.redact("src/lib.rs"), Cow::Borrowed(_)));
+
+        assert!(
+            toml::from_str::<Config>("[[redact]]\npattern = \"(\"\nreplacement = \"\"").is_err()
+        );
+    }
+}
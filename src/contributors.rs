@@ -0,0 +1,215 @@
+//! Files and directories that contributed the most to the change between two entries, to explain
+//! a single point of a chart, like in the tooltips of a dashboard.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+};
+
+use anyhow::{bail, ensure, Result};
+use chrono::NaiveDate;
+use serde_json::{json, Value};
+use tokei::LanguageType;
+
+use crate::{
+    models::{Detail, Entry, EntryFile},
+    stats_file::StatsFile,
+};
+
+/// Default amount of files and directories to list.
+pub const DEFAULT_LIMIT: usize = 10;
+
+/// Change of the lines of a single file or directory.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+struct Change {
+    code: i64,
+    comments: i64,
+}
+
+impl Change {
+    fn size(self) -> u64 {
+        self.code.unsigned_abs() + self.comments.unsigned_abs()
+    }
+}
+
+/// Compare the latest entry of the first history on or before `to` with the latest one on or
+/// before `from`. Without `from`, the entry right before is taken, which is the previous point
+/// of an unbucketed chart. Only files of the given languages are compared, or all of them if
+/// the filter is empty.
+pub fn between(
+    input: &Path,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    filter: &HashSet<LanguageType>,
+    limit: usize,
+) -> Result<Value> {
+    let file = StatsFile::open(input)?;
+    ensure!(
+        file.manifest().metadata.detail == Detail::PerFile,
+        "the stats file doesn't contain statistics per file, which are needed to find the \
+         contributors of a change"
+    );
+
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        bail!("the stats file contains no entries");
+    };
+
+    let mut base = None::<Entry>;
+    let mut latest = None::<Entry>;
+    let mut done = false;
+
+    for index in range {
+        file.read_chunk(index, |entry| {
+            if done || entry.failed {
+                return Ok(());
+            }
+            if to.is_some_and(|to| entry.timestamp.date_naive() > to) {
+                done = true;
+                return Ok(());
+            }
+
+            if let Some(old) = latest.replace(entry) {
+                if from.is_none_or(|from| old.timestamp.date_naive() <= from) {
+                    base = Some(old);
+                }
+            }
+            Ok(())
+        })?;
+
+        if done {
+            break;
+        }
+    }
+
+    let Some(latest) = latest else {
+        bail!("no entries on or before the given day");
+    };
+
+    let selected = |file: &&EntryFile| filter.is_empty() || filter.contains(&file.language);
+    let empty = HashMap::new();
+    let changes = changes(
+        base.as_ref().map_or(&empty, |base| &base.files),
+        &latest.files,
+        selected,
+    );
+
+    let mut directories = BTreeMap::<&str, Change>::new();
+    for (path, change) in &changes {
+        let dir = path.rsplit_once('/').map_or(".", |(dir, _)| dir);
+        let total = directories.entry(dir).or_default();
+        total.code += change.code;
+        total.comments += change.comments;
+    }
+
+    Ok(json!({
+        "from": base.map(|base| base.timestamp),
+        "to": latest.timestamp,
+        "files": top(changes.iter().map(|(path, &change)| (path.as_str(), change)), limit),
+        "directories": top(directories.into_iter(), limit),
+    }))
+}
+
+/// Changes of all selected files that differ between the two entries.
+fn changes(
+    before: &HashMap<String, EntryFile>,
+    after: &HashMap<String, EntryFile>,
+    selected: impl Fn(&&EntryFile) -> bool,
+) -> BTreeMap<String, Change> {
+    let lines = |files: &HashMap<String, EntryFile>, path: &str| {
+        files.get(path).filter(&selected).map_or((0, 0), |file| {
+            (file.statistics.code as i64, file.statistics.comments as i64)
+        })
+    };
+
+    before
+        .keys()
+        .chain(after.keys())
+        .filter_map(|path| {
+            let (code_before, comments_before) = lines(before, path);
+            let (code_after, comments_after) = lines(after, path);
+            let change = Change {
+                code: code_after - code_before,
+                comments: comments_after - comments_before,
+            };
+
+            (change.size() > 0).then(|| (path.clone(), change))
+        })
+        .collect()
+}
+
+/// The largest changes, most of all changed lines first.
+fn top<'a>(changes: impl Iterator<Item = (&'a str, Change)>, limit: usize) -> Vec<Value> {
+    let mut changes = changes
+        .filter(|(_, change)| change.size() > 0)
+        .collect::<Vec<_>>();
+    changes.sort_by(|a, b| b.1.size().cmp(&a.1.size()).then_with(|| a.0.cmp(b.0)));
+
+    changes
+        .into_iter()
+        .take(limit)
+        .map(|(path, change)| {
+            json!({
+                "path": path,
+                "code": change.code,
+                "comments": change.comments,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokei::CodeStats;
+
+    use super::*;
+
+    fn files(list: &[(&str, LanguageType, usize, usize)]) -> HashMap<String, EntryFile> {
+        list.iter()
+            .map(|&(path, language, code, comments)| {
+                let mut statistics = CodeStats::new();
+                statistics.code = code;
+                statistics.comments = comments;
+
+                let file = EntryFile {
+                    language,
+                    statistics,
+                    api_docs: None,
+                    comment_kinds: None,
+                    licensed: None,
+                };
+                (path.to_owned(), file)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn largest_changes_come_first() {
+        let before = files(&[
+            ("src/main.rs", LanguageType::Rust, 10, 2),
+            ("src/old.rs", LanguageType::Rust, 5, 5),
+            ("build.sh", LanguageType::Sh, 3, 0),
+        ]);
+        let after = files(&[
+            ("src/main.rs", LanguageType::Rust, 12, 2),
+            ("src/api/new.rs", LanguageType::Rust, 20, 10),
+            ("build.sh", LanguageType::Sh, 30, 1),
+        ]);
+
+        let changes = changes(&before, &after, |_| true);
+        assert_eq!(4, changes.len());
+        assert_eq!(
+            Change {
+                code: -5,
+                comments: -5
+            },
+            changes["src/old.rs"]
+        );
+
+        let files = top(changes.iter().map(|(p, &c)| (p.as_str(), c)), 2);
+        assert_eq!("src/api/new.rs", files[0]["path"]);
+        assert_eq!("build.sh", files[1]["path"]);
+
+        let rust = super::changes(&before, &after, |file| file.language == LanguageType::Rust);
+        assert!(!rust.contains_key("build.sh"));
+    }
+}
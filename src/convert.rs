@@ -0,0 +1,220 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{ensure, Result};
+use clap::Args;
+use rayon::prelude::*;
+use tokei::{Config as TokeiConfig, LanguageType};
+
+use crate::{
+    models::{Detail, Entry},
+    progress::Progress,
+    stats_file::{self, ChunkWriter, Manifest, Staged, StatsFile, FORMAT_VERSION},
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Map all files to their language again, with the language detection of the bundled tokei
+    /// version, and drop files that aren't of any known language anymore. This fixes files that
+    /// were counted as the wrong language, without reading the repository again. Their line
+    /// counts stay as they were parsed. Only works for stats files with statistics per file.
+    #[arg(long)]
+    pub redetect_languages: bool,
+}
+
+pub fn run(input: &Path, output: &Path, options: &Options) -> Result<()> {
+    let file = StatsFile::open(input)?;
+
+    ensure!(
+        !options.redetect_languages || file.manifest().metadata.detail == Detail::PerFile,
+        "languages can only be detected again for stats files with statistics per file"
+    );
+
+    println!("converting...");
+
+    let dir = tempfile::tempdir()?;
+    let config = TokeiConfig::default();
+    let changed = AtomicU64::new(0);
+    let (progress, updater) = Progress::new(file.manifest().entries);
+
+    let chunks = (0..file.manifest().chunks.len())
+        .into_par_iter()
+        .map(|index| {
+            let count = file.manifest().chunks[index].entries;
+            let mut writer = ChunkWriter::create(dir.path(), index, count)?;
+
+            file.read_chunk(index, |mut entry| {
+                if options.redetect_languages {
+                    changed.fetch_add(redetect(&mut entry, &config), Ordering::Relaxed);
+                }
+
+                writer.write(&entry)?;
+                updater.inc();
+                Ok(())
+            })?;
+
+            writer.finish()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    progress.wait()?;
+
+    if options.redetect_languages {
+        println!(
+            "changed {} files over all entries",
+            changed.load(Ordering::Relaxed)
+        );
+    }
+
+    println!("saving statistics...");
+
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        entries: file.manifest().entries,
+        chunks,
+        metadata: file.manifest().metadata.clone(),
+    };
+
+    let staged = Staged::new(output)?;
+    stats_file::write(staged.path(), dir.path(), &manifest, || {})?;
+    staged.commit()?;
+
+    println!("done");
+
+    Ok(())
+}
+
+/// Detect the language of each file in the entry from its path again, returning the amount of
+/// files whose language changed or that were dropped.
+fn redetect(entry: &mut Entry, config: &TokeiConfig) -> u64 {
+    let mut changed = 0;
+
+    entry.files.retain(|key, file| {
+        let name = key.rsplit('/').next().unwrap_or(key);
+        match LanguageType::from_path(name, config) {
+            Some(lang) => {
+                if lang != file.language {
+                    file.language = lang;
+                    changed += 1;
+                }
+                true
+            }
+            None => {
+                changed += 1;
+                false
+            }
+        }
+    });
+
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use super::*;
+    use crate::stats_file::{
+        tests::{read_entries, statistics, timestamp, write_legacy_file, EntryFileV1, EntryV1},
+        LEGACY_VERSION,
+    };
+
+    fn legacy_entry(files: &[(&str, LanguageType)]) -> EntryV1 {
+        EntryV1 {
+            timestamp: timestamp(),
+            files: files
+                .iter()
+                .map(|&(path, language)| {
+                    let file = EntryFileV1 {
+                        language,
+                        statistics: statistics(10, 4),
+                    };
+                    (path.to_owned(), file)
+                })
+                .collect(),
+        }
+    }
+
+    /// Line counts of the files of each entry, ordered by their path.
+    fn files(entries: &[Entry]) -> Vec<BTreeMap<&str, (LanguageType, usize, usize)>> {
+        entries
+            .iter()
+            .map(|entry| {
+                entry
+                    .files
+                    .iter()
+                    .map(|(path, file)| {
+                        let stats = &file.statistics;
+                        (path.as_str(), (file.language, stats.code, stats.comments))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn legacy_file_is_converted() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_legacy_file(
+            &dir,
+            &[
+                legacy_entry(&[("src/main.rs", LanguageType::Rust)]),
+                legacy_entry(&[
+                    ("src/main.rs", LanguageType::Rust),
+                    ("build.sh", LanguageType::Sh),
+                ]),
+            ],
+        );
+        let output = dir.path().join("converted.stats");
+        let options = Options {
+            redetect_languages: false,
+        };
+        run(&input, &output, &options).unwrap();
+
+        let legacy = StatsFile::open(input).unwrap();
+        let converted = StatsFile::open(output).unwrap();
+        assert_eq!(LEGACY_VERSION, legacy.manifest().version);
+        assert_eq!(FORMAT_VERSION, converted.manifest().version);
+        assert_eq!(2, converted.manifest().entries);
+        assert_eq!(
+            files(&read_entries(&legacy).unwrap()),
+            files(&read_entries(&converted).unwrap())
+        );
+    }
+
+    #[test]
+    fn languages_are_detected_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_legacy_file(
+            &dir,
+            &[legacy_entry(&[
+                ("src/main.rs", LanguageType::Rust),
+                ("web/app.tsx", LanguageType::TypeScript),
+                ("data/table.unknown", LanguageType::Text),
+            ])],
+        );
+        let output = dir.path().join("converted.stats");
+        let options = Options {
+            redetect_languages: true,
+        };
+        run(&input, &output, &options).unwrap();
+
+        let entries = read_entries(&StatsFile::open(output).unwrap()).unwrap();
+        let languages = entries[0]
+            .files
+            .iter()
+            .map(|(path, file)| (path.as_str(), file.language))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            HashMap::from([
+                ("src/main.rs", LanguageType::Rust),
+                ("web/app.tsx", LanguageType::Tsx),
+            ]),
+            languages
+        );
+        // The line counts stay as they were.
+        assert_eq!(10, entries[0].files["web/app.tsx"].statistics.code);
+    }
+}
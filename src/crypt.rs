@@ -0,0 +1,95 @@
+//! Encryption of stats files at rest, with the [age](https://age-encryption.org) command line
+//! tool. Encrypted files are decrypted transparently into memory when opened, with the identity
+//! file given in the `COMMENTSTATS_AGE_IDENTITY` environment variable.
+
+use std::{env, fs::File, io::Read, path::Path, process::Command};
+
+use anyhow::{ensure, Context, Result};
+
+/// Environment variable with the path of the age identity file used for decryption.
+const IDENTITY_VAR: &str = "COMMENTSTATS_AGE_IDENTITY";
+/// Start of binary age files.
+const HEADER: &[u8] = b"age-encryption.org/v1\n";
+/// Start of age files in the PEM-like ASCII armor.
+const ARMORED_HEADER: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Check whether the content, read from its start, is encrypted with age.
+pub fn is_encrypted(content: impl Read) -> Result<bool> {
+    let mut start = Vec::with_capacity(ARMORED_HEADER.len());
+    content
+        .take(ARMORED_HEADER.len() as u64)
+        .read_to_end(&mut start)?;
+
+    Ok(start.starts_with(HEADER) || start.starts_with(ARMORED_HEADER))
+}
+
+/// Encrypt `input` for the recipients, like `age1...` keys or SSH public keys, and write the
+/// result to `output`.
+pub fn encrypt(input: &Path, output: &Path, recipients: &[String]) -> Result<()> {
+    let mut age = Command::new("age");
+    age.arg("--encrypt");
+    for recipient in recipients {
+        age.arg("--recipient").arg(recipient);
+    }
+    // Written through stdout, as age may refuse to overwrite an existing output file.
+    age.arg(input).stdout(File::create(output)?);
+
+    run(age).with_context(|| format!("failed encrypting {}", output.display()))
+}
+
+/// Decrypt the `content` of the file at `path` into memory, so its plain text never ends up on
+/// disk.
+pub fn decrypt(path: &Path, content: File) -> Result<Vec<u8>> {
+    let identity = env::var_os(IDENTITY_VAR).with_context(|| {
+        format!(
+            "{} is encrypted, set {IDENTITY_VAR} to the age identity file to decrypt it",
+            path.display()
+        )
+    })?;
+
+    let output = Command::new("age")
+        .args(["--decrypt", "--identity"])
+        .arg(identity)
+        .stdin(content)
+        .output()
+        .context("failed running age, is it installed?")?;
+    ensure!(
+        output.status.success(),
+        "failed decrypting {}: {}",
+        path.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(output.stdout)
+}
+
+fn run(mut age: Command) -> Result<()> {
+    let status = age
+        .status()
+        .context("failed running age, is it installed?")?;
+    ensure!(status.success(), "age exited with {status}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn encryption_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.stats");
+
+        fs::write(&path, b"age-encryption.org/v1\n-> X25519 abc\n").unwrap();
+        assert!(is_encrypted(File::open(&path).unwrap()).unwrap());
+
+        fs::write(&path, b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n").unwrap();
+        assert!(is_encrypted(File::open(&path).unwrap()).unwrap());
+
+        fs::write(&path, b"PK\x03\x04").unwrap();
+        assert!(!is_encrypted(File::open(&path).unwrap()).unwrap());
+    }
+}
@@ -0,0 +1,477 @@
+//! Behavior shared by the long-running commands, to run them as a service.
+//!
+//! - Readiness, reloads and shutdowns are reported to systemd through `NOTIFY_SOCKET`, if set,
+//!   which allows using `Type=notify` units.
+//! - `SIGHUP` loads the configuration file again.
+//! - `SIGINT` and `SIGTERM` stop the command once the current request is done. A running scan
+//!   finishes the chunks it's working on and saves the commits scanned so far, which the next
+//!   update continues from. A second signal stops it immediately. Stats files are replaced only
+//!   once they're fully written, so even that never leaves a partial file behind.
+//!
+//! - `GET /healthz` and `GET /readyz` report whether the service is alive and has fresh data, for
+//!   supervision by Kubernetes or uptime monitors. The service is ready once all of its stats
+//!   files exist and none is older than `--max-age`.
+//!
+//! Signals are only handled on Unix. Elsewhere, the process simply ends on interruption.
+//! Running as a Windows service isn't supported, as that requires registering with the service
+//! control manager. Wrappers that start the command as a regular process work, but stop it
+//! without a chance to save a running scan.
+
+use std::{
+    fs,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueHint};
+use serde_json::{json, Value};
+
+use crate::{
+    config::{self, Config},
+    http::{self, Workers},
+};
+
+/// Set by `SIGINT` and `SIGTERM`.
+static STOP: AtomicBool = AtomicBool::new(false);
+/// Set by `SIGHUP`.
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Whether a shutdown was requested, for work that doesn't have access to the [`Daemon`], like
+/// scans. Always `false` outside of the long-running commands.
+pub fn stop_requested() -> bool {
+    STOP.load(Ordering::Relaxed)
+}
+
+/// Options of the commands that keep running until they are stopped.
+#[derive(Args, Default)]
+pub struct DaemonArgs {
+    /// Write the process ID to this file while running. It's removed again on shutdown.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub pid_file: Option<PathBuf>,
+    /// Keep this file updated with the current state as JSON, like `{"state":"idle",...}`, for
+    /// monitoring without HTTP.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub health_file: Option<PathBuf>,
+    /// Report the service as not ready on `GET /readyz` when a stats file wasn't written for
+    /// this amount of time, like `12h`. By default, the age of the data isn't checked.
+    #[arg(long, value_name = "INTERVAL")]
+    pub max_age: Option<Interval>,
+}
+
+/// Span of time, given as numbers with a unit on the command line, like `1h30m`. Supported units
+/// are `s`, `m`, `h` and `d`.
+#[derive(Clone, Copy)]
+pub struct Interval(pub Duration);
+
+impl FromStr for Interval {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut total = 0_u64;
+        let mut rest = s.trim();
+
+        if rest.is_empty() {
+            return Err("expected a number with a unit, like `6h`".to_owned());
+        }
+
+        while !rest.is_empty() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(|| format!("missing unit after `{rest}`"))?;
+            let (number, tail) = rest.split_at(end);
+            let number = number
+                .parse::<u64>()
+                .map_err(|e| format!("invalid number: {e}"))?;
+
+            let unit = tail.chars().next().unwrap_or_default();
+            let seconds = match unit {
+                's' => 1,
+                'm' => 60,
+                'h' => 60 * 60,
+                'd' => 24 * 60 * 60,
+                _ => return Err(format!("unknown unit `{unit}`, expected s, m, h or d")),
+            };
+
+            total = number
+                .checked_mul(seconds)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(|| "interval is too long".to_owned())?;
+            rest = &tail[unit.len_utf8()..];
+        }
+
+        if total == 0 {
+            return Err("interval must be greater than zero".to_owned());
+        }
+
+        Ok(Self(Duration::from_secs(total)))
+    }
+}
+
+/// Running service, that cleans up its files when dropped.
+pub struct Daemon {
+    pid_file: Option<PathBuf>,
+    health_file: Option<PathBuf>,
+    config_path: Option<PathBuf>,
+    status: Arc<Status>,
+}
+
+/// Current state of the service, shared with the health checks.
+pub struct Status {
+    started: DateTime<Utc>,
+    state: Mutex<&'static str>,
+    last_success: Mutex<Option<DateTime<Utc>>>,
+    /// Stats files whose age tells the freshness of the data.
+    files: Vec<PathBuf>,
+    max_age: Option<Duration>,
+}
+
+impl Daemon {
+    /// Install the signal handlers and write the PID file. The configuration is loaded from
+    /// `config_path` again on reloads, like it was on start. The `files` are the stats files
+    /// that the service provides.
+    pub fn start(
+        args: &DaemonArgs,
+        config_path: Option<PathBuf>,
+        files: Vec<PathBuf>,
+    ) -> Result<Self> {
+        install_handlers().context("failed installing signal handlers")?;
+
+        if let Some(path) = &args.pid_file {
+            fs::write(path, format!("{}\n", process::id()))
+                .with_context(|| format!("failed writing {}", path.display()))?;
+        }
+
+        Ok(Self {
+            pid_file: args.pid_file.clone(),
+            health_file: args.health_file.clone(),
+            config_path,
+            status: Arc::new(Status {
+                started: Utc::now(),
+                state: Mutex::new("starting"),
+                last_success: Mutex::new(None),
+                files,
+                max_age: args.max_age.map(|age| age.0),
+            }),
+        })
+    }
+
+    /// Report that the service is up, together with a short status line.
+    pub fn ready(&self, status: &str) {
+        notify(&format!("READY=1\nSTATUS={status}"));
+    }
+
+    /// Whether a shutdown was requested.
+    pub fn stopping(&self) -> bool {
+        stop_requested()
+    }
+
+    /// Load the configuration again if a reload was requested. A broken configuration is
+    /// reported and `None` returned, so the current one is kept.
+    pub fn reload(&self) -> Option<Config> {
+        if !RELOAD.swap(false, Ordering::Relaxed) {
+            return None;
+        }
+
+        notify("RELOADING=1");
+        let config = match config::load(self.config_path.clone()) {
+            Ok(loaded) => {
+                println!("configuration reloaded");
+                Some(loaded)
+            }
+            Err(e) => {
+                eprintln!("Error: {e:?}");
+                None
+            }
+        };
+        notify("READY=1");
+
+        config
+    }
+
+    /// Record that a scan just succeeded.
+    pub fn succeeded(&self) {
+        *self
+            .status
+            .last_success
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Utc::now());
+    }
+
+    pub fn status(&self) -> &Status {
+        &self.status
+    }
+
+    /// Answer health checks on their own address in the background, for commands that don't
+    /// serve HTTP otherwise.
+    pub fn listen(&self, addr: SocketAddr) -> Result<()> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("failed listening on {addr}"))?;
+        let status = Arc::clone(&self.status);
+        let workers = Workers::default();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let result = stream
+                    .context("failed accepting connection")
+                    .and_then(|stream| {
+                        http::prepare(&stream)?;
+                        let Some(worker) = workers.claim() else {
+                            return Ok(http::busy(&stream)?);
+                        };
+
+                        let status = Arc::clone(&status);
+                        thread::Builder::new()
+                            .spawn(move || {
+                                let _worker = worker;
+                                if let Err(e) = answer(&stream, &status) {
+                                    eprintln!("Error: {e:?}");
+                                }
+                            })
+                            .context("failed starting worker")?;
+                        Ok(())
+                    });
+
+                if let Err(e) = result {
+                    eprintln!("Error: {e:?}");
+                }
+            }
+        });
+
+        println!("answering health checks on http://{addr}");
+
+        Ok(())
+    }
+
+    /// Update the current state, and write it to the health file if one was requested.
+    pub fn health(&self, state: &'static str) {
+        *self
+            .status
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = state;
+
+        let Some(path) = &self.health_file else {
+            return;
+        };
+
+        let mut content = self.status.check("/healthz").map(|(_, body)| body);
+        if let Some(Value::Object(fields)) = &mut content {
+            fields.insert("pid".to_owned(), process::id().into());
+            fields.insert("updated".to_owned(), json!(Utc::now()));
+        }
+        let content = content.unwrap_or_default();
+
+        // Written to a temporary file first, so readers never see a partial file.
+        let temp = path.with_extension("tmp");
+        let result = fs::write(&temp, content.to_string()).and_then(|()| fs::rename(&temp, path));
+        if let Err(e) = result {
+            eprintln!("failed writing {}: {e}", path.display());
+        }
+    }
+}
+
+impl Status {
+    /// Answer a health check of the given path. Returns the HTTP status and body, or `None` if
+    /// the path isn't a health check.
+    pub fn check(&self, path: &str) -> Option<(&'static str, Value)> {
+        let mut body = json!({
+            "state": *self.state.lock().unwrap_or_else(PoisonError::into_inner),
+            "started": self.started,
+            "last_success": *self.last_success.lock().unwrap_or_else(PoisonError::into_inner),
+        });
+
+        match path {
+            "/healthz" => Some(("200 OK", body)),
+            "/readyz" => {
+                let (age, problem) = self.data_age();
+                body["data_age"] = age.map(|age| age.as_secs()).into();
+
+                let status = match problem {
+                    Some(problem) => {
+                        body["error"] = problem.into();
+                        "503 Service Unavailable"
+                    }
+                    None => "200 OK",
+                };
+                Some((status, body))
+            }
+            _ => None,
+        }
+    }
+
+    /// Find the time since the oldest stats file was written, together with the reason why the
+    /// data isn't ready yet, if any.
+    fn data_age(&self) -> (Option<Duration>, Option<String>) {
+        let mut oldest = Duration::ZERO;
+
+        for file in &self.files {
+            let modified = fs::metadata(file).and_then(|metadata| metadata.modified());
+            let Ok(modified) = modified else {
+                return (None, Some(format!("{} doesn't exist yet", file.display())));
+            };
+
+            let age = SystemTime::now()
+                .duration_since(modified)
+                .unwrap_or_default();
+            oldest = oldest.max(age);
+        }
+
+        let problem = self
+            .max_age
+            .filter(|&max_age| oldest > max_age)
+            .map(|max_age| format!("data is older than {}s", max_age.as_secs()));
+
+        (Some(oldest), problem)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        notify("STOPPING=1");
+        self.health("stopped");
+
+        if let Some(path) = &self.pid_file {
+            fs::remove_file(path).ok();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn install_handlers() -> Result<()> {
+    use crate::interrupt;
+
+    extern "C" fn handle(signal: libc::c_int) {
+        if signal == libc::SIGHUP {
+            RELOAD.store(true, Ordering::Relaxed);
+        } else if STOP.swap(true, Ordering::Relaxed) {
+            // Only async-signal-safe functions may be called here.
+            unsafe { libc::_exit(128 + signal) };
+        }
+    }
+
+    for signal in [libc::SIGHUP, libc::SIGINT, libc::SIGTERM] {
+        interrupt::install(signal, Some(handle))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_handlers() -> Result<()> {
+    Ok(())
+}
+
+/// Answer a single health check request.
+fn answer(stream: &TcpStream, status: &Status) -> Result<()> {
+    let Some(request) = http::read_request(stream)? else {
+        return Ok(());
+    };
+
+    let (code, body) = match request {
+        Ok(request) if request.method == "GET" => {
+            let target = &request.target;
+            let path = target.split_once('?').map_or(&**target, |(path, _)| path);
+            status
+                .check(path)
+                .unwrap_or_else(|| ("404 Not Found", json!({ "error": "unknown endpoint" })))
+        }
+        Ok(_) => ("404 Not Found", json!({ "error": "unknown endpoint" })),
+        Err(rejection) => return Ok(http::reject(stream, &rejection)?),
+    };
+
+    http::respond(stream, code, "", &body.to_string())?;
+
+    Ok(())
+}
+
+/// Send a state change to systemd, as described in `sd_notify(3)`. Failures are only reported,
+/// as the service keeps working without them.
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::{
+        env,
+        os::unix::{ffi::OsStrExt, net::UnixDatagram},
+        path::Path,
+    };
+
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result =
+        UnixDatagram::unbound().and_then(|socket| match path.as_bytes().strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+                let addr = SocketAddr::from_abstract_name(name)?;
+                socket.send_to_addr(state.as_bytes(), &addr)
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => Err(std::io::ErrorKind::Unsupported.into()),
+            None => socket.send_to(state.as_bytes(), Path::new(&path)),
+        });
+
+    if let Err(e) = result {
+        eprintln!("failed notifying systemd: {e}");
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_are_parsed() {
+        let parse = |s: &str| s.parse::<Interval>().map(|i| i.0.as_secs());
+
+        assert_eq!(Ok(30), parse("30s"));
+        assert_eq!(Ok(6 * 3600), parse("6h"));
+        assert_eq!(Ok(36 * 3600 + 90), parse("1d12h1m30s"));
+
+        assert!(parse("").is_err());
+        assert!(parse("6").is_err());
+        assert!(parse("6w").is_err());
+        assert!(parse("0m").is_err());
+    }
+
+    #[test]
+    fn readiness_follows_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("stats.stats");
+        let status = |max_age| Status {
+            started: Utc::now(),
+            state: Mutex::new("idle"),
+            last_success: Mutex::new(None),
+            files: vec![file.clone()],
+            max_age,
+        };
+
+        let (code, body) = status(None).check("/readyz").unwrap();
+        assert_eq!("503 Service Unavailable", code);
+        assert!(body["data_age"].is_null());
+        assert_eq!("200 OK", status(None).check("/healthz").unwrap().0);
+
+        fs::write(&file, "").unwrap();
+        assert_eq!("200 OK", status(None).check("/readyz").unwrap().0);
+
+        let stale = status(Some(Duration::ZERO));
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!("503 Service Unavailable", stale.check("/readyz").unwrap().0);
+
+        assert!(status(None).check("/api/series").is_none());
+    }
+}
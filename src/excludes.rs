@@ -0,0 +1,155 @@
+//! Paths of third-party and generated code that are left out of the statistics by default.
+//!
+//! Dependencies and build outputs end up committed by accident in many repositories, and a single
+//! checked in `node_modules` directory easily outweighs the actual code of a project.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+/// Patterns of the excluded paths, relative to the repository root.
+const DEFAULT_PATTERNS: &[&str] = &[
+    // Dependencies
+    "**/node_modules/**",
+    "**/vendor/**",
+    "**/.yarn/**",
+    // Build outputs
+    "**/target/**",
+    "**/dist/**",
+    // Generated protobuf code
+    "**/*.pb.go",
+    "**/*.pb.cc",
+    "**/*.pb.h",
+    "**/*.pb.swift",
+    "**/*_pb2.py",
+    "**/*_pb2_grpc.py",
+    "**/*_pb.js",
+    "**/*_pb.d.ts",
+];
+
+/// Matcher for the default exclusions and the paths given on the command line.
+pub struct Excludes {
+    defaults: Option<GlobSet>,
+    exclude: GlobSet,
+    /// Patterns of the only paths to count, or `None` to count all of them.
+    include: Option<GlobSet>,
+}
+
+impl Excludes {
+    /// Create the matcher, with the default exclusions if `defaults` is set. Paths that match
+    /// any of the `exclude` patterns are left out, and if there are `include` patterns, all
+    /// paths that match none of them as well.
+    pub fn new(defaults: bool, exclude: &[String], include: &[String]) -> Result<Self> {
+        let defaults = defaults.then(|| {
+            build(DEFAULT_PATTERNS.iter().copied()).expect("default exclusions are valid")
+        });
+
+        Ok(Self {
+            defaults,
+            exclude: build(exclude.iter().map(String::as_str))?,
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build(include.iter().map(String::as_str))?)
+            },
+        })
+    }
+
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.defaults.as_ref().is_some_and(|set| set.is_match(path))
+            || self.exclude.is_match(path)
+            || self.include.as_ref().is_some_and(|set| !set.is_match(path))
+    }
+}
+
+/// Check a pattern from the command line, so mistakes are reported before the scan starts.
+pub fn parse_pattern(pattern: &str) -> Result<String, globset::Error> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map(|_| pattern.to_owned())
+}
+
+/// Combine the patterns into a set, where `*` doesn't match across directories.
+fn build<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("invalid glob pattern `{pattern}`"))?,
+        );
+    }
+
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(
+            DEFAULT_PATTERNS.len(),
+            build(DEFAULT_PATTERNS.iter().copied()).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn defaults_exclude_third_party_code() {
+        let excludes = Excludes::new(true, &[], &[]).unwrap();
+
+        for path in [
+            "node_modules/left-pad/index.js",
+            "web/node_modules/react/index.js",
+            "vendor/github.com/pkg/errors/errors.go",
+            ".yarn/releases/yarn.cjs",
+            "target/debug/build/out.rs",
+            "app/dist/bundle.js",
+            "api/service.pb.go",
+            "proto/service_pb2.py",
+            "web/service_pb.d.ts",
+        ] {
+            assert!(excludes.is_excluded(Path::new(path)), "{path}");
+        }
+
+        for path in [
+            "src/main.rs",
+            "src/vendor.rs",
+            "targets/list.rs",
+            "distribution/setup.py",
+            "api/service.go",
+        ] {
+            assert!(!excludes.is_excluded(Path::new(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn user_patterns_replace_defaults() {
+        let exclude = ["**/generated/**".to_owned()];
+        let path = |excludes: &Excludes, path| excludes.is_excluded(Path::new(path));
+
+        // Without the defaults, only the given patterns apply.
+        let excludes = Excludes::new(false, &exclude, &[]).unwrap();
+        assert!(!path(&excludes, "node_modules/left-pad/index.js"));
+        assert!(!path(&excludes, "vendor/lib.go"));
+        assert!(path(&excludes, "src/generated/schema.rs"));
+
+        // With them, the given patterns come on top.
+        let excludes = Excludes::new(true, &exclude, &[]).unwrap();
+        assert!(path(&excludes, "vendor/lib.go"));
+        assert!(path(&excludes, "src/generated/schema.rs"));
+        assert!(!path(&excludes, "src/main.rs"));
+
+        // Included paths are still subject to the exclusions.
+        let excludes = Excludes::new(true, &[], &["**/*.go".to_owned()]).unwrap();
+        assert!(path(&excludes, "vendor/lib.go"));
+        assert!(path(&excludes, "src/main.rs"));
+        assert!(!path(&excludes, "cmd/main.go"));
+
+        assert!(Excludes::new(false, &["src/[".to_owned()], &[]).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+//! Exit codes of the tool, so shell pipelines and CI steps can react to the outcome of a run:
+//!
+//! - `0`: Success.
+//! - `1`: Any other error.
+//! - `2`: Invalid command line arguments.
+//! - `3`: Nothing left to render after filtering, see [`render::NoData`].
+//! - `4`: Cloning the repository failed.
+//! - `5`: Scanning the repository failed.
+//! - `6`: Rendering the chart failed.
+//! - `7`: Writing the report failed.
+//! - `8`: The configuration file is invalid or unreadable.
+//! - `9`: A threshold of `check` was violated.
+//! - `10`: Only partially successful, like some repositories of an organization failing, or
+//!   warnings with `--fail-on warning`.
+
+use std::{
+    fmt::{self, Display},
+    process::ExitCode,
+};
+
+use anyhow::Error;
+use clap::ValueEnum;
+
+use crate::render;
+
+/// Summary of the exit codes for the `--help` output.
+pub const HELP: &str = "\
+Exit codes:
+  0   success
+  1   any other error
+  2   invalid arguments
+  3   nothing left to render after filtering
+  4   cloning the repository failed
+  5   scanning the repository failed
+  6   rendering the chart failed
+  7   writing the report failed
+  8   invalid configuration file
+  9   a threshold of check was violated
+  10  partial success, or warnings with --fail-on warning";
+
+/// Exit code for [`render::NoData`] errors, when filters leave nothing to render.
+const NO_DATA: u8 = 3;
+
+/// Failure with its own exit code, attached to errors as context.
+#[derive(Clone, Copy, Debug)]
+pub enum Failure {
+    Clone,
+    Scan,
+    Render,
+    Report,
+    Config,
+    Threshold,
+    Partial,
+}
+
+impl Failure {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Self::Clone => 4,
+            Self::Scan => 5,
+            Self::Render => 6,
+            Self::Report => 7,
+            Self::Config => 8,
+            Self::Threshold => 9,
+            Self::Partial => 10,
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Clone => "failed cloning the repository",
+            Self::Scan => "failed scanning the repository",
+            Self::Render => "failed rendering the chart",
+            Self::Report => "failed writing the report",
+            Self::Config => "invalid configuration",
+            Self::Threshold => "thresholds violated",
+            Self::Partial => "finished only partially",
+        })
+    }
+}
+
+/// Least severe outcome that lets the tool exit with an error.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FailOn {
+    /// Only fail on errors.
+    Error,
+    /// Fail on warnings as well, like skipped files or commits that couldn't be scanned.
+    Warning,
+}
+
+/// Exit code for the error, following the list above.
+pub fn code(error: &Error) -> ExitCode {
+    if error.is::<render::NoData>() {
+        ExitCode::from(NO_DATA)
+    } else if let Some(failure) = error.downcast_ref::<Failure>() {
+        ExitCode::from(failure.exit_code())
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    const ALL: [Failure; 7] = [
+        Failure::Clone,
+        Failure::Scan,
+        Failure::Render,
+        Failure::Report,
+        Failure::Config,
+        Failure::Threshold,
+        Failure::Partial,
+    ];
+
+    #[test]
+    fn failures_map_to_exit_codes() {
+        for (failure, expected) in ALL.into_iter().zip(4..) {
+            let error = anyhow!("cause").context(failure);
+            assert_eq!(ExitCode::from(expected), code(&error), "{failure}");
+        }
+
+        let error = Error::new(render::NoData("nothing".to_owned()));
+        assert_eq!(ExitCode::from(NO_DATA), code(&error));
+        assert_eq!(ExitCode::FAILURE, code(&anyhow!("plain error")));
+
+        // Filters that leave nothing to render aren't a failure of rendering itself.
+        let error = Error::new(render::NoData("nothing".to_owned())).context(Failure::Render);
+        assert_eq!(ExitCode::from(NO_DATA), code(&error));
+    }
+
+    #[test]
+    fn help_lists_all_codes() {
+        let codes = HELP
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().next().unwrap().parse().unwrap())
+            .collect::<Vec<u8>>();
+
+        assert_eq!((0..=10).collect::<Vec<_>>(), codes);
+        for failure in ALL {
+            assert!(codes.contains(&failure.exit_code()));
+        }
+    }
+}
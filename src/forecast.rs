@@ -0,0 +1,222 @@
+//! Linear forecast of the line counts, to answer where the code and comments are heading, like
+//! at the end of the next quarter.
+//!
+//! A straight line is fitted through the values of the last year, and continued from the latest
+//! value. The uncertainty is the 95 % prediction interval of that fit, which widens the further
+//! the forecast reaches.
+
+use anyhow::Result;
+use chrono::{DateTime, Days};
+use serde_json::{json, Value};
+
+use crate::stats_file::StatsFile;
+
+/// Days that the forecast reaches into the future by default, about a quarter.
+pub const DEFAULT_DAYS: u32 = 91;
+
+/// Days of history before the latest value that the trend is fitted through.
+const WINDOW: f64 = 365.0;
+
+/// Factor of the standard error for a 95 % prediction interval.
+const Z: f64 = 1.96;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Straight line through the recent values of a series.
+pub struct Trend {
+    /// Time and value of the latest point, which the forecast starts from.
+    latest: (i64, f64),
+    /// Change of the value per day.
+    slope: f64,
+    /// Standard deviation of the values around the line.
+    spread: f64,
+    points: f64,
+    /// Mean and sum of squared deviations of the fitted days, for the width of the interval.
+    mean: f64,
+    variance: f64,
+}
+
+/// Expected value at some point in time, with the bounds of its prediction interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Prediction {
+    pub value: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Trend {
+    /// Fit the trend by least squares through the points of the last year, given as Unix
+    /// timestamps ordered by time. Needs at least three points on different days.
+    pub fn fit(points: &[(i64, f64)]) -> Option<Self> {
+        let &(last, value) = points.last()?;
+        let days = |time: i64| (time - last) as f64 / DAY as f64;
+
+        let recent = points
+            .iter()
+            .map(|&(time, value)| (days(time), value))
+            .filter(|&(day, _)| day >= -WINDOW)
+            .collect::<Vec<_>>();
+        if recent.len() < 3 {
+            return None;
+        }
+
+        let n = recent.len() as f64;
+        let mean = recent.iter().map(|&(day, _)| day).sum::<f64>() / n;
+        let mean_value = recent.iter().map(|&(_, value)| value).sum::<f64>() / n;
+        let variance = recent
+            .iter()
+            .map(|&(day, _)| (day - mean).powi(2))
+            .sum::<f64>();
+        if variance < f64::EPSILON {
+            return None;
+        }
+
+        let slope = recent
+            .iter()
+            .map(|&(day, value)| (day - mean) * (value - mean_value))
+            .sum::<f64>()
+            / variance;
+        let intercept = mean_value - slope * mean;
+        let residuals = recent
+            .iter()
+            .map(|&(day, value)| (value - intercept - slope * day).powi(2))
+            .sum::<f64>();
+
+        Some(Self {
+            latest: (last, value),
+            slope,
+            spread: (residuals / (n - 2.0)).sqrt(),
+            points: n,
+            mean,
+            variance,
+        })
+    }
+
+    /// Predict the value at the given Unix timestamp, continuing the trend from the latest
+    /// value. Line counts can't drop below zero, so neither can the prediction.
+    pub fn predict(&self, time: i64) -> Prediction {
+        let day = (time - self.latest.0) as f64 / DAY as f64;
+        let value = self.latest.1 + self.slope * day;
+        let error = Z
+            * self.spread
+            * (1.0 + 1.0 / self.points + (day - self.mean).powi(2) / self.variance).sqrt();
+
+        Prediction {
+            value: value.max(0.0),
+            lower: (value - error).max(0.0),
+            upper: (value + error).max(0.0),
+        }
+    }
+
+    /// Predictions for each week after the latest value, up to the given amount of days.
+    pub fn extend(&self, days: u32) -> Vec<(i64, Prediction)> {
+        let start = self.latest.0;
+        let end = start + i64::from(days) * DAY;
+
+        (start..end)
+            .step_by(7 * DAY as usize)
+            .chain([end])
+            .map(|time| {
+                let mut prediction = self.predict(time);
+                // Start right at the latest value, so the forecast continues the line.
+                if time == start {
+                    prediction.lower = prediction.value;
+                    prediction.upper = prediction.value;
+                }
+                (time, prediction)
+            })
+            .collect()
+    }
+}
+
+impl Prediction {
+    fn to_json(self) -> Value {
+        json!({
+            "value": self.value.round(),
+            "lower": self.lower.round(),
+            "upper": self.upper.round(),
+        })
+    }
+}
+
+/// Section of `report.json` with the code and comment lines expected the given amount of days
+/// after the latest entry of the first history, or `null` if it's too short for a trend.
+pub fn report(file: &StatsFile, days: u32) -> Result<Value> {
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        return Ok(Value::Null);
+    };
+
+    let mut code = Vec::new();
+    let mut comments = Vec::new();
+    for index in range {
+        file.read_chunk(index, |entry| {
+            // Failed commits have no data, which would look like a drop to zero.
+            if !entry.failed {
+                let stats = entry.total_stats()?.statistics;
+                let time = entry.timestamp.timestamp();
+                code.push((time, stats.code as f64));
+                comments.push((time, stats.comments as f64));
+            }
+            Ok(())
+        })?;
+    }
+
+    let (Some(code), Some(comments)) = (Trend::fit(&code), Trend::fit(&comments)) else {
+        return Ok(Value::Null);
+    };
+
+    let time = code.latest.0 + i64::from(days) * DAY;
+    let date = DateTime::from_timestamp(code.latest.0, 0)
+        .and_then(|latest| latest.date_naive().checked_add_days(Days::new(days.into())));
+
+    Ok(json!({
+        "days": days,
+        "date": date,
+        "code": code.predict(time).to_json(),
+        "comments": comments.predict(time).to_json(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trend_is_continued() {
+        // Ten lines per day, with some noise, and a very old point that is left out.
+        let mut points = vec![(-1000 * DAY, 50_000.0)];
+        points.extend((0..100).map(|day| {
+            let noise = if day % 2 == 0 { 5.0 } else { -5.0 };
+            (day * DAY, 1000.0 + day as f64 * 10.0 + noise)
+        }));
+
+        let trend = Trend::fit(&points).unwrap();
+        assert!((trend.slope - 10.0).abs() < 0.1);
+        assert_eq!(100.0, trend.points);
+
+        let latest = points.last().unwrap().1;
+        let prediction = trend.predict(99 * DAY + 30 * DAY);
+        assert!((prediction.value - (latest + 300.0)).abs() < 3.0);
+        assert!(prediction.lower < prediction.value && prediction.value < prediction.upper);
+
+        // The interval widens the further the forecast reaches.
+        let later = trend.predict(99 * DAY + 300 * DAY);
+        assert!(later.upper - later.lower > prediction.upper - prediction.lower);
+
+        let extended = trend.extend(14);
+        assert_eq!(3, extended.len());
+        assert_eq!(latest, extended[0].1.upper);
+        assert_eq!(113 * DAY, extended[2].0);
+    }
+
+    #[test]
+    fn short_histories_have_no_trend() {
+        assert!(Trend::fit(&[]).is_none());
+        assert!(Trend::fit(&[(0, 1.0), (DAY, 2.0)]).is_none());
+        assert!(Trend::fit(&[(DAY, 1.0), (DAY, 2.0), (DAY, 3.0)]).is_none());
+
+        // Shrinking code stops at zero.
+        let trend = Trend::fit(&[(0, 30.0), (DAY, 20.0), (2 * DAY, 10.0)]).unwrap();
+        assert_eq!(0.0, trend.predict(10 * DAY).value);
+    }
+}
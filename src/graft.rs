@@ -0,0 +1,247 @@
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use chrono::{DateTime, FixedOffset};
+use tokei::LanguageType;
+
+use crate::{
+    models::{Detail, Entry},
+    stats_file::{ChunkInfo, ChunkWriter, StatsFile},
+};
+
+/// History of another repository, that the scanned one was split off from. Its entries are put in
+/// front of the scanned history, so both form one continuous timeline.
+pub struct Graft {
+    file: StatsFile,
+    /// Directory inside the other repository that was split off. Only files below it are taken
+    /// over, with their path relative to it.
+    dir: Option<String>,
+}
+
+impl Graft {
+    pub fn open(path: &Path, dir: Option<&str>) -> Result<Self> {
+        let file = StatsFile::open(path)
+            .with_context(|| format!("failed opening graft {}", path.display()))?;
+        let dir = dir
+            .map(|dir| dir.trim_matches('/').to_owned())
+            .filter(|dir| !dir.is_empty());
+
+        ensure!(
+            dir.is_none() || file.manifest().metadata.detail == Detail::PerFile,
+            "only grafts with statistics per file can be limited to a directory"
+        );
+
+        Ok(Self { file, dir })
+    }
+
+    /// Granularity of the grafted statistics.
+    pub fn detail(&self) -> Detail {
+        self.file.manifest().metadata.detail
+    }
+
+    /// Write all entries from before `cutoff` into chunks, numbered starting at `offset`, reduced
+    /// to `detail`. Only the first history of the grafted file is used.
+    pub fn write(
+        &self,
+        dir: &Path,
+        offset: usize,
+        cutoff: DateTime<FixedOffset>,
+        languages: &HashSet<LanguageType>,
+        detail: Detail,
+    ) -> Result<Vec<ChunkInfo>> {
+        let Some((_, range)) = self.file.manifest().histories().into_iter().next() else {
+            return Ok(Vec::new());
+        };
+
+        let mut chunks = Vec::new();
+
+        for index in range {
+            // Chunks start with their entry count, so the entries before the cutoff have to be
+            // counted before they can be written.
+            let mut count = 0;
+            self.file.read_chunk(index, |entry| {
+                if entry.timestamp < cutoff {
+                    count += 1;
+                }
+                Ok(())
+            })?;
+
+            if count == 0 {
+                break;
+            }
+
+            let mut writer = ChunkWriter::create(dir, offset + chunks.len(), count)?;
+            self.file.read_chunk(index, |entry| {
+                if entry.timestamp < cutoff {
+                    writer.write(&self.rebase(entry, languages).reduce(detail)?)?;
+                }
+                Ok(())
+            })?;
+            chunks.push(writer.finish()?);
+
+            if count < self.file.manifest().chunks[index].entries {
+                break;
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Limit the entry to the split off directory and the selected languages.
+    fn rebase(&self, mut entry: Entry, languages: &HashSet<LanguageType>) -> Entry {
+        entry.files = entry
+            .files
+            .into_iter()
+            .filter(|(_, file)| languages.is_empty() || languages.contains(&file.language))
+            .filter_map(|(key, file)| match &self.dir {
+                Some(dir) => key
+                    .strip_prefix(dir.as_str())
+                    .and_then(|key| key.strip_prefix('/'))
+                    .map(|key| (key.to_owned(), file)),
+                None => Some((key, file)),
+            })
+            .collect();
+        entry
+            .languages
+            .retain(|lang, _| languages.is_empty() || languages.contains(lang));
+
+        // The size is only known for the whole repository, not the directory.
+        if self.dir.is_some() {
+            entry.bytes = 0;
+        }
+
+        entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use tempfile::TempDir;
+    use tokei::CodeStats;
+
+    use super::*;
+    use crate::{
+        models::EntryFile,
+        stats_file::{self, Manifest, Metadata, FORMAT_VERSION},
+    };
+
+    fn timestamp(day: u32) -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339(&format!("2023-11-{day:02}T12:30:00+01:00")).unwrap()
+    }
+
+    fn entry(day: u32, files: &[(&str, LanguageType, usize)]) -> Entry {
+        Entry {
+            timestamp: timestamp(day),
+            files: files
+                .iter()
+                .map(|&(path, language, code)| {
+                    let mut statistics = CodeStats::new();
+                    statistics.code = code;
+                    let file = EntryFile {
+                        language,
+                        statistics,
+                        api_docs: None,
+                        comment_kinds: None,
+                        licensed: None,
+                    };
+                    (path.to_owned(), file)
+                })
+                .collect(),
+            languages: HashMap::new(),
+            totals: None,
+            bytes: 1024,
+            partial: false,
+            failed: false,
+            notes: Vec::new(),
+            commit_type: None,
+            commit: None,
+        }
+    }
+
+    /// Combine the chunks in the directory into a stats file.
+    fn write_file(dir: &TempDir, chunks: Vec<ChunkInfo>, detail: Detail) -> PathBuf {
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            entries: chunks.iter().map(|chunk| chunk.entries).sum(),
+            chunks,
+            metadata: Metadata {
+                detail,
+                ..Metadata::default()
+            },
+        };
+        let output = dir.path().join("test.stats");
+        stats_file::write(&output, dir.path(), &manifest, || {}).unwrap();
+
+        output
+    }
+
+    fn read_entries(path: &Path) -> Vec<Entry> {
+        let file = StatsFile::open(path).unwrap();
+        let mut entries = Vec::new();
+        for index in 0..file.manifest().chunks.len() {
+            file.read_chunk(index, |entry| {
+                entries.push(entry);
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        entries
+    }
+
+    #[test]
+    fn entries_before_cutoff_are_grafted() {
+        let old = tempfile::tempdir().unwrap();
+        let files = [
+            ("README.md", LanguageType::Markdown, 5),
+            ("app/lib.rs", LanguageType::Rust, 10),
+            ("app/run.sh", LanguageType::Sh, 3),
+        ];
+        let mut chunks = Vec::new();
+        for (index, days) in [[1, 2], [3, 4]].into_iter().enumerate() {
+            let mut writer = ChunkWriter::create(old.path(), index, 2).unwrap();
+            for day in days {
+                writer.write(&entry(day, &files)).unwrap();
+            }
+            chunks.push(writer.finish().unwrap());
+        }
+        let graft = write_file(&old, chunks, Detail::PerFile);
+
+        // The split off repository starts on the fourth day, and only records Rust.
+        let graft = Graft::open(&graft, Some("/app/")).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let chunks = graft
+            .write(
+                dir.path(),
+                0,
+                timestamp(4) - chrono::Duration::hours(1),
+                &HashSet::from([LanguageType::Rust]),
+                Detail::PerLanguage,
+            )
+            .unwrap();
+        assert_eq!(
+            vec![2, 1],
+            chunks.iter().map(|c| c.entries).collect::<Vec<_>>()
+        );
+
+        let entries = read_entries(&write_file(&dir, chunks, Detail::PerLanguage));
+        assert_eq!(
+            vec![timestamp(1), timestamp(2), timestamp(3)],
+            entries.iter().map(|e| e.timestamp).collect::<Vec<_>>()
+        );
+        for entry in entries {
+            assert!(entry.files.is_empty());
+            assert_eq!(0, entry.bytes);
+            assert_eq!(
+                vec![(LanguageType::Rust, 1, 10)],
+                entry
+                    .languages
+                    .iter()
+                    .map(|(&lang, s)| (lang, s.files, s.statistics.code))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+}
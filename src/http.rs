@@ -0,0 +1,221 @@
+//! Minimal HTTP/1.1 handling, shared by the API of `serve` and the health checks of the other
+//! long-running commands.
+//!
+//! Each connection is answered by its own worker thread, so a slow client doesn't hold up
+//! others. Reads and writes time out, and requests with overly long headers are rejected, so
+//! clients can't tie up a worker or the memory indefinitely.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Time that a single read or write on a connection may take.
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest accepted request line, including the line break.
+const MAX_REQUEST_LINE: usize = 8 * 1024;
+/// Longest accepted header section, including the line breaks.
+const MAX_HEADERS: usize = 32 * 1024;
+/// Amount of connections that are handled at the same time. Further ones are rejected right away.
+const MAX_WORKERS: usize = 32;
+/// Time to wait for the rest of a rejected request, before the connection is closed.
+const LINGER: Duration = Duration::from_secs(1);
+/// Most data of a rejected request that is read and discarded before closing the connection.
+const MAX_LINGER: u64 = 1024 * 1024;
+
+/// Request line and the headers of a request that are of interest.
+pub struct Request {
+    pub method: String,
+    pub target: String,
+    pub authorization: Option<String>,
+}
+
+/// Reason to reject a request before it's routed, with its HTTP status.
+pub struct Rejection {
+    pub status: &'static str,
+    pub message: &'static str,
+}
+
+/// Count of busy workers, to limit the amount of connections handled at once.
+#[derive(Clone, Default)]
+pub struct Workers(Arc<AtomicUsize>);
+
+/// Claim on one of the [`Workers`], released when dropped.
+pub struct Worker(Arc<AtomicUsize>);
+
+impl Workers {
+    /// Claim a worker for a new connection, or `None` if all of them are busy.
+    pub fn claim(&self) -> Option<Worker> {
+        self.0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |busy| {
+                (busy < MAX_WORKERS).then_some(busy + 1)
+            })
+            .ok()
+            .map(|_| Worker(Arc::clone(&self.0)))
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Prepare an accepted connection, which may come from a non-blocking listener, for reading the
+/// request.
+pub fn prepare(stream: &TcpStream) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))
+}
+
+/// Read the request line and headers. The body, if any, is ignored. Returns `None` for
+/// connections that are closed without sending anything, like port probes.
+pub fn read_request(stream: &TcpStream) -> io::Result<Option<Result<Request, Rejection>>> {
+    match read_from(BufReader::new(stream)) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        result => result.map(Some),
+    }
+}
+
+fn read_from(mut reader: impl BufRead) -> io::Result<Result<Request, Rejection>> {
+    let Some(request_line) = read_line(&mut reader, MAX_REQUEST_LINE)? else {
+        return Ok(Err(Rejection {
+            status: "400 Bad Request",
+            message: "request line is too long",
+        }));
+    };
+    if request_line.is_empty() {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let mut authorization = None;
+    let mut remaining = MAX_HEADERS;
+    loop {
+        let Some(header) = read_line(&mut reader, remaining)? else {
+            return Ok(Err(Rejection {
+                status: "431 Request Header Fields Too Large",
+                message: "headers are too large",
+            }));
+        };
+        // The headers end with an empty line, or the end of the stream for broken clients.
+        if header.trim_end().is_empty() {
+            break;
+        }
+        remaining -= header.len();
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_owned());
+            }
+        }
+    }
+
+    Ok(
+        match request_line.split_whitespace().collect::<Vec<_>>()[..] {
+            [method, target, _] => Ok(Request {
+                method: method.to_owned(),
+                target: target.to_owned(),
+                authorization,
+            }),
+            _ => Err(Rejection {
+                status: "400 Bad Request",
+                message: "invalid request line",
+            }),
+        },
+    )
+}
+
+/// Read a single line of at most `limit` bytes. Returns `None` if the line is longer.
+fn read_line(reader: &mut impl BufRead, limit: usize) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(limit as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+
+    if line.len() > limit {
+        return Ok(None);
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write a JSON response and close the connection. The `headers` are added as they are, each
+/// ending with a line break.
+pub fn respond(mut stream: &TcpStream, status: &str, headers: &str, body: &str) -> io::Result<()> {
+    // Written at once, as the stream is unbuffered.
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{headers}\
+         Connection: close\r\n\r\n{body}",
+        body.len(),
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+/// Answer a rejected request with its status. The rest of the request is read and discarded
+/// first, as closing a connection with unread data resets it, and the client may never see the
+/// response.
+pub fn reject(stream: &TcpStream, rejection: &Rejection) -> io::Result<()> {
+    let body = serde_json::json!({ "error": rejection.message }).to_string();
+    respond(stream, rejection.status, "", &body)?;
+
+    stream.shutdown(Shutdown::Write)?;
+    stream.set_read_timeout(Some(LINGER))?;
+    // The client may keep sending until it notices the response, which isn't an error.
+    io::copy(&mut stream.take(MAX_LINGER), &mut io::sink()).ok();
+
+    Ok(())
+}
+
+/// Reject a connection because all workers are busy.
+pub fn busy(stream: &TcpStream) -> io::Result<()> {
+    let body = r#"{"error":"too many connections, try again later"}"#;
+    respond(stream, "503 Service Unavailable", "", body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_limited() {
+        let request = read_from(
+            "GET /healthz HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer abc\r\n\r\n"
+                .as_bytes(),
+        )
+        .unwrap()
+        .ok()
+        .unwrap();
+        assert_eq!(
+            ("GET", "/healthz", Some("Bearer abc")),
+            (
+                &*request.method,
+                &*request.target,
+                request.authorization.as_deref()
+            )
+        );
+
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_REQUEST_LINE));
+        let rejection = read_from(long_line.as_bytes()).unwrap().err().unwrap();
+        assert_eq!("400 Bad Request", rejection.status);
+
+        let many_headers = format!("GET / HTTP/1.1\r\n{}\r\n", "X-Pad: 1\r\n".repeat(10_000));
+        let rejection = read_from(many_headers.as_bytes()).unwrap().err().unwrap();
+        assert_eq!("431 Request Header Fields Too Large", rejection.status);
+
+        let rejection = read_from("GET\r\n\r\n".as_bytes()).unwrap().err().unwrap();
+        assert_eq!("400 Bad Request", rejection.status);
+
+        let closed = read_from("".as_bytes()).err().unwrap();
+        assert_eq!(io::ErrorKind::UnexpectedEof, closed.kind());
+    }
+}
@@ -0,0 +1,76 @@
+//! Interruption of commands that load a lot of data, so Ctrl-C finishes them early with what was
+//! loaded so far, instead of throwing it all away. A second Ctrl-C ends the process immediately.
+//!
+//! Interruptions are only caught on Unix. Elsewhere, the process simply ends on Ctrl-C.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+
+/// Set by `SIGINT` while caught.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches interruptions until dropped, when the default behavior is restored.
+pub struct Guard(());
+
+/// Start catching interruptions.
+pub fn catch() -> Result<Guard> {
+    watch(true).context("failed installing signal handler")?;
+    Ok(Guard(()))
+}
+
+/// Whether the user asked to stop since interruptions were caught.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        watch(false).ok();
+    }
+}
+
+#[cfg(unix)]
+fn watch(catch: bool) -> std::io::Result<()> {
+    extern "C" fn handle(signal: libc::c_int) {
+        if INTERRUPTED.swap(true, Ordering::Relaxed) {
+            // Only async-signal-safe functions may be called here.
+            unsafe { libc::_exit(128 + signal) };
+        }
+    }
+
+    install(libc::SIGINT, catch.then_some(handle))
+}
+
+#[cfg(not(unix))]
+fn watch(_catch: bool) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Handle the signal with `handler`, or restore its default behavior for `None`. System calls
+/// that the signal interrupts are restarted, so only the handler notices it. Handlers may only
+/// touch atomics and call async-signal-safe functions.
+#[cfg(unix)]
+pub fn install(
+    signal: libc::c_int,
+    handler: Option<extern "C" fn(libc::c_int)>,
+) -> std::io::Result<()> {
+    use std::{io, mem, ptr};
+
+    // SAFETY: The action is fully initialized, and the handler is restricted like stated above.
+    unsafe {
+        let mut action = mem::zeroed::<libc::sigaction>();
+        action.sa_sigaction = match handler {
+            Some(handler) => handler as libc::sighandler_t,
+            None => libc::SIG_DFL,
+        };
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+
+        if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,302 @@
+//! Static language metadata, generated from the `languages.json` definitions of tokei 12.1.2, as
+//! tokei doesn't expose the information through its API.
+
+use tokei::LanguageType;
+
+/// File extensions and file names of each language.
+pub const FILE_PATTERNS: &[(LanguageType, &[&str], &[&str])] = &[
+    (LanguageType::Abap, &["abap"], &[]),
+    (LanguageType::ABNF, &["abnf"], &[]),
+    (LanguageType::ActionScript, &["as"], &[]),
+    (LanguageType::Ada, &["ada", "adb", "ads", "pad"], &[]),
+    (LanguageType::Alex, &["x"], &[]),
+    (LanguageType::Agda, &["agda"], &[]),
+    (LanguageType::Alloy, &["als"], &[]),
+    (LanguageType::Arduino, &["ino"], &[]),
+    (LanguageType::AsciiDoc, &["adoc", "asciidoc"], &[]),
+    (LanguageType::Asn1, &["asn1"], &[]),
+    (LanguageType::Assembly, &["asm"], &[]),
+    (LanguageType::AssemblyGAS, &["s"], &[]),
+    (LanguageType::Asp, &["asa", "asp"], &[]),
+    (
+        LanguageType::AspNet,
+        &[
+            "asax", "ascx", "asmx", "aspx", "master", "sitemap", "webinfo",
+        ],
+        &[],
+    ),
+    (LanguageType::Autoconf, &["in"], &[]),
+    (LanguageType::AutoHotKey, &["ahk"], &[]),
+    (LanguageType::Automake, &["am"], &[]),
+    (LanguageType::Sh, &["sh"], &[]),
+    (LanguageType::Bash, &["bash"], &[]),
+    (LanguageType::BrightScript, &["brs"], &[]),
+    (LanguageType::Elvish, &["elv"], &[]),
+    (LanguageType::Fish, &["fish"], &[]),
+    (LanguageType::Batch, &["bat", "btm", "cmd"], &[]),
+    (LanguageType::Bean, &["bean", "beancount"], &[]),
+    (LanguageType::C, &["c", "ec", "pgc"], &[]),
+    (LanguageType::Cabal, &["cabal"], &[]),
+    (LanguageType::Cassius, &["cassius"], &[]),
+    (LanguageType::Ceylon, &["ceylon"], &[]),
+    (LanguageType::CHeader, &["h"], &[]),
+    (LanguageType::Clojure, &["clj"], &[]),
+    (LanguageType::ClojureScript, &["cljs"], &[]),
+    (LanguageType::ClojureC, &["cljc"], &[]),
+    (LanguageType::CMake, &["cmake"], &["cmakelists.txt"]),
+    (
+        LanguageType::Cobol,
+        &["cob", "cbl", "ccp", "cobol", "cpy"],
+        &[],
+    ),
+    (LanguageType::CodeQL, &["ql", "qll"], &[]),
+    (LanguageType::CoffeeScript, &["coffee", "cjsx"], &[]),
+    (LanguageType::Cogent, &["cogent"], &[]),
+    (LanguageType::ColdFusion, &["cfm"], &[]),
+    (LanguageType::ColdFusionScript, &["cfc"], &[]),
+    (LanguageType::Coq, &["v"], &[]),
+    (
+        LanguageType::Cpp,
+        &["cc", "cpp", "cxx", "c++", "pcc", "tpp"],
+        &[],
+    ),
+    (
+        LanguageType::CppHeader,
+        &["hh", "hpp", "hxx", "inl", "ipp"],
+        &[],
+    ),
+    (LanguageType::Crystal, &["cr"], &[]),
+    (LanguageType::CSharp, &["cs", "csx"], &[]),
+    (LanguageType::CShell, &["csh"], &[]),
+    (LanguageType::Css, &["css"], &[]),
+    (LanguageType::D, &["d"], &[]),
+    (LanguageType::Daml, &["daml"], &[]),
+    (LanguageType::Dart, &["dart"], &[]),
+    (LanguageType::DeviceTree, &["dts", "dtsi"], &[]),
+    (LanguageType::Dhall, &["dhall"], &[]),
+    (LanguageType::DreamMaker, &["dm", "dme"], &[]),
+    (
+        LanguageType::Dockerfile,
+        &["dockerfile", "dockerignore"],
+        &["dockerfile"],
+    ),
+    (LanguageType::DotNetResource, &["resx"], &[]),
+    (LanguageType::Dust, &["dust"], &[]),
+    (LanguageType::Edn, &["edn"], &[]),
+    (LanguageType::Elisp, &["el"], &[]),
+    (LanguageType::Elixir, &["ex", "exs"], &[]),
+    (LanguageType::Elm, &["elm"], &[]),
+    (LanguageType::EmacsDevEnv, &["ede"], &[]),
+    (LanguageType::Emojicode, &["emojic", "\u{1f347}"], &[]),
+    (LanguageType::Erlang, &["erl", "hrl"], &[]),
+    (LanguageType::FEN, &["fen"], &[]),
+    (LanguageType::FlatBuffers, &["fbs"], &[]),
+    (LanguageType::Fstar, &["fst"], &[]),
+    (
+        LanguageType::Forth,
+        &[
+            "4th", "forth", "fr", "frt", "fth", "f83", "fb", "fpm", "e4", "rx", "ft",
+        ],
+        &[],
+    ),
+    (
+        LanguageType::FortranLegacy,
+        &["f", "for", "ftn", "f77", "pfo"],
+        &[],
+    ),
+    (
+        LanguageType::FortranModern,
+        &["f03", "f08", "f90", "f95"],
+        &[],
+    ),
+    (LanguageType::FreeMarker, &["ftl", "ftlh", "ftlx"], &[]),
+    (LanguageType::FSharp, &["fs", "fsi", "fsx", "fsscript"], &[]),
+    (LanguageType::Futhark, &["fut"], &[]),
+    (LanguageType::GDB, &["gdb"], &[]),
+    (LanguageType::GdScript, &["gd"], &[]),
+    (LanguageType::Gherkin, &["feature"], &[]),
+    (LanguageType::Gleam, &["gleam"], &[]),
+    (
+        LanguageType::Glsl,
+        &["vert", "tesc", "tese", "geom", "frag", "comp", "glsl"],
+        &[],
+    ),
+    (LanguageType::Go, &["go"], &[]),
+    (LanguageType::Gohtml, &["gohtml"], &[]),
+    (LanguageType::Graphql, &["gql", "graphql"], &[]),
+    (LanguageType::Groovy, &["groovy", "grt", "gtpl", "gvy"], &[]),
+    (LanguageType::Gwion, &["gw"], &[]),
+    (LanguageType::Happy, &["y", "ly"], &[]),
+    (LanguageType::Handlebars, &["hbs", "handlebars"], &[]),
+    (LanguageType::Haskell, &["hs"], &[]),
+    (LanguageType::Hcl, &["tf", "tfvars"], &[]),
+    (LanguageType::Headache, &["ha"], &[]),
+    (LanguageType::Hlsl, &["hlsl"], &[]),
+    (LanguageType::HolyC, &["HC", "hc"], &[]),
+    (LanguageType::Html, &["html", "htm"], &[]),
+    (LanguageType::Hamlet, &["hamlet"], &[]),
+    (LanguageType::Haxe, &["hx"], &[]),
+    (LanguageType::Hex, &["hex"], &[]),
+    (LanguageType::Idris, &["idr", "lidr"], &[]),
+    (LanguageType::Ini, &["ini"], &[]),
+    (LanguageType::IntelHex, &["ihex"], &[]),
+    (LanguageType::Isabelle, &["thy"], &[]),
+    (LanguageType::Jai, &["jai"], &[]),
+    (LanguageType::Java, &["java"], &[]),
+    (LanguageType::JavaScript, &["js", "mjs"], &[]),
+    (LanguageType::Json, &["json"], &[]),
+    (LanguageType::Jsonnet, &["jsonnet", "libsonnet"], &[]),
+    (LanguageType::Jsx, &["jsx"], &[]),
+    (LanguageType::Julia, &["jl"], &[]),
+    (LanguageType::Julius, &["julius"], &[]),
+    (LanguageType::Jupyter, &["ipynb"], &[]),
+    (LanguageType::K, &["k"], &[]),
+    (LanguageType::KakouneScript, &["kak"], &[]),
+    (LanguageType::Kotlin, &["kt", "kts"], &[]),
+    (LanguageType::Lean, &["lean", "hlean"], &[]),
+    (LanguageType::Less, &["less"], &[]),
+    (LanguageType::Liquid, &["liquid"], &[]),
+    (LanguageType::LinkerScript, &["lds"], &[]),
+    (LanguageType::Lisp, &["lisp", "lsp"], &[]),
+    (LanguageType::LiveScript, &["ls"], &[]),
+    (LanguageType::LLVM, &["ll"], &[]),
+    (LanguageType::Logtalk, &["lgt", "logtalk"], &[]),
+    (LanguageType::Lua, &["lua"], &[]),
+    (LanguageType::Lucius, &["lucius"], &[]),
+    (LanguageType::Madlang, &["mad"], &[]),
+    (
+        LanguageType::Makefile,
+        &["makefile", "mak", "mk"],
+        &["makefile"],
+    ),
+    (LanguageType::Markdown, &["md", "markdown"], &[]),
+    (LanguageType::ModuleDef, &["def"], &[]),
+    (LanguageType::MoonScript, &["moon"], &[]),
+    (
+        LanguageType::Meson,
+        &[],
+        &["meson.build", "meson_options.txt"],
+    ),
+    (LanguageType::Mint, &["mint"], &[]),
+    (LanguageType::Mustache, &["mustache"], &[]),
+    (LanguageType::Nim, &["nim"], &[]),
+    (LanguageType::Nix, &["nix"], &[]),
+    (LanguageType::ObjectiveC, &["m"], &[]),
+    (LanguageType::ObjectiveCpp, &["mm"], &[]),
+    (
+        LanguageType::OCaml,
+        &["ml", "mli", "mll", "mly", "re", "rei"],
+        &[],
+    ),
+    (LanguageType::Odin, &["odin"], &[]),
+    (LanguageType::OpenType, &["fea"], &[]),
+    (LanguageType::Org, &["org"], &[]),
+    (LanguageType::Oz, &["oz"], &[]),
+    (LanguageType::Pan, &["pan", "tpl"], &[]),
+    (LanguageType::Pascal, &["pas", "pp"], &[]),
+    (LanguageType::Perl, &["pl", "pm"], &[]),
+    (LanguageType::Perl6, &["pl6", "pm6"], &[]),
+    (LanguageType::Pest, &["pest"], &[]),
+    (LanguageType::NotQuitePerl, &["nqp"], &[]),
+    (LanguageType::Php, &["php"], &[]),
+    (LanguageType::Polly, &["polly"], &[]),
+    (LanguageType::Pony, &["pony"], &[]),
+    (LanguageType::PostCss, &["pcss", "sss"], &[]),
+    (LanguageType::Processing, &["pde"], &[]),
+    (LanguageType::Prolog, &["p", "pro"], &[]),
+    (
+        LanguageType::PowerShell,
+        &["ps1", "psm1", "psd1", "ps1xml", "cdxml", "pssc", "psc1"],
+        &[],
+    ),
+    (LanguageType::PSL, &["psl"], &[]),
+    (LanguageType::Protobuf, &["proto"], &[]),
+    (LanguageType::Pug, &["pug"], &[]),
+    (LanguageType::PureScript, &["purs"], &[]),
+    (LanguageType::Python, &["py", "pyw"], &[]),
+    (LanguageType::Qcl, &["qcl"], &[]),
+    (LanguageType::Q, &["q"], &[]),
+    (LanguageType::Qml, &["qml"], &[]),
+    (LanguageType::R, &["r"], &[]),
+    (LanguageType::Racket, &["rkt"], &[]),
+    (LanguageType::Rakefile, &["rake"], &["rakefile"]),
+    (LanguageType::Razor, &["cshtml"], &[]),
+    (LanguageType::Renpy, &["rpy"], &[]),
+    (LanguageType::RON, &["ron"], &[]),
+    (LanguageType::RPMSpecfile, &["spec"], &[]),
+    (LanguageType::Ruby, &["rb"], &[]),
+    (LanguageType::RubyHtml, &["rhtml", "erb"], &[]),
+    (LanguageType::Rust, &["rs"], &[]),
+    (LanguageType::ReStructuredText, &["rst"], &[]),
+    (LanguageType::Sass, &["sass", "scss"], &[]),
+    (LanguageType::Scala, &["sc", "scala"], &[]),
+    (LanguageType::Scheme, &["scm", "ss"], &[]),
+    (LanguageType::Scons, &[], &["sconstruct", "sconscript"]),
+    (LanguageType::Sml, &["sml"], &[]),
+    (LanguageType::Solidity, &["sol"], &[]),
+    (LanguageType::SpecmanE, &["e"], &[]),
+    (LanguageType::Spice, &["ckt"], &[]),
+    (LanguageType::Sql, &["sql"], &[]),
+    (LanguageType::SRecode, &["srt"], &[]),
+    (LanguageType::Stan, &["stan"], &[]),
+    (LanguageType::Stratego, &["str"], &[]),
+    (LanguageType::Stylus, &["styl"], &[]),
+    (LanguageType::Svelte, &["svelte"], &[]),
+    (LanguageType::Svg, &["svg"], &[]),
+    (LanguageType::Swift, &["swift"], &[]),
+    (LanguageType::Swig, &["swg", "i"], &[]),
+    (LanguageType::SystemVerilog, &["sv", "svh"], &[]),
+    (LanguageType::Tcl, &["tcl"], &[]),
+    (LanguageType::Tera, &["tera"], &[]),
+    (LanguageType::Tex, &["tex", "sty"], &[]),
+    (LanguageType::Text, &["text", "txt"], &[]),
+    (LanguageType::Thrift, &["thrift"], &[]),
+    (LanguageType::Toml, &["toml"], &[]),
+    (LanguageType::Tsx, &["tsx"], &[]),
+    (LanguageType::Ttcn, &["ttcn", "ttcn3", "ttcnpp"], &[]),
+    (LanguageType::Twig, &["twig"], &[]),
+    (LanguageType::TypeScript, &["ts"], &[]),
+    (LanguageType::UnrealPlugin, &["uplugin"], &[]),
+    (LanguageType::UnrealProject, &["uproject"], &[]),
+    (LanguageType::UnrealScript, &["uc", "uci", "upkg"], &[]),
+    (LanguageType::UnrealShader, &["usf"], &[]),
+    (LanguageType::UnrealShaderHeader, &["ush"], &[]),
+    (LanguageType::UnrealDeveloperMarkdown, &["udn"], &[]),
+    (LanguageType::UrWeb, &["ur", "urs"], &[]),
+    (LanguageType::UrWebProject, &["urp"], &[]),
+    (LanguageType::Vala, &["vala"], &[]),
+    (LanguageType::VB6, &["frm", "bas", "cls"], &[]),
+    (LanguageType::VBScript, &["vbs"], &[]),
+    (LanguageType::Velocity, &["vm"], &[]),
+    (LanguageType::Verilog, &["vg", "vh"], &[]),
+    (
+        LanguageType::VerilogArgsFile,
+        &["irunargs", "xrunargs"],
+        &[],
+    ),
+    (LanguageType::Vhdl, &["vhd", "vhdl"], &[]),
+    (LanguageType::VisualBasic, &["vb"], &[]),
+    (LanguageType::VisualStudioSolution, &["sln"], &[]),
+    (
+        LanguageType::VisualStudioProject,
+        &["vcproj", "vcxproj"],
+        &[],
+    ),
+    (LanguageType::VimScript, &["vim"], &[]),
+    (LanguageType::Vue, &["vue"], &[]),
+    (LanguageType::WebAssembly, &["wat", "wast"], &[]),
+    (LanguageType::Wolfram, &["nb", "wl"], &[]),
+    (LanguageType::Xaml, &["xaml"], &[]),
+    (LanguageType::XcodeConfig, &["xcconfig"], &[]),
+    (LanguageType::Xml, &["xml"], &[]),
+    (LanguageType::XSL, &["xsl", "xslt"], &[]),
+    (
+        LanguageType::MsBuild,
+        &["csproj", "vbproj", "fsproj", "props", "targets"],
+        &[],
+    ),
+    (LanguageType::Xtend, &["xtend"], &[]),
+    (LanguageType::Yaml, &["yaml", "yml"], &[]),
+    (LanguageType::Zig, &["zig"], &[]),
+    (LanguageType::Zsh, &["zsh"], &[]),
+];
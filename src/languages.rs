@@ -0,0 +1,311 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Result};
+use clap::Args;
+use serde::Serialize;
+use tokei::LanguageType;
+
+use crate::{config::Config, language_data};
+
+/// Common alternative names for languages, that are neither the tokei name nor a file extension.
+const ALIASES: &[(&str, LanguageType)] = &[
+    ("c++", LanguageType::Cpp),
+    ("cplusplus", LanguageType::Cpp),
+    ("csharp", LanguageType::CSharp),
+    ("fsharp", LanguageType::FSharp),
+    ("golang", LanguageType::Go),
+    ("js", LanguageType::JavaScript),
+    ("node", LanguageType::JavaScript),
+    ("objc", LanguageType::ObjectiveC),
+    ("shell", LanguageType::Sh),
+    ("ts", LanguageType::TypeScript),
+    ("vb", LanguageType::VisualBasic),
+];
+
+/// Minimum similarity for a language name to be suggested for an unknown input.
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Resolve a user provided language name. Matching is case-insensitive and accepts the variant
+/// name (as printed by `list-filters`), the display name, common aliases and file extensions.
+pub fn parse(value: &str) -> Result<LanguageType, String> {
+    let lower = value.to_lowercase();
+
+    LanguageType::list()
+        .iter()
+        .copied()
+        .find(|lang| {
+            format!("{lang:?}").to_lowercase() == lower || lang.name().to_lowercase() == lower
+        })
+        .or_else(|| {
+            ALIASES
+                .iter()
+                .find_map(|&(alias, lang)| (alias == lower).then_some(lang))
+        })
+        .or_else(|| LanguageType::from_file_extension(lower.trim_start_matches('.')))
+        .ok_or_else(|| unknown_language(value))
+}
+
+fn unknown_language(value: &str) -> String {
+    let lower = value.to_lowercase();
+    let mut candidates = LanguageType::list()
+        .iter()
+        .map(|lang| {
+            let name = format!("{lang:?}");
+            let score = strsim::jaro_winkler(&lower, &name.to_lowercase())
+                .max(strsim::jaro_winkler(&lower, &lang.name().to_lowercase()));
+            (score, name)
+        })
+        .filter(|(score, _)| *score >= SUGGESTION_THRESHOLD)
+        .collect::<Vec<_>>();
+
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    if candidates.is_empty() {
+        format!("unknown language `{value}`, see `list-filters` for all available languages")
+    } else {
+        let names = candidates
+            .into_iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+
+        format!(
+            "unknown language `{value}`, did you mean: {}?",
+            names.join(", ")
+        )
+    }
+}
+
+/// Built-in language groups, usable with `--filter-group`.
+const GROUPS: &[(&str, &[LanguageType])] = &[
+    (
+        "config",
+        &[
+            LanguageType::Hcl,
+            LanguageType::Ini,
+            LanguageType::Json,
+            LanguageType::Toml,
+            LanguageType::Xml,
+            LanguageType::Yaml,
+        ],
+    ),
+    (
+        "docs",
+        &[
+            LanguageType::AsciiDoc,
+            LanguageType::Markdown,
+            LanguageType::Org,
+            LanguageType::ReStructuredText,
+            LanguageType::Tex,
+            LanguageType::Text,
+        ],
+    ),
+    (
+        "shell",
+        &[
+            LanguageType::Bash,
+            LanguageType::Batch,
+            LanguageType::CShell,
+            LanguageType::Fish,
+            LanguageType::PowerShell,
+            LanguageType::Sh,
+            LanguageType::Zsh,
+        ],
+    ),
+    (
+        "web",
+        &[
+            LanguageType::Css,
+            LanguageType::Html,
+            LanguageType::JavaScript,
+            LanguageType::Jsx,
+            LanguageType::Less,
+            LanguageType::Sass,
+            LanguageType::Svelte,
+            LanguageType::Tsx,
+            LanguageType::TypeScript,
+            LanguageType::Vue,
+        ],
+    ),
+];
+
+/// Language selection shared by all commands that work on a subset of languages.
+#[derive(Args, Default)]
+pub struct FilterArgs {
+    /// One or more languages to filter the output with. Names are case-insensitive and common
+    /// aliases or file extensions (like `c++`, `golang` or `ts`) are accepted.
+    #[arg(short, long, value_parser = parse)]
+    pub filter: Vec<LanguageType>,
+    /// One or more named groups of languages to filter the output with. Built-in groups are
+    /// `config`, `docs`, `shell` and `web`, additional ones can be defined in the config file.
+    #[arg(long)]
+    pub filter_group: Vec<String>,
+}
+
+impl FilterArgs {
+    /// Combine the individual languages and groups into the final set of languages. An empty set
+    /// means no filtering was requested.
+    pub fn resolve(&self, config: &Config) -> Result<HashSet<LanguageType>> {
+        let mut languages = self.filter.iter().copied().collect::<HashSet<_>>();
+
+        for name in &self.filter_group {
+            languages.extend(group(name, config)?);
+        }
+
+        Ok(languages)
+    }
+}
+
+/// Look up the languages of a group, preferring custom groups from the config over the built-in
+/// ones.
+fn group(name: &str, config: &Config) -> Result<Vec<LanguageType>> {
+    if let Some(names) = config.filter_groups.get(name) {
+        return names
+            .iter()
+            .map(|lang| parse(lang).map_err(|e| anyhow!("in filter group `{name}`: {e}")))
+            .collect();
+    }
+
+    if let Some((_, languages)) = GROUPS.iter().find(|(group, _)| *group == name) {
+        return Ok(languages.to_vec());
+    }
+
+    let mut available = GROUPS
+        .iter()
+        .map(|(group, _)| *group)
+        .chain(config.filter_groups.keys().map(String::as_str))
+        .collect::<Vec<_>>();
+    available.sort_unstable();
+
+    bail!(
+        "unknown filter group `{name}`, available groups: {}",
+        available.join(", ")
+    )
+}
+
+/// Rough classification of languages, used to structure language listings.
+#[derive(Clone, Copy, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Programming,
+    Markup,
+    Data,
+}
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Programming => "Programming",
+            Self::Markup => "Markup",
+            Self::Data => "Data",
+        }
+    }
+}
+
+pub fn category(lang: LanguageType) -> Category {
+    use LanguageType::*;
+
+    match lang {
+        AsciiDoc
+        | Cassius
+        | Css
+        | Dust
+        | FreeMarker
+        | Gohtml
+        | Hamlet
+        | Handlebars
+        | Html
+        | Less
+        | Liquid
+        | Lucius
+        | Markdown
+        | Mustache
+        | Org
+        | PostCss
+        | Pug
+        | Razor
+        | ReStructuredText
+        | RubyHtml
+        | Sass
+        | Stylus
+        | Svg
+        | Tera
+        | Tex
+        | Text
+        | Twig
+        | UnrealDeveloperMarkdown
+        | Velocity
+        | Xaml
+        | XSL => Category::Markup,
+        Cabal | DeviceTree | Dhall | DotNetResource | Edn | FEN | FlatBuffers | Graphql | Hcl
+        | Hex | Ini | IntelHex | Json | Jsonnet | Jupyter | ModuleDef | MsBuild | Protobuf
+        | RON | RPMSpecfile | Thrift | Toml | UnrealPlugin | UnrealProject | VerilogArgsFile
+        | VisualStudioProject | VisualStudioSolution | XcodeConfig | Xml | Yaml => Category::Data,
+        lang if lang.is_literate() => Category::Markup,
+        _ => Category::Programming,
+    }
+}
+
+/// File extensions that are mapped to the given language.
+pub fn extensions(lang: LanguageType) -> impl Iterator<Item = &'static str> {
+    language_data::FILE_PATTERNS
+        .iter()
+        .filter(move |(l, _, _)| *l == lang)
+        .flat_map(|(_, extensions, _)| extensions.iter().copied())
+        // Guard against differences between the bundled data and the tokei version in use.
+        .filter(move |ext| LanguageType::from_file_extension(ext) == Some(lang))
+}
+
+/// Exact file names that are mapped to the given language.
+pub fn file_names(lang: LanguageType) -> impl Iterator<Item = &'static str> {
+    language_data::FILE_PATTERNS
+        .iter()
+        .filter(move |(l, _, _)| *l == lang)
+        .flat_map(|(_, _, names)| names.iter().copied())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_are_resolved() {
+        assert_eq!(Ok(LanguageType::Cpp), parse("c++"));
+        assert_eq!(Ok(LanguageType::Go), parse("golang"));
+        assert_eq!(Ok(LanguageType::TypeScript), parse("ts"));
+        assert_eq!(Ok(LanguageType::Rust), parse(".rs"));
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        for name in ["Rust", "rust", "RUST", "rUsT"] {
+            assert_eq!(Ok(LanguageType::Rust), parse(name));
+        }
+        assert_eq!(Ok(LanguageType::Cpp), parse("C++"));
+        assert_eq!(Ok(LanguageType::Go), parse("GoLang"));
+        assert_eq!(Ok(LanguageType::JavaScript), parse("javascript"));
+    }
+
+    #[test]
+    fn typos_get_suggestions() {
+        assert_eq!(
+            Err("unknown language `Pyhton`, did you mean: Python?".to_owned()),
+            parse("Pyhton")
+        );
+        assert_eq!(
+            Err(
+                "unknown language `Javscript`, did you mean: JavaScript, VBScript, VimScript?"
+                    .to_owned()
+            ),
+            parse("Javscript")
+        );
+        assert_eq!(
+            Err(
+                "unknown language `zzzz`, see `list-filters` for all available languages"
+                    .to_owned()
+            ),
+            parse("zzzz")
+        );
+    }
+}
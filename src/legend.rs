@@ -0,0 +1,177 @@
+use std::fmt::Write as _;
+
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+
+/// Left and right padding of the plot area, as used by poloto.
+const PADDING_X: f64 = 150.0;
+/// Rough average width of a single character of the legend font, which the poloto themes set to
+/// 20px. SVG has no text measurement, so the layout is based on an estimate that works well for
+/// the themes' sans-serif font.
+const CHAR_WIDTH: f64 = 11.0;
+/// Length of the colored line in front of each label.
+const SAMPLE_WIDTH: f64 = 50.0;
+const SAMPLE_GAP: f64 = 10.0;
+const ENTRY_GAP: f64 = 30.0;
+const ROW_HEIGHT: f64 = 25.0;
+/// Amount of distinct series colors in the poloto themes.
+const COLORS: usize = 8;
+
+/// Location of the chart legend. The space above and below the plot is limited, so legends with
+/// many series are best kept on the right.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Placement {
+    /// Row between the title and the plot.
+    Top,
+    /// Row between the axis ticks and the axis label.
+    Bottom,
+    /// List next to the plot.
+    Right,
+    /// Don't show a legend.
+    None,
+}
+
+/// Values available to a label [`Template`].
+pub struct Values<'a> {
+    /// Name of the language group, or `All` when not grouped by language.
+    pub language: &'a str,
+    /// Name of the scanned revision.
+    pub reference: &'a str,
+    /// Kind of lines in the series, `code` or `comments`.
+    pub kind: &'a str,
+    /// Line count at the most recent entry.
+    pub latest: u64,
+    /// Highest line count over the whole history.
+    pub peak: u64,
+}
+
+/// Series label with `{placeholder}` fields, like `{language} {kind} ({latest} lines)`.
+#[derive(Clone)]
+pub struct Template(String);
+
+impl Template {
+    const PLACEHOLDERS: &'static [&'static str] = &["language", "ref", "kind", "latest", "peak"];
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut rest = value;
+
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}') else {
+                return Err("unclosed `{` in label template".to_owned());
+            };
+
+            let name = &rest[start + 1..start + end];
+            if !Self::PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "unknown placeholder `{{{name}}}`, available are: {}",
+                    Self::PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{p}}}"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            rest = &rest[start + end + 1..];
+        }
+
+        Ok(Self(value.to_owned()))
+    }
+
+    pub fn format(&self, values: &Values<'_>) -> String {
+        self.0
+            .replace("{language}", values.language)
+            .replace("{ref}", values.reference)
+            .replace("{kind}", values.kind)
+            .replace("{latest}", &values.latest.to_string())
+            .replace("{peak}", &values.peak.to_string())
+    }
+}
+
+/// Escape text for use as SVG content. Labels are inserted into the document as is, so they
+/// must not contain markup characters.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Draw a horizontal legend for the given (already escaped) labels into the rendered SVG
+/// document. Only used for [`Placement::Top`] and [`Placement::Bottom`], as poloto itself draws
+/// the legend on the right.
+pub fn draw(
+    svg: &mut String,
+    placement: Placement,
+    labels: &[String],
+    viewbox: [f64; 2],
+) -> Result<()> {
+    let [width, height] = viewbox;
+    let available = width - PADDING_X * 2.0;
+
+    let y = match placement {
+        Placement::Top => 72.0,
+        Placement::Bottom => height - 45.0,
+        Placement::Right | Placement::None => return Ok(()),
+    };
+
+    let mut rows = vec![Vec::new()];
+    let mut row_width = 0.0;
+
+    for (i, label) in labels.iter().enumerate() {
+        let entry_width = SAMPLE_WIDTH + SAMPLE_GAP + label.chars().count() as f64 * CHAR_WIDTH;
+
+        if row_width > 0.0 && row_width + ENTRY_GAP + entry_width > available {
+            rows.push(Vec::new());
+            row_width = 0.0;
+        }
+
+        if row_width > 0.0 {
+            row_width += ENTRY_GAP;
+        }
+
+        rows.last_mut()
+            .unwrap()
+            .push((i, label, row_width, entry_width));
+        row_width += entry_width;
+    }
+
+    let Some(end) = svg.rfind("</svg>") else {
+        bail!("rendered chart is not a valid SVG document");
+    };
+
+    let mut legend = String::new();
+    let count = rows.len();
+
+    for (r, row) in rows.into_iter().enumerate() {
+        // Additional rows grow away from the plot, so the legend never overlaps it.
+        let row_y = match placement {
+            Placement::Top => y - (count - 1 - r) as f64 * ROW_HEIGHT,
+            _ => y + r as f64 * ROW_HEIGHT,
+        };
+        let total = row.last().map_or(0.0, |(_, _, x, w)| x + w);
+        let start = PADDING_X + (available - total) / 2.0;
+
+        for (i, label, x, _) in row {
+            let x = start + x;
+            let color = i % COLORS;
+
+            writeln!(
+                legend,
+                "\t<g class=\"poloto_legend poloto_imgs poloto_line poloto{color} \
+                 poloto_stroke\"><line x1=\"{x:.2}\" x2=\"{:.2}\" y1=\"{row_y:.2}\" \
+                 y2=\"{row_y:.2}\"/></g>",
+                x + SAMPLE_WIDTH
+            )?;
+            writeln!(
+                legend,
+                "\t<text class=\"poloto_legend poloto_text poloto_line poloto{color}\" \
+                 x=\"{:.2}\" y=\"{row_y:.2}\">{label}</text>",
+                x + SAMPLE_WIDTH + SAMPLE_GAP
+            )?;
+        }
+    }
+
+    svg.insert_str(end, &legend);
+
+    Ok(())
+}
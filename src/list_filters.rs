@@ -1,7 +1,214 @@
-use tokei::LanguageType;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
 
-pub fn run() {
-    for filter in LanguageType::list() {
-        println!("{:?}", filter);
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum, ValueHint};
+use serde::Serialize;
+use tokei::{CodeStats, LanguageType};
+
+use crate::{
+    axis::Locale,
+    config::Config,
+    languages::{self, Category},
+    models::Entry,
+    scan,
+    stats_file::StatsFile,
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Only list languages whose name, display name or file extensions contain this term.
+    #[arg(long)]
+    search: Option<String>,
+    /// Output format of the listing.
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Only list languages detected in this Git repository (at `HEAD`) or stats file (at its
+    /// latest entry), together with their file and line counts.
+    #[arg(long, value_hint = ValueHint::AnyPath)]
+    input: Option<PathBuf>,
+    /// Locale for the separators of the file and line counts, like `de-DE`. Defaults to the
+    /// locale from the `LC_ALL`, `LC_NUMERIC` or `LANG` environment variable.
+    #[arg(long)]
+    locale: Option<Locale>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// Human readable listing, grouped by category.
+    Text,
+    /// JSON array with one object per language.
+    Json,
+}
+
+#[derive(Serialize)]
+struct Language {
+    name: String,
+    display_name: &'static str,
+    category: Category,
+    extensions: Vec<&'static str>,
+    file_names: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    usage: Option<Usage>,
+}
+
+/// How much of a language was detected in the input.
+#[derive(Clone, Copy, Default, Serialize)]
+struct Usage {
+    files: u64,
+    code: u64,
+    comments: u64,
+    blanks: u64,
+}
+
+impl Usage {
+    /// Add the counts of a language's files, failing instead of silently wrapping around on
+    /// overflow, like the totals of `render`.
+    fn checked_add(self, files: u64, stats: &CodeStats) -> Option<Self> {
+        Some(Self {
+            files: self.files.checked_add(files)?,
+            code: self.code.checked_add(u64::try_from(stats.code).ok()?)?,
+            comments: self
+                .comments
+                .checked_add(u64::try_from(stats.comments).ok()?)?,
+            blanks: self.blanks.checked_add(u64::try_from(stats.blanks).ok()?)?,
+        })
+    }
+}
+
+impl Language {
+    fn new(lang: LanguageType) -> Self {
+        Self {
+            name: format!("{lang:?}"),
+            display_name: lang.name(),
+            category: languages::category(lang),
+            extensions: languages::extensions(lang).collect(),
+            file_names: languages::file_names(lang).collect(),
+            usage: None,
+        }
+    }
+
+    fn matches(&self, term: &str) -> bool {
+        self.name.to_lowercase().contains(term)
+            || self.display_name.to_lowercase().contains(term)
+            || self
+                .extensions
+                .iter()
+                .chain(&self.file_names)
+                .any(|ext| ext.to_lowercase().contains(term))
+    }
+}
+
+pub fn run(options: &Options, config: &Config) -> Result<()> {
+    let usage = options
+        .input
+        .as_ref()
+        .map(|input| detect(input, config))
+        .transpose()?;
+
+    let term = options.search.as_deref().map(str::to_lowercase);
+    let mut list = LanguageType::list()
+        .iter()
+        .filter_map(|&lang| {
+            let mut language = Language::new(lang);
+            if let Some(usage) = &usage {
+                language.usage = Some(*usage.get(&lang)?);
+            }
+            Some(language)
+        })
+        .filter(|lang| term.as_deref().is_none_or(|term| lang.matches(term)))
+        .collect::<Vec<_>>();
+
+    if usage.is_some() {
+        list.sort_by_key(|lang| std::cmp::Reverse(lang.usage.map(|u| u.code)));
+    }
+
+    match options.format {
+        Format::Text => print_text(
+            list,
+            &options.locale.clone().unwrap_or_else(Locale::from_env),
+        ),
+        Format::Json => println!("{}", serde_json::to_string_pretty(&list)?),
+    }
+
+    Ok(())
+}
+
+/// Collect the languages used in the latest state of a repository or stats file.
+fn detect(input: &Path, config: &Config) -> Result<HashMap<LanguageType, Usage>> {
+    let entry = if input.is_dir() {
+        scan::head_entry(input, &scan::Options::default(), config)?
+    } else {
+        match StatsFile::open(input)?.last_entry()? {
+            Some(entry) => entry,
+            None => return Ok(HashMap::new()),
+        }
+    };
+
+    summarize(&entry)
+}
+
+fn summarize(entry: &Entry) -> Result<HashMap<LanguageType, Usage>> {
+    let mut usage = HashMap::<_, Usage>::new();
+
+    let files = entry
+        .files
+        .values()
+        .map(|file| (file.language, 1, &file.statistics));
+    let languages = entry
+        .languages
+        .iter()
+        .map(|(&lang, summary)| (lang, summary.files, &summary.statistics));
+
+    for (lang, files, stats) in files.chain(languages) {
+        let usage = usage.entry(lang).or_default();
+
+        *usage = usage
+            .checked_add(files, stats)
+            .with_context(|| format!("line count overflow for {lang}"))?;
+    }
+
+    Ok(usage)
+}
+
+fn print_text(list: Vec<Language>, locale: &Locale) {
+    let mut categories = BTreeMap::<_, Vec<_>>::new();
+    for lang in list {
+        categories.entry(lang.category).or_default().push(lang);
+    }
+
+    for (i, (category, list)) in categories.into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+
+        println!("{}:", category.name());
+
+        for lang in list {
+            let patterns = lang
+                .extensions
+                .iter()
+                .map(|ext| format!(".{ext}"))
+                .chain(lang.file_names.iter().map(|name| (*name).to_owned()))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if let Some(usage) = lang.usage {
+                println!(
+                    "  {:<24} {:>6} files {:>10} code {:>10} comments {:>10} blanks",
+                    lang.name,
+                    locale.format(usage.files as f64),
+                    locale.format(usage.code as f64),
+                    locale.format(usage.comments as f64),
+                    locale.format(usage.blanks as f64)
+                );
+            } else if lang.name == lang.display_name {
+                println!("  {:<24} {patterns}", lang.name);
+            } else {
+                println!("  {:<24} {patterns} ({})", lang.name, lang.display_name);
+            }
+        }
     }
 }
@@ -1,63 +1,280 @@
-use std::path::PathBuf;
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, Subcommand, ValueHint};
-use tokei::LanguageType;
 
+use crate::exit::{FailOn, Failure};
+
+mod activity;
+mod api_docs;
+mod attributes;
+mod axis;
+mod bench;
+mod changepoints;
+mod chart;
+mod check;
+mod codeowners;
+mod comments;
+mod commit_graph;
+mod commit_type;
+mod config;
+mod contributors;
+mod convert;
+mod crypt;
+mod daemon;
+mod excludes;
+mod exit;
+mod forecast;
+mod graft;
+mod http;
+mod interrupt;
+mod language_data;
+mod languages;
+mod legend;
 mod list_filters;
 mod models;
+mod org;
+mod palette;
+mod partial;
+mod percentiles;
+mod pipeline;
+mod preview;
+mod profile;
 mod progress;
+mod provenance;
+mod prune;
 mod render;
+mod replay;
 mod scan;
+mod serve;
+mod signature;
+mod site;
+mod space;
+mod staleness;
+mod stats;
+mod stats_file;
+mod submodules;
+mod tokei_config;
+mod update;
+mod warnings;
+mod watch;
+mod watchdog;
 
 /// Generate statistical graphs about the code/comment rate in code repositories.
 #[derive(Parser)]
-#[command(about, author, version)]
+#[command(about, author, version, after_long_help = exit::HELP)]
 struct Opt {
+    /// Configuration file to load. Defaults to `commentstats.toml` in the current directory, if
+    /// it exists.
+    #[arg(long, global = true, value_hint = ValueHint::FilePath)]
+    config: Option<PathBuf>,
+    /// Least severe outcome that fails the run. With `warning`, runs that report any warnings
+    /// exit with code 10.
+    #[arg(long, global = true, value_enum, default_value_t = FailOn::Error)]
+    fail_on: FailOn,
+    /// Fail the run if any warnings were reported, same as `--fail-on warning`. For strict CI
+    /// checks.
+    #[arg(long, global = true)]
+    warnings_as_errors: bool,
+    /// Amount of threads for scanning and rendering, to limit the CPU usage on shared machines.
+    /// Defaults to one per CPU core.
+    #[arg(long, global = true)]
+    jobs: Option<NonZeroUsize>,
     #[clap(subcommand)]
     cmd: Command,
 }
 
 #[derive(Subcommand)]
 enum Command {
+    /// Break down the comment lines added by the weekday and local hour of their commits.
+    Activity {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+    },
+    /// Generate a synthetic repository and measure scan and render throughput.
+    Bench {
+        /// Shape of the synthetic repository as `<commits>x<files>`, for example `1000x500`.
+        #[arg(long)]
+        synthetic: bench::Synthetic,
+        /// Nest all files this many directories deep, to check the handling of long paths.
+        #[arg(long, default_value_t = 0)]
+        depth: usize,
+    },
+    /// Check the comment ratio of the latest entry against thresholds, or against a baseline
+    /// stats file. Exits with code 9 if any of them is violated.
+    Check {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: check::Options,
+    },
     /// List all possible languages that can be used as filters.
-    ListFilters,
+    ListFilters(list_filters::Options),
     /// Scan a repository and generate statistics.
     Scan {
-        /// Target Git repository.
-        #[arg(value_hint = ValueHint::DirPath)]
+        /// Target Git repository, as path or URL to clone from.
+        #[arg(value_hint = ValueHint::AnyPath)]
+        input: String,
+        /// Keep clones of repositories that are given by URL in this directory and only fetch
+        /// new commits on later scans, instead of cloning into a temporary directory each time.
+        #[arg(long, value_hint = ValueHint::DirPath)]
+        cache_dir: Option<PathBuf>,
+        #[command(flatten)]
+        options: scan::Options,
+    },
+    /// Rewrite an existing stats file in the current format, optionally correcting its content.
+    Convert {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
         input: PathBuf,
+        #[command(flatten)]
+        options: convert::Options,
+    },
+    /// Thin out old entries of a stats file to the latest one per day, week or month, to keep
+    /// its size bounded.
+    Prune {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: prune::Options,
     },
     /// Load statistics from a pre-generated `stats.json` file.
     Render {
-        /// Output image width.
-        #[arg(long, default_value_t = 1600)]
-        width: u32,
-        /// Output image height.
-        #[arg(long, default_value_t = 1000)]
-        height: u32,
-        /// One or more languages to filter the plotting output with.
-        #[arg(short, long)]
-        filter: Vec<LanguageType>,
+        #[command(flatten)]
+        options: render::Options,
         /// Location fo the statistics file.
         #[arg(value_hint = ValueHint::FilePath)]
         input: PathBuf,
     },
+    /// Clone or update all repositories of an organization and scan them, writing a stats file
+    /// for each and a combined one.
+    Org(org::Options),
+    /// Regenerate the charts listed in the configuration file from their stats files, like after
+    /// upgrading the tool.
+    Replay(replay::Options),
+    /// Clone, scan and render a repository and write a report, all in one go. Exits with code 3
+    /// if there is nothing to render and 4 to 7 if cloning, scanning, rendering or writing the
+    /// report failed.
+    Run(pipeline::Options),
+    /// Answer queries about stats files over HTTP with JSON, like `GET /api/series?lang=Rust`.
+    Serve {
+        /// Location of the statistics files, or directories containing them.
+        #[arg(required = true, value_hint = ValueHint::AnyPath)]
+        inputs: Vec<PathBuf>,
+        #[command(flatten)]
+        options: serve::Options,
+    },
+    /// Compare the comment density of several repositories, drawing each of them next to the
+    /// range between the 25th and 75th percentile of all of them.
+    Percentiles {
+        /// Location of the statistics files, at least two.
+        #[arg(required = true, num_args = 2.., value_hint = ValueHint::FilePath)]
+        inputs: Vec<PathBuf>,
+        #[command(flatten)]
+        options: percentiles::Options,
+    },
+    /// Generate a static website with charts of the whole repository, each language and each
+    /// top-level directory, together with a page to download the data.
+    Site {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: site::Options,
+    },
+    /// List files whose code changed recently, while their comment lines stayed the same for
+    /// months.
+    Staleness {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: staleness::Options,
+    },
+    /// Summarize a stats file, optionally listing the commits with unusually large changes.
+    Stats {
+        /// Location of the statistics file.
+        #[arg(value_hint = ValueHint::FilePath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: stats::Options,
+    },
+    /// Keep the stats file of a repository up to date, scanning its new commits on a schedule.
+    Watch {
+        /// Target Git repository.
+        #[arg(value_hint = ValueHint::DirPath)]
+        input: PathBuf,
+        #[command(flatten)]
+        options: watch::Options,
+    },
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            exit::code(&e)
+        }
+    }
 }
 
-fn main() -> Result<()> {
+fn run() -> Result<()> {
     let opt = Opt::parse();
+    let config = config::load(opt.config.clone()).context(Failure::Config)?;
+
+    if let Some(jobs) = opt.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs.get())
+            .build_global()
+            .context("failed setting up the thread pool")?;
+    }
 
     match opt.cmd {
-        Command::ListFilters => list_filters::run(),
-        Command::Scan { input } => scan::run(input)?,
-        Command::Render {
-            filter,
+        Command::Activity { input } => activity::run(&input)?,
+        Command::Bench { synthetic, depth } => bench::run(synthetic, depth)?,
+        Command::Check { input, options } => check::run(&input, &options)?,
+        Command::ListFilters(options) => list_filters::run(&options, &config)?,
+        Command::Scan {
             input,
-            width,
-            height,
-        } => render::run(filter, input, (width, height))?,
+            cache_dir,
+            options,
+        } => {
+            let checkout = org::local(&input, cache_dir.as_deref()).context(Failure::Clone)?;
+            scan::run(checkout.path, Path::new("stats.stats"), &options, &config)
+                .context(Failure::Scan)?
+        }
+        Command::Convert { input, options } => {
+            convert::run(&input, Path::new("stats.stats"), &options)?
+        }
+        Command::Prune { input, options } => {
+            prune::run(&input, Path::new("stats.stats"), &options)?
+        }
+        Command::Render { options, input } => {
+            let output = PathBuf::from(format!("stats.{}", options.extension()));
+            let _interrupt = interrupt::catch()?;
+            render::run(input, &output, &options, &config).context(Failure::Render)?
+        }
+        Command::Org(options) => org::run(options, &config)?,
+        Command::Replay(options) => replay::run(&options, &config)?,
+        Command::Run(options) => pipeline::run(&options, &config)?,
+        Command::Percentiles { inputs, options } => {
+            let output = PathBuf::from(format!("stats.{}", options.format.extension()));
+            percentiles::run(&inputs, &output, &options, &config).context(Failure::Render)?
+        }
+        Command::Serve { inputs, options } => serve::run(&inputs, options, config, opt.config)?,
+        Command::Site { input, options } => site::run(&input, &options, &config)?,
+        Command::Staleness { input, options } => staleness::run(&input, &options)?,
+        Command::Stats { input, options } => stats::run(&input, &options)?,
+        Command::Watch { input, options } => watch::run(input, options, config, opt.config)?,
     }
 
-    Ok(())
+    warnings::check(opt.fail_on == FailOn::Warning || opt.warnings_as_errors)
 }
@@ -1,35 +1,261 @@
-use std::{
-    collections::{HashMap, HashSet},
-    path::PathBuf,
-};
+use std::collections::HashMap;
 
+use anyhow::{Context, Result};
 use chrono::prelude::*;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 use tokei::{CodeStats, LanguageType};
 
+use crate::commit_type::CommitType;
+
+/// Statistics of a single commit. A stats file contains one entry per commit, ordered by commit
+/// time, with commits of the same timestamp in topological order (parents first).
+///
+/// Depending on the [`Detail`] the file was scanned with, only one of `files`, `languages` or
+/// `totals` is filled.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Entry {
     pub timestamp: DateTime<FixedOffset>,
-    pub files: HashMap<PathBuf, EntryFile>,
+    /// Files by their path relative to the repository root, always separated by `/`.
+    pub files: HashMap<String, EntryFile>,
+    /// Statistics of all files of each language.
+    pub languages: HashMap<LanguageType, Summary>,
+    /// Statistics of all files combined.
+    pub totals: Option<Summary>,
+    /// Total size of all tracked files in bytes, including files of unknown languages.
+    pub bytes: u64,
+    /// Whether files were left out, because the commit exceeded its time budget.
+    pub partial: bool,
+    /// Whether the commit couldn't be scanned at all. Such entries have no files and only keep
+    /// their place in the history.
+    pub failed: bool,
+    /// Remarks about files that were skipped or unusual changes in this commit.
+    pub notes: Vec<Note>,
+    /// Type of the commit by its Conventional Commits message. `None` for commits that couldn't
+    /// be scanned.
+    pub commit_type: Option<CommitType>,
+    /// Hash, author and message of the commit. `None` for commits that couldn't be read, and for
+    /// entries of [`LEGACY_VERSION`](crate::stats_file::LEGACY_VERSION) files.
+    pub commit: Option<CommitInfo>,
 }
 
-impl Entry {
-    pub fn filtered<'a>(
-        &'a self,
-        filter: &'a HashSet<LanguageType>,
-    ) -> impl Iterator<Item = &'a CodeStats> {
-        self.files.iter().filter_map(move |(_, v)| {
-            if filter.contains(&v.language) {
-                Some(&v.statistics)
-            } else {
-                None
-            }
-        })
-    }
+/// Identity of the commit that an entry was scanned from.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CommitInfo {
+    /// Full hash in hexadecimal.
+    pub id: String,
+    pub author: String,
+    pub email: String,
+    /// First line of the commit message.
+    pub summary: String,
+}
+
+/// Remark about an entry, that explains changes of its statistics that may look suspicious.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Note {
+    /// The file was skipped, because its size in bytes exceeded the limit.
+    TooLarge { path: String, size: u64 },
+    /// The file was skipped, because it couldn't be parsed as its language.
+    Unparseable { path: String },
+    /// The file was skipped, because it only points to an object in Git LFS.
+    LfsPointer { path: String },
+    /// The code lines grew by this amount at once, which usually means that third-party code
+    /// was imported.
+    Jump { lines: u64 },
 }
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct EntryFile {
     pub language: LanguageType,
     pub statistics: CodeStats,
+    /// Documentation of public API items, for Rust files scanned with `--api-docs`.
+    pub api_docs: Option<ApiDocs>,
+    /// Kinds of the comment lines, for files scanned with `--comment-quality`.
+    pub comment_kinds: Option<CommentKinds>,
+    /// Whether the file declares its license with an SPDX tag, for files scanned with `--spdx`.
+    pub licensed: Option<bool>,
+}
+
+/// Amount of public API items with and without documentation.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ApiDocs {
+    pub documented: u64,
+    pub undocumented: u64,
+}
+
+impl ApiDocs {
+    fn add(self, other: Self) -> Option<Self> {
+        Some(Self {
+            documented: self.documented.checked_add(other.documented)?,
+            undocumented: self.undocumented.checked_add(other.undocumented)?,
+        })
+    }
+}
+
+/// Comment lines split by what they contain, as told apart by heuristics.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CommentKinds {
+    /// Comment lines that look like natural language.
+    pub prose: u64,
+    /// Comment lines that look like commented-out code.
+    pub code: u64,
+}
+
+impl CommentKinds {
+    fn add(self, other: Self) -> Option<Self> {
+        Some(Self {
+            prose: self.prose.checked_add(other.prose)?,
+            code: self.code.checked_add(other.code)?,
+        })
+    }
+}
+
+/// Statistics of several files, summed up.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Summary {
+    /// Amount of files the statistics were collected from.
+    pub files: u64,
+    pub statistics: CodeStats,
+    /// Documentation of public API items, if any of the files had it analyzed.
+    pub api_docs: Option<ApiDocs>,
+    /// Kinds of the comment lines, if any of the files had them analyzed.
+    pub comment_kinds: Option<CommentKinds>,
+    /// Amount of files with an SPDX license tag, if any of the files were checked for it.
+    pub licensed: Option<u64>,
+}
+
+impl From<&EntryFile> for Summary {
+    fn from(file: &EntryFile) -> Self {
+        Self {
+            files: 1,
+            statistics: file.statistics.clone(),
+            api_docs: file.api_docs,
+            comment_kinds: file.comment_kinds,
+            licensed: file.licensed.map(u64::from),
+        }
+    }
+}
+
+impl Summary {
+    /// Add the statistics of another summary. Returns `None` on overflow, leaving this one as it
+    /// was.
+    fn add(&mut self, other: &Self) -> Option<()> {
+        let files = self.files.checked_add(other.files)?;
+        let api_docs = add_optional(self.api_docs, other.api_docs, ApiDocs::add)?;
+        let comment_kinds =
+            add_optional(self.comment_kinds, other.comment_kinds, CommentKinds::add)?;
+        let licensed = add_optional(self.licensed, other.licensed, u64::checked_add)?;
+        add_stats(&mut self.statistics, &other.statistics)?;
+
+        self.files = files;
+        self.api_docs = api_docs;
+        self.comment_kinds = comment_kinds;
+        self.licensed = licensed;
+
+        Some(())
+    }
+}
+
+/// Granularity of the statistics that are stored for each entry.
+/// Ordered from most to least detailed.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum,
+)]
+pub enum Detail {
+    /// Statistics of each single file.
+    #[default]
+    PerFile,
+    /// Statistics summed up per language.
+    PerLanguage,
+    /// Statistics of all languages combined.
+    TotalsOnly,
+}
+
+impl Entry {
+    /// Create a copy of this entry with its statistics summed up to the given detail. Entries
+    /// that are already less detailed are copied as they are.
+    pub fn reduce(&self, detail: Detail) -> Result<Self> {
+        let (languages, totals) = match detail {
+            Detail::PerFile => return Ok(self.clone()),
+            Detail::PerLanguage if self.totals.is_some() => (HashMap::new(), self.totals.clone()),
+            Detail::PerLanguage => (self.language_stats()?, None),
+            Detail::TotalsOnly => (HashMap::new(), Some(self.total_stats()?)),
+        };
+
+        Ok(Self {
+            timestamp: self.timestamp,
+            files: HashMap::new(),
+            languages,
+            totals,
+            bytes: self.bytes,
+            partial: self.partial,
+            failed: self.failed,
+            notes: self.notes.clone(),
+            commit_type: self.commit_type,
+            commit: self.commit.clone(),
+        })
+    }
+
+    /// Statistics per language, summed up from the files if needed. Empty if only the totals
+    /// are known.
+    pub fn language_stats(&self) -> Result<HashMap<LanguageType, Summary>> {
+        if self.files.is_empty() {
+            return Ok(self.languages.clone());
+        }
+
+        let mut languages = HashMap::<_, Summary>::new();
+        for file in self.files.values() {
+            languages
+                .entry(file.language)
+                .or_default()
+                .add(&Summary::from(file))
+                .with_context(|| self.overflow())?;
+        }
+
+        Ok(languages)
+    }
+
+    /// Statistics of all languages combined.
+    pub fn total_stats(&self) -> Result<Summary> {
+        if let Some(totals) = &self.totals {
+            return Ok(totals.clone());
+        }
+
+        let mut totals = Summary::default();
+        for summary in self.language_stats()?.values() {
+            totals.add(summary).with_context(|| self.overflow())?;
+        }
+
+        Ok(totals)
+    }
+
+    fn overflow(&self) -> String {
+        format!("line count overflow at {}", self.timestamp)
+    }
+}
+
+/// Add up optional statistics, which are only missing if neither side has them. The outer
+/// `None` means that the sum overflowed.
+fn add_optional<T>(
+    a: Option<T>,
+    b: Option<T>,
+    add: impl FnOnce(T, T) -> Option<T>,
+) -> Option<Option<T>> {
+    match (a, b) {
+        (Some(a), Some(b)) => add(a, b).map(Some),
+        (a, b) => Some(a.or(b)),
+    }
+}
+
+/// Add up line counts. Returns `None` on overflow, leaving the total as it was.
+fn add_stats(total: &mut CodeStats, stats: &CodeStats) -> Option<()> {
+    let code = total.code.checked_add(stats.code)?;
+    let comments = total.comments.checked_add(stats.comments)?;
+    let blanks = total.blanks.checked_add(stats.blanks)?;
+
+    total.code = code;
+    total.comments = comments;
+    total.blanks = blanks;
+
+    Some(())
 }
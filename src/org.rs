@@ -0,0 +1,370 @@
+//! Scans of all repositories of an organization, keeping clones and stats files in one directory
+//! so that repeated runs only fetch and scan what changed since the last one.
+//!
+//! The directory is laid out as follows:
+//!
+//! - `repos/<name>.git`: Mirror clones of the repositories that were given by URL.
+//! - `stats/<name>.stats`: Stats file of each repository, which can be served as is.
+//! - `combined.stats`: All repositories in a single stats file, with one history each. Render it
+//!   with `--group-by ref` to compare them.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use clap::{Args, ValueHint};
+use tempfile::TempDir;
+
+use crate::{
+    config::Config,
+    crypt,
+    exit::Failure,
+    scan,
+    stats_file::{self, ChunkWriter, History, Manifest, Staged, StatsFile, FORMAT_VERSION},
+};
+
+#[derive(Args)]
+#[group(skip)]
+pub struct Options {
+    /// GitHub organization whose repositories are scanned, except archived ones. They're listed
+    /// with the `gh` command line tool, which has to be installed and logged in.
+    #[arg(long, value_name = "ORG", required_unless_present = "repos")]
+    pub github_org: Option<String>,
+    /// File that lists the repositories to scan, one per line. Each line is either a local
+    /// repository or a URL to clone from. Empty lines and lines starting with `#` are ignored.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath, conflicts_with = "github_org")]
+    pub repos: Option<PathBuf>,
+    /// Directory that keeps the clones and stats files between runs.
+    #[arg(short, long, default_value = "org", value_hint = ValueHint::DirPath)]
+    pub dir: PathBuf,
+    #[command(flatten)]
+    pub scan: scan::Options,
+}
+
+/// Single repository of the organization.
+struct Repo {
+    name: String,
+    source: Source,
+}
+
+enum Source {
+    /// Repository on the local disk, that is scanned in place.
+    Local(PathBuf),
+    /// Repository that is cloned from a URL into the working directory.
+    Remote(String),
+}
+
+pub fn run(mut options: Options, config: &Config) -> Result<()> {
+    ensure!(
+        options.scan.graft.is_none(),
+        "grafts can't be used when scanning several repositories"
+    );
+    // Keeping the entries of earlier runs is the point of the working directory.
+    options.scan.update = true;
+
+    let repos = match (&options.github_org, &options.repos) {
+        (Some(org), _) => github_repos(org)?,
+        (None, Some(list)) => {
+            let content = fs::read_to_string(list)
+                .with_context(|| format!("failed reading {}", list.display()))?;
+            parse_list(&content)?
+        }
+        (None, None) => bail!("either --github-org or --repos is needed"),
+    };
+    ensure!(!repos.is_empty(), "no repositories found to scan");
+
+    let clones = options.dir.join("repos");
+    let stats = options.dir.join("stats");
+    for dir in [&clones, &stats] {
+        fs::create_dir_all(dir).with_context(|| format!("failed creating {}", dir.display()))?;
+    }
+
+    let mut scanned = Vec::with_capacity(repos.len());
+    let mut failed = Vec::new();
+
+    for (i, repo) in repos.iter().enumerate() {
+        println!("[{}/{}] {}", i + 1, repos.len(), repo.name);
+
+        let output = stats.join(format!("{}.stats", repo.name));
+        let result = checkout(repo, &clones)
+            .and_then(|path| scan::run(path, &output, &options.scan, config));
+
+        match result {
+            Ok(()) => scanned.push((repo.name.as_str(), output)),
+            Err(e) => {
+                eprintln!("failed scanning {}: {e:#}", repo.name);
+                failed.push(repo.name.as_str());
+            }
+        }
+    }
+
+    if !scanned.is_empty() {
+        println!("combining statistics...");
+
+        let name = options.github_org.clone().or_else(|| {
+            let dir = options.dir.canonicalize().ok()?;
+            Some(dir.file_name()?.to_str()?.to_owned())
+        });
+        combine(
+            &scanned,
+            &options.dir.join("combined.stats"),
+            name,
+            &options.scan,
+        )?;
+    }
+
+    if !failed.is_empty() {
+        let error = anyhow!(
+            "failed scanning {} of {} repositories: {}",
+            failed.len(),
+            repos.len(),
+            failed.join(", ")
+        );
+        // The combined statistics are still written if any repository succeeded.
+        return Err(error.context(if scanned.is_empty() {
+            Failure::Scan
+        } else {
+            Failure::Partial
+        }));
+    }
+
+    println!("done");
+
+    Ok(())
+}
+
+/// List the repositories of a GitHub organization.
+fn github_repos(org: &str) -> Result<Vec<Repo>> {
+    let output = Command::new("gh")
+        .args(["repo", "list", org, "--no-archived", "--limit", "10000"])
+        .args(["--json", "name,url", "--jq", ".[] | .name + \" \" + .url"])
+        .output()
+        .context("failed running gh, is it installed?")?;
+    ensure!(
+        output.status.success(),
+        "failed listing the repositories of {org}: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let mut repos = String::from_utf8(output.stdout)?
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(name, url)| Repo {
+            name: name.to_owned(),
+            source: Source::Remote(url.to_owned()),
+        })
+        .collect::<Vec<_>>();
+    repos.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(repos)
+}
+
+/// Parse a list of repositories. Lines that name an existing directory are local repositories,
+/// everything else is cloned.
+fn parse_list(content: &str) -> Result<Vec<Repo>> {
+    let mut names = HashSet::new();
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let source = if Path::new(line).is_dir() {
+                Source::Local(PathBuf::from(line))
+            } else {
+                Source::Remote(line.to_owned())
+            };
+            let name = repo_name(line).with_context(|| format!("invalid repository `{line}`"))?;
+            ensure!(
+                names.insert(name.clone()),
+                "more than one repository is named `{name}`"
+            );
+
+            Ok(Repo { name, source })
+        })
+        .collect()
+}
+
+/// Derive the name of a repository from the last part of its path or URL, like `commentstats`
+/// for `https://github.com/dnaka91/commentstats.git`.
+pub fn repo_name(location: &str) -> Option<String> {
+    let last = location
+        .trim_end_matches(['/', '\\'])
+        .rsplit(['/', '\\', ':'])
+        .next()?;
+    let name = last.strip_suffix(".git").unwrap_or(last);
+
+    (!name.is_empty() && name != "." && name != "..").then(|| name.to_owned())
+}
+
+/// Local copy of a repository that was given by path or URL. Clones in a temporary directory are
+/// removed again when this is dropped.
+pub struct Checkout {
+    pub path: PathBuf,
+    _temp: Option<TempDir>,
+}
+
+/// Make a repository given by path or URL available locally. Existing directories are used in
+/// place, everything else is cloned with `git`, so its SSH agent and credential helpers take
+/// care of authentication. Clones are kept in the cache directory and updated on later runs, or
+/// put into a temporary directory without one.
+pub fn local(location: &str, cache_dir: Option<&Path>) -> Result<Checkout> {
+    if Path::new(location).is_dir() {
+        return Ok(Checkout {
+            path: PathBuf::from(location),
+            _temp: None,
+        });
+    }
+
+    println!("cloning {location}...");
+
+    let temp = cache_dir.is_none().then(tempfile::tempdir).transpose()?;
+    let dir = cache_dir.unwrap_or_else(|| temp.as_ref().map_or(Path::new("."), TempDir::path));
+    fs::create_dir_all(dir).with_context(|| format!("failed creating {}", dir.display()))?;
+
+    // The stats file takes its name from the directory of the clone.
+    let name = repo_name(location).unwrap_or_else(|| "repo".to_owned());
+    let path = dir.join(format!("{name}.git"));
+    mirror(location, &path)?;
+
+    Ok(Checkout { path, _temp: temp })
+}
+
+/// Make the repository available locally, cloning it on the first run and fetching the latest
+/// changes on later ones.
+fn checkout(repo: &Repo, clones: &Path) -> Result<PathBuf> {
+    let url = match &repo.source {
+        Source::Local(path) => return Ok(path.clone()),
+        Source::Remote(url) => url,
+    };
+
+    let path = clones.join(format!("{}.git", repo.name));
+    mirror(url, &path)?;
+
+    Ok(path)
+}
+
+/// Clone a repository into `path` with the `git` command line tool, as bare mirror of all its
+/// references. If `path` exists already, it's updated instead.
+pub fn mirror(url: &str, path: &Path) -> Result<()> {
+    let mut git = Command::new("git");
+
+    if path.exists() {
+        git.arg("-C")
+            .arg(path)
+            .args(["fetch", "--prune", "--quiet"]);
+    } else {
+        git.args(["clone", "--mirror", "--quiet", url]).arg(path);
+    }
+
+    let status = git
+        .status()
+        .context("failed running git, is it installed?")?;
+    ensure!(status.success(), "git exited with {status}");
+
+    Ok(())
+}
+
+/// Put the first history of each stats file into a single file, naming each history after its
+/// repository. All files were scanned with the same options, so the metadata of the first one
+/// applies to all of them. The combined file is encrypted and signed like the others.
+fn combine(
+    inputs: &[(&str, PathBuf)],
+    output: &Path,
+    name: Option<String>,
+    options: &scan::Options,
+) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let mut chunks = Vec::new();
+    let mut histories = Vec::with_capacity(inputs.len());
+    let mut metadata = None;
+
+    for (repo, path) in inputs {
+        let file = StatsFile::open(path)?;
+        let Some((history, range)) = file.manifest().histories().into_iter().next() else {
+            continue;
+        };
+
+        histories.push(History {
+            reference: Some((*repo).to_owned()),
+            commit: history.and_then(|h| h.commit.clone()),
+            chunks: range.len(),
+        });
+
+        for index in range {
+            let count = file.manifest().chunks[index].entries;
+            let mut writer = ChunkWriter::create(dir.path(), chunks.len(), count)?;
+            file.read_chunk(index, |entry| writer.write(&entry))?;
+            chunks.push(writer.finish()?);
+        }
+
+        metadata.get_or_insert_with(|| file.manifest().metadata.clone());
+    }
+
+    let Some(mut metadata) = metadata else {
+        return Ok(());
+    };
+    metadata.name = name;
+    metadata.histories = histories;
+
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        entries: chunks.iter().map(|chunk| chunk.entries).sum(),
+        chunks,
+        metadata,
+    };
+
+    let mut staged = Staged::new(output)?;
+    if options.recipients.is_empty() {
+        stats_file::write(staged.path(), dir.path(), &manifest, || {})?;
+    } else {
+        let plain = dir.path().join("combined.plain");
+        stats_file::write(&plain, dir.path(), &manifest, || {})?;
+        crypt::encrypt(&plain, staged.path(), &options.recipients)?;
+    }
+
+    if let Some(key) = &options.sign {
+        staged.sign(key)?;
+    }
+    staged.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_derived() {
+        assert_eq!(
+            Some("commentstats"),
+            repo_name("https://github.com/dnaka91/commentstats.git").as_deref()
+        );
+        assert_eq!(
+            Some("tokei"),
+            repo_name("git@github.com:XAMPPRocky/tokei").as_deref()
+        );
+        assert_eq!(Some("project"), repo_name("../work/project/").as_deref());
+        assert_eq!(None, repo_name(".."));
+    }
+
+    #[test]
+    fn list_is_parsed() {
+        let repos = parse_list(
+            "# services\n\
+             https://example.com/a.git\n\
+             \n\
+             git@example.com:team/b\n",
+        )
+        .unwrap();
+
+        let names = repos.iter().map(|r| r.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["a", "b"], names);
+        assert!(matches!(repos[0].source, Source::Remote(_)));
+
+        assert!(parse_list("https://example.com/a\nhttps://example.org/a.git").is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Color palettes of the charts. The default palette is the one of the poloto themes, the others
+//! replace its colors and give each series its own dash pattern, so the series stay apart for
+//! colorblind readers and in grayscale printouts.
+
+use clap::ValueEnum;
+
+/// Dash patterns of the series, in the same order as the colors. The first series is drawn
+/// solid, as it's usually the most important one.
+const DASHES: [&str; 8] = [
+    "none",
+    "14 6",
+    "3 5",
+    "14 5 3 5",
+    "24 8",
+    "3 3",
+    "24 5 3 5 3 5",
+    "8 8",
+];
+
+/// Colors of the series in a chart.
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Palette {
+    /// Colors of the poloto theme, told apart by color only.
+    #[default]
+    Default,
+    /// Okabe-Ito colors, which stay distinct for all common kinds of color blindness.
+    Colorblind,
+    /// Dark, saturated colors and thicker lines.
+    HighContrast,
+    /// Shades of gray, for printing.
+    Grayscale,
+}
+
+impl Palette {
+    /// Colors of the series, repeating after the last one.
+    pub fn colors(self) -> Option<[&'static str; 8]> {
+        match self {
+            Self::Default => None,
+            Self::Colorblind => Some([
+                "#0072b2", "#d55e00", "#009e73", "#e69f00", "#56b4e9", "#cc79a7", "#000000",
+                "#f0e442",
+            ]),
+            Self::HighContrast => Some([
+                "#000000", "#c00000", "#0033cc", "#006600", "#7a3b00", "#6a0dad", "#b34700",
+                "#005f5f",
+            ]),
+            Self::Grayscale => Some([
+                "#000000", "#555555", "#888888", "#222222", "#666666", "#999999", "#333333",
+                "#777777",
+            ]),
+        }
+    }
+
+    /// Dash patterns of the series, in SVG syntax, for palettes that use them.
+    pub fn dashes(self) -> Option<[&'static str; 8]> {
+        self.colors().map(|_| DASHES)
+    }
+
+    /// CSS rules that override the colors of the poloto theme, to be added after it.
+    pub fn style(self) -> Option<String> {
+        let colors = self.colors()?;
+        let mut style = String::new();
+
+        if self == Self::HighContrast {
+            style.push_str(".poloto_background{fill:white;}\n.poloto_line{stroke-width:4}\n");
+        }
+
+        for (i, (color, dash)) in colors.iter().zip(DASHES).enumerate() {
+            style.push_str(&format!(
+                ".poloto{i}.poloto_stroke{{stroke:{color};}}\n\
+                 .poloto{i}.poloto_fill{{fill:{color};}}\n\
+                 .poloto_line.poloto{i}.poloto_stroke{{stroke-dasharray:{dash};}}\n"
+            ));
+        }
+
+        Some(style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn style_overrides_colors_and_dashes() {
+        assert!(Palette::Default.style().is_none());
+
+        let style = Palette::Colorblind.style().unwrap();
+        assert!(style.contains(".poloto0.poloto_stroke{stroke:#0072b2;}"));
+        assert!(style.contains(".poloto_line.poloto1.poloto_stroke{stroke-dasharray:14 6;}"));
+        assert!(!style.contains("poloto8"));
+    }
+}
@@ -0,0 +1,137 @@
+//! Support for partial clones, like the ones made with `git clone --filter=blob:none` in CI, which
+//! leave out the content of all files until it's needed.
+//!
+//! libgit2 can't fetch missing objects from the promisor remote on its own, so the blobs of all
+//! scanned commits are fetched up front with the `git` command line tool, in batches. If that
+//! fails, the scan goes on and skips the files whose content is missing.
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{ensure, Context, Result};
+use git2::{FileMode, Oid, Repository, Tree};
+
+/// Amount of objects to request from the remote with a single fetch.
+const BATCH_SIZE: usize = 10_000;
+
+/// Name of the remote that missing objects can be fetched from, if the repository is a partial
+/// clone.
+pub fn promisor(repo: &Repository) -> Option<String> {
+    let config = repo.config().ok()?.snapshot().ok()?;
+    if let Ok(remote) = config.get_string("extensions.partialclone") {
+        return Some(remote);
+    }
+
+    let entries = config.entries(Some(r"remote\..*\.promisor")).ok()?;
+    let mut remote = None;
+    entries
+        .for_each(|entry| {
+            if remote.is_none() && entry.value() == Some("true") {
+                remote = entry
+                    .name()
+                    .and_then(|name| name.strip_prefix("remote."))
+                    .and_then(|name| name.strip_suffix(".promisor"))
+                    .map(str::to_owned);
+            }
+        })
+        .ok()?;
+
+    remote
+}
+
+/// Fetch the blobs of the commits that are missing locally. Each history is compared commit by
+/// commit, starting with the full tree of its first commit, which covers every file of every
+/// scanned tree. Returns the amount of fetched objects.
+pub fn fetch_missing(
+    repo: &Repository,
+    path: &Path,
+    remote: &str,
+    histories: &[Vec<Oid>],
+) -> Result<usize> {
+    let odb = repo.odb()?;
+    let mut missing = HashSet::new();
+
+    for oids in histories {
+        let mut previous = None::<Tree<'_>>;
+        for &oid in oids {
+            let tree = repo.find_commit(oid)?.tree()?;
+            let diff = repo.diff_tree_to_tree(previous.as_ref(), Some(&tree), None)?;
+
+            for delta in diff.deltas() {
+                let file = delta.new_file();
+                // Submodules point to commits of other repositories, which the remote doesn't have.
+                if file.mode() == FileMode::Commit || file.id().is_zero() {
+                    continue;
+                }
+                if !odb.exists(file.id()) {
+                    missing.insert(file.id());
+                }
+            }
+
+            previous = Some(tree);
+        }
+    }
+
+    let missing = missing.into_iter().collect::<Vec<_>>();
+
+    for batch in missing.chunks(BATCH_SIZE) {
+        fetch(path, remote, batch)?;
+    }
+
+    Ok(missing.len())
+}
+
+/// Fetch exactly the given objects, the same way git does for its own lazy fetches.
+fn fetch(path: &Path, remote: &str, oids: &[Oid]) -> Result<()> {
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(path)
+        .args(["-c", "fetch.negotiationAlgorithm=noop", "fetch", remote])
+        .args([
+            "--no-tags",
+            "--no-write-fetch-head",
+            "--recurse-submodules=no",
+            "--filter=blob:none",
+            "--quiet",
+            "--stdin",
+        ])
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed running git, is it installed?")?;
+
+    let mut stdin = child.stdin.take().context("no input to git")?;
+    for oid in oids {
+        writeln!(stdin, "{oid}")?;
+    }
+    drop(stdin);
+
+    let status = child.wait()?;
+    ensure!(status.success(), "git exited with {status}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn promisor_remote_is_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        assert_eq!(None, promisor(&repo));
+
+        let mut config = repo.config().unwrap();
+        config.set_bool("remote.origin.promisor", true).unwrap();
+        assert_eq!(Some("origin".to_owned()), promisor(&repo));
+
+        config
+            .set_str("extensions.partialclone", "upstream")
+            .unwrap();
+        assert_eq!(Some("upstream".to_owned()), promisor(&repo));
+    }
+}
@@ -0,0 +1,264 @@
+//! Comparison of the comment density of several repositories against the spread of all of them,
+//! so each project can see where it stands within the organization.
+//!
+//! For each week, the 25th, 50th and 75th percentile of the density are taken over all
+//! repositories that existed by then, with the latest value of each repository on or before
+//! that week. The range between the outer percentiles is drawn as band, next to a line for each
+//! repository.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{NaiveDate, NaiveTime};
+use clap::Args;
+
+use crate::{
+    axis::YUnit,
+    chart::{self, Chart, Format, Shape},
+    config::Config,
+    legend::Placement,
+    palette::Palette,
+    stats_file::StatsFile,
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Output image width.
+    #[arg(long, default_value_t = 1600)]
+    pub width: u32,
+    /// Output image height.
+    #[arg(long, default_value_t = 1000)]
+    pub height: u32,
+    /// Location of the legend.
+    #[arg(long, value_enum, default_value_t = Placement::Right)]
+    pub legend: Placement,
+    /// Colors of the series.
+    #[arg(long, value_enum, default_value_t = Palette::Default)]
+    pub palette: Palette,
+    /// Output format. The chart is written to `stats.<extension>`.
+    #[arg(long, value_enum, default_value_t = Format::Svg)]
+    pub format: Format,
+}
+
+/// Comment density of a repository after each day with commits.
+struct Repository {
+    name: String,
+    points: Vec<(NaiveDate, f64)>,
+}
+
+impl Repository {
+    fn load(path: &Path) -> Result<Self> {
+        let file = StatsFile::open(path)?;
+        let name = file.manifest().metadata.name.clone().unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+
+        let mut points = Vec::<(NaiveDate, f64)>::new();
+        if let Some((_, range)) = file.manifest().histories().into_iter().next() {
+            for index in range {
+                file.read_chunk(index, |entry| {
+                    let stats = entry.total_stats()?.statistics;
+                    // Failed commits and ones without code have no meaningful density.
+                    if entry.failed || stats.code == 0 {
+                        return Ok(());
+                    }
+
+                    let date = entry.timestamp.date_naive();
+                    let density = stats.comments as f64 * 1000.0 / stats.code as f64;
+                    match points.last_mut() {
+                        Some(last) if last.0 == date => last.1 = density,
+                        _ => points.push((date, density)),
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+
+        ensure!(
+            !points.is_empty(),
+            "{} contains no entries with code",
+            path.display()
+        );
+
+        Ok(Self { name, points })
+    }
+
+    /// Latest value on or before the day, or `None` if the repository didn't exist yet.
+    fn at(&self, date: NaiveDate) -> Option<f64> {
+        let index = self.points.partition_point(|&(day, _)| day <= date);
+        index.checked_sub(1).map(|i| self.points[i].1)
+    }
+}
+
+pub fn run(inputs: &[PathBuf], output: &Path, options: &Options, config: &Config) -> Result<()> {
+    if inputs.len() < 2 {
+        bail!("comparing repositories needs at least two stats files");
+    }
+
+    println!("loading input data...");
+
+    let repos = inputs
+        .iter()
+        .map(|path| {
+            Repository::load(path).with_context(|| format!("failed loading {}", path.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Forks and clones of the same repository share its name, so tell them apart by their file.
+    let names = repos.iter().map(|repo| &repo.name).collect::<Vec<_>>();
+    let labels = repos
+        .iter()
+        .zip(inputs)
+        .map(|(repo, path)| {
+            let label = if names.iter().filter(|&&n| *n == repo.name).count() > 1 {
+                format!("{} ({})", repo.name, path.display())
+            } else {
+                repo.name.clone()
+            };
+            config.redact(&label).into_owned()
+        })
+        .collect::<Vec<_>>();
+
+    println!("rendering...");
+
+    let time = |date: NaiveDate| date.and_time(NaiveTime::default()).and_utc().timestamp();
+    let spread = spread(&repos);
+
+    let mut series = vec![
+        chart::Series {
+            label: "25th to 75th percentile".to_owned(),
+            shape: Shape::Area(vec![spread
+                .iter()
+                .map(|&(date, [low, _, high])| (time(date), low, high))
+                .collect()]),
+        },
+        chart::Series {
+            label: "Median".to_owned(),
+            shape: Shape::Dashed(
+                spread
+                    .iter()
+                    .map(|&(date, [_, median, _])| (time(date), median))
+                    .collect(),
+            ),
+        },
+    ];
+    series.extend(repos.iter().zip(labels).map(|(repo, label)| {
+        chart::Series {
+            label,
+            shape: Shape::Line(
+                repo.points
+                    .iter()
+                    .map(|&(date, value)| (time(date), value))
+                    .collect(),
+            ),
+        }
+    }));
+
+    let chart = Chart {
+        title: format!("Comment density of {} repositories", repos.len()),
+        x_label: "Date".to_owned(),
+        y_label: "Comments per 1000 lines of code".to_owned(),
+        y_unit: YUnit::Auto,
+        locale: None,
+        width: options.width,
+        height: options.height,
+        legend: options.legend,
+        palette: options.palette,
+        series,
+    };
+
+    fs::write(output, options.format.render(&chart)?)?;
+
+    println!("done");
+
+    Ok(())
+}
+
+/// 25th, 50th and 75th percentile of the repositories for each week from the first value of any
+/// of them to the last one, with the weeks starting at the first value.
+fn spread(repos: &[Repository]) -> Vec<(NaiveDate, [f64; 3])> {
+    let first = repos
+        .iter()
+        .filter_map(|r| r.points.first())
+        .map(|p| p.0)
+        .min();
+    let last = repos
+        .iter()
+        .filter_map(|r| r.points.last())
+        .map(|p| p.0)
+        .max();
+    let (Some(first), Some(last)) = (first, last) else {
+        return Vec::new();
+    };
+
+    let mut dates = first
+        .iter_days()
+        .step_by(7)
+        .take_while(|&date| date < last)
+        .collect::<Vec<_>>();
+    dates.push(last);
+
+    dates
+        .into_iter()
+        .filter_map(|date| {
+            let mut values = repos
+                .iter()
+                .filter_map(|repo| repo.at(date))
+                .collect::<Vec<_>>();
+            if values.is_empty() {
+                return None;
+            }
+            values.sort_unstable_by(f64::total_cmp);
+
+            Some((date, [0.25, 0.5, 0.75].map(|q| percentile(&values, q))))
+        })
+        .collect()
+}
+
+/// Percentile of the sorted values, interpolating linearly between the closest ones.
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    let position = (sorted.len() - 1) as f64 * q;
+    let (low, high) = (position.floor() as usize, position.ceil() as usize);
+    sorted[low] + (sorted[high] - sorted[low]) * (position - low as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Days;
+
+    use super::*;
+
+    fn date(day: u64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() + Days::new(day)
+    }
+
+    #[test]
+    fn percentiles_cover_existing_repositories() {
+        let repo = |name: &str, points: &[(u64, f64)]| Repository {
+            name: name.to_owned(),
+            points: points.iter().map(|&(day, v)| (date(day), v)).collect(),
+        };
+        let repos = [
+            repo("a", &[(0, 100.0), (10, 200.0)]),
+            repo("b", &[(3, 300.0)]),
+            repo("c", &[(8, 50.0), (14, 150.0)]),
+        ];
+
+        let spread = spread(&repos);
+        let dates = spread.iter().map(|&(d, _)| d).collect::<Vec<_>>();
+        assert_eq!(vec![date(0), date(7), date(14)], dates);
+
+        // Only the first repository existed at the start.
+        assert_eq!([100.0; 3], spread[0].1);
+        assert_eq!([150.0, 200.0, 250.0], spread[1].1);
+        assert_eq!([175.0, 200.0, 250.0], spread[2].1);
+
+        assert_eq!(2.5, percentile(&[1.0, 2.0, 3.0, 4.0], 0.5));
+        assert_eq!(7.0, percentile(&[7.0], 0.75));
+    }
+}
@@ -0,0 +1,309 @@
+//! Single command that goes from a repository URL to the finished artifacts, meant to be the
+//! entry point of a container in CI pipelines, like
+//! `docker run commentstats run --repo https://... --out-dir /artifacts`.
+//!
+//! The output directory receives:
+//!
+//! - `stats.stats`: The full statistics, to be rendered differently later.
+//! - `stats.svg`: The default chart, with a forecast of the next quarter.
+//! - `report.json`: Figures of the latest commit, for checks in later pipeline steps. With
+//!   `--baseline`, also the figures of the baseline and the changes since then, and if the
+//!   repository has a `CODEOWNERS` file, the figures of each owner. The `activity` section
+//!   breaks down the comment lines added by weekday and hour, see [`activity`], and the
+//!   `forecast` section holds the code and comment lines expected at the end of the next
+//!   quarter, see [`forecast`].
+//!
+//! With `--manifest`, the chart and report get a manifest next to them, see [`provenance`].
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueHint};
+use serde_json::{json, Value};
+
+use crate::{
+    activity::Activity, check::Totals, codeowners::CodeOwners, config::Config, exit::Failure,
+    forecast, languages::FilterArgs, models::Entry, org, provenance, render, scan,
+    stats_file::StatsFile,
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Repository to scan, as URL to clone from or path to a local repository.
+    #[arg(long)]
+    pub repo: String,
+    /// Directory to write the stats file, chart and report to.
+    #[arg(long, default_value = "artifacts", value_hint = ValueHint::DirPath)]
+    pub out_dir: PathBuf,
+    /// Write a manifest next to the chart and the report, with the tool version, a hash of the
+    /// stats file and the time of generation.
+    #[arg(long)]
+    pub manifest: bool,
+    /// Stats file to compare the report against, like the one of the last release. The report
+    /// then also contains the figures of the baseline and the changes since then.
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    pub baseline: Option<PathBuf>,
+}
+
+pub fn run(options: &Options, config: &Config) -> Result<()> {
+    let out = &options.out_dir;
+    fs::create_dir_all(out).with_context(|| format!("failed creating {}", out.display()))?;
+
+    let checkout = org::local(&options.repo, None).context(Failure::Clone)?;
+    let repo = checkout.path.clone();
+
+    let stats = out.join("stats.stats");
+    scan::run(repo.clone(), &stats, &scan::Options::default(), config).context(Failure::Scan)?;
+
+    render::run(
+        stats.clone(),
+        &out.join("stats.svg"),
+        &render::Options {
+            manifest: options.manifest,
+            forecast: Some(forecast::DEFAULT_DAYS),
+            ..render::Options::default()
+        },
+        config,
+    )
+    .context(Failure::Render)?;
+
+    let report_path = out.join("report.json");
+    CodeOwners::find(&repo)
+        .and_then(|owners| {
+            report(
+                &stats,
+                &report_path,
+                options.baseline.as_deref(),
+                owners.as_ref(),
+                config,
+            )
+        })
+        .and_then(|()| {
+            if !options.manifest {
+                return Ok(());
+            }
+            provenance::write(
+                &provenance::path(&report_path),
+                &report_path,
+                &stats,
+                render::filters(&FilterArgs::default(), None),
+            )
+        })
+        .context(Failure::Report)?;
+
+    println!("artifacts written to {}", out.display());
+
+    Ok(())
+}
+
+/// Write the figures of the latest commit as JSON.
+fn report(
+    stats: &Path,
+    output: &Path,
+    baseline: Option<&Path>,
+    owners: Option<&CodeOwners>,
+    config: &Config,
+) -> Result<()> {
+    let file = StatsFile::open(stats)?;
+    let Some(latest) = file.last_entry()? else {
+        bail!("the stats file contains no entries");
+    };
+
+    let ratio = |code: usize, comments: usize| {
+        if code == 0 {
+            0.0
+        } else {
+            comments as f64 / code as f64
+        }
+    };
+
+    let mut languages = latest
+        .language_stats()?
+        .into_iter()
+        .map(|(lang, summary)| {
+            let stats = &summary.statistics;
+            (lang, summary.files, stats.code, stats.comments)
+        })
+        .collect::<Vec<_>>();
+    languages.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.name().cmp(b.0.name())));
+
+    let totals = latest.total_stats()?;
+    let mut report = json!({
+        "name": file.manifest().metadata.name.as_deref().map(|name| config.redact(name)),
+        "commits": file.manifest().entries,
+        "latest": latest.timestamp,
+        "files": totals.files,
+        "code": totals.statistics.code,
+        "comments": totals.statistics.comments,
+        "blanks": totals.statistics.blanks,
+        "total": totals.statistics.lines(),
+        "comment_ratio": ratio(totals.statistics.code, totals.statistics.comments),
+        "languages": languages
+            .into_iter()
+            .map(|(lang, files, code, comments)| json!({
+                "name": lang.name(),
+                "files": files,
+                "code": code,
+                "comments": comments,
+                "comment_ratio": ratio(code, comments),
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    report["activity"] = Activity::load(&file)?.to_json();
+    report["forecast"] = forecast::report(&file, forecast::DEFAULT_DAYS)?;
+
+    if let Some(rules) = owners {
+        report["owners"] = owner_report(&latest, rules);
+    }
+
+    if let Some(path) = baseline {
+        let baseline = Totals::latest(path)?;
+        let current = Totals {
+            code: totals.statistics.code,
+            comments: totals.statistics.comments,
+        };
+
+        report["baseline"] = json!({
+            "code": baseline.code,
+            "comments": baseline.comments,
+            "comment_ratio": baseline.ratio(),
+        });
+        report["delta"] = current.delta(baseline);
+    }
+
+    fs::write(output, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("failed writing {}", output.display()))
+}
+
+/// Figures of each owner, with the unowned files under a `null` name. Files with several owners
+/// count for each of them.
+fn owner_report(latest: &Entry, rules: &CodeOwners) -> Value {
+    let mut owners = BTreeMap::<Option<&str>, (u64, Totals)>::new();
+
+    for (path, file) in &latest.files {
+        let names = rules.owners(path);
+        let names = if names.is_empty() {
+            vec![None]
+        } else {
+            names.iter().map(|name| Some(name.as_str())).collect()
+        };
+
+        for name in names {
+            let (files, totals) = owners.entry(name).or_default();
+            *files += 1;
+            totals.code += file.statistics.code;
+            totals.comments += file.statistics.comments;
+        }
+    }
+
+    owners
+        .into_iter()
+        .map(|(name, (files, totals))| {
+            json!({
+                "name": name,
+                "files": files,
+                "code": totals.code,
+                "comments": totals.comments,
+                "comment_ratio": totals.ratio(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::ExitCode;
+
+    use git2::{FileMode, Repository, Signature, Time};
+
+    use super::*;
+    use crate::exit;
+
+    /// Run the pipeline for the repository, writing the artifacts below `dir`.
+    fn run_in(dir: &Path, repo: &Path) -> Result<()> {
+        let options = Options {
+            repo: repo.to_string_lossy().into_owned(),
+            out_dir: dir.join("artifacts"),
+            manifest: false,
+            baseline: None,
+        };
+        run(&options, &Config::default())
+    }
+
+    #[test]
+    fn failures_get_their_exit_code() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Paths that aren't directories are cloned, which fails for missing ones.
+        let error = run_in(dir.path(), &dir.path().join("missing")).unwrap_err();
+        assert_eq!(ExitCode::from(4), exit::code(&error));
+
+        // Directories are scanned in place, which fails if they aren't a repository.
+        let plain = dir.path().join("plain");
+        fs::create_dir(&plain).unwrap();
+        let error = run_in(dir.path(), &plain).unwrap_err();
+        assert_eq!(ExitCode::from(5), exit::code(&error));
+    }
+
+    /// Commit the file as the whole content of the repository, a day after the previous commit.
+    fn commit(repo: &Repository, content: &str) {
+        let blob = repo.blob(content.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("main.rs", blob, FileMode::Blob.into()).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let time = parent
+            .as_ref()
+            .map_or(1_700_000_000, |parent| parent.time().seconds() + 86_400);
+        let sig = Signature::new("Jane Doe", "jane@example.com", &Time::new(time, 0)).unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            "commit",
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn artifacts_are_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        // Each day adds two lines of code and one comment.
+        for day in 1..=4 {
+            commit(&repo, &"// A comment.\nfn main() {\n}\n".repeat(day));
+        }
+
+        run_in(dir.path(), repo.path()).unwrap();
+
+        let out = dir.path().join("artifacts");
+        assert!(out.join("stats.stats").is_file());
+        assert!(out.join("stats.svg").is_file());
+
+        let report = fs::read_to_string(out.join("report.json")).unwrap();
+        let report = serde_json::from_str::<Value>(&report).unwrap();
+        assert_eq!(4, report["commits"]);
+        assert_eq!(8, report["code"]);
+        assert_eq!(4, report["comments"]);
+        assert_eq!("Rust", report["languages"][0]["name"]);
+
+        // The same trend continues until the end of the next quarter.
+        let forecast = &report["forecast"];
+        assert_eq!(forecast::DEFAULT_DAYS, forecast["days"]);
+        assert_eq!("2024-02-16", forecast["date"]);
+        assert_eq!(8.0 + 2.0 * 91.0, forecast["code"]["value"]);
+        assert_eq!(4.0 + 91.0, forecast["comments"]["value"]);
+        for kind in ["code", "comments"] {
+            let value = |name: &str| forecast[kind][name].as_f64().unwrap();
+            assert!(value("lower") <= value("value") && value("value") <= value("upper"));
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Intermediate charts during long scans, to check early on that the filters and the output look
+//! right, instead of finding a mistake after hours.
+//!
+//! Whenever enough commits were scanned since the last preview, the chunks that are complete by
+//! then are bundled into a temporary stats file and rendered with the default options. Chunks are
+//! scanned in parallel, so a preview can have gaps where the chunks in between are still running.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+
+use anyhow::Result;
+
+use crate::{
+    config::Config,
+    render,
+    stats_file::{self, ChunkInfo, Manifest, Metadata, FORMAT_VERSION},
+};
+
+/// Name of the temporary stats file that previews are rendered from.
+const PREVIEW_NAME: &str = "preview.stats";
+
+pub struct Preview<'a> {
+    /// Commits to scan between two previews.
+    every: u64,
+    /// Temporary directory that the chunks are written to.
+    dir: PathBuf,
+    /// Location of the chart.
+    output: PathBuf,
+    config: &'a Config,
+    /// Metadata of the scan, whose histories are filled with the finished chunks.
+    metadata: Metadata,
+    state: Mutex<State>,
+    /// Held while rendering, so previews that come due in the meantime are skipped instead of
+    /// piling up.
+    rendering: Mutex<()>,
+}
+
+struct State {
+    /// Finished chunks of each history, by their index.
+    chunks: Vec<BTreeMap<usize, ChunkInfo>>,
+    /// Commits scanned so far.
+    scanned: u64,
+    /// Amount of scanned commits at which the next preview is rendered.
+    due: u64,
+}
+
+impl<'a> Preview<'a> {
+    /// Create the previews for a scan whose chunks are written to `dir`. The `grafts` are the
+    /// chunks that are put in front of each history, which are complete from the start.
+    pub fn new(
+        every: u64,
+        dir: &Path,
+        output: &Path,
+        config: &'a Config,
+        metadata: Metadata,
+        grafts: &[Vec<ChunkInfo>],
+    ) -> Self {
+        let mut index = 0;
+        let chunks = (0..metadata.histories.len())
+            .map(|history| {
+                grafts
+                    .get(history)
+                    .into_iter()
+                    .flatten()
+                    .map(|chunk| {
+                        index += 1;
+                        (index - 1, chunk.clone())
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            every,
+            dir: dir.to_owned(),
+            output: output.to_owned(),
+            config,
+            metadata,
+            state: Mutex::new(State {
+                chunks,
+                scanned: 0,
+                due: every,
+            }),
+            rendering: Mutex::new(()),
+        }
+    }
+
+    /// Location of the preview chart for the given output, named like it with `.preview.svg`.
+    pub fn path(output: &Path) -> PathBuf {
+        let extension = render::Options::default().extension().to_owned();
+        output.with_extension(format!("preview.{extension}"))
+    }
+
+    /// Record a finished chunk of a history with the amount of commits in it, rendering a new
+    /// preview if one is due.
+    pub fn add(&self, history: usize, index: usize, chunk: &ChunkInfo, commits: u64) -> Result<()> {
+        let manifest = {
+            let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(chunks) = state.chunks.get_mut(history) {
+                chunks.insert(index, chunk.clone());
+            }
+
+            state.scanned += commits;
+            if state.scanned < state.due {
+                return Ok(());
+            }
+            state.due = state.scanned + self.every;
+
+            self.manifest(&state)
+        };
+
+        let Ok(_rendering) = self.rendering.try_lock() else {
+            return Ok(());
+        };
+
+        let path = self.dir.join(PREVIEW_NAME);
+        stats_file::write(&path, &self.dir, &manifest, || {})?;
+
+        let options = render::Options::default();
+        let chart = render::chart(path, &options, self.config)?;
+        fs::write(&self.output, options.format.render(&chart)?)?;
+
+        Ok(())
+    }
+
+    /// Manifest of the chunks finished so far, in the order of their histories.
+    fn manifest(&self, state: &State) -> Manifest {
+        let mut metadata = self.metadata.clone();
+        for (history, chunks) in metadata.histories.iter_mut().zip(&state.chunks) {
+            history.chunks = chunks.len();
+        }
+
+        let chunks = state
+            .chunks
+            .iter()
+            .flat_map(BTreeMap::values)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        Manifest {
+            version: FORMAT_VERSION,
+            entries: chunks.iter().map(|chunk| chunk.entries).sum(),
+            chunks,
+            metadata,
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use tokei::LanguageType;
+
+/// Step of the scan that time is recorded for.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Diffing the tree of a commit against its base.
+    Diff,
+    /// Loading blobs from the object database.
+    Load,
+    /// Parsing blobs with tokei.
+    Parse,
+    /// Encoding entries into chunks.
+    Encode,
+}
+
+impl Phase {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Diff => "diff",
+            Self::Load => "load",
+            Self::Parse => "parse",
+            Self::Encode => "encode",
+        }
+    }
+}
+
+/// Time spent in each phase of a scan, further split by language where it applies. Times are
+/// summed up over all workers, so they describe the total work rather than the wall-clock time.
+#[derive(Default)]
+pub struct Profile {
+    times: Mutex<BTreeMap<(Phase, Option<LanguageType>), Duration>>,
+}
+
+impl Profile {
+    /// Run `f` and add its duration to the given phase.
+    pub fn time<T>(
+        &self,
+        phase: Phase,
+        language: Option<LanguageType>,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let start = Instant::now();
+        let value = f();
+        let elapsed = start.elapsed();
+
+        *self
+            .times
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry((phase, language))
+            .or_default() += elapsed;
+
+        value
+    }
+
+    /// Write the profile in the [speedscope](https://www.speedscope.app) file format, as a sampled
+    /// profile with one stack per phase and language. Besides speedscope itself, the format can be
+    /// loaded by most flame graph viewers.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let times = self.times.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut frames = Vec::<String>::new();
+        let mut frame = |name: &str| match frames.iter().position(|f| f == name) {
+            Some(index) => index,
+            None => {
+                frames.push(name.to_owned());
+                frames.len() - 1
+            }
+        };
+
+        let mut samples = Vec::with_capacity(times.len());
+        let mut weights = Vec::with_capacity(times.len());
+        for (&(phase, language), time) in times.iter() {
+            let mut stack = vec![frame(phase.name())];
+            if let Some(language) = language {
+                stack.push(frame(language.name()));
+            }
+
+            samples.push(stack);
+            weights.push(time.as_nanos() as u64);
+        }
+
+        let total = weights.iter().sum::<u64>();
+        let profile = json!({
+            "$schema": "https://www.speedscope.app/file-format-schema.json",
+            "exporter": concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION")),
+            "name": "scan",
+            "shared": {
+                "frames": frames.iter().map(|name| json!({ "name": name })).collect::<Vec<_>>(),
+            },
+            "profiles": [{
+                "type": "sampled",
+                "name": "scan",
+                "unit": "nanoseconds",
+                "startValue": 0,
+                "endValue": total,
+                "samples": samples,
+                "weights": weights,
+            }],
+        });
+
+        let file = File::create(path)
+            .with_context(|| format!("failed creating profile at {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &profile)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+}
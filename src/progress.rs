@@ -1,6 +1,6 @@
 use std::{
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
     thread::{self, JoinHandle},
@@ -12,18 +12,22 @@ use pbr::ProgressBar;
 
 pub struct Progress {
     handle: JoinHandle<()>,
+    done: Arc<AtomicBool>,
 }
 
 impl Progress {
     pub fn new(total: u64) -> (Self, Updater) {
         let progress = Arc::new(AtomicU64::new(0));
         let progress2 = Arc::clone(&progress);
+        let done = Arc::new(AtomicBool::new(false));
+        let done2 = Arc::clone(&done);
         let mut pb = ProgressBar::new(total);
         pb.set_width(Some(80));
 
         let handle = thread::spawn(move || loop {
             let p = progress2.load(Ordering::Relaxed);
-            if p >= total {
+            if p >= total || done2.load(Ordering::Relaxed) {
+                pb.set(p);
                 pb.finish();
                 println!();
                 break;
@@ -33,17 +37,20 @@ impl Progress {
             thread::sleep(Duration::from_millis(200));
         });
 
-        (Self { handle }, Updater { progress })
+        (Self { handle, done }, Updater { progress })
     }
 
+    /// Stop the progress printer and wait for it to exit. If the work was aborted early, the
+    /// progress is finished at its current state.
     pub fn wait(self) -> Result<()> {
+        self.done.store(true, Ordering::Relaxed);
         self.handle
             .join()
             .map_err(|_| anyhow!("failed joining progress printer thread"))
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Updater {
     progress: Arc<AtomicU64>,
 }
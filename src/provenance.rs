@@ -0,0 +1,53 @@
+//! Manifests that describe how an exported artifact was generated, so archived charts and
+//! reports stay auditable and can be reproduced later.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// Location of the manifest of an artifact, next to it with an additional `.manifest.json`
+/// extension.
+pub fn path(artifact: &Path) -> PathBuf {
+    let mut path = OsString::from(artifact.as_os_str());
+    path.push(".manifest.json");
+    path.into()
+}
+
+/// Write the manifest of an artifact that was generated from the stats file. The `filters`
+/// describe which part of the data the artifact shows.
+pub fn write(output: &Path, artifact: &Path, stats: &Path, filters: Value) -> Result<()> {
+    let mut hasher = Sha256::new();
+    let mut file =
+        File::open(stats).with_context(|| format!("failed opening {}", stats.display()))?;
+    io::copy(&mut file, &mut hasher)?;
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+
+    let manifest = json!({
+        "tool": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "generated": Utc::now(),
+        "artifact": artifact.file_name().map(|name| name.to_string_lossy()),
+        "command": env::args().collect::<Vec<_>>(),
+        "stats_file": {
+            "name": stats.file_name().map(|name| name.to_string_lossy()),
+            "sha256": hash,
+        },
+        "filters": filters,
+    });
+
+    let content = serde_json::to_string_pretty(&manifest)?;
+    fs::write(output, content).with_context(|| format!("failed writing {}", output.display()))
+}
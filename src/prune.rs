@@ -0,0 +1,252 @@
+//! Thinning of old entries, to keep the stats files of repositories that are scanned continuously
+//! for years at a bounded size.
+//!
+//! The rules work like the retention policies of backup tools: each one keeps the latest entry
+//! of the given amount of most recent days, weeks or months that have any entries. An entry is
+//! kept if any of the rules keeps it, all others are removed.
+
+use std::{collections::HashSet, path::Path, str::FromStr};
+
+use anyhow::{ensure, Result};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Weekday};
+use clap::Args;
+use rayon::prelude::*;
+
+use crate::stats_file::{self, ChunkWriter, History, Manifest, Staged, StatsFile, FORMAT_VERSION};
+
+#[derive(Args)]
+pub struct Options {
+    /// Keep the latest entry of this many days, or `all`.
+    #[arg(long, value_name = "N")]
+    pub keep_daily: Option<Keep>,
+    /// Keep the latest entry of this many weeks, or `all`. Weeks start on Monday.
+    #[arg(long, value_name = "N")]
+    pub keep_weekly: Option<Keep>,
+    /// Keep the latest entry of this many months, or `all`.
+    #[arg(long, value_name = "N")]
+    pub keep_monthly: Option<Keep>,
+}
+
+/// Calendar period that entries are grouped by.
+#[derive(Clone, Copy)]
+pub enum Period {
+    Day,
+    /// Weeks starting on Monday.
+    Week,
+    Month,
+}
+
+impl Period {
+    /// First day of the period that the day belongs to.
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date.week(Weekday::Mon).first_day(),
+            Self::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+/// Amount of periods to keep an entry for.
+#[derive(Clone, Copy)]
+pub enum Keep {
+    Last(usize),
+    All,
+}
+
+impl FromStr for Keep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::All);
+        }
+
+        s.parse()
+            .map(Self::Last)
+            .map_err(|e| format!("expected a number or `all`: {e}"))
+    }
+}
+
+impl Keep {
+    fn allows(self, count: usize) -> bool {
+        match self {
+            Self::Last(limit) => count < limit,
+            Self::All => true,
+        }
+    }
+}
+
+pub fn run(input: &Path, output: &Path, options: &Options) -> Result<()> {
+    let rules = [
+        (options.keep_daily, Period::Day),
+        (options.keep_weekly, Period::Week),
+        (options.keep_monthly, Period::Month),
+    ]
+    .into_iter()
+    .filter_map(|(keep, period)| Some((keep?, period)))
+    .collect::<Vec<_>>();
+    ensure!(
+        !rules.is_empty(),
+        "at least one of --keep-daily, --keep-weekly and --keep-monthly is needed"
+    );
+
+    let file = StatsFile::open(input)?;
+
+    println!("selecting entries...");
+
+    let histories = file.manifest().histories();
+    let mut kept = HashSet::new();
+
+    for (_, range) in &histories {
+        let mut timestamps = Vec::new();
+        for index in range.clone() {
+            let mut position = 0;
+            file.read_chunk(index, |entry| {
+                // Failed entries have no data, so they are never worth keeping.
+                if !entry.failed {
+                    timestamps.push(((index, position), entry.timestamp));
+                }
+                position += 1;
+                Ok(())
+            })?;
+        }
+
+        kept.extend(select(&timestamps, &rules));
+    }
+
+    println!(
+        "removing {} entries...",
+        file.manifest().entries - kept.len() as u64
+    );
+
+    let dir = tempfile::tempdir()?;
+    let kept = &kept;
+    let mut chunks = (0..file.manifest().chunks.len())
+        .into_par_iter()
+        .map(|index| {
+            let count = (0..file.manifest().chunks[index].entries as usize)
+                .filter(|&position| kept.contains(&(index, position)))
+                .count();
+            let mut writer = ChunkWriter::create(dir.path(), index, count as u64)?;
+
+            let mut position = 0;
+            file.read_chunk(index, |entry| {
+                if kept.contains(&(index, position)) {
+                    writer.write(&entry)?;
+                }
+                position += 1;
+                Ok(())
+            })?;
+
+            writer.finish()
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter();
+
+    // Chunks that lost all their entries are left out, and the histories shrink accordingly.
+    let mut metadata = file.manifest().metadata.clone();
+    let mut kept_chunks = Vec::new();
+    let mut new_histories = Vec::with_capacity(histories.len());
+
+    for (history, range) in &histories {
+        let before = kept_chunks.len();
+        kept_chunks.extend(
+            chunks
+                .by_ref()
+                .take(range.len())
+                .filter(|chunk| chunk.entries > 0),
+        );
+
+        if let Some(history) = history {
+            new_histories.push(History {
+                chunks: kept_chunks.len() - before,
+                ..(*history).clone()
+            });
+        }
+    }
+    metadata.histories = new_histories;
+
+    println!("saving statistics...");
+
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        entries: kept_chunks.iter().map(|chunk| chunk.entries).sum(),
+        chunks: kept_chunks,
+        metadata,
+    };
+
+    let staged = Staged::new(output)?;
+    stats_file::write(staged.path(), dir.path(), &manifest, || {})?;
+    staged.commit()?;
+
+    println!("done");
+
+    Ok(())
+}
+
+/// Select the entries to keep from a single history, ordered by time. Each rule keeps the latest
+/// entry of its most recent periods.
+fn select<K: Copy>(entries: &[(K, DateTime<FixedOffset>)], rules: &[(Keep, Period)]) -> Vec<K> {
+    let mut kept = Vec::new();
+    let mut state = vec![(None, 0); rules.len()];
+
+    for &(key, timestamp) in entries.iter().rev() {
+        let date = timestamp.date_naive();
+        let mut keep = false;
+
+        for ((limit, period), (last, count)) in rules.iter().zip(&mut state) {
+            let current = period.start(date);
+            if *last != Some(current) && limit.allows(*count) {
+                *last = Some(current);
+                *count += 1;
+                keep = true;
+            }
+        }
+
+        if keep {
+            kept.push(key);
+        }
+    }
+
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn latest_entry_of_each_period_is_kept() {
+        let offset = FixedOffset::east_opt(0).unwrap();
+        let entries = [
+            (2024, 1, 10, 9),
+            (2024, 1, 10, 17),
+            (2024, 2, 5, 12),
+            (2024, 2, 20, 8),
+            (2024, 2, 20, 9),
+            (2024, 2, 21, 9),
+            (2024, 2, 22, 9),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (y, m, d, h))| {
+            let time = offset.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+            (i, time)
+        })
+        .collect::<Vec<_>>();
+
+        let daily = [(Keep::Last(2), Period::Day)];
+        assert_eq!(vec![6, 5], select(&entries, &daily));
+
+        let monthly = [(Keep::All, Period::Month)];
+        assert_eq!(vec![6, 1], select(&entries, &monthly));
+
+        let combined = [(Keep::Last(1), Period::Day), (Keep::Last(3), Period::Week)];
+        // 2024-02-20 to 22 share a week, so the weekly rule keeps 6 (also the latest day), then 2
+        // and 1.
+        assert_eq!(vec![6, 2, 1], select(&entries, &combined));
+    }
+}
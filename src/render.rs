@@ -1,125 +1,2052 @@
 use std::{
-    collections::HashSet,
-    fs::{self, File},
-    io::BufReader,
-    path::PathBuf,
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    str::FromStr,
 };
 
-use anyhow::Result;
-use chrono::{NaiveDate, NaiveTime};
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime};
+use clap::{Args, ValueEnum, ValueHint};
 use poloto_chrono::UnixTime;
 use rayon::prelude::*;
+use serde_json::{json, Value};
 use tokei::LanguageType;
-use zip::ZipArchive;
-use zstd::Decoder as ZstdDecoder;
 
-use crate::{models::Entry, progress::Progress};
+use crate::{
+    axis::{Locale, YUnit},
+    changepoints,
+    chart::{self, Chart, Format, Shape},
+    codeowners::CodeOwners,
+    commit_type::CommitType,
+    config::Config,
+    forecast::Trend,
+    interrupt,
+    languages::FilterArgs,
+    legend::{self, Placement, Template},
+    models::{Detail, Summary},
+    palette::Palette,
+    progress::{Progress, Updater},
+    provenance,
+    prune::Period,
+    stats_file::{History, Metadata, StatsFile, LEGACY_VERSION},
+    warnings::{Category, Warnings},
+};
+
+/// Default for [`Options::min_share`], which keeps all languages.
+const DEFAULT_MIN_SHARE: Share = Share(0.0);
+
+#[derive(Args)]
+pub struct Options {
+    /// Output image width.
+    #[arg(long, default_value_t = 1600)]
+    pub width: u32,
+    /// Output image height.
+    #[arg(long, default_value_t = 1000)]
+    pub height: u32,
+    #[command(flatten)]
+    pub filter: FilterArgs,
+    /// Value to plot over time.
+    #[arg(long, value_enum, default_value_t = Metric::Lines)]
+    pub metric: Metric,
+    /// Split the chart into separate series.
+    #[arg(long, value_enum, default_value_t = GroupBy::None)]
+    pub group_by: GroupBy,
+    /// `CODEOWNERS` file for `--group-by owner`, or a Git repository to read it from at `HEAD`.
+    #[arg(long, value_hint = ValueHint::AnyPath)]
+    pub codeowners: Option<PathBuf>,
+    /// When grouping by language, collapse languages with less than this share of all code lines
+    /// over the whole history into a single "Other" series. Given in percent, like `1%`.
+    #[arg(long, default_value_t = DEFAULT_MIN_SHARE)]
+    pub min_share: Share,
+    /// Location of the legend.
+    #[arg(long, value_enum, default_value_t = Placement::Right)]
+    pub legend: Placement,
+    /// Colors of the series. All palettes but the default one also draw each series with its own
+    /// dash pattern.
+    #[arg(long, value_enum, default_value_t = Palette::Default)]
+    pub palette: Palette,
+    /// Template for the series labels, like `{language} {kind} ({latest} lines)`. Available
+    /// placeholders are `{language}`, `{ref}`, `{kind}`, `{latest}` and `{peak}`.
+    #[arg(long, value_parser = Template::parse)]
+    pub label: Option<Template>,
+    /// Chart title. Defaults to the repository name, scanned reference and date range if the
+    /// stats file contains that information.
+    #[arg(long)]
+    pub title: Option<String>,
+    /// Mark commits with notes from the scan, like skipped files or sudden jumps of the code
+    /// lines, which explain suspicious changes in the chart.
+    #[arg(long)]
+    pub notes: bool,
+    /// How to fill days without any commits.
+    #[arg(long, value_enum, default_value_t = Fill::None)]
+    pub fill: Fill,
+    /// Combine the values within each period into their mean, to smooth out the chart.
+    #[arg(long, value_enum, default_value_t = Bucket::None)]
+    pub bucket: Bucket,
+    /// Draw a shaded band from the minimum to the maximum value within each period around the
+    /// mean, so the smoothing doesn't hide how much the values changed.
+    #[arg(long, requires = "bucket")]
+    pub band: bool,
+    /// How to label the values on the y-axis. Separators follow `--locale`.
+    #[arg(long, value_enum, default_value_t = YUnit::Auto)]
+    pub y_unit: YUnit,
+    /// Locale for the number separators and date formats of the title and axes, like `de-DE`.
+    /// Defaults to the locale from the `LC_ALL`, `LC_NUMERIC`, `LC_TIME` or `LANG` environment
+    /// variables, while Vega-Lite specs then follow the locale they are displayed with.
+    #[arg(long)]
+    pub locale: Option<Locale>,
+    /// Which line counts to plot for the `lines` metric. Plotting only one of them keeps charts
+    /// grouped by language readable.
+    #[arg(long, value_enum, default_value_t = SeriesSelection::Both)]
+    pub series: SeriesSelection,
+    /// Shade the periods in which the share of comments declined for more than this many
+    /// consecutive weeks, to point out the parts of the history that need an explanation.
+    #[arg(long, value_name = "WEEKS")]
+    pub regressions: Option<usize>,
+    /// Continue the code and comment lines for this many days with a dashed line along their
+    /// trend of the last year, like `91` for the next quarter, and shade the range in which the
+    /// values are expected to end up.
+    #[arg(long, value_name = "DAYS")]
+    pub forecast: Option<u32>,
+    /// Split the code and comment lines into periods with distinct trends, drawing the trend of
+    /// each period as dashed line and marking where a new one starts.
+    #[arg(long)]
+    pub changepoints: bool,
+    /// Output format. The chart is written to `stats.<extension>`.
+    #[arg(long, value_enum, default_value_t = Format::Svg)]
+    pub format: Format,
+    /// Tera template to render instead of a plain chart, like a branded HTML report. The output
+    /// gets the extension of the template.
+    #[arg(long, conflicts_with = "format", value_hint = ValueHint::FilePath)]
+    pub template: Option<PathBuf>,
+    /// Only count the files below this directory, relative to the repository root, like
+    /// `src/server`. Needs a stats file with statistics per file.
+    #[arg(long)]
+    pub path: Option<String>,
+    /// Write a manifest next to the output, with the tool version, a hash of the stats file,
+    /// the filters and the time of generation, so the chart can be traced back to its data.
+    #[arg(long)]
+    pub manifest: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            width: 1600,
+            height: 1000,
+            filter: FilterArgs::default(),
+            metric: Metric::Lines,
+            group_by: GroupBy::None,
+            codeowners: None,
+            min_share: DEFAULT_MIN_SHARE,
+            legend: Placement::Right,
+            palette: Palette::Default,
+            label: None,
+            title: None,
+            notes: false,
+            fill: Fill::None,
+            bucket: Bucket::None,
+            band: false,
+            y_unit: YUnit::Auto,
+            locale: None,
+            series: SeriesSelection::Both,
+            regressions: None,
+            forecast: None,
+            changepoints: false,
+            format: Format::Svg,
+            template: None,
+            path: None,
+            manifest: false,
+        }
+    }
+}
+
+impl Options {
+    /// File extension of the output.
+    pub fn extension(&self) -> &str {
+        match &self.template {
+            Some(template) => template
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or("txt"),
+            None => self.format.extension(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Metric {
+    /// Code and comment lines of the selected languages.
+    Lines,
+    /// Total size of all tracked files, regardless of their language.
+    Bytes,
+    /// Comment lines per 1000 code lines of the selected languages, which can be compared
+    /// between repositories of different size.
+    Density,
+    /// Amount of files of the selected languages.
+    FileCount,
+    /// Documented and undocumented public API items of Rust files, for stats files scanned
+    /// with `--api-docs`.
+    ApiDocs,
+    /// Comment lines of the selected languages, split into natural language and commented-out
+    /// code, for stats files scanned with `--comment-quality`.
+    CommentQuality,
+    /// Files of the selected languages with an SPDX license tag, next to the amount of all
+    /// files, for stats files scanned with `--spdx`.
+    Spdx,
+}
+
+impl Metric {
+    fn unit(self) -> &'static str {
+        match self {
+            Self::Lines => "Lines",
+            Self::Bytes => "Bytes",
+            Self::Density => "Comments per 1000 lines of code",
+            Self::FileCount => "Files",
+            Self::ApiDocs => "Public items",
+            Self::CommentQuality => "Comment lines",
+            Self::Spdx => "Files",
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SeriesSelection {
+    /// A code and a comments series.
+    Both,
+    /// Only the code lines.
+    Code,
+    /// Only the comment lines.
+    Comments,
+    /// Only the blank lines.
+    Blanks,
+    /// Only the sum of code, comment and blank lines.
+    Total,
+    /// Code, comment, blank and total lines.
+    All,
+}
+
+impl SeriesSelection {
+    fn includes(self, kind: Kind) -> bool {
+        match kind {
+            Kind::Code => matches!(self, Self::Both | Self::Code | Self::All),
+            Kind::Comments => matches!(self, Self::Both | Self::Comments | Self::All),
+            Kind::Blanks => matches!(self, Self::Blanks | Self::All),
+            Kind::Total => matches!(self, Self::Total | Self::All),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    /// A single code and comments series for all selected languages.
+    None,
+    /// A code and comments series for each language.
+    Language,
+    /// A code and comments series for each revision that was scanned with `scan --rev`.
+    Ref,
+    /// A code and comments series for each owner from `--codeowners`, and one for the files
+    /// without owner. Files with several owners count for each of them.
+    Owner,
+    /// A code and comments series for each Conventional Commits type, like `feat` or `docs`,
+    /// adding up the lines that the commits of that type added. Removed lines aren't subtracted,
+    /// so the series show where the growth came from.
+    CommitType,
+    /// A code and comments series for each year, limited to the files that were first added in
+    /// that year, to compare newer code with legacy code. Renamed files count as added anew.
+    Cohort,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Fill {
+    /// Connect the values of consecutive commits directly, even across long quiet periods.
+    None,
+    /// Repeat the last value on each day without commits, so the values are evenly spaced in
+    /// time and quiet periods show up as flat lines.
+    Forward,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Bucket {
+    /// Show the value of each commit.
+    None,
+    /// One value per week, starting on Monday.
+    Week,
+    /// One value per calendar month.
+    Month,
+}
+
+impl Bucket {
+    /// Period to combine the values of, or `None` to keep each of them.
+    fn period(self) -> Option<Period> {
+        match self {
+            Self::None => None,
+            Self::Week => Some(Period::Week),
+            Self::Month => Some(Period::Month),
+        }
+    }
+}
+
+/// Fraction of the total, given as percentage on the command line.
+#[derive(Clone, Copy)]
+pub struct Share(f64);
+
+impl FromStr for Share {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = s
+            .trim_end_matches('%')
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| format!("invalid percentage: {e}"))?;
+
+        if !(0.0..=100.0).contains(&percent) {
+            return Err("percentage must be between 0% and 100%".to_owned());
+        }
+
+        Ok(Self(percent / 100.0))
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0 * 100.0)
+    }
+}
 
 struct SimpleEntry {
     timestamp: NaiveDate,
+    languages: BTreeMap<LanguageType, Lines>,
+    /// Lines of all languages, for stats files that only contain totals.
+    totals: Option<Lines>,
+    /// Total size of all tracked files.
+    bytes: u64,
+    /// Amount of notes the scan recorded for the commit.
+    notes: usize,
+    commit_type: Option<CommitType>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Lines {
+    /// Amount of files the lines were counted in.
+    files: u64,
     code: u64,
     comments: u64,
+    blanks: u64,
+    /// Public API items with and without documentation.
+    documented: u64,
+    undocumented: u64,
+    /// Comment lines split by their content.
+    prose: u64,
+    commented_code: u64,
+    /// Files with an SPDX license tag.
+    licensed: u64,
 }
 
-pub fn run(mut filter: Vec<LanguageType>, input: PathBuf, size: (u32, u32)) -> Result<()> {
-    if filter.is_empty() {
-        filter = LanguageType::list().to_owned();
+impl Lines {
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(Self {
+            files: self.files.checked_add(other.files)?,
+            code: self.code.checked_add(other.code)?,
+            comments: self.comments.checked_add(other.comments)?,
+            blanks: self.blanks.checked_add(other.blanks)?,
+            documented: self.documented.checked_add(other.documented)?,
+            undocumented: self.undocumented.checked_add(other.undocumented)?,
+            prose: self.prose.checked_add(other.prose)?,
+            commented_code: self.commented_code.checked_add(other.commented_code)?,
+            licensed: self.licensed.checked_add(other.licensed)?,
+        })
     }
 
-    println!("loading input data...");
+    /// Amounts that grew since the previous line counts, leaving out the ones that shrank.
+    fn growth(self, previous: Self) -> Self {
+        Self {
+            files: self.files.saturating_sub(previous.files),
+            code: self.code.saturating_sub(previous.code),
+            comments: self.comments.saturating_sub(previous.comments),
+            blanks: self.blanks.saturating_sub(previous.blanks),
+            documented: self.documented.saturating_sub(previous.documented),
+            undocumented: self.undocumented.saturating_sub(previous.undocumented),
+            prose: self.prose.saturating_sub(previous.prose),
+            commented_code: self.commented_code.saturating_sub(previous.commented_code),
+            licensed: self.licensed.saturating_sub(previous.licensed),
+        }
+    }
+}
 
-    let filter = filter.into_iter().collect::<HashSet<_>>();
-    let data = load_data(input, &filter)?;
+/// Single line or shaded area of the chart.
+struct Series {
+    kind: Kind,
+    points: Vec<(UnixTime, u64)>,
+    /// Separate shapes of a shaded area, each with a lower and upper value at each point.
+    ranges: Vec<Vec<(UnixTime, u64, u64)>>,
+}
 
-    println!("rendering...");
+impl Series {
+    fn line(kind: Kind, points: Vec<(UnixTime, u64)>) -> Self {
+        Self {
+            kind,
+            points,
+            ranges: Vec::new(),
+        }
+    }
+
+    fn area(kind: Kind, ranges: Vec<Vec<(UnixTime, u64, u64)>>) -> Self {
+        Self {
+            kind,
+            points: Vec::new(),
+            ranges,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Code,
+    Comments,
+    Blanks,
+    /// Code, comment and blank lines combined.
+    Total,
+    Bytes,
+    Density,
+    Files,
+    Documented,
+    Undocumented,
+    Prose,
+    CommentedCode,
+    Licensed,
+    Notes,
+    Band,
+    Regressions,
+    CodeForecast,
+    CommentsForecast,
+    /// Range in which the forecast values are expected to end up.
+    CodeUncertainty,
+    CommentsUncertainty,
+    /// Trends of the periods between changepoints.
+    CodeSegments,
+    CommentsSegments,
+    CodeChangepoints,
+    CommentsChangepoints,
+}
 
-    let svg = poloto::header()
-        .with_viewbox_width(1600.0)
-        .with_dim([size.0 as f64, size.1 as f64]);
-
-    let buf = poloto::frame()
-        .with_tick_lines([true, true])
-        .with_viewbox(svg.get_viewbox())
-        .build()
-        .data(poloto::plots!(
-            poloto::build::markers([], [0.0]),
-            poloto::build::plot("Code").line(data.iter().map(|e| (
-                UnixTime(e.timestamp.and_time(NaiveTime::default()).timestamp()),
-                e.code as f64
-            ))),
-            poloto::build::plot("Comments").line(data.iter().map(|e| (
-                UnixTime(e.timestamp.and_time(NaiveTime::default()).timestamp()),
-                e.comments as f64
-            )))
-        ))
-        .build_and_label(("Code over time", "Date", "Lines"))
-        .append_to(svg.light_theme())
-        .render_string()?;
-
-    fs::write("stats.svg", buf)?;
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Code => "code",
+            Self::Comments => "comments",
+            Self::Blanks => "blanks",
+            Self::Total => "total",
+            Self::Bytes => "size",
+            Self::Density => "density",
+            Self::Files => "files",
+            Self::Documented => "documented",
+            Self::Undocumented => "undocumented",
+            Self::Prose => "prose",
+            Self::CommentedCode => "commented-out code",
+            Self::Licensed => "licensed",
+            Self::Notes => "notes",
+            Self::Band => "range",
+            Self::Regressions => "regressions",
+            Self::CodeForecast => "code forecast",
+            Self::CommentsForecast => "comments forecast",
+            Self::CodeUncertainty => "code forecast range",
+            Self::CommentsUncertainty => "comments forecast range",
+            Self::CodeSegments => "code trends",
+            Self::CommentsSegments => "comments trends",
+            Self::CodeChangepoints => "code changepoints",
+            Self::CommentsChangepoints => "comments changepoints",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Self::Code => "Code",
+            Self::Comments => "Comments",
+            Self::Blanks => "Blanks",
+            Self::Total => "Total",
+            Self::Bytes => "Size",
+            Self::Density => "Density",
+            Self::Files => "Files",
+            Self::Documented => "Documented",
+            Self::Undocumented => "Undocumented",
+            Self::Prose => "Prose",
+            Self::CommentedCode => "Commented-out code",
+            Self::Licensed => "Licensed",
+            Self::Notes => "Notes",
+            Self::Band => "Range",
+            Self::Regressions => "Regressions",
+            Self::CodeForecast => "Code forecast",
+            Self::CommentsForecast => "Comments forecast",
+            Self::CodeUncertainty => "Code forecast range",
+            Self::CommentsUncertainty => "Comments forecast range",
+            Self::CodeSegments => "Code trends",
+            Self::CommentsSegments => "Comments trends",
+            Self::CodeChangepoints => "Code changepoints",
+            Self::CommentsChangepoints => "Comments changepoints",
+        }
+    }
+
+    /// Kinds of the forecast line and its range, for series that can be forecast.
+    fn forecast(self) -> Option<(Self, Self)> {
+        match self {
+            Self::Code => Some((Self::CodeForecast, Self::CodeUncertainty)),
+            Self::Comments => Some((Self::CommentsForecast, Self::CommentsUncertainty)),
+            _ => None,
+        }
+    }
+
+    /// Kinds of the segment trends and the changepoints, for series that can be segmented.
+    fn segments(self) -> Option<(Self, Self)> {
+        match self {
+            Self::Code => Some((Self::CodeSegments, Self::CodeChangepoints)),
+            Self::Comments => Some((Self::CommentsSegments, Self::CommentsChangepoints)),
+            _ => None,
+        }
+    }
+}
+
+/// Set of languages that are combined into a single pair of code and comment series.
+struct Group {
+    /// Prefix of the default series labels, or `None` if the chart isn't grouped.
+    name: Option<String>,
+    /// Name of the language, if grouped by language.
+    language: Option<String>,
+    /// Index of the loaded history to take the data from.
+    history: usize,
+    languages: HashSet<LanguageType>,
+}
 
+/// Error for filters that leave nothing to render. It is reported with its own exit code, so
+/// scripts can tell it apart from other failures.
+#[derive(Debug)]
+pub struct NoData(pub(crate) String);
+
+impl Display for NoData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NoData {}
+
+pub fn run(input: PathBuf, output: &Path, options: &Options, config: &Config) -> Result<()> {
+    let warnings = Warnings::default();
+    let chart = chart(input.clone(), options, config)?;
+    if interrupt::interrupted() {
+        warnings.warn(
+            Category::Partial,
+            "loading was interrupted, the chart only covers the entries loaded before",
+        );
+    }
+
+    let content = match &options.template {
+        Some(template) => chart::template::render(template, &chart)?,
+        None => options.format.render(&chart)?,
+    };
+
+    fs::write(output, content)?;
+
+    if options.manifest {
+        provenance::write(
+            &provenance::path(output),
+            output,
+            &input,
+            filters(&options.filter, options.path.as_deref()),
+        )?;
+    }
+
+    warnings.print_summary();
     println!("done");
 
     Ok(())
 }
 
-fn load_data(input: PathBuf, filter: &HashSet<LanguageType>) -> Result<Vec<SimpleEntry>> {
-    let config = bincode::config::standard();
+/// Load the stats file and collect the series to draw, without rendering them yet.
+pub fn chart(input: PathBuf, options: &Options, config: &Config) -> Result<Chart> {
+    chart_with(input, options, config, &interrupt::interrupted)
+}
+
+/// Like [`chart`], with the check whether the user asked to stop loading.
+fn chart_with(
+    input: PathBuf,
+    options: &Options,
+    config: &Config,
+    interrupted: &(dyn Fn() -> bool + Sync),
+) -> Result<Chart> {
+    let mut filter = options.filter.resolve(config)?;
+    let filtered = !filter.is_empty();
+    if !filtered {
+        filter = LanguageType::list().iter().copied().collect();
+    }
+
+    println!("loading input data...");
 
-    let (total_entries, file_count) = {
-        let input = BufReader::new(File::open(&input)?);
-        let mut input = ZipArchive::new(input)?;
+    let file = StatsFile::open(input)?;
+    // Files from before the manifest only contain the plain line counts of each file.
+    let legacy = file.manifest().version == LEGACY_VERSION;
 
-        let count = input.len() - 1;
-        let file = input.by_index_raw(0)?;
-        let mut file = BufReader::new(ZstdDecoder::new(file)?);
+    if let Metric::Bytes = options.metric {
+        ensure!(
+            !legacy,
+            "the stats file doesn't contain repository sizes yet, scan the repository again"
+        );
+        ensure!(
+            !matches!(options.group_by, GroupBy::Language),
+            "repository sizes can't be grouped by language"
+        );
+    }
 
-        (
-            bincode::decode_from_std_read::<u64, _, _>(&mut file, config)?,
-            count,
-        )
+    if !matches!(options.series, SeriesSelection::Both) {
+        ensure!(
+            matches!(options.metric, Metric::Lines),
+            "only the lines metric can be limited to some of its series"
+        );
+    }
+
+    if options.regressions.is_some() {
+        ensure!(
+            matches!(options.metric, Metric::Lines),
+            "regressions can only be shown for the lines metric"
+        );
+    }
+
+    if options.forecast.is_some() {
+        ensure!(
+            matches!(options.metric, Metric::Lines),
+            "forecasts can only be shown for the lines metric"
+        );
+    }
+
+    if options.changepoints {
+        ensure!(
+            matches!(options.metric, Metric::Lines),
+            "changepoints can only be shown for the lines metric"
+        );
+    }
+
+    if let Metric::ApiDocs = options.metric {
+        ensure!(
+            file.manifest().metadata.api_docs,
+            "the stats file doesn't contain API documentation, scan the repository with --api-docs"
+        );
+    }
+
+    if let Metric::CommentQuality = options.metric {
+        ensure!(
+            file.manifest().metadata.comment_quality,
+            "the stats file doesn't contain comment kinds, scan the repository with \
+             --comment-quality"
+        );
+    }
+
+    if let Metric::Spdx = options.metric {
+        ensure!(
+            file.manifest().metadata.spdx,
+            "the stats file doesn't contain license tags, scan the repository with --spdx"
+        );
+    }
+
+    let path = options
+        .path
+        .as_deref()
+        .map(|path| path.trim_matches('/'))
+        .filter(|path| !path.is_empty());
+    if path.is_some() {
+        ensure!(
+            file.manifest().metadata.detail == Detail::PerFile,
+            "the stats file doesn't contain statistics per file, which are needed to limit it to a \
+             path"
+        );
+        ensure!(
+            !matches!(options.metric, Metric::Bytes),
+            "repository sizes can't be limited to a path"
+        );
+    }
+
+    let owners = match options.group_by {
+        GroupBy::Owner => {
+            let Some(codeowners) = &options.codeowners else {
+                bail!("grouping by owner needs a CODEOWNERS file, given with --codeowners");
+            };
+            ensure!(
+                file.manifest().metadata.detail == Detail::PerFile,
+                "the stats file doesn't contain statistics per file, which are needed to group \
+                 it by owner"
+            );
+            ensure!(
+                !matches!(options.metric, Metric::Bytes),
+                "repository sizes can't be grouped by owner"
+            );
+            Some(CodeOwners::load(codeowners)?)
+        }
+        _ => None,
     };
 
-    println!("processing data...");
+    let cohorts = match options.group_by {
+        GroupBy::Cohort => {
+            ensure!(
+                file.manifest().metadata.detail == Detail::PerFile,
+                "the stats file doesn't contain statistics per file, which are needed to group \
+                 it by cohort"
+            );
+            ensure!(
+                !matches!(options.metric, Metric::Bytes),
+                "repository sizes can't be grouped by cohort"
+            );
+            Some(cohorts(&file)?)
+        }
+        _ => None,
+    };
 
-    let (progress, updater) = Progress::new(total_entries);
+    if file.manifest().metadata.detail == Detail::TotalsOnly {
+        ensure!(
+            !filtered && !matches!(options.group_by, GroupBy::Language),
+            "the stats file only contains totals, which can't be filtered or grouped by language"
+        );
+    }
 
-    let data = (1..file_count + 1)
-        .into_par_iter()
-        .try_fold(Vec::new, |mut list, i| {
-            let input = BufReader::new(File::open(&input)?);
-            let mut input = ZipArchive::new(input)?;
-            let file = input.by_index_raw(i)?;
+    let mut histories = file.manifest().histories();
 
-            let mut reader = ZstdDecoder::new(file)?;
-            let count = bincode::decode_from_std_read::<u64, _, _>(&mut reader, config)?;
+    // Only comparing references needs all histories, otherwise the first one is shown.
+    if !matches!(options.group_by, GroupBy::Ref) {
+        histories.truncate(1);
+    }
 
-            list.reserve(count as usize);
+    let ranges = histories
+        .iter()
+        .map(|(_, range)| range.clone())
+        .collect::<Vec<_>>();
+    let mut names = histories
+        .iter()
+        .enumerate()
+        .map(|(i, (history, _))| history_name(i, *history))
+        .collect::<Vec<_>>();
+    let title_names = names.clone();
 
-            for _ in 0..count {
-                let entry =
-                    bincode::serde::decode_from_std_read::<Entry, _, _>(&mut reader, config)?;
-                let filtered = entry
-                    .filtered(filter)
-                    .fold((0, 0), |acc, cs| (acc.0 + cs.code, acc.1 + cs.comments));
+    // Each owner gets its own copy of the data, limited to the owner's files, like a history.
+    let (data, splits) = match (&owners, &cohorts) {
+        (Some(rules), _) => {
+            let owner_names = owner_names(&file, rules, &filter, path)?;
+            let data = owner_names
+                .iter()
+                .map(|name| {
+                    let split = Some(Split::Owner(rules, name.as_deref()));
+                    let mut data = load_data(&file, &filter, path, split, &ranges, interrupted)?;
+                    Ok(data.swap_remove(0))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            names = vec![names[0].clone(); data.len()];
+            let owner_names = owner_names
+                .into_iter()
+                .map(|owner| owner.unwrap_or_else(|| "Unowned".to_owned()))
+                .collect();
+            (data, owner_names)
+        }
+        // Same for cohorts, leaving out the ones without any of the selected files.
+        (_, Some(years)) => {
+            let mut data = Vec::new();
+            let mut cohort_names = Vec::new();
+            for year in years.values().copied().collect::<BTreeSet<_>>() {
+                let split = Some(Split::Cohort(years, year));
+                let cohort =
+                    load_data(&file, &filter, path, split, &ranges, interrupted)?.swap_remove(0);
+                if cohort.iter().any(|e| !e.languages.is_empty()) {
+                    data.push(cohort);
+                    cohort_names.push(year.to_string());
+                }
+            }
+            names = vec![names[0].clone(); data.len()];
+            (data, cohort_names)
+        }
+        _ => (
+            load_data(&file, &filter, path, None, &ranges, interrupted)?,
+            Vec::new(),
+        ),
+    };
 
-                list.push(SimpleEntry {
-                    timestamp: entry.timestamp.date_naive(),
-                    code: filtered.0 as u64,
-                    comments: filtered.1 as u64,
+    if !matches!(options.metric, Metric::Bytes)
+        && data
+            .iter()
+            .flatten()
+            .all(|e| e.languages.is_empty() && e.totals.is_none())
+    {
+        return Err(no_data(&file, options, filtered)?.into());
+    }
+
+    // The same goes for commit types, which only cover the lines added by their commits.
+    let (data, splits) = match options.group_by {
+        GroupBy::CommitType => {
+            let (types, data) = split_commit_types(&data[0])?;
+            names = vec![names[0].clone(); data.len()];
+            let types = types.iter().map(|kind| kind.name().to_owned()).collect();
+            (data, types)
+        }
+        _ => (data, splits),
+    };
+
+    let groups = groups(&data, &names, &splits, filter, options);
+
+    println!("rendering...");
+
+    let series = groups
+        .iter()
+        .map(|group| {
+            Ok((
+                group,
+                group_series(&data[group.history], group, options.metric)?,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Regressions are based on both line counts, even if only one of them is shown.
+    let regressions = match options.regressions {
+        Some(weeks) => series
+            .iter()
+            .map(|(group, series)| (*group, regressions(series, weeks)))
+            .filter(|(_, periods)| !periods.is_empty())
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let series = series
+        .into_iter()
+        .flat_map(|(group, series)| {
+            series
+                .into_iter()
+                .filter(|series| options.series.includes(series.kind))
+                .map(move |series| (group, series))
+        })
+        .collect::<Vec<_>>();
+
+    let notes = if options.notes {
+        note_series(&data, &series)
+    } else {
+        Vec::new()
+    };
+    let series = series
+        .into_iter()
+        .flat_map(|(group, mut series)| {
+            if let Fill::Forward = options.fill {
+                series.points = fill_forward(&series.points);
+            }
+
+            let mut band = None;
+            if let Some(period) = options.bucket.period() {
+                let periods = aggregate(&series.points, period);
+                series.points = periods.iter().map(|p| (p.start, p.mean)).collect();
+                band = options.band.then(|| {
+                    let range = periods.iter().map(|p| (p.start, p.min, p.max)).collect();
+                    Series::area(Kind::Band, vec![range])
                 });
+            }
+
+            let forecast = options
+                .forecast
+                .and_then(|days| forecast_series(&series, days))
+                .into_iter()
+                .flatten()
+                .map(move |forecast| (group, forecast));
+            let segments = options
+                .changepoints
+                .then(|| segment_series(&series))
+                .flatten()
+                .into_iter()
+                .flatten()
+                .map(move |segments| (group, segments));
+
+            std::iter::once((group, series))
+                .chain(band.map(|band| (group, band)))
+                .chain(forecast)
+                .chain(segments)
+        })
+        .chain(notes)
+        .collect::<Vec<_>>();
 
-                updater.inc();
+    // Shade the full height of the plot, which starts at zero.
+    let top = series
+        .iter()
+        .flat_map(|(_, series)| {
+            let values = series.points.iter().map(|&(_, value)| value);
+            let ranges = series.ranges.iter().flatten().map(|&(_, _, max)| max);
+            values.chain(ranges)
+        })
+        .max()
+        .unwrap_or_default();
+    let series = series
+        .into_iter()
+        .chain(regressions.into_iter().map(|(group, periods)| {
+            let ranges = periods
+                .into_iter()
+                .map(|(start, end)| vec![(start, 0, top), (end, 0, top)])
+                .collect();
+            (group, Series::area(Kind::Regressions, ranges))
+        }))
+        .collect::<Vec<_>>();
+
+    let mut title = match &options.title {
+        Some(title) => title.clone(),
+        None => default_title(&file.manifest().metadata, options, &title_names, &data),
+    };
+    if interrupted() {
+        title.push_str(" — PARTIAL, loading was interrupted");
+    }
+
+    // The title and labels carry names and paths from the stats file, which may be redacted.
+    Ok(Chart {
+        title: config.redact(&title).into_owned(),
+        x_label: "Date".to_owned(),
+        y_label: options.y_unit.label(options.metric.unit()),
+        y_unit: options.y_unit,
+        locale: options.locale.clone(),
+        width: options.width,
+        height: options.height,
+        legend: options.legend,
+        palette: options.palette,
+        series: series
+            .iter()
+            .map(|(group, series)| {
+                let points = || {
+                    series
+                        .points
+                        .iter()
+                        .map(|&(UnixTime(t), value)| (t, value as f64))
+                        .collect()
+                };
+                let ranges = || {
+                    series
+                        .ranges
+                        .iter()
+                        .map(|range| {
+                            range
+                                .iter()
+                                .map(|&(UnixTime(t), min, max)| (t, min as f64, max as f64))
+                                .collect()
+                        })
+                        .collect()
+                };
+
+                chart::Series {
+                    label: config
+                        .redact(&label(options, group, &names[group.history], series))
+                        .into_owned(),
+                    shape: match series.kind {
+                        Kind::Notes | Kind::CodeChangepoints | Kind::CommentsChangepoints => {
+                            Shape::Markers(points())
+                        }
+                        Kind::CodeForecast
+                        | Kind::CommentsForecast
+                        | Kind::CodeSegments
+                        | Kind::CommentsSegments => Shape::Dashed(points()),
+                        Kind::Band
+                        | Kind::Regressions
+                        | Kind::CodeUncertainty
+                        | Kind::CommentsUncertainty => Shape::Area(ranges()),
+                        _ => Shape::Line(points()),
+                    },
+                }
+            })
+            .collect(),
+    })
+}
+
+/// Describe the selected part of the data, for manifests.
+pub fn filters(filter: &FilterArgs, path: Option<&str>) -> Value {
+    json!({
+        "languages": filter.filter.iter().map(|lang| lang.name()).collect::<Vec<_>>(),
+        "filter_groups": filter.filter_group,
+        "path": path,
+    })
+}
+
+/// Explain why there is nothing to render, naming the filters that removed all data.
+fn no_data(file: &StatsFile, options: &Options, filtered: bool) -> Result<NoData> {
+    if file.manifest().entries == 0 {
+        return Ok(NoData("the stats file contains no entries".to_owned()));
+    }
+
+    if !filtered {
+        return Ok(NoData(
+            "the stats file contains no files of any known language".to_owned(),
+        ));
+    }
+
+    let mut available = file
+        .last_entry()?
+        .map(|entry| entry.language_stats())
+        .transpose()?
+        .map(|languages| languages.into_keys().collect::<Vec<_>>())
+        .unwrap_or_default();
+    available.sort_unstable();
+
+    let mut filters = options
+        .filter
+        .filter
+        .iter()
+        .map(|lang| format!("language `{lang:?}`"))
+        .chain(
+            options
+                .filter
+                .filter_group
+                .iter()
+                .map(|group| format!("filter group `{group}`")),
+        )
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if !available.is_empty() {
+        filters.push_str("\n\nlanguages in the latest entry: ");
+        filters.push_str(
+            &available
+                .iter()
+                .map(|lang| format!("{lang:?}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+    }
+
+    Ok(NoData(format!(
+        "no data left to render after filtering by {filters}"
+    )))
+}
+
+/// Name of a history for labels and titles: the scanned reference, or the abbreviated commit
+/// if it was scanned by ID.
+fn history_name(index: usize, history: Option<&History>) -> String {
+    history
+        .and_then(|history| {
+            history.reference.clone().or_else(|| {
+                let commit = history.commit.as_deref()?;
+                Some(commit[..commit.len().min(7)].to_owned())
+            })
+        })
+        .unwrap_or_else(|| format!("history {}", index + 1))
+}
+
+/// Describe the repository, references and covered time range, or fall back to a generic title
+/// for stats files without metadata.
+fn default_title(
+    metadata: &Metadata,
+    options: &Options,
+    names: &[String],
+    data: &[Vec<SimpleEntry>],
+) -> String {
+    let Some(name) = &metadata.name else {
+        return "Code over time".to_owned();
+    };
+
+    let mut details = names.to_vec();
+    if let Some(path) = &options.path {
+        details.insert(0, path.trim_matches('/').to_owned());
+    }
+
+    let first = data
+        .iter()
+        .filter_map(|d| d.first())
+        .map(|e| e.timestamp)
+        .min();
+    let last = data
+        .iter()
+        .filter_map(|d| d.last())
+        .map(|e| e.timestamp)
+        .max();
+    if let (Some(first), Some(last)) = (first, last) {
+        let locale = options.locale.clone().unwrap_or_else(Locale::from_env);
+        details.push(format!(
+            "{} to {}",
+            locale.format_date(first),
+            locale.format_date(last)
+        ));
+    }
+
+    let subject = match options.metric {
+        Metric::Lines => match options.series {
+            SeriesSelection::Both => "code & comments",
+            SeriesSelection::Code => "code",
+            SeriesSelection::Comments => "comments",
+            SeriesSelection::Blanks => "blank lines",
+            SeriesSelection::Total => "total lines",
+            SeriesSelection::All => "all lines",
+        },
+        Metric::Bytes => "repository size",
+        Metric::Density => "comment density",
+        Metric::FileCount => "file count",
+        Metric::ApiDocs => "API documentation",
+        Metric::CommentQuality => "comment quality",
+        Metric::Spdx => "license tags",
+    };
+
+    if details.is_empty() {
+        format!("{name} — {subject}")
+    } else {
+        format!("{name} — {subject} ({})", details.join(", "))
+    }
+}
+
+/// Create the legend label of a single series.
+fn label(options: &Options, group: &Group, reference: &str, series: &Series) -> String {
+    let values = series.points.iter().map(|&(_, value)| value);
+
+    match (&options.label, &group.name) {
+        (Some(template), _) => template.format(&legend::Values {
+            language: group.language.as_deref().unwrap_or("All"),
+            reference,
+            kind: series.kind.name(),
+            latest: values.clone().next_back().unwrap_or_default(),
+            peak: values.max().unwrap_or_default(),
+        }),
+        (None, Some(name)) => format!("{name} {}", series.kind.name()),
+        (None, None) => series.kind.title().to_owned(),
+    }
+}
+
+/// Repeat the previous value for each day that has no points of its own. Points are expected to
+/// be at midnight of their day, ordered by time.
+fn fill_forward(points: &[(UnixTime, u64)]) -> Vec<(UnixTime, u64)> {
+    const DAY: i64 = 24 * 60 * 60;
+
+    let mut filled = Vec::with_capacity(points.len());
+    for &(time, value) in points {
+        if let Some(&(UnixTime(last), last_value)) = filled.last() {
+            filled.extend(
+                (last + DAY..time.0)
+                    .step_by(DAY as usize)
+                    .map(|day| (UnixTime(day), last_value)),
+            );
+        }
+        filled.push((time, value));
+    }
+
+    filled
+}
+
+/// Summary of the values within one period.
+struct Aggregate {
+    start: UnixTime,
+    mean: u64,
+    min: u64,
+    max: u64,
+}
+
+/// Combine the points of each period into one at its start.
+fn aggregate(points: &[(UnixTime, u64)], period: Period) -> Vec<Aggregate> {
+    let mut buckets = BTreeMap::<_, Vec<u64>>::new();
+    for &(UnixTime(time), value) in points {
+        let date = DateTime::from_timestamp(time, 0)
+            .unwrap_or_default()
+            .date_naive();
+        buckets.entry(period.start(date)).or_default().push(value);
+    }
+
+    buckets
+        .into_iter()
+        .map(|(start, values)| {
+            let sum = values.iter().map(|&v| u128::from(v)).sum::<u128>();
+
+            Aggregate {
+                start: UnixTime(start.and_time(NaiveTime::default()).and_utc().timestamp()),
+                mean: (sum / values.len() as u128) as u64,
+                min: values.iter().copied().min().unwrap_or_default(),
+                max: values.iter().copied().max().unwrap_or_default(),
             }
+        })
+        .collect()
+}
 
-            Ok(list)
+/// Find the periods in which the weekly share of comments declined for more than `weeks`
+/// consecutive weeks, as the start of their first and last week. Weeks without commits are
+/// skipped, and weeks without code end a period.
+fn regressions(series: &[Series], weeks: usize) -> Vec<(UnixTime, UnixTime)> {
+    let weekly = |kind| {
+        series
+            .iter()
+            .find(|series| series.kind == kind)
+            .map(|series| aggregate(&series.points, Period::Week))
+            .unwrap_or_default()
+    };
+    let ratios = weekly(Kind::Code)
+        .into_iter()
+        .zip(weekly(Kind::Comments))
+        .map(|(code, comments)| {
+            let ratio = (code.mean > 0).then(|| comments.mean as f64 / code.mean as f64);
+            (code.start, ratio)
         })
-        .try_reduce(Vec::new, |mut list, sublist| {
-            list.extend(sublist);
-            Ok(list)
+        .collect::<Vec<_>>();
+
+    let mut periods = Vec::new();
+    let mut start = 0;
+
+    for i in 1..=ratios.len() {
+        let declined = ratios.get(i).is_some_and(|&(_, ratio)| {
+            matches!((ratios[i - 1].1, ratio), (Some(prev), Some(ratio)) if ratio < prev)
         });
 
+        if !declined {
+            if i - 1 - start > weeks {
+                periods.push((ratios[start].0, ratios[i - 1].0));
+            }
+            start = i;
+        }
+    }
+
+    periods
+}
+
+/// Continue a code or comments series along its trend, as a dashed line and the range around
+/// it. Other series and ones that are too short for a trend aren't forecast.
+fn forecast_series(series: &Series, days: u32) -> Option<[Series; 2]> {
+    let (line, range) = series.kind.forecast()?;
+    let points = series
+        .points
+        .iter()
+        .map(|&(UnixTime(time), value)| (time, value as f64))
+        .collect::<Vec<_>>();
+    let predictions = Trend::fit(&points)?.extend(days);
+
+    let round = |value: f64| value.round() as u64;
+    Some([
+        Series::line(
+            line,
+            predictions
+                .iter()
+                .map(|(time, p)| (UnixTime(*time), round(p.value)))
+                .collect(),
+        ),
+        Series::area(
+            range,
+            vec![predictions
+                .iter()
+                .map(|(time, p)| (UnixTime(*time), round(p.lower), round(p.upper)))
+                .collect()],
+        ),
+    ])
+}
+
+/// Split a code or comments series into periods with distinct trends, as a dashed line along
+/// the trend of each period and markers where a new period starts. Other series and ones
+/// without changepoints aren't segmented.
+fn segment_series(series: &Series) -> Option<[Series; 2]> {
+    let (line, markers) = series.kind.segments()?;
+    let points = series
+        .points
+        .iter()
+        .map(|&(UnixTime(time), value)| (time, value as f64))
+        .collect::<Vec<_>>();
+    let segments = changepoints::segments(&points);
+    if segments.len() < 2 {
+        return None;
+    }
+
+    let value = |value: f64| value.max(0.0).round() as u64;
+    let trends = segments
+        .iter()
+        .flat_map(|segment| {
+            [
+                (series.points[segment.points.start].0, value(segment.start)),
+                (series.points[segment.points.end - 1].0, value(segment.end)),
+            ]
+        })
+        .collect();
+    let starts = segments
+        .iter()
+        .skip(1)
+        .map(|segment| series.points[segment.points.start])
+        .collect();
+
+    Some([Series::line(line, trends), Series::line(markers, starts)])
+}
+
+/// Create a series of markers for the commits with notes of each history, placed on the first
+/// series of that history.
+fn note_series<'a>(
+    data: &[Vec<SimpleEntry>],
+    series: &[(&'a Group, Series)],
+) -> Vec<(&'a Group, Series)> {
+    let mut notes = Vec::new();
+    let mut histories = HashSet::new();
+
+    for (group, series) in series {
+        if !histories.insert(group.history) {
+            continue;
+        }
+
+        // Series have one point for each entry of their history.
+        let points = data[group.history]
+            .iter()
+            .zip(&series.points)
+            .filter(|(entry, _)| entry.notes > 0)
+            .map(|(_, &point)| point)
+            .collect::<Vec<_>>();
+
+        if !points.is_empty() {
+            notes.push((*group, Series::line(Kind::Notes, points)));
+        }
+    }
+
+    notes
+}
+
+/// Calculate the series of a single group, depending on the metric.
+fn group_series(data: &[SimpleEntry], group: &Group, metric: Metric) -> Result<Vec<Series>> {
+    let time = |e: &SimpleEntry| UnixTime(e.timestamp.and_time(NaiveTime::default()).timestamp());
+
+    if let Metric::Bytes = metric {
+        return Ok(vec![Series::line(
+            Kind::Bytes,
+            data.iter().map(|e| (time(e), e.bytes)).collect(),
+        )]);
+    }
+
+    let lines = data
+        .iter()
+        .map(|e| {
+            if let Some(totals) = e.totals {
+                return Ok(totals);
+            }
+
+            e.languages
+                .iter()
+                .filter(|(lang, _)| group.languages.contains(lang))
+                .try_fold(Lines::default(), |total, (_, &lines)| {
+                    total.checked_add(lines)
+                })
+                .with_context(|| format!("line count overflow at {}", e.timestamp))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let series = |kind, value: fn(&Lines) -> u64| {
+        Series::line(
+            kind,
+            data.iter()
+                .zip(&lines)
+                .map(|(e, l)| (time(e), value(l)))
+                .collect(),
+        )
+    };
+
+    Ok(match metric {
+        Metric::Bytes => unreachable!("sizes don't depend on the line counts"),
+        Metric::Lines => vec![
+            series(Kind::Code, |l| l.code),
+            series(Kind::Comments, |l| l.comments),
+            series(Kind::Blanks, |l| l.blanks),
+            series(Kind::Total, |l| {
+                l.code.saturating_add(l.comments).saturating_add(l.blanks)
+            }),
+        ],
+        Metric::Density => vec![series(Kind::Density, |l| density(*l))],
+        Metric::FileCount => vec![series(Kind::Files, |l| l.files)],
+        Metric::ApiDocs => vec![
+            series(Kind::Documented, |l| l.documented),
+            series(Kind::Undocumented, |l| l.undocumented),
+        ],
+        Metric::CommentQuality => vec![
+            series(Kind::Prose, |l| l.prose),
+            series(Kind::CommentedCode, |l| l.commented_code),
+        ],
+        Metric::Spdx => vec![
+            series(Kind::Licensed, |l| l.licensed),
+            series(Kind::Files, |l| l.files),
+        ],
+    })
+}
+
+/// Comment lines per 1000 code lines, rounded to the closest integer. Zero without any code.
+fn density(lines: Lines) -> u64 {
+    if lines.code == 0 {
+        return 0;
+    }
+
+    (lines.comments as f64 * 1000.0 / lines.code as f64).round() as u64
+}
+
+/// Split the selected languages and histories into the groups that get their own series.
+/// `splits` names the copies of the data for owners or commit types.
+fn groups(
+    data: &[Vec<SimpleEntry>],
+    names: &[String],
+    splits: &[String],
+    filter: HashSet<LanguageType>,
+    options: &Options,
+) -> Vec<Group> {
+    match options.group_by {
+        GroupBy::None => vec![Group {
+            name: None,
+            language: None,
+            history: 0,
+            languages: filter,
+        }],
+        GroupBy::Ref => names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Group {
+                name: Some(name.clone()),
+                language: None,
+                history: i,
+                languages: filter.clone(),
+            })
+            .collect(),
+        GroupBy::Owner | GroupBy::CommitType | GroupBy::Cohort => splits
+            .iter()
+            .enumerate()
+            .map(|(i, split)| Group {
+                name: Some(split.clone()),
+                language: None,
+                history: i,
+                languages: filter.clone(),
+            })
+            .collect(),
+        GroupBy::Language => {
+            let mut totals = BTreeMap::<_, u64>::new();
+            for entry in &data[0] {
+                for (&lang, lines) in &entry.languages {
+                    let total = totals.entry(lang).or_default();
+                    *total = total.saturating_add(lines.code);
+                }
+            }
+
+            let sum = totals
+                .values()
+                .fold(0, |sum: u64, &t| sum.saturating_add(t));
+            let (mut major, minor) = totals.into_iter().partition::<Vec<_>, _>(|&(_, total)| {
+                total as f64 >= sum as f64 * options.min_share.0
+            });
+
+            major.sort_by_key(|&(_, total)| Reverse(total));
+
+            let mut groups = major
+                .into_iter()
+                .map(|(lang, _)| Group {
+                    name: Some(lang.name().to_owned()),
+                    language: Some(lang.name().to_owned()),
+                    history: 0,
+                    languages: HashSet::from([lang]),
+                })
+                .collect::<Vec<_>>();
+
+            if !minor.is_empty() {
+                groups.push(Group {
+                    name: Some("Other".to_owned()),
+                    language: Some("Other".to_owned()),
+                    history: 0,
+                    languages: minor.into_iter().map(|(lang, _)| lang).collect(),
+                });
+            }
+
+            groups
+        }
+    }
+}
+
+/// Part of the files to limit the data to, when grouping by owner or cohort.
+#[derive(Clone, Copy)]
+enum Split<'a> {
+    /// Files of the owner, or the unowned files for `None`.
+    Owner(&'a CodeOwners, Option<&'a str>),
+    /// Files that were first added in the year, by the years of all files.
+    Cohort(&'a HashMap<String, i32>, i32),
+}
+
+impl Split<'_> {
+    fn contains(self, path: &str) -> bool {
+        match self {
+            Self::Owner(rules, owner) => rules.owns(path, owner),
+            Self::Cohort(years, year) => years.get(path) == Some(&year),
+        }
+    }
+}
+
+/// Year in which each file of the first history first showed up.
+fn cohorts(file: &StatsFile) -> Result<HashMap<String, i32>> {
+    println!("finding cohorts...");
+
+    let mut years = HashMap::new();
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        return Ok(years);
+    };
+
+    // Entries are ordered by time, so the first entry that contains a file is the one that
+    // added it.
+    for index in range {
+        file.read_chunk(index, |entry| {
+            for path in entry.files.into_keys() {
+                years.entry(path).or_insert(entry.timestamp.year());
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(years)
+}
+
+/// Owners of the selected files in the latest entry, ordered by their code lines, followed by
+/// `None` if there are unowned files.
+fn owner_names(
+    file: &StatsFile,
+    rules: &CodeOwners,
+    filter: &HashSet<LanguageType>,
+    path: Option<&str>,
+) -> Result<Vec<Option<String>>> {
+    let mut totals = BTreeMap::<Option<&str>, usize>::new();
+    let latest = file.last_entry()?;
+    let files = latest.iter().flat_map(|entry| &entry.files);
+
+    for (key, file) in files {
+        if !filter.contains(&file.language) || path.is_some_and(|path| !is_below(key, path)) {
+            continue;
+        }
+
+        let owners = rules.owners(key);
+        if owners.is_empty() {
+            *totals.entry(None).or_default() += file.statistics.code;
+        }
+        for owner in owners {
+            *totals.entry(Some(owner)).or_default() += file.statistics.code;
+        }
+    }
+
+    let mut owners = totals.into_iter().collect::<Vec<_>>();
+    owners.sort_by_key(|&(owner, code)| (owner.is_none(), Reverse(code)));
+
+    Ok(owners
+        .into_iter()
+        .map(|(owner, _)| owner.map(str::to_owned))
+        .collect())
+}
+
+/// Split the entries by the Conventional Commits type of their commits. Each type gets a copy of
+/// all entries, with the lines that the commits of that type added up to each of them.
+fn split_commit_types(entries: &[SimpleEntry]) -> Result<(Vec<CommitType>, Vec<Vec<SimpleEntry>>)> {
+    let types = entries
+        .iter()
+        .filter_map(|e| e.commit_type)
+        .collect::<BTreeSet<_>>();
+    ensure!(
+        !types.is_empty(),
+        "the stats file doesn't contain commit types, scan the repository again"
+    );
+
+    let data = types
+        .iter()
+        .map(|&kind| {
+            let mut languages = BTreeMap::<_, Lines>::new();
+            let mut totals = Lines::default();
+            let mut bytes = 0_u64;
+            let mut previous = None::<&SimpleEntry>;
+
+            entries
+                .iter()
+                .map(|entry| {
+                    let overflow = || format!("line count overflow at {}", entry.timestamp);
+
+                    if entry.commit_type == Some(kind) {
+                        for (lang, &lines) in &entry.languages {
+                            let before = previous
+                                .and_then(|p| p.languages.get(lang).copied())
+                                .unwrap_or_default();
+                            let sum = languages.entry(*lang).or_default();
+                            *sum = sum
+                                .checked_add(lines.growth(before))
+                                .with_context(overflow)?;
+                        }
+
+                        if let Some(lines) = entry.totals {
+                            let before = previous.and_then(|p| p.totals).unwrap_or_default();
+                            totals = totals
+                                .checked_add(lines.growth(before))
+                                .with_context(overflow)?;
+                        }
+
+                        let before = previous.map_or(0, |p| p.bytes);
+                        bytes = bytes.saturating_add(entry.bytes.saturating_sub(before));
+                    }
+
+                    previous = Some(entry);
+
+                    Ok(SimpleEntry {
+                        timestamp: entry.timestamp,
+                        languages: languages.clone(),
+                        totals: entry.totals.map(|_| totals),
+                        bytes,
+                        notes: entry.notes,
+                        commit_type: entry.commit_type,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((types.into_iter().collect(), data))
+}
+
+/// Load the entries of each given range of chunks, usually one per history.
+fn load_data(
+    file: &StatsFile,
+    filter: &HashSet<LanguageType>,
+    path: Option<&str>,
+    split: Option<Split<'_>>,
+    ranges: &[Range<usize>],
+    interrupted: &(dyn Fn() -> bool + Sync),
+) -> Result<Vec<Vec<SimpleEntry>>> {
+    println!("processing data...");
+
+    let chunks = &file.manifest().chunks;
+    let total = ranges
+        .iter()
+        .flat_map(|r| &chunks[r.clone()])
+        .map(|c| c.entries)
+        .sum();
+    let (progress, updater) = Progress::new(total);
+
+    let data = ranges
+        .iter()
+        .map(|range| {
+            let data = range
+                .clone()
+                .into_par_iter()
+                .map(|i| {
+                    // Chunks that weren't started before an interruption are left out.
+                    if interrupted() {
+                        return Ok(None);
+                    }
+                    load_chunk(file, i, filter, path, split, &updater).map(Some)
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            // Only the chunks before the first one that was left out are kept, so the history
+            // ends early instead of having gaps.
+            Ok(data
+                .into_iter()
+                .map_while(|chunk| chunk)
+                .flatten()
+                .collect())
+        })
+        .collect();
+
     progress.wait()?;
 
+    if interrupted() {
+        println!("interrupted, rendering the entries loaded so far...");
+    }
+
     data
 }
+
+fn load_chunk(
+    file: &StatsFile,
+    index: usize,
+    filter: &HashSet<LanguageType>,
+    path: Option<&str>,
+    split: Option<Split<'_>>,
+    updater: &Updater,
+) -> Result<Vec<SimpleEntry>> {
+    let mut list = Vec::with_capacity(file.manifest().chunks[index].entries as usize);
+
+    file.read_chunk(index, |entry| {
+        updater.inc();
+
+        // Failed commits have no data and would show up as drop to zero.
+        if entry.failed {
+            return Ok(());
+        }
+
+        let mut languages = BTreeMap::<_, Lines>::new();
+
+        let overflow = || format!("line count overflow at {}", entry.timestamp);
+        let files = entry
+            .files
+            .iter()
+            .filter(|(key, _)| path.is_none_or(|path| is_below(key, path)))
+            .filter(|(key, _)| split.is_none_or(|split| split.contains(key)))
+            .map(|(_, file)| (&file.language, Cow::Owned(Summary::from(file))));
+        let summaries = entry
+            .languages
+            .iter()
+            .map(|(lang, summary)| (lang, Cow::Borrowed(summary)));
+
+        // Only one of files or languages is filled, depending on the detail of the stats file.
+        for (lang, summary) in files.chain(summaries) {
+            if !filter.contains(lang) {
+                continue;
+            }
+
+            let lines = languages.entry(*lang).or_default();
+            *lines = add_lines(*lines, &summary).with_context(overflow)?;
+        }
+
+        let totals = entry
+            .totals
+            .as_ref()
+            .map(|summary| add_lines(Lines::default(), summary).with_context(overflow))
+            .transpose()?;
+
+        list.push(SimpleEntry {
+            timestamp: entry.timestamp.date_naive(),
+            languages,
+            totals,
+            bytes: entry.bytes,
+            notes: entry.notes.len(),
+            commit_type: entry.commit_type,
+        });
+
+        Ok(())
+    })?;
+
+    Ok(list)
+}
+
+/// Whether the file is inside the directory, or is the given path itself.
+fn is_below(file: &str, dir: &str) -> bool {
+    file.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Add the line counts from tokei, together with the results of the optional analyses, to a
+/// running total, failing instead of silently wrapping around on overflow.
+fn add_lines(total: Lines, summary: &Summary) -> Option<Lines> {
+    let api_docs = summary.api_docs.unwrap_or_default();
+    let comment_kinds = summary.comment_kinds.unwrap_or_default();
+    total.checked_add(Lines {
+        files: summary.files,
+        code: u64::try_from(summary.statistics.code).ok()?,
+        comments: u64::try_from(summary.statistics.comments).ok()?,
+        blanks: u64::try_from(summary.statistics.blanks).ok()?,
+        documented: api_docs.documented,
+        undocumented: api_docs.undocumented,
+        prose: comment_kinds.prose,
+        commented_code: comment_kinds.code,
+        licensed: summary.licensed.unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use chrono::{DateTime, FixedOffset};
+    use tempfile::TempDir;
+    use tokei::CodeStats;
+
+    use super::*;
+    use crate::{
+        models::{Entry, EntryFile},
+        stats_file::{self, ChunkWriter, Manifest, FORMAT_VERSION},
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn time(year: i32, month: u32, day: u32) -> UnixTime {
+        UnixTime(
+            date(year, month, day)
+                .and_time(NaiveTime::default())
+                .and_utc()
+                .timestamp(),
+        )
+    }
+
+    fn lines(code: u64, comments: u64) -> Lines {
+        Lines {
+            code,
+            comments,
+            ..Lines::default()
+        }
+    }
+
+    fn simple_entry(timestamp: NaiveDate, languages: &[(LanguageType, u64)]) -> SimpleEntry {
+        SimpleEntry {
+            timestamp,
+            languages: languages
+                .iter()
+                .map(|&(lang, code)| (lang, lines(code, 0)))
+                .collect(),
+            totals: None,
+            bytes: 0,
+            notes: 0,
+            commit_type: None,
+        }
+    }
+
+    fn entry(timestamp: &str, files: &[(&str, LanguageType, usize, usize)]) -> Entry {
+        Entry {
+            timestamp: DateTime::<FixedOffset>::parse_from_rfc3339(timestamp).unwrap(),
+            files: files
+                .iter()
+                .map(|&(path, language, code, comments)| {
+                    let mut statistics = CodeStats::new();
+                    statistics.code = code;
+                    statistics.comments = comments;
+                    let file = EntryFile {
+                        language,
+                        statistics,
+                        api_docs: None,
+                        comment_kinds: None,
+                        licensed: None,
+                    };
+                    (path.to_owned(), file)
+                })
+                .collect(),
+            languages: HashMap::new(),
+            totals: None,
+            bytes: 0,
+            partial: false,
+            failed: false,
+            notes: Vec::new(),
+            commit_type: None,
+            commit: None,
+        }
+    }
+
+    /// Write a stats file with a chunk for each of the given lists of entries.
+    fn write_stats(dir: &TempDir, chunks: &[Vec<Entry>]) -> PathBuf {
+        let chunks = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, entries)| {
+                let mut writer =
+                    ChunkWriter::create(dir.path(), index, entries.len() as u64).unwrap();
+                for entry in entries {
+                    writer.write(entry).unwrap();
+                }
+                writer.finish().unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            entries: chunks.iter().map(|chunk| chunk.entries).sum(),
+            chunks,
+            metadata: Metadata::default(),
+        };
+        let output = dir.path().join("test.stats");
+        stats_file::write(&output, dir.path(), &manifest, || {}).unwrap();
+
+        output
+    }
+
+    #[test]
+    fn small_languages_are_collapsed_into_other() {
+        let data = vec![vec![
+            simple_entry(date(2023, 11, 20), &[(LanguageType::Rust, 400)]),
+            simple_entry(
+                date(2023, 11, 21),
+                &[
+                    (LanguageType::Rust, 500),
+                    (LanguageType::Python, 80),
+                    (LanguageType::Sh, 20),
+                ],
+            ),
+        ]];
+        let filter = LanguageType::list().iter().copied().collect::<HashSet<_>>();
+        let names = |options: &Options| {
+            groups(&data, &[], &[], filter.clone(), options)
+                .into_iter()
+                .map(|group| (group.name.unwrap(), group.languages.len()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut options = Options {
+            group_by: GroupBy::Language,
+            ..Options::default()
+        };
+        assert_eq!(
+            vec![
+                ("Rust".to_owned(), 1),
+                ("Python".to_owned(), 1),
+                ("Shell".to_owned(), 1)
+            ],
+            names(&options)
+        );
+
+        // Python has 8% of all code lines over the history and Shell 2%.
+        options.min_share = "5%".parse().unwrap();
+        assert_eq!(
+            vec![
+                ("Rust".to_owned(), 1),
+                ("Python".to_owned(), 1),
+                ("Other".to_owned(), 1)
+            ],
+            names(&options)
+        );
+
+        options.min_share = "10%".parse().unwrap();
+        assert_eq!(
+            vec![("Rust".to_owned(), 1), ("Other".to_owned(), 2)],
+            names(&options)
+        );
+    }
+
+    #[test]
+    fn fill_forward_repeats_values_over_gaps() {
+        let points = [
+            (time(2023, 11, 20), 5),
+            (time(2023, 11, 21), 6),
+            (time(2023, 11, 24), 9),
+        ];
+
+        assert_eq!(
+            vec![
+                (time(2023, 11, 20).0, 5),
+                (time(2023, 11, 21).0, 6),
+                (time(2023, 11, 22).0, 6),
+                (time(2023, 11, 23).0, 6),
+                (time(2023, 11, 24).0, 9),
+            ],
+            fill_forward(&points)
+                .into_iter()
+                .map(|(UnixTime(t), value)| (t, value))
+                .collect::<Vec<_>>()
+        );
+        assert!(fill_forward(&[]).is_empty());
+    }
+
+    #[test]
+    fn buckets_keep_mean_minimum_and_maximum() {
+        // Monday, Wednesday and Sunday of one week, then the Monday after.
+        let points = [
+            (time(2023, 11, 20), 10),
+            (time(2023, 11, 22), 30),
+            (time(2023, 11, 26), 20),
+            (time(2023, 11, 27), 40),
+        ];
+        let summary = |bucket| {
+            aggregate(&points, bucket)
+                .into_iter()
+                .map(|p| (p.start.0, p.mean, p.min, p.max))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            vec![
+                (time(2023, 11, 20).0, 20, 10, 30),
+                (time(2023, 11, 27).0, 40, 40, 40),
+            ],
+            summary(Period::Week)
+        );
+        assert_eq!(
+            vec![(time(2023, 11, 1).0, 25, 10, 40)],
+            summary(Period::Month)
+        );
+    }
+
+    #[test]
+    fn regressions_need_more_than_the_given_weeks() {
+        let weekly = |kind, values: &[u64]| {
+            let start = date(2023, 11, 6);
+            let points = values
+                .iter()
+                .zip(0..)
+                .map(|(&value, week)| {
+                    let day = start + chrono::Days::new(week * 7);
+                    (time(day.year(), day.month(), day.day()), value)
+                })
+                .collect();
+            Series::line(kind, points)
+        };
+
+        // The share of comments declines for three consecutive weeks, then recovers.
+        let series = [
+            weekly(Kind::Code, &[100, 100, 100, 100, 100]),
+            weekly(Kind::Comments, &[50, 40, 30, 20, 30]),
+        ];
+        assert_eq!(
+            vec![(time(2023, 11, 6).0, time(2023, 11, 27).0)],
+            regressions(&series, 2)
+                .into_iter()
+                .map(|(start, end)| (start.0, end.0))
+                .collect::<Vec<_>>()
+        );
+        assert!(regressions(&series, 3).is_empty());
+
+        // A week without code interrupts the decline.
+        let series = [
+            weekly(Kind::Code, &[100, 100, 0, 100, 100]),
+            weekly(Kind::Comments, &[50, 40, 30, 20, 10]),
+        ];
+        assert!(regressions(&series, 1).is_empty());
+    }
+
+    #[test]
+    fn density_is_per_thousand_code_lines() {
+        assert_eq!(250, density(lines(200, 50)));
+        assert_eq!(333, density(lines(3, 1)));
+        assert_eq!(2000, density(lines(5, 10)));
+        assert_eq!(0, density(lines(0, 10)));
+        assert_eq!(0, density(lines(0, 0)));
+    }
+
+    #[test]
+    fn no_data_lists_available_languages() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = write_stats(
+            &dir,
+            &[vec![entry(
+                "2023-11-20T12:30:00+01:00",
+                &[
+                    ("main.py", LanguageType::Python, 10, 2),
+                    ("run.sh", LanguageType::Sh, 5, 1),
+                ],
+            )]],
+        );
+        let options = Options {
+            filter: FilterArgs {
+                filter: vec![LanguageType::Rust],
+                filter_group: Vec::new(),
+            },
+            ..Options::default()
+        };
+
+        let error = chart(input, &options, &Config::default())
+            .err()
+            .unwrap()
+            .downcast::<NoData>()
+            .unwrap();
+        assert_eq!(
+            "no data left to render after filtering by language `Rust`\n\nlanguages in the \
+             latest entry: Python, Sh",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn interruption_keeps_loaded_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let chunks = ["2023-11-20", "2023-11-21", "2023-11-22"].map(|day| {
+            let timestamp = format!("{day}T12:30:00+01:00");
+            vec![entry(&timestamp, &[("main.rs", LanguageType::Rust, 10, 2)])]
+        });
+        let input = write_stats(&dir, &chunks);
+
+        // The user stops right after the first chunk was started. With a single thread, the
+        // chunks are loaded in order.
+        let checks = AtomicUsize::new(0);
+        let interrupted = || checks.fetch_add(1, Ordering::Relaxed) > 0;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let chart = pool
+            .install(|| chart_with(input, &Options::default(), &Config::default(), &interrupted))
+            .unwrap();
+
+        assert!(chart.title.ends_with(" — PARTIAL, loading was interrupted"));
+        assert!(!chart.series.is_empty());
+        for series in chart.series {
+            let Shape::Line(points) = series.shape else {
+                panic!("{} isn't a line", series.label);
+            };
+            assert_eq!(1, points.len());
+        }
+    }
+}
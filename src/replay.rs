@@ -0,0 +1,159 @@
+//! Regeneration of the charts listed in the configuration file, from their existing stats files.
+//! Meant to be run after upgrading the tool, so all published charts adopt new styling and
+//! features at once.
+//!
+//! ```toml
+//! [[charts]]
+//! name = "languages"
+//! input = "stats.stats"
+//! output = "public/languages.svg"
+//! args = ["--group-by", "language", "--palette", "colorblind"]
+//! ```
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser};
+
+use crate::{
+    config::{ChartEntry, Config},
+    exit::Failure,
+    render,
+};
+
+#[derive(Args)]
+#[group(skip)]
+pub struct Options {
+    /// Names of the charts to regenerate.
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    pub names: Vec<String>,
+    /// Regenerate all configured charts.
+    #[arg(long)]
+    pub all: bool,
+}
+
+/// Arguments of a configured chart, which are the same as for the `render` command.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct ChartArgs {
+    #[command(flatten)]
+    options: render::Options,
+}
+
+pub fn run(options: &Options, config: &Config) -> Result<()> {
+    let charts = select(&config.charts, options)?;
+
+    // Check all arguments up front, so a typo doesn't leave half of the charts regenerated.
+    let charts = charts
+        .into_iter()
+        .map(|chart| {
+            let args = ChartArgs::try_parse_from(&chart.args)
+                .with_context(|| format!("invalid arguments for chart `{}`", chart.name))
+                .context(Failure::Config)?;
+            Ok((chart, args.options))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut failed = Vec::new();
+
+    for (i, (chart, render_options)) in charts.iter().enumerate() {
+        println!("[{}/{}] {}", i + 1, charts.len(), chart.name);
+
+        let result = render::run(chart.input.clone(), &chart.output, render_options, config);
+        if let Err(e) = result {
+            eprintln!("failed rendering {}: {e:#}", chart.name);
+            failed.push(chart.name.as_str());
+        }
+    }
+
+    if !failed.is_empty() {
+        let error = anyhow!(
+            "failed rendering {} of {} charts: {}",
+            failed.len(),
+            charts.len(),
+            failed.join(", ")
+        );
+        return Err(error.context(if failed.len() == charts.len() {
+            Failure::Render
+        } else {
+            Failure::Partial
+        }));
+    }
+
+    Ok(())
+}
+
+/// Pick the charts to regenerate, in the order of the configuration file.
+fn select<'a>(charts: &'a [ChartEntry], options: &Options) -> Result<Vec<&'a ChartEntry>> {
+    if charts.is_empty() {
+        bail!("the configuration file lists no charts");
+    }
+
+    if let Some(name) = options
+        .names
+        .iter()
+        .find(|name| !charts.iter().any(|chart| &chart.name == *name))
+    {
+        bail!(
+            "unknown chart `{name}`, available are: {}",
+            charts
+                .iter()
+                .map(|chart| chart.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    Ok(charts
+        .iter()
+        .filter(|chart| options.all || options.names.contains(&chart.name))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charts_are_selected_by_name() {
+        let config = toml::from_str::<Config>(
+            r#"
+            [[charts]]
+            name = "total"
+            input = "stats.stats"
+            output = "total.svg"
+
+            [[charts]]
+            name = "languages"
+            input = "stats.stats"
+            output = "languages.svg"
+            args = ["--group-by", "language"]
+            "#,
+        )
+        .unwrap();
+
+        let names = |options: Options| {
+            select(&config.charts, &options)
+                .map(|charts| charts.iter().map(|c| c.name.as_str()).collect::<Vec<_>>())
+        };
+
+        let all = Options {
+            names: Vec::new(),
+            all: true,
+        };
+        assert_eq!(vec!["total", "languages"], names(all).unwrap());
+
+        let some = Options {
+            names: vec!["languages".to_owned()],
+            all: false,
+        };
+        assert_eq!(vec!["languages"], names(some).unwrap());
+
+        let unknown = Options {
+            names: vec!["files".to_owned()],
+            all: false,
+        };
+        assert!(names(unknown).is_err());
+
+        assert!(ChartArgs::try_parse_from(&config.charts[1].args).is_ok());
+        assert!(ChartArgs::try_parse_from(["--group-by", "nothing"]).is_err());
+    }
+}
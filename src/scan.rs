@@ -1,21 +1,54 @@
 use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    env,
     fs::File,
-    io::{self, BufWriter, Write},
-    path::{Path, PathBuf},
+    io::{self, Read},
+    panic::{self, AssertUnwindSafe},
+    path::{Component, Path, PathBuf, MAIN_SEPARATOR},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, bail, ensure, Context, Result};
 use chrono::prelude::*;
-use git2::{Delta, ObjectType, Oid, Repository, Sort, Tree};
+use clap::{Args, ValueEnum, ValueHint};
+use git2::{
+    Commit, Delta, DiffDelta, DiffFile, DiffFindOptions, ErrorClass, FileMode, ObjectType, Odb,
+    Oid, Repository, Sort, Tree, TreeEntry, TreeWalkMode, TreeWalkResult,
+};
 use pbr::ProgressBar;
 use rayon::prelude::*;
-use tokei::{Config as TokeiConfig, LanguageType};
-use zip::{write::FileOptions, ZipWriter};
-use zstd::Encoder as ZstdEncoder;
+use tokei::{CodeStats, LanguageType};
 
 use crate::{
-    models::{Entry, EntryFile},
+    api_docs,
+    attributes::{self, Rules},
+    comments::{self, Heuristics},
+    commit_graph,
+    commit_type::CommitType,
+    config::Config,
+    crypt, daemon,
+    excludes::{self, Excludes},
+    graft::Graft,
+    languages::FilterArgs,
+    models::{CommitInfo, Detail, Entry, EntryFile, Note},
+    partial,
+    preview::Preview,
+    profile::{Phase, Profile},
     progress::{Progress, Updater},
+    prune::Period,
+    space,
+    stats_file::{
+        self, ChunkInfo, ChunkWriter, Compression, History, Manifest, Metadata, SizeCounter,
+        Staged, FORMAT_VERSION, ZSTD_COMPRESSION_DEFAULT,
+    },
+    submodules,
+    tokei_config::{self, Tokei},
+    update::Previous,
+    warnings::{Category, Warnings},
+    watchdog::Watchdog,
 };
 
 /// The amount of chunks to create. This is a _goal_ value that means if there is not enough data
@@ -25,178 +58,3190 @@ const CHUNK_AMOUNT: usize = 1000;
 /// and work split amount. Small repositories can be handled quickly so that mostly bigger repos
 /// benefit from the chunking anyways.
 const MIN_CHUNK_SIZE: usize = 1000;
-const ZSTD_COMPRESSION_DEFAULT: i32 = 11;
+/// Maximum amount of symlinks to follow in a row, before considering the link broken. Protects
+/// against cycles between links.
+const MAX_SYMLINK_DEPTH: usize = 8;
+/// Maximum amount of trees whose symlinks are kept in [`Symlinks`], to bound its memory use.
+const MAX_SYMLINK_TREES: usize = 100_000;
+/// Maximum amount of tree pairs whose changes are kept in [`Deltas`], to bound its memory use.
+const MAX_CACHED_DELTAS: usize = 100_000;
+/// Maximum amount of chunk files that are written at the same time. Each worker only writes one
+/// chunk at a time, but the amount of workers is configurable and file handles are scarce on
+/// some platforms (Windows in particular).
+const MAX_OPEN_CHUNKS: usize = 64;
+/// Default for [`Options::max_file_size`]. Source files beyond this size are almost always
+/// generated or vendored and can take tokei a very long time to process.
+const DEFAULT_MAX_FILE_SIZE: u64 = 32 * 1024 * 1024;
+/// Amount of retries for object reads that failed with a possibly transient error.
+const MAX_RETRIES: u32 = 3;
+/// Delay before the first retry of an object read, doubled for each further one.
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Amount of commits at the start of history that are scanned to estimate the size of the stats
+/// file.
+const SIZE_SAMPLE: usize = 50;
+/// Amount of the slowest files that are reported for commits exceeding their time budget.
+const SLOWEST_FILES: usize = 5;
+/// Minimum amount of code lines a single commit has to add to be noted as [`Note::Jump`]. It
+/// also has to grow the code by at least half.
+const JUMP_LINES: u64 = 10_000;
+/// Largest packed object that is loaded to count its lines with `--estimate-file-size`. Larger
+/// ones can't be streamed and are estimated from their size alone.
+const MAX_PACKED_ESTIMATE: u64 = 8 * 1024 * 1024;
+/// Assumed length of a line including its line break, for estimates from the size of a file.
+const AVERAGE_LINE_LENGTH: u64 = 40;
+/// Start of the pointer files that Git LFS commits in place of the actual content.
+const LFS_POINTER: &[u8] = b"version https://git-lfs.github.com/spec/";
 
-pub fn run(input: PathBuf) -> Result<()> {
-    let repo = Repository::open(&input)?;
-    let mut walk = repo.revwalk()?;
+#[derive(Args)]
+pub struct Options {
+    /// Resolve symlinks that point to other files inside the repository and count the target's
+    /// content under the link's path, unless the target is counted on its own already. By default
+    /// symlinks are skipped.
+    #[arg(long)]
+    pub follow_symlinks: bool,
+    /// Count the files of submodules below their path, as if they were part of the repository.
+    /// Their repositories are looked up in the `modules` directory of the repository, where
+    /// `git submodule update` clones them, or at their path in the work tree. By default
+    /// submodules are skipped.
+    #[arg(long)]
+    pub recurse_submodules: bool,
+    /// Skip files larger than this amount of bytes instead of parsing them.
+    #[arg(long, default_value_t = DEFAULT_MAX_FILE_SIZE)]
+    pub max_file_size: u64,
+    /// Only estimate the statistics of files larger than this amount of bytes, by counting their
+    /// lines instead of parsing them. All lines that aren't blank are counted as code. This is
+    /// much faster and keeps memory usage low for huge files. Files over 8 MiB that are stored
+    /// in pack files can't be read piece by piece, so their lines are estimated from their size.
+    /// By default all files are parsed.
+    #[arg(long, value_name = "BYTES")]
+    pub estimate_file_size: Option<u64>,
+    /// Count files in dependency and build directories (like `node_modules`, `vendor`, `target`
+    /// and `dist`) and generated protobuf code. By default they are left out, as they are
+    /// usually committed by accident and outweigh the actual code.
+    #[arg(long)]
+    pub no_default_excludes: bool,
+    /// Leave out the files matching this glob, relative to the repository root, like
+    /// `third_party/**` or `**/*.generated.ts`. Can be given multiple times.
+    #[arg(long, value_name = "GLOB", value_parser = excludes::parse_pattern)]
+    pub exclude: Vec<String>,
+    /// Only count the files matching this glob, relative to the repository root, like `src/**`.
+    /// Can be given multiple times, and files that are also excluded are still left out.
+    #[arg(long, value_name = "GLOB", value_parser = excludes::parse_pattern)]
+    pub include: Vec<String>,
+    /// Count files that are marked as `linguist-vendored`, `linguist-generated` or
+    /// `linguist-documentation` in `.gitattributes`, and count files by their extension even if
+    /// `linguist-language` sets another language. By default the attributes are followed, like
+    /// in GitHub's language statistics.
+    #[arg(long)]
+    pub ignore_gitattributes: bool,
+    /// Configuration file of tokei, in the format of `tokei.toml`, for additional file
+    /// extensions and languages. By default the `tokei.toml`, `.tokeirc` and `languages.toml`
+    /// files in the root of the first revision are used.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub tokei_config: Option<PathBuf>,
+    /// Count the files with this extension as the given language, like `tpl=html` or `inc=php`.
+    /// Can be given multiple times, and takes precedence over the extensions of tokei and its
+    /// configuration files.
+    #[arg(long, value_name = "EXT=LANGUAGE", value_parser = tokei_config::parse_mapping)]
+    pub map_ext: Vec<(String, LanguageType)>,
+    /// Time budget for a single commit in seconds. Commits that take longer are reported with
+    /// their slowest files, and the files that weren't parsed in time are left out of the
+    /// commit's statistics, which is flagged as partial. By default there is no limit.
+    #[arg(long, value_name = "SECONDS")]
+    pub commit_timeout: Option<u64>,
+    /// Continue the scan when a commit can't be read, even after retrying. Failed commits are
+    /// recorded without any files and left out when rendering, instead of aborting the scan.
+    #[arg(long)]
+    pub keep_going: bool,
+    /// Record the time spent in each phase of the scan, split by language, and write it to this
+    /// file. The report uses the speedscope format, which can be viewed as flame graph.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub profile: Option<PathBuf>,
+    /// Don't check for enough free disk space before scanning. The check estimates the size of
+    /// the stats file from the first commits, which may be off for unusual histories.
+    #[arg(long)]
+    pub skip_space_check: bool,
+    /// Stats file of a repository that this one was split off from, without keeping its history.
+    /// Entries of that file from before the first commit of this repository are put in front, so
+    /// both form one continuous timeline.
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    pub graft: Option<PathBuf>,
+    /// Directory inside the grafted repository that this repository was split off from. Only
+    /// files below it are taken over, relative to it. Defaults to the whole repository.
+    #[arg(long, value_name = "PATH", requires = "graft")]
+    pub graft_dir: Option<String>,
+    /// Keep the entries of an existing output file and only scan the commits that are newer than
+    /// its latest entry of each revision. The file must have been scanned with the same options.
+    #[arg(long, conflicts_with = "graft")]
+    pub update: bool,
+    /// Count the documented and undocumented public items of Rust files, to follow the
+    /// documentation coverage of the API. Parsing the files a second time makes the scan slower.
+    #[arg(long)]
+    pub api_docs: bool,
+    /// Split comment lines into natural language and commented-out code, using the heuristics
+    /// of the `comment-heuristics` table in the configuration file.
+    #[arg(long)]
+    pub comment_quality: bool,
+    /// Leave out boilerplate comments at the start of files, like shebangs, encoding
+    /// declarations and SPDX tags, instead of counting them as comment lines.
+    #[arg(long)]
+    pub ignore_boilerplate: bool,
+    /// Check the header of each file for an `SPDX-License-Identifier` tag, to follow how many
+    /// files declare their license.
+    #[arg(long)]
+    pub spdx: bool,
+    /// Revision to scan, like a branch, tag or commit. Can be given multiple times to record the
+    /// history of each revision separately in the same stats file. Defaults to `HEAD`. Nothing
+    /// needs to be checked out for it.
+    #[arg(long = "rev", visible_aliases = ["ref", "branch"], value_name = "REV")]
+    pub revs: Vec<String>,
+    /// Only scan the commits reachable from the second revision but not from the first, like
+    /// `v1.0..main`. The first commit of the range is scanned with all its files, so the entries
+    /// still cover the whole repository.
+    #[arg(long, value_name = "REV..REV", conflicts_with = "revs")]
+    pub range: Option<String>,
+    /// Only follow the first parent of merge commits, so merge-heavy histories get one entry per
+    /// commit of the main line, instead of interleaving the commits of all merged branches.
+    #[arg(long)]
+    pub first_parent: bool,
+    /// Leave out merge commits, whose changes were already counted in the commits of the merged
+    /// branches. The commits after them are compared to the last scanned commit instead.
+    #[arg(long)]
+    pub no_merges: bool,
+    /// Write a commit-graph file to the repository before reading its history, if it has none,
+    /// which speeds up the walk over histories with hundreds of thousands of commits. This runs
+    /// `git commit-graph write`, changing the scanned repository.
+    #[arg(long)]
+    pub write_commit_graph: bool,
+    /// Render a chart of the commits scanned so far every time this many more were scanned, to
+    /// check the filters and output early on. It's written next to the output with the extension
+    /// `.preview.svg` and the default render options, and has gaps where commits are still
+    /// being scanned.
+    #[arg(
+        long,
+        value_name = "COMMITS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "recipients"
+    )]
+    pub preview_every: Option<u64>,
+    /// Only scan every n-th commit, and always the latest one, to cut down the scan time of huge
+    /// histories. Applied after `--snapshot-interval`.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub sample_every: Option<u64>,
+    /// Only scan the latest commit of each day, week or month, which still gives a smooth chart
+    /// for long histories.
+    #[arg(long, value_enum, value_name = "INTERVAL")]
+    pub snapshot_interval: Option<Interval>,
+    /// Only scan the commits made on or after this date, like `2024-01-01`.
+    #[arg(long, value_name = "DATE")]
+    pub since: Option<NaiveDate>,
+    /// Only scan the commits made on or before this date, like `2024-12-31`.
+    #[arg(long, value_name = "DATE")]
+    pub until: Option<NaiveDate>,
+    /// How detailed the statistics of each commit are recorded. Less detail makes the stats file
+    /// smaller and faster to load, but can't be grouped by language or inspected per file.
+    #[arg(long, value_enum, default_value_t = Detail::PerFile)]
+    pub detail: Detail,
+    /// Compression of the entries in the stats file.
+    #[arg(long, value_enum, default_value_t = Compression::Zstd)]
+    pub compression: Compression,
+    /// Zstd level from -7 (fastest) to 22 (smallest). Higher levels make the scan slower, but
+    /// the stats file smaller.
+    #[arg(
+        long,
+        default_value_t = ZSTD_COMPRESSION_DEFAULT,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-7..=22),
+    )]
+    pub compression_level: i32,
+    /// Encrypt the stats file with age for this recipient, like an `age1...` key or an SSH
+    /// public key. Can be given multiple times. Commands that read the file decrypt it with the
+    /// identity file given in the `COMMENTSTATS_AGE_IDENTITY` environment variable.
+    #[arg(long = "encrypt", value_name = "RECIPIENT")]
+    pub recipients: Vec<String>,
+    /// Sign the stats file with this SSH private key, writing the signature next to it with an
+    /// additional `.sig` extension. Commands that read the file verify it against the allowed
+    /// signers file given in the `COMMENTSTATS_ALLOWED_SIGNERS` environment variable, if set.
+    #[arg(long, value_name = "KEY", value_hint = ValueHint::FilePath)]
+    pub sign: Option<PathBuf>,
+    /// Only record the selected languages, leaving out all others from the stats file.
+    #[command(flatten)]
+    pub filter: FilterArgs,
+}
 
-    println!("reading history...");
+/// Period to scan a single snapshot of, see [`Options::snapshot_interval`].
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Interval {
+    /// The latest commit of each day.
+    Daily,
+    /// The latest commit of each week, starting on Monday.
+    Weekly,
+    /// The latest commit of each calendar month.
+    Monthly,
+}
+
+impl Interval {
+    fn period(self) -> Period {
+        match self {
+            Self::Daily => Period::Day,
+            Self::Weekly => Period::Week,
+            Self::Monthly => Period::Month,
+        }
+    }
+}
 
-    walk.push_head()?;
-    walk.set_sorting(Sort::TIME | Sort::REVERSE)?;
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            follow_symlinks: false,
+            recurse_submodules: false,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            estimate_file_size: None,
+            no_default_excludes: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            ignore_gitattributes: false,
+            tokei_config: None,
+            map_ext: Vec::new(),
+            commit_timeout: None,
+            keep_going: false,
+            profile: None,
+            skip_space_check: false,
+            graft: None,
+            graft_dir: None,
+            update: false,
+            api_docs: false,
+            comment_quality: false,
+            ignore_boilerplate: false,
+            spdx: false,
+            revs: Vec::new(),
+            range: None,
+            first_parent: false,
+            no_merges: false,
+            write_commit_graph: false,
+            sample_every: None,
+            preview_every: None,
+            snapshot_interval: None,
+            since: None,
+            until: None,
+            detail: Detail::PerFile,
+            compression: Compression::Zstd,
+            compression_level: ZSTD_COMPRESSION_DEFAULT,
+            recipients: Vec::new(),
+            sign: None,
+            filter: FilterArgs::default(),
+        }
+    }
+}
 
-    let oids = walk
-        .map(|oid| oid.map_err(Into::into))
-        .collect::<Result<Vec<_>>>()?;
+pub fn run(input: PathBuf, output: &Path, options: &Options, config: &Config) -> Result<()> {
+    let languages = options.filter.resolve(config)?;
 
-    let dir = tempfile::tempdir()?;
-    let config = bincode::config::standard();
+    let repo = open_repository(&input)?;
+    let warnings = Warnings::default();
 
-    let mut info_file = new_zstd_file(dir.path().join("info"))?;
-    bincode::encode_into_std_write(oids.len() as u64, &mut info_file, config)?;
-    info_file.finish()?.flush()?;
+    if options.write_commit_graph && !commit_graph::exists(&repo) {
+        println!("writing commit graph...");
 
-    println!("scanning...");
+        // The graph only speeds up the scan, which works just as well without it.
+        if let Err(e) = commit_graph::write(&repo) {
+            warnings.warn(
+                Category::Other,
+                format_args!("failed writing the commit graph: {e:#}"),
+            );
+        }
+    }
 
-    let (progress, updater) = Progress::new(oids.len() as u64);
+    let (revisions, hide) = match &options.range {
+        Some(range) => {
+            let (reference, oid, hide) = range_revisions(&repo, range)?;
+            (vec![(Some(reference), oid)], Some(hide))
+        }
+        None => (revisions(&repo, &options.revs)?, None),
+    };
 
-    let chunk_size = MIN_CHUNK_SIZE.max(oids.len() / CHUNK_AMOUNT);
+    println!("reading history...");
+
+    let mut histories = revisions
+        .iter()
+        .map(|&(_, oid)| history(&repo, oid, hide, options))
+        .collect::<Result<Vec<_>>>()?;
+
+    let limited = options.range.is_some() || options.since.is_some() || options.until.is_some();
+    ensure!(
+        !limited || histories.iter().any(|oids| !oids.is_empty()),
+        "no commits within the given range and dates"
+    );
 
-    oids.par_chunks(chunk_size).enumerate().try_for_each_init(
-        || Repository::open(&input),
-        |repo, (i, chunk)| -> Result<()> {
-            let repo = repo.as_ref().map_err(|e| anyhow!("{}", e))?;
+    let dir = tempfile::tempdir()?;
+    let dir_path = long_path(dir.path())?;
+
+    // Chunks that are put in front of each history, either kept from the previous scan or
+    // taken over from a graft.
+    let mut grafts = Vec::with_capacity(histories.len());
 
-            let mut file = new_zstd_file(dir.path().join(format!("stats-{:03}", i,)))?;
-            bincode::encode_into_std_write(chunk.len() as u64, &mut file, config)?;
+    if options.update && output.exists() {
+        println!("keeping previous scan...");
 
-            let mut previous_entry = None;
-            let mut previous_tree = None;
+        // Updating must never leave a decrypted copy of an encrypted file behind.
+        ensure!(
+            !options.recipients.is_empty() || !crypt::is_encrypted(File::open(output)?)?,
+            "the previous scan is encrypted, give its recipients with --encrypt again"
+        );
 
-            for &oid in chunk {
-                let (entry, tree) =
-                    commit_stats(repo, oid, previous_entry, previous_tree, &updater)?;
+        let previous = Previous::open(output, options)?;
 
-                bincode::serde::encode_into_std_write(&entry, &mut file, config)?;
+        for ((reference, _), oids) in revisions.iter().zip(&mut histories) {
+            let Some((range, latest)) = previous.history(reference.as_deref())? else {
+                grafts.push(Vec::new());
+                continue;
+            };
 
-                previous_entry = Some(entry);
-                previous_tree = Some(tree);
+            let mut newer = Vec::with_capacity(oids.len());
+            for &oid in oids.iter() {
+                if commit_time(&repo.find_commit(oid)?)? > latest {
+                    newer.push(oid);
+                }
             }
+            *oids = newer;
 
-            file.finish()?.flush()?;
+            let offset = grafts.iter().map(Vec::len).sum();
+            grafts.push(previous.write(&dir_path, offset, range)?);
+        }
+    }
 
-            Ok(())
-        },
+    let total = histories.iter().map(Vec::len).sum::<usize>();
+
+    let promisor = partial::promisor(&repo);
+    let partial = promisor.is_some();
+    if let Some(remote) = &promisor {
+        println!("fetching missing objects...");
+
+        // Without the objects, the scan still works but leaves out the files that are missing.
+        if let Err(e) = partial::fetch_missing(&repo, &input, remote, &histories) {
+            warnings.warn(
+                Category::Other,
+                format_args!(
+                    "failed fetching missing objects from {remote}, their files are skipped: {e:#}"
+                ),
+            );
+        }
+    }
+
+    let tokei = Tokei::load(
+        &repo,
+        revisions.first().map(|&(_, oid)| oid),
+        options.tokei_config.as_deref(),
+        &options.map_ext,
+        &warnings,
     )?;
 
+    if !options.skip_space_check {
+        println!("estimating output size...");
+
+        let tips = revisions.iter().map(|&(_, oid)| oid).collect::<Vec<_>>();
+        let size = estimate_size(
+            &repo, &histories, &tips, options, &languages, &tokei, partial,
+        )
+        .context("failed estimating output size")?;
+        let output_dir = output
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        // Chunks are written to the temporary directory first, and then copied into the output.
+        space::check(size, &[&dir_path, output_dir])?;
+    }
+
+    let mut detail = options.detail;
+    if let Some(path) = &options.graft {
+        println!("grafting history...");
+
+        let graft = Graft::open(path, options.graft_dir.as_deref())?;
+        // Grafted entries can't gain any detail they didn't have before.
+        detail = detail.max(graft.detail());
+
+        for oids in &histories {
+            let Some(&first) = oids.first() else {
+                grafts.push(Vec::new());
+                continue;
+            };
+
+            let cutoff = commit_time(&repo.find_commit(first)?)?;
+            let offset = grafts.iter().map(Vec::len).sum();
+            grafts.push(graft.write(&dir_path, offset, cutoff, &languages, detail)?);
+        }
+    }
+
+    println!("scanning...");
+
+    let (progress, updater) = Progress::new(total as u64);
+    let shared = Shared {
+        options,
+        languages,
+        tokei,
+        updater,
+        warnings,
+        open_chunks: OpenChunks::default(),
+        symlinks: Symlinks::default(),
+        watchdog: Watchdog::new(options.commit_timeout.map(Duration::from_secs)),
+        profile: options.profile.is_some().then(Profile::default),
+        heuristics: options
+            .comment_quality
+            .then(|| config.comment_heuristics.clone()),
+        excludes: Excludes::new(
+            !options.no_default_excludes,
+            &options.exclude,
+            &options.include,
+        )?,
+        deltas: Deltas::default(),
+        partial,
+    };
+
+    let mut chunks = Vec::new();
+    let mut metadata = Metadata {
+        name: repo_name(&repo),
+        histories: Vec::with_capacity(histories.len()),
+        detail,
+        api_docs: options.api_docs,
+        comment_quality: options.comment_quality,
+        spdx: options.spdx,
+    };
+
+    let preview = options.preview_every.map(|every| {
+        let histories = revisions
+            .iter()
+            .map(|(reference, oid)| History {
+                reference: reference.clone(),
+                commit: Some(oid.to_string()),
+                chunks: 0,
+            })
+            .collect();
+        let metadata = Metadata {
+            histories,
+            ..metadata.clone()
+        };
+        Preview::new(
+            every,
+            &dir_path,
+            &Preview::path(output),
+            config,
+            metadata,
+            &grafts,
+        )
+    });
+
+    let mut done = 0;
+    thread::scope(|scope| -> Result<()> {
+        let _watchdog = shared.watchdog.spawn(scope, &shared.warnings);
+
+        // Grafted chunks are numbered first, but each is put in front of its history.
+        let mut offset = grafts.iter().map(Vec::len).sum();
+        let mut grafts = grafts.into_iter();
+
+        for (history, ((reference, oid), oids)) in revisions.into_iter().zip(&histories).enumerate()
+        {
+            let mut history_chunks = grafts.next().unwrap_or_default();
+            let target = Target {
+                dir: &dir_path,
+                history,
+                offset,
+                preview: preview.as_ref(),
+            };
+            let scanned = scan_history(&input, &target, oids, &shared)?;
+            offset += scanned.len();
+            done += scanned.iter().map(|chunk| chunk.entries).sum::<u64>();
+            history_chunks.extend(scanned);
+
+            metadata.histories.push(History {
+                reference,
+                commit: Some(oid.to_string()),
+                chunks: history_chunks.len(),
+            });
+            chunks.extend(history_chunks);
+        }
+
+        Ok(())
+    })?;
+
     progress.wait()?;
 
-    println!("saving statistics...");
+    // Stopped scans keep the commits that were done, so the next update continues from there.
+    if done < total as u64 {
+        shared.warnings.warn(
+            Category::Partial,
+            format_args!("the scan was stopped, saving {done} of {total} commits"),
+        );
+    }
 
-    let mut files = std::fs::read_dir(dir.path())?
-        .map(|r| r.map(|e| e.path()).map_err(Into::into))
-        .collect::<Result<Vec<_>>>()?;
+    println!("saving statistics...");
 
-    files.sort();
+    let manifest = Manifest {
+        version: FORMAT_VERSION,
+        entries: chunks.iter().map(|chunk| chunk.entries).sum(),
+        chunks,
+        metadata,
+    };
 
-    let mut zip_file = ZipWriter::new(BufWriter::new(File::create("stats.stats")?));
-    let mut pb = ProgressBar::new(files.len() as u64);
+    let mut pb = ProgressBar::new(manifest.chunks.len() as u64);
     pb.set_width(Some(80));
 
-    for path in &files {
-        let mut file = File::open(path)?;
-        let name = path.file_name().unwrap().to_string_lossy();
+    let mut staged = Staged::new(output)?;
 
-        zip_file.start_file(name, FileOptions::default())?;
-        io::copy(&mut file, &mut zip_file)?;
+    // Encrypted files are only written in plain text to the temporary directory.
+    let plain = if options.recipients.is_empty() {
+        staged.path().to_owned()
+    } else {
+        dir_path.join("stats.plain")
+    };
 
+    stats_file::write(&plain, &dir_path, &manifest, || {
         pb.inc();
-    }
+    })?;
 
-    zip_file.finish()?.flush()?;
     pb.finish();
+    println!();
+
+    if !options.recipients.is_empty() {
+        println!("encrypting statistics...");
+        crypt::encrypt(&plain, staged.path(), &options.recipients)?;
+    }
+
+    if let Some(key) = &options.sign {
+        staged.sign(key)?;
+    }
+    staged.commit()?;
+
+    if let (Some(profile), Some(path)) = (&shared.profile, &options.profile) {
+        profile.write(path)?;
+    }
+
+    shared.warnings.print_summary();
 
     Ok(())
 }
 
+/// Resolve the revisions to scan, together with the name they are recorded under. Without any
+/// explicitly given revisions, only `HEAD` is scanned.
+fn revisions(repo: &Repository, revs: &[String]) -> Result<Vec<(Option<String>, Oid)>> {
+    if revs.is_empty() {
+        let head = repo.head()?;
+        let reference = (head.is_branch() || head.is_tag())
+            .then(|| head.shorthand().map(str::to_owned))
+            .flatten();
+
+        return Ok(vec![(reference, head.peel_to_commit()?.id())]);
+    }
+
+    let mut seen = HashSet::new();
+
+    revs.iter()
+        .map(|rev| {
+            ensure!(seen.insert(rev), "revision `{rev}` given more than once");
+
+            let commit = repo
+                .revparse_single(rev)
+                .and_then(|object| object.peel_to_commit())
+                .with_context(|| format!("failed resolving revision `{rev}`"))?;
+
+            Ok((Some(rev.clone()), commit.id()))
+        })
+        .collect()
+}
+
+/// Resolve a `--range` like `v1.0..main` to the name and commit of its end, and the commit of
+/// its start, whose history is left out.
+fn range_revisions(repo: &Repository, range: &str) -> Result<(String, Oid, Oid)> {
+    let Some((from, to)) = range
+        .split_once("..")
+        .filter(|(_, to)| !to.starts_with('.'))
+    else {
+        bail!("invalid range `{range}`, expected two revisions like `v1.0..main`");
+    };
+    // Like Git, an omitted side of the range stands for `HEAD`.
+    let [from, to] = [from, to].map(|rev| if rev.is_empty() { "HEAD" } else { rev });
+
+    let resolve = |rev: &str| {
+        repo.revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .with_context(|| format!("failed resolving revision `{rev}`"))
+    };
+
+    Ok((to.to_owned(), resolve(to)?, resolve(from)?))
+}
+
+/// Collect all commits reachable from the given one, in the order they are stored. Leaves out
+/// the history of `hide`, the commits outside of the `--since` and `--until` dates and, with
+/// `--first-parent` or `--no-merges`, the commits of merged branches or the merges themselves.
+fn history(repo: &Repository, oid: Oid, hide: Option<Oid>, options: &Options) -> Result<Vec<Oid>> {
+    let mut walk = repo.revwalk()?;
+
+    walk.push(oid)?;
+    if let Some(hide) = hide {
+        walk.hide(hide)?;
+    }
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    if options.first_parent {
+        walk.simplify_first_parent()?;
+    }
+
+    let mut commits = Vec::new();
+    for oid in walk {
+        let oid = oid?;
+        let commit = retry(|| repo.find_commit(oid))?;
+        if options.no_merges && commit.parent_count() > 1 {
+            continue;
+        }
+
+        // Dates are compared in the time zone the commit was made in.
+        let date = commit_time(&commit)?.date_naive();
+        if options.since.is_some_and(|since| date < since)
+            || options.until.is_some_and(|until| date > until)
+        {
+            continue;
+        }
+
+        commits.push((commit.time().seconds(), date, oid));
+    }
+
+    // Entries are ordered by commit time. The sort is stable, so commits with the same timestamp
+    // keep their topological order (parents before children), which makes the output
+    // reproducible regardless of how the history was created.
+    commits.sort_by_key(|&(time, _, _)| time);
+
+    Ok(sample(
+        commits
+            .into_iter()
+            .map(|(_, date, oid)| (date, oid))
+            .collect(),
+        options,
+    ))
+}
+
+/// Thin out the ordered commits to the ones selected by `--snapshot-interval` and
+/// `--sample-every`. The latest commit is always kept, so the scan ends at the current state.
+fn sample(commits: Vec<(NaiveDate, Oid)>, options: &Options) -> Vec<Oid> {
+    let mut commits = commits;
+
+    if let Some(period) = options.snapshot_interval.map(Interval::period) {
+        commits = commits
+            .iter()
+            .enumerate()
+            .filter(|&(i, &(date, _))| {
+                commits
+                    .get(i + 1)
+                    .is_none_or(|&(next, _)| period.start(next) != period.start(date))
+            })
+            .map(|(_, &commit)| commit)
+            .collect();
+    }
+
+    if let Some(every) = options.sample_every {
+        let last = commits.len().saturating_sub(1);
+        commits = commits
+            .into_iter()
+            .enumerate()
+            .filter(|&(i, _)| (i as u64).is_multiple_of(every) || i == last)
+            .map(|(_, commit)| commit)
+            .collect();
+    }
+
+    commits.into_iter().map(|(_, oid)| oid).collect()
+}
+
+/// Estimate the size of the stats file, by encoding the entries of a sample of commits from the
+/// start of the first history. The size per file is extrapolated to all histories, assuming their
+/// amount of files grew linearly up to the one of their `tips`.
+fn estimate_size(
+    repo: &Repository,
+    histories: &[Vec<Oid>],
+    tips: &[Oid],
+    options: &Options,
+    languages: &HashSet<LanguageType>,
+    tokei: &Tokei,
+    partial: bool,
+) -> Result<u64> {
+    let Some(first) = histories.first() else {
+        return Ok(0);
+    };
+
+    let shared = Shared {
+        options,
+        languages: languages.clone(),
+        tokei: tokei.clone(),
+        updater: Updater::default(),
+        // The sample is scanned again later, which reports any issues.
+        warnings: Warnings::quiet(),
+        open_chunks: OpenChunks::default(),
+        symlinks: Symlinks::default(),
+        watchdog: Watchdog::new(None),
+        profile: None,
+        heuristics: None,
+        excludes: Excludes::new(
+            !options.no_default_excludes,
+            &options.exclude,
+            &options.include,
+        )?,
+        deltas: Deltas::default(),
+        partial,
+    };
+
+    let sample = &first[..first.len().min(SIZE_SAMPLE)];
+    let mut bases = Bases::new(repo, sample)?;
+    let mut attributes = attributes::Cache::default();
+    let mut counter = SizeCounter::new(options.compression, options.compression_level)?;
+    let (mut commits, mut files) = (0, 0);
+
+    for &oid in sample {
+        let base = bases.take(oid);
+        let (entry, tree) = match commit_stats(repo, oid, base, &mut attributes, &shared) {
+            Ok(stats) => stats,
+            // Failed commits are reported by the actual scan.
+            Err(_) if options.keep_going => continue,
+            Err(e) => return Err(e),
+        };
+
+        counter.add(&entry.reduce(options.detail)?)?;
+        commits += 1;
+        files += entry.files.len();
+
+        bases.insert(oid, entry, tree);
+    }
+
+    let size = counter.finish()? as f64;
+    let per_commit = size / commits.max(1) as f64;
+    let per_file = size / files.max(1) as f64;
+    let sample_files = files as f64 / commits.max(1) as f64;
+
+    let mut total = 0.0;
+    for (oids, &tip) in histories.iter().zip(tips) {
+        let files = (sample_files + count_files(repo, tip, &shared)? as f64) / 2.0;
+        total += oids.len() as f64 * per_commit.max(per_file * files);
+    }
+
+    Ok(total as u64)
+}
+
+/// Count the files of a commit that would be recorded, by their path only.
+fn count_files(repo: &Repository, oid: Oid, shared: &Shared<'_>) -> Result<usize> {
+    let tree = repo.find_commit(oid)?.tree()?;
+    let mut count = 0;
+
+    tree.walk(TreeWalkMode::PreOrder, |_, item| {
+        let recorded = item.kind() == Some(ObjectType::Blob)
+            && item
+                .name()
+                .and_then(|name| shared.tokei.language(name))
+                .is_some_and(|lang| {
+                    shared.languages.is_empty() || shared.languages.contains(&lang)
+                });
+        if recorded {
+            count += 1;
+        }
+
+        TreeWalkResult::Ok
+    })?;
+
+    Ok(count)
+}
+
+/// Where the chunks of a single history go.
+struct Target<'a> {
+    /// Directory that the chunks are written to.
+    dir: &'a Path,
+    /// Index of the history, in the order of the revisions.
+    history: usize,
+    /// Number of the first chunk of the history.
+    offset: usize,
+    /// Previews to add the finished chunks to, if enabled.
+    preview: Option<&'a Preview<'a>>,
+}
+
+/// Scan the commits of a single history into chunks. Once the service is asked to stop, no further
+/// chunks are started, and only the ones up to the first unfinished chunk are returned, so the
+/// history stays complete up to the point where it was stopped.
+fn scan_history(
+    input: &Path,
+    target: &Target<'_>,
+    oids: &[Oid],
+    shared: &Shared<'_>,
+) -> Result<Vec<ChunkInfo>> {
+    let chunk_size = MIN_CHUNK_SIZE.max(oids.len() / CHUNK_AMOUNT);
+
+    oids.par_chunks(chunk_size)
+        .enumerate()
+        .map_init(
+            || open_repository(input),
+            |repo, (i, chunk)| -> Result<Option<ChunkInfo>> {
+                if daemon::stop_requested() {
+                    return Ok(None);
+                }
+
+                let repo = repo.as_ref().map_err(|e| anyhow!("{}", e))?;
+
+                let _slot = shared.open_chunks.acquire();
+                let mut file = ChunkWriter::create_with(
+                    target.dir,
+                    target.offset + i,
+                    chunk.len() as u64,
+                    shared.options.compression,
+                    shared.options.compression_level,
+                )?;
+                let mut bases = Bases::new(repo, chunk)?;
+                let mut attributes = attributes::Cache::default();
+
+                for &oid in chunk {
+                    let base = bases.take(oid);
+                    let (entry, tree) = match commit_stats(repo, oid, base, &mut attributes, shared)
+                    {
+                        Ok(stats) => stats,
+                        Err(e) if shared.options.keep_going => {
+                            shared
+                                .warnings
+                                .fail(format_args!("{oid}: failed scanning commit: {e:#}"));
+                            file.write(&failed_entry(repo, oid))?;
+                            shared.updater.inc();
+                            continue;
+                        }
+                        Err(e) => return Err(e.context(format!("failed scanning commit {oid}"))),
+                    };
+
+                    shared.time(Phase::Encode, None, || match shared.options.detail {
+                        Detail::PerFile => file.write(&entry),
+                        detail => file.write(&entry.reduce(detail)?),
+                    })?;
+
+                    bases.insert(oid, entry, tree);
+                }
+
+                let info = file.finish()?;
+                if let Some(preview) = target.preview {
+                    let index = target.offset + i;
+                    // Previews are only a convenience, which must not fail the scan.
+                    if let Err(e) = preview.add(target.history, index, &info, chunk.len() as u64) {
+                        shared.warnings.warn(
+                            Category::Other,
+                            format_args!("failed rendering preview: {e:#}"),
+                        );
+                    }
+                }
+
+                Ok(Some(info))
+            },
+        )
+        .collect::<Result<Vec<_>>>()
+        .map(|chunks| chunks.into_iter().map_while(|chunk| chunk).collect())
+}
+
+/// Name of the repository, derived from its directory.
+fn repo_name(repo: &Repository) -> Option<String> {
+    let dir = repo.workdir().unwrap_or_else(|| repo.path());
+    let name = dir.canonicalize().ok()?.file_name()?.to_str()?.to_owned();
+
+    Some(name.strip_suffix(".git").map(str::to_owned).unwrap_or(name))
+}
+
+/// Compute the statistics of the current `HEAD` commit of a repository only, without walking
+/// its history.
+pub fn head_entry(input: &Path, options: &Options, config: &Config) -> Result<Entry> {
+    let repo = open_repository(input)?;
+    let oid = repo
+        .head()?
+        .peel_to_commit()
+        .context("HEAD doesn't point to a commit")?
+        .id();
+
+    let warnings = Warnings::default();
+    let promisor = partial::promisor(&repo);
+    let partial = promisor.is_some();
+    if let Some(remote) = &promisor {
+        if let Err(e) = partial::fetch_missing(&repo, input, remote, &[vec![oid]]) {
+            warnings.warn(
+                Category::Other,
+                format_args!(
+                    "failed fetching missing objects from {remote}, their files are skipped: {e:#}"
+                ),
+            );
+        }
+    }
+
+    let shared = Shared {
+        options,
+        languages: options.filter.resolve(config)?,
+        tokei: Tokei::load(
+            &repo,
+            Some(oid),
+            options.tokei_config.as_deref(),
+            &options.map_ext,
+            &warnings,
+        )?,
+        updater: Updater::default(),
+        warnings,
+        open_chunks: OpenChunks::default(),
+        symlinks: Symlinks::default(),
+        watchdog: Watchdog::new(options.commit_timeout.map(Duration::from_secs)),
+        profile: None,
+        heuristics: None,
+        excludes: Excludes::new(
+            !options.no_default_excludes,
+            &options.exclude,
+            &options.include,
+        )?,
+        deltas: Deltas::default(),
+        partial,
+    };
+
+    let (entry, _) = thread::scope(|scope| {
+        let _watchdog = shared.watchdog.spawn(scope, &shared.warnings);
+        commit_stats(&repo, oid, None, &mut attributes::Cache::default(), &shared)
+    })?;
+    shared.warnings.print_summary();
+
+    Ok(entry)
+}
+
+/// Open the repository at the path, together with the object directories in
+/// `GIT_ALTERNATE_OBJECT_DIRECTORIES`, like git does. The alternates of `objects/info/alternates`,
+/// as set up by reference clones and shared object stores, are followed by libgit2 itself.
+pub fn open_repository(path: &Path) -> Result<Repository, git2::Error> {
+    let repo = Repository::open(path)?;
+
+    if let Some(dirs) = env::var_os("GIT_ALTERNATE_OBJECT_DIRECTORIES") {
+        let odb = repo.odb()?;
+        for dir in env::split_paths(&dirs) {
+            if dir.as_os_str().is_empty() {
+                continue;
+            }
+            let dir = dir.to_str().ok_or_else(|| {
+                git2::Error::from_str("alternate object directory with non UTF-8 path")
+            })?;
+            odb.add_disk_alternate(dir)?;
+        }
+    }
+
+    Ok(repo)
+}
+
+/// Tracker for the states that commits of a chunk are diffed against.
+///
+/// Each commit is based on its first parent, so merges and interleaved branches only need to
+/// process the files that actually changed in that commit. States are kept around just as long
+/// as commits of the chunk still refer to them, and are moved out instead of cloned whenever
+/// possible.
+struct Bases<'a> {
+    parents: HashMap<Oid, Option<Oid>>,
+    /// Amount of commits in the chunk that still need the state of a commit as base.
+    children: HashMap<Oid, usize>,
+    states: HashMap<Oid, (Entry, Tree<'a>)>,
+    /// The last computed state that isn't needed by any other commit. Used as cheapest available
+    /// base when the parent is not part of the chunk.
+    previous: Option<(Entry, Tree<'a>)>,
+}
+
+impl<'a> Bases<'a> {
+    fn new(repo: &Repository, chunk: &[Oid]) -> Result<Self> {
+        let parents = chunk
+            .iter()
+            .map(|&oid| Ok((oid, retry(|| repo.find_commit(oid))?.parent_ids().next())))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let mut children = HashMap::<_, usize>::new();
+        for parent in parents.values().flatten() {
+            *children.entry(*parent).or_default() += 1;
+        }
+
+        Ok(Self {
+            parents,
+            children,
+            states: HashMap::new(),
+            previous: None,
+        })
+    }
+
+    /// Get the base for the given commit: its first parent's state if available, nothing for
+    /// root commits or the last computed state as fallback if the parent isn't part of the
+    /// chunk. Diffing against any state gives correct results, the parent is just the cheapest.
+    fn take(&mut self, oid: Oid) -> Option<(Entry, Tree<'a>)> {
+        let parent = self.parents.get(&oid).copied().flatten()?;
+
+        if let Some(remaining) = self.children.get_mut(&parent) {
+            *remaining -= 1;
+
+            if *remaining > 0 {
+                if let Some((entry, tree)) = self.states.get(&parent) {
+                    return Some((entry.clone(), tree.clone()));
+                }
+            } else if let Some(state) = self.states.remove(&parent) {
+                return Some(state);
+            }
+        }
+
+        self.previous.take()
+    }
+
+    fn insert(&mut self, oid: Oid, entry: Entry, tree: Tree<'a>) {
+        if self.children.get(&oid).is_some_and(|&count| count > 0) {
+            self.states.insert(oid, (entry, tree));
+        } else {
+            self.previous = Some((entry, tree));
+        }
+    }
+}
+
+/// State shared between all workers of a scan.
+struct Shared<'a> {
+    options: &'a Options,
+    /// Languages to record, or all if empty.
+    languages: HashSet<LanguageType>,
+    tokei: Tokei,
+    updater: Updater,
+    warnings: Warnings,
+    open_chunks: OpenChunks,
+    symlinks: Symlinks,
+    watchdog: Watchdog,
+    /// Recorded times of the scan phases, if requested.
+    profile: Option<Profile>,
+    /// Heuristics to classify comment lines with, if requested.
+    heuristics: Option<Heuristics>,
+    /// Default exclusions, unless disabled, and the ones from the options.
+    excludes: Excludes,
+    deltas: Deltas,
+    /// Whether the repository is a partial clone, where the content of files may be missing.
+    partial: bool,
+}
+
+impl Shared<'_> {
+    /// Run `f`, recording its time in the profile if enabled.
+    fn time<T>(&self, phase: Phase, language: Option<LanguageType>, f: impl FnOnce() -> T) -> T {
+        match &self.profile {
+            Some(profile) => profile.time(phase, language, f),
+            None => f(),
+        }
+    }
+}
+
+/// Counting semaphore that limits the amount of chunk files open at the same time to
+/// [`MAX_OPEN_CHUNKS`].
+#[derive(Default)]
+struct OpenChunks {
+    count: Mutex<usize>,
+    released: Condvar,
+}
+
+impl OpenChunks {
+    fn acquire(&self) -> OpenChunk<'_> {
+        let mut count = self.count.lock().unwrap_or_else(PoisonError::into_inner);
+        while *count >= MAX_OPEN_CHUNKS {
+            count = self
+                .released
+                .wait(count)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+
+        *count += 1;
+        OpenChunk(self)
+    }
+}
+
+struct OpenChunk<'a>(&'a OpenChunks);
+
+impl Drop for OpenChunk<'_> {
+    fn drop(&mut self) {
+        *self.0.count.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Turn the path into an extended-length path on Windows, which lifts the limit of 260
+/// characters per path. A no-op on other platforms.
+#[cfg(windows)]
+fn long_path(path: &Path) -> Result<PathBuf> {
+    // Canonical paths on Windows always use the `\\?\` prefix.
+    std::fs::canonicalize(path).map_err(Into::into)
+}
+
+#[cfg(not(windows))]
+fn long_path(path: &Path) -> Result<PathBuf> {
+    Ok(path.to_owned())
+}
+
 fn commit_stats<'a>(
     repo: &'a Repository,
     oid: Oid,
-    previous_entry: Option<Entry>,
-    previous_tree: Option<Tree<'_>>,
-    updater: &Updater,
+    base: Option<(Entry, Tree<'_>)>,
+    attributes: &mut attributes::Cache,
+    shared: &Shared<'_>,
 ) -> Result<(Entry, Tree<'a>)> {
-    let config = TokeiConfig::default();
-    let commit = repo.find_commit(oid)?;
-    let tree = commit.tree()?;
-    let time = commit.time();
-    let time = FixedOffset::east_opt(time.offset_minutes() * 60)
-        .context("offset out of bounds")?
-        .from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(time.seconds(), 0)
-                .context("timestamp out of bounds")?,
-        );
+    let warnings = &shared.warnings;
+    let mut budget = Budget::new(oid, &shared.watchdog);
+    // Histories start without a base, which isn't a jump of its own.
+    let before = base.as_ref().map(|(entry, _)| code_lines(entry));
+    let commit = retry(|| repo.find_commit(oid))?;
+    let tree = retry(|| commit.tree())?;
+
+    let rules = if shared.options.ignore_gitattributes {
+        None
+    } else {
+        Some(attributes.load(repo, &tree)?)
+    };
+
+    // Changed attributes can exclude or include files that are otherwise untouched by the diff,
+    // so the whole tree has to be re-evaluated.
+    let base = match (base, &rules) {
+        (Some((_, base_tree)), Some(rules))
+            if attributes.load(repo, &base_tree)?.fingerprint() != rules.fingerprint() =>
+        {
+            None
+        }
+        (base, _) => base,
+    };
+    let rules = rules.as_ref();
+
+    let time = commit_time(&commit)?;
 
-    let diff = repo.diff_tree_to_tree(previous_tree.as_ref(), Some(&tree), None)?;
+    let (previous_entry, previous_tree) = base.unzip();
+    let key = (previous_tree.as_ref().map(Tree::id), tree.id());
+    let (files, bytes) = previous_entry
+        .map(|e| (e.files, e.bytes))
+        .unwrap_or_default();
     let mut entry = Entry {
         timestamp: time,
-        files: previous_entry.map(|e| e.files).unwrap_or_default(),
+        files,
+        languages: HashMap::new(),
+        totals: None,
+        bytes,
+        partial: false,
+        failed: false,
+        notes: Vec::new(),
+        commit_type: Some(CommitType::classify(commit.summary().unwrap_or_default())),
+        commit: Some(commit_info(&commit)),
+    };
+
+    // The same pair of trees gives the same changes, like for commits that were cherry-picked
+    // onto another scanned reference.
+    let mut notes = match shared.deltas.get(key) {
+        Some(changes) => changes.apply(&mut entry),
+        None => {
+            let changes = tree_changes(
+                repo,
+                oid,
+                previous_tree.as_ref(),
+                &tree,
+                rules,
+                &mut budget,
+                shared,
+                &mut entry,
+            )?;
+            let notes = changes.notes.clone();
+            if budget.skipped == 0 {
+                shared.deltas.insert(key, changes);
+            }
+            notes
+        }
     };
 
+    if let Some(before) = before {
+        let lines = code_lines(&entry).saturating_sub(before);
+        if lines >= JUMP_LINES && lines >= before / 2 {
+            notes.push(Note::Jump { lines });
+        }
+    }
+
+    entry.partial = budget.finish(warnings);
+    entry.notes = notes;
+    shared.updater.inc();
+
+    Ok((entry, tree))
+}
+
+/// Apply the changes between the trees to the files of the entry, which are those of the
+/// previous tree. Returns the changed files with their new statistics, for [`Deltas`].
+#[allow(clippy::too_many_arguments)]
+fn tree_changes(
+    repo: &Repository,
+    oid: Oid,
+    previous_tree: Option<&Tree<'_>>,
+    tree: &Tree<'_>,
+    rules: Option<&Rules>,
+    budget: &mut Budget<'_>,
+    shared: &Shared<'_>,
+    entry: &mut Entry,
+) -> Result<Changes> {
+    let warnings = &shared.warnings;
+    let diff = shared.time(Phase::Diff, None, || -> Result<_> {
+        let mut diff = retry(|| repo.diff_tree_to_tree(previous_tree, Some(tree), None))?;
+        // Renamed and copied files keep the statistics of their source, if they didn't change.
+        diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+        Ok(diff)
+    })?;
+    let mut bytes = entry.bytes;
+    let mut notes = Vec::new();
+    let odb = repo.odb()?;
+    let mut touched = HashSet::new();
+    let mut relinked = Vec::new();
+
     for delta in diff.deltas() {
-        match delta.status() {
-            Delta::Added | Delta::Modified => {
-                let item = tree.get_path(delta.new_file().path().unwrap()).unwrap();
-
-                if matches!(item.kind(), Some(ObjectType::Blob)) {
-                    let name = item.name().unwrap_or_default();
-                    let lang = LanguageType::from_path(name, &config);
-
-                    if let Some(lang) = lang {
-                        let blob = item
-                            .to_object(repo)?
-                            .into_blob()
-                            .map_err(|_| anyhow!("not a blob"))?;
-
-                        let stats = lang.parse_from_slice(blob.content(), &config);
-                        let stats = stats.summarise();
-
-                        entry.files.insert(
-                            delta.new_file().path().unwrap().to_owned(),
-                            EntryFile {
-                                language: lang,
-                                statistics: stats,
-                            },
-                        );
-                    }
+        let old_path = delta.old_file().path();
+        let new_path = delta.new_file().path();
+        touched.extend(old_path.into_iter().chain(new_path).map(file_key));
+
+        if shared.options.recurse_submodules {
+            let changes = SubmoduleChange::of(repo, previous_tree, Some(tree), &delta);
+            if !changes.is_empty() {
+                for change in &changes {
+                    submodule_changes(
+                        oid,
+                        Path::new(""),
+                        change,
+                        budget,
+                        shared,
+                        entry,
+                        &mut notes,
+                        &mut touched,
+                        &mut bytes,
+                    )?;
                 }
+                continue;
+            }
+        }
+
+        // Copies keep their source, so only the new file adds to the total size.
+        if delta.status() != Delta::Copied {
+            bytes = bytes.saturating_sub(blob_size(&odb, &delta.old_file(), shared)?);
+        }
+        bytes = bytes.saturating_add(blob_size(&odb, &delta.new_file(), shared)?);
+
+        match (delta.status(), old_path, new_path) {
+            (Delta::Added | Delta::Modified, _, Some(path)) => {
+                let key = file_key(path);
+                // Files that can't be counted anymore, like ones that grew too large, must not
+                // keep the statistics of their previous version.
+                match budget.parse(path, || {
+                    parse_file(repo, oid, tree, path, rules, shared, &mut notes)
+                })? {
+                    Some(file) => entry.files.insert(key, file),
+                    None => entry.files.remove(&key),
+                };
+            }
+            (Delta::Deleted, Some(path), _) => {
+                entry.files.remove(&file_key(path));
             }
-            Delta::Deleted => {
-                entry.files.remove(delta.old_file().path().unwrap());
+            (status @ (Delta::Renamed | Delta::Copied), Some(old_path), Some(new_path)) => {
+                let old = if status == Delta::Renamed {
+                    entry.files.remove(&file_key(old_path))
+                } else {
+                    entry.files.get(&file_key(old_path)).cloned()
+                };
+
+                // Case-only renames on case-insensitive file systems (like `Readme.md` to
+                // `README.md`) can reference an old path that was never recorded. Fall back to
+                // treating the new path as a freshly added file.
+                let file = match old {
+                    Some(old) if keeps_statistics(&delta, &old, new_path, rules, shared) => {
+                        Some(old)
+                    }
+                    // Changed files, and files that are counted differently at their new path,
+                    // are counted anew.
+                    Some(_) => budget.parse(new_path, || {
+                        parse_file(repo, oid, tree, new_path, rules, shared, &mut notes)
+                    })?,
+                    None => {
+                        let file = budget.parse(new_path, || {
+                            parse_file(repo, oid, tree, new_path, rules, shared, &mut notes)
+                        })?;
+                        // Sources that aren't counted at all are expected to be unknown.
+                        if file.is_some() && language(old_path, rules, shared).is_some() {
+                            warnings.warn(
+                                Category::Renames,
+                                format_args!(
+                                    "{oid}: unknown source {} for {}, treating as added",
+                                    old_path.display(),
+                                    new_path.display(),
+                                ),
+                            );
+                        }
+                        file
+                    }
+                };
+
+                if let Some(file) = file {
+                    entry.files.insert(file_key(new_path), file);
+                }
             }
-            Delta::Renamed => {
-                let old = entry
-                    .files
-                    .remove(delta.old_file().path().unwrap())
-                    .unwrap();
-                entry
-                    .files
-                    .insert(delta.new_file().path().unwrap().to_owned(), old);
+            (status, old_path, new_path) => warnings.warn(
+                Category::Changes,
+                format_args!(
+                    "{oid}: skipping unexpected {status:?} delta ({} -> {})",
+                    old_path.map_or("?".into(), Path::to_string_lossy),
+                    new_path.map_or("?".into(), Path::to_string_lossy),
+                ),
+            ),
+        }
+    }
+
+    // Links count the content of their targets, so they change whenever any file on the way to
+    // the target does.
+    if shared.options.follow_symlinks && !touched.is_empty() {
+        for link in shared.symlinks.find(repo, tree)?.iter() {
+            let key = file_key(link);
+            if touched.contains(&key) {
+                continue;
             }
-            Delta::Copied => {
-                let old = entry
-                    .files
-                    .get(delta.old_file().path().unwrap())
-                    .unwrap()
-                    .clone();
-                entry
-                    .files
-                    .insert(delta.new_file().path().unwrap().to_owned(), old);
+
+            let Ok(item) = tree.get_path(link) else {
+                continue;
+            };
+            let changed = match resolve_symlink(repo, tree, link, &item) {
+                Some((_, hops)) => hops.iter().any(|hop| touched.contains(&file_key(hop))),
+                // Broken links only change if they were counted until now.
+                None => entry.files.contains_key(&key),
+            };
+            if !changed {
+                continue;
             }
-            _ => unreachable!(),
+
+            match budget.parse(link, || {
+                parse_file(repo, oid, tree, link, rules, shared, &mut notes)
+            })? {
+                Some(file) => entry.files.insert(key.clone(), file),
+                None => entry.files.remove(&key),
+            };
+            relinked.push(key);
         }
     }
 
-    updater.inc();
+    entry.bytes = bytes;
 
-    Ok((entry, tree))
+    let files = touched
+        .into_iter()
+        .chain(relinked)
+        .map(|key| {
+            let file = entry.files.get(&key).cloned();
+            (key, file)
+        })
+        .collect();
+
+    Ok(Changes {
+        files,
+        bytes,
+        notes,
+    })
 }
 
-fn new_zstd_file<'a>(path: impl AsRef<Path>) -> Result<ZstdEncoder<'a, BufWriter<File>>> {
-    ZstdEncoder::new(
-        BufWriter::new(File::create(path.as_ref())?),
-        ZSTD_COMPRESSION_DEFAULT,
-    )
-    .map_err(Into::into)
+/// Change of a submodule from one of its commits to another, with the repository to look them
+/// up in, if it was found.
+struct SubmoduleChange {
+    repo: Option<Repository>,
+    path: PathBuf,
+    old: Option<Oid>,
+    new: Option<Oid>,
+}
+
+impl SubmoduleChange {
+    /// Changes of the submodules in a delta between two trees, empty if neither side of it is a
+    /// submodule. Submodules that moved are removed at their old path and added at the new one.
+    fn of(
+        repo: &Repository,
+        previous_tree: Option<&Tree<'_>>,
+        tree: Option<&Tree<'_>>,
+        delta: &DiffDelta<'_>,
+    ) -> Vec<Self> {
+        let gitlink = |file: DiffFile<'_>, tree: Option<&Tree<'_>>| {
+            if file.mode() != FileMode::Commit || file.id().is_zero() {
+                return None;
+            }
+            let path = file.path()?.to_owned();
+            let repo = tree.and_then(|tree| submodules::open(repo, tree, &path));
+            Some((repo, path, file.id()))
+        };
+
+        match (
+            gitlink(delta.old_file(), previous_tree),
+            gitlink(delta.new_file(), tree),
+        ) {
+            (Some((old_repo, old_path, old)), Some((repo, path, new))) if old_path == path => {
+                vec![Self {
+                    repo: repo.or(old_repo),
+                    path,
+                    old: Some(old),
+                    new: Some(new),
+                }]
+            }
+            (old, new) => old
+                .map(|(repo, path, old)| Self {
+                    repo,
+                    path,
+                    old: Some(old),
+                    new: None,
+                })
+                .into_iter()
+                .chain(new.map(|(repo, path, new)| Self {
+                    repo,
+                    path,
+                    old: None,
+                    new: Some(new),
+                }))
+                .collect(),
+        }
+    }
+}
+
+/// Apply the changes of a submodule to the files of the entry, which are counted below the
+/// submodule's path. Nested submodules are followed as well.
+///
+/// Submodules whose repository or commit can't be found are left out with a warning, as are
+/// their files that were counted until then.
+#[allow(clippy::too_many_arguments)]
+fn submodule_changes(
+    oid: Oid,
+    prefix: &Path,
+    change: &SubmoduleChange,
+    budget: &mut Budget<'_>,
+    shared: &Shared<'_>,
+    entry: &mut Entry,
+    notes: &mut Vec<Note>,
+    touched: &mut HashSet<String>,
+    bytes: &mut u64,
+) -> Result<()> {
+    let warnings = &shared.warnings;
+    let path = prefix.join(&change.path);
+
+    // Files below the submodule's path, for when its previous state can't be diffed against.
+    let mut remove_all = |entry: &mut Entry| {
+        let below = format!("{}/", file_key(&path));
+        entry.files.retain(|key, _| {
+            let keep = !key.starts_with(&below);
+            if !keep {
+                touched.insert(key.clone());
+            }
+            keep
+        });
+    };
+
+    let Some(repo) = &change.repo else {
+        warnings.warn(
+            Category::Submodules,
+            format_args!(
+                "{oid}: repository of submodule {} not found, leaving out its files",
+                path.display()
+            ),
+        );
+        remove_all(entry);
+        return Ok(());
+    };
+
+    // Commits that were never fetched into the submodule won't show up by trying again.
+    let find_tree = |commit| {
+        if !repo.odb().is_ok_and(|odb| odb.exists(commit)) {
+            return None;
+        }
+        retry(|| repo.find_commit(commit)?.tree()).ok()
+    };
+    // Missing commits were reported already when they were added.
+    let old_tree = change.old.and_then(find_tree);
+    let new_tree = change.new.and_then(|commit| {
+        let tree = find_tree(commit);
+        if tree.is_none() {
+            warnings.warn(
+                Category::Submodules,
+                format_args!(
+                    "{oid}: commit {commit} of submodule {} not found, leaving out its files",
+                    path.display()
+                ),
+            );
+        }
+        tree
+    });
+
+    if change.old.is_some() && old_tree.is_none() {
+        remove_all(entry);
+    }
+
+    let diff = retry(|| repo.diff_tree_to_tree(old_tree.as_ref(), new_tree.as_ref(), None))?;
+    let odb = repo.odb()?;
+
+    for delta in diff.deltas() {
+        let nested = SubmoduleChange::of(repo, old_tree.as_ref(), new_tree.as_ref(), &delta);
+        if !nested.is_empty() {
+            for change in &nested {
+                submodule_changes(
+                    oid, &path, change, budget, shared, entry, notes, touched, bytes,
+                )?;
+            }
+            continue;
+        }
+
+        *bytes = bytes.saturating_sub(blob_size(&odb, &delta.old_file(), shared)?);
+        *bytes = bytes.saturating_add(blob_size(&odb, &delta.new_file(), shared)?);
+
+        match (delta.status(), delta.new_file().path(), &new_tree) {
+            (Delta::Added | Delta::Modified, Some(file), Some(tree)) => {
+                let full = path.join(file);
+                let key = file_key(&full);
+                // Attributes and exclusions of the repository apply to the path in it.
+                let parsed = if is_excluded(&full, None, shared) {
+                    None
+                } else {
+                    budget.parse(&full, || {
+                        parse_file(repo, oid, tree, file, None, shared, notes)
+                    })?
+                };
+
+                match parsed {
+                    Some(parsed) => entry.files.insert(key.clone(), parsed),
+                    None => entry.files.remove(&key),
+                };
+                touched.insert(key);
+            }
+            _ => {
+                if let Some(file) = delta.old_file().path() {
+                    let key = file_key(&path.join(file));
+                    entry.files.remove(&key);
+                    touched.insert(key);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Total code lines of all files in the entry.
+fn code_lines(entry: &Entry) -> u64 {
+    entry
+        .files
+        .values()
+        .map(|file| file.statistics.code as u64)
+        .fold(0, u64::saturating_add)
+}
+
+fn commit_time(commit: &Commit<'_>) -> Result<DateTime<FixedOffset>> {
+    let time = commit.time();
+
+    Ok(FixedOffset::east_opt(time.offset_minutes() * 60)
+        .context("offset out of bounds")?
+        .from_utc_datetime(
+            &NaiveDateTime::from_timestamp_opt(time.seconds(), 0)
+                .context("timestamp out of bounds")?,
+        ))
+}
+
+/// Placeholder entry for a commit that couldn't be scanned, recorded with
+/// [`Options::keep_going`]. It keeps the commit's time if that can still be read, so it stays in
+/// order with the other entries.
+fn failed_entry(repo: &Repository, oid: Oid) -> Entry {
+    let commit = retry(|| repo.find_commit(oid)).ok();
+    let timestamp = commit
+        .as_ref()
+        .and_then(|commit| commit_time(commit).ok())
+        .unwrap_or_default();
+
+    Entry {
+        timestamp,
+        files: HashMap::new(),
+        languages: HashMap::new(),
+        totals: None,
+        bytes: 0,
+        partial: false,
+        failed: true,
+        notes: Vec::new(),
+        commit_type: None,
+        commit: commit.as_ref().map(commit_info),
+    }
+}
+
+/// Hash, author and summary of a commit, replacing invalid UTF-8 instead of failing on it.
+fn commit_info(commit: &Commit<'_>) -> CommitInfo {
+    let author = commit.author();
+    CommitInfo {
+        id: commit.id().to_string(),
+        author: String::from_utf8_lossy(author.name_bytes()).into_owned(),
+        email: String::from_utf8_lossy(author.email_bytes()).into_owned(),
+        summary: commit
+            .summary_bytes()
+            .map(|summary| String::from_utf8_lossy(summary).into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+/// Run an object read, retrying it with exponential backoff if it fails with a possibly transient
+/// error. Network file systems occasionally fail reads that succeed right after.
+fn retry<T>(mut read: impl FnMut() -> Result<T, git2::Error>) -> Result<T, git2::Error> {
+    let mut delay = RETRY_DELAY;
+
+    for _ in 0..MAX_RETRIES {
+        match read() {
+            Err(e) if is_transient(&e) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    read()
+}
+
+/// Whether the error may go away when trying again. These are all errors of the storage layer,
+/// including missing objects, as a pack file that failed to load makes its objects unavailable.
+fn is_transient(e: &git2::Error) -> bool {
+    matches!(
+        e.class(),
+        ErrorClass::Os | ErrorClass::Odb | ErrorClass::Zlib | ErrorClass::Filesystem
+    )
+}
+
+/// Time spent on a single commit, checked against [`Options::commit_timeout`]. Once the budget
+/// is used up, all further files are left out instead of being parsed.
+struct Budget<'a> {
+    oid: Oid,
+    watchdog: &'a Watchdog,
+    started: Instant,
+    /// Parse time of each file, to report the slowest ones.
+    files: Vec<(Duration, PathBuf)>,
+    skipped: usize,
+}
+
+impl<'a> Budget<'a> {
+    fn new(oid: Oid, watchdog: &'a Watchdog) -> Self {
+        watchdog.begin(oid);
+
+        Self {
+            oid,
+            watchdog,
+            started: Instant::now(),
+            files: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Parse the file at the given path with `parse`, unless the budget is used up already.
+    fn parse(
+        &mut self,
+        path: &Path,
+        parse: impl FnOnce() -> Result<Option<EntryFile>>,
+    ) -> Result<Option<EntryFile>> {
+        let Some(limit) = self.watchdog.limit() else {
+            return parse();
+        };
+
+        if self.started.elapsed() >= limit {
+            self.skipped += 1;
+            return Ok(None);
+        }
+
+        self.watchdog.file(self.oid, path);
+
+        let started = Instant::now();
+        let file = parse()?;
+        self.files.push((started.elapsed(), path.to_owned()));
+
+        Ok(file)
+    }
+
+    /// Report the commit if it exceeded its budget. Returns whether any files were left out.
+    fn finish(mut self, warnings: &Warnings) -> bool {
+        let elapsed = self.started.elapsed();
+        if self.watchdog.limit().is_none_or(|limit| elapsed < limit) {
+            return false;
+        }
+
+        self.files.sort_by_key(|&(time, _)| Reverse(time));
+        let slowest = self
+            .files
+            .iter()
+            .take(SLOWEST_FILES)
+            .map(|(time, path)| format!("{} ({:.1}s)", path.display(), time.as_secs_f64()))
+            .collect::<Vec<_>>();
+
+        warnings.warn(
+            Category::Slow,
+            format_args!(
+                "{}: exceeded time budget after {:.1}s, left out {} files, slowest: {}",
+                self.oid,
+                elapsed.as_secs_f64(),
+                self.skipped,
+                if slowest.is_empty() {
+                    "none".to_owned()
+                } else {
+                    slowest.join(", ")
+                },
+            ),
+        );
+
+        self.skipped > 0
+    }
+}
+
+impl Drop for Budget<'_> {
+    fn drop(&mut self) {
+        self.watchdog.end(self.oid);
+    }
+}
+
+/// Paths of the symlinks in trees, by the tree ID, so directories that didn't change aren't
+/// searched again for each commit.
+#[derive(Default)]
+struct Symlinks {
+    trees: Mutex<HashMap<Oid, Arc<[PathBuf]>>>,
+}
+
+impl Symlinks {
+    /// All symlinks in the tree, relative to it.
+    fn find(&self, repo: &Repository, tree: &Tree<'_>) -> Result<Arc<[PathBuf]>> {
+        let cached = self
+            .trees
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&tree.id())
+            .cloned();
+        if let Some(links) = cached {
+            return Ok(links);
+        }
+
+        let mut links = Vec::new();
+        for item in tree {
+            let Some(name) = item.name() else {
+                continue;
+            };
+
+            if item.filemode() == i32::from(FileMode::Link) {
+                links.push(PathBuf::from(name));
+            } else if item.filemode() == i32::from(FileMode::Tree) {
+                let subtree = repo.find_tree(item.id())?;
+                links.extend(
+                    self.find(repo, &subtree)?
+                        .iter()
+                        .map(|link| Path::new(name).join(link)),
+                );
+            }
+        }
+
+        let links = Arc::<[PathBuf]>::from(links);
+        let mut trees = self.trees.lock().unwrap_or_else(PoisonError::into_inner);
+        if trees.len() < MAX_SYMLINK_TREES {
+            trees.insert(tree.id(), Arc::clone(&links));
+        }
+
+        Ok(links)
+    }
+}
+
+/// Changes between two trees, given as the files that differ after the second one.
+struct Changes {
+    /// Statistics of the changed files, or `None` for files that aren't counted anymore.
+    files: Vec<(String, Option<EntryFile>)>,
+    bytes: u64,
+    notes: Vec<Note>,
+}
+
+impl Changes {
+    /// Apply the changes to the entry of the first tree. Returns the notes of the changes.
+    fn apply(&self, entry: &mut Entry) -> Vec<Note> {
+        for (key, file) in &self.files {
+            match file {
+                Some(file) => entry.files.insert(key.clone(), file.clone()),
+                None => entry.files.remove(key),
+            };
+        }
+        entry.bytes = self.bytes;
+
+        self.notes.clone()
+    }
+}
+
+/// IDs of the previous tree, if any, and the current one.
+type TreePair = (Option<Oid>, Oid);
+
+/// Changes between pairs of trees, so commits with the same trees aren't diffed and parsed
+/// again.
+#[derive(Default)]
+struct Deltas {
+    trees: Mutex<HashMap<TreePair, Arc<Changes>>>,
+}
+
+impl Deltas {
+    fn get(&self, key: TreePair) -> Option<Arc<Changes>> {
+        self.trees
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+            .cloned()
+    }
+
+    fn insert(&self, key: TreePair, changes: Changes) {
+        let mut trees = self.trees.lock().unwrap_or_else(PoisonError::into_inner);
+        if trees.len() < MAX_CACHED_DELTAS {
+            trees.insert(key, Arc::new(changes));
+        }
+    }
+}
+
+/// Key of a file in [`Entry::files`]. Paths are stored with `/` as separator on all platforms,
+/// so stats files are portable.
+fn file_key(path: &Path) -> String {
+    let key = path.to_string_lossy();
+
+    if MAIN_SEPARATOR == '/' {
+        key.into_owned()
+    } else {
+        key.replace(MAIN_SEPARATOR, "/")
+    }
+}
+
+/// Size of a file in a diff, read from the object header without loading its content. Missing
+/// sides of a diff and submodules have no size, and neither have files that are missing from a
+/// partial clone.
+fn blob_size(odb: &Odb<'_>, file: &DiffFile<'_>, shared: &Shared<'_>) -> Result<u64> {
+    if file.id().is_zero() || file.mode() == FileMode::Commit {
+        return Ok(0);
+    }
+
+    // Objects left out of a partial clone won't show up by trying again.
+    if shared.partial && !odb.exists(file.id()) {
+        shared.warnings.skip(format_args!(
+            "missing object {} in partial clone, counting it as empty",
+            file.id()
+        ));
+        return Ok(0);
+    }
+
+    let (size, _) = retry(|| odb.read_header(file.id()))?;
+    Ok(size as u64)
+}
+
+/// Parse the blob at the given path of the tree, if it is a file of a known language. Broken or
+/// unexpected tree entries are reported as warning and skipped, so a single odd file doesn't
+/// abort the whole scan.
+///
+/// Submodules (gitlinks) are never parsed, as they only reference a commit in another
+/// repository. Symlinks are skipped unless [`Options::follow_symlinks`] is set. Files excluded by
+/// default or by the attribute `rules` are skipped as well.
+fn parse_file(
+    repo: &Repository,
+    oid: Oid,
+    tree: &Tree<'_>,
+    path: &Path,
+    rules: Option<&Rules>,
+    shared: &Shared<'_>,
+    notes: &mut Vec<Note>,
+) -> Result<Option<EntryFile>> {
+    if is_excluded(path, rules, shared) {
+        return Ok(None);
+    }
+
+    let Shared {
+        options,
+        languages,
+        tokei,
+        warnings,
+        ..
+    } = shared;
+
+    let item = match tree.get_path(path) {
+        Ok(item) => item,
+        Err(e) => {
+            warnings.warn(
+                Category::Unreadable,
+                format_args!(
+                    "{oid}: failed looking up {}: {}",
+                    path.display(),
+                    e.message()
+                ),
+            );
+            return Ok(None);
+        }
+    };
+
+    let mode = item.filemode();
+    if mode == i32::from(FileMode::Commit) {
+        return Ok(None);
+    }
+
+    let (name_item, item) = if mode == i32::from(FileMode::Link) {
+        if !options.follow_symlinks {
+            return Ok(None);
+        }
+
+        match resolve_symlink(repo, tree, path, &item) {
+            // Targets that are counted on their own would be counted twice otherwise.
+            Some((_, hops))
+                if hops
+                    .last()
+                    .is_some_and(|target| language(target, rules, shared).is_some()) =>
+            {
+                return Ok(None);
+            }
+            Some((target, _)) => (item, target),
+            None => {
+                warnings.warn(
+                    Category::Skipped,
+                    format_args!(
+                        "{oid}: skipping broken or external symlink {}",
+                        path.display()
+                    ),
+                );
+                return Ok(None);
+            }
+        }
+    } else {
+        (item.clone(), item)
+    };
+
+    if !matches!(item.kind(), Some(ObjectType::Blob)) {
+        return Ok(None);
+    }
+
+    let Some(name) = name_item.name() else {
+        warnings.warn(
+            Category::Skipped,
+            format_args!("{oid}: skipping {} with non UTF-8 name", path.display()),
+        );
+        return Ok(None);
+    };
+    let Some(lang) = rules
+        .and_then(|rules| rules.language(path))
+        .or_else(|| tokei.language(name))
+        .or_else(|| tokei.language(item.name()?))
+    else {
+        return Ok(None);
+    };
+
+    if !languages.is_empty() && !languages.contains(&lang) {
+        return Ok(None);
+    }
+
+    if shared.partial && !repo.odb()?.exists(item.id()) {
+        warnings.skip(format_args!(
+            "{oid}: skipping {}, its content is missing from the partial clone",
+            path.display()
+        ));
+        return Ok(None);
+    }
+
+    // The size is checked from the object header, before the content is loaded into memory.
+    let size = match shared.time(Phase::Load, Some(lang), || {
+        retry(|| repo.odb()?.read_header(item.id()))
+    }) {
+        Ok((size, _)) => size as u64,
+        Err(e) => {
+            warnings.warn(
+                Category::Unreadable,
+                format_args!("{oid}: failed loading {}: {}", path.display(), e.message()),
+            );
+            return Ok(None);
+        }
+    };
+
+    if size > options.max_file_size {
+        warnings.skip(format_args!(
+            "{oid}: skipping {}, size of {size} bytes exceeds the limit",
+            path.display(),
+        ));
+        notes.push(Note::TooLarge {
+            path: file_key(path),
+            size,
+        });
+        return Ok(None);
+    }
+
+    if options.estimate_file_size.is_some_and(|limit| size > limit) {
+        return match shared.time(Phase::Load, Some(lang), || {
+            estimate_file(repo, item.id(), size)
+        }) {
+            Ok(stats) => Ok(Some(EntryFile {
+                language: lang,
+                statistics: stats,
+                api_docs: None,
+                comment_kinds: None,
+                licensed: None,
+            })),
+            Err(e) => {
+                warnings.warn(
+                    Category::Unreadable,
+                    format_args!("{oid}: failed loading {}: {e}", path.display()),
+                );
+                Ok(None)
+            }
+        };
+    }
+
+    let blob = match shared
+        .time(Phase::Load, Some(lang), || retry(|| item.to_object(repo)))
+        .map(|o| o.into_blob())
+    {
+        Ok(Ok(blob)) => blob,
+        Ok(Err(_)) => {
+            warnings.warn(
+                Category::Unreadable,
+                format_args!("{oid}: {} is not a blob", path.display()),
+            );
+            return Ok(None);
+        }
+        Err(e) => {
+            warnings.warn(
+                Category::Unreadable,
+                format_args!("{oid}: failed loading {}: {}", path.display(), e.message()),
+            );
+            return Ok(None);
+        }
+    };
+
+    if blob.content().starts_with(LFS_POINTER) {
+        warnings.skip(format_args!(
+            "{oid}: skipping {}, content is stored in Git LFS",
+            path.display()
+        ));
+        notes.push(Note::LfsPointer {
+            path: file_key(path),
+        });
+        return Ok(None);
+    }
+
+    // tokei is not expected to panic, but a single odd blob must not take down a scan that might
+    // have been running for hours already.
+    let stats = match shared.time(Phase::Parse, Some(lang), || {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            lang.parse_from_slice(blob.content(), &tokei.config)
+        }))
+    }) {
+        Ok(stats) => stats,
+        Err(_) => {
+            warnings.skip(format_args!(
+                "{oid}: skipping {}, failed to parse as {lang}",
+                path.display()
+            ));
+            notes.push(Note::Unparseable {
+                path: file_key(path),
+            });
+            return Ok(None);
+        }
+    };
+
+    let api_docs = (options.api_docs && lang == LanguageType::Rust)
+        .then(|| {
+            shared.time(Phase::Parse, Some(lang), || {
+                api_docs::analyze(blob.content())
+            })
+        })
+        .flatten();
+    let mut stats = stats.summarise();
+    let content = if options.ignore_boilerplate {
+        let (lines, content) = comments::strip_boilerplate(lang, blob.content());
+        stats.comments = stats.comments.saturating_sub(lines);
+        content
+    } else {
+        blob.content()
+    };
+
+    let comment_kinds = shared.heuristics.as_ref().map(|heuristics| {
+        shared.time(Phase::Parse, Some(lang), || {
+            heuristics.classify(lang, content)
+        })
+    });
+
+    let licensed = options.spdx.then(|| comments::has_spdx_tag(blob.content()));
+
+    Ok(Some(EntryFile {
+        language: lang,
+        statistics: stats,
+        api_docs,
+        comment_kinds,
+        licensed,
+    }))
+}
+
+/// Estimate the statistics of a blob of the given size by counting its lines, without parsing
+/// it. Loose objects are streamed, so they're never loaded into memory as a whole. Packed objects
+/// can't be streamed by libgit2, so only ones up to [`MAX_PACKED_ESTIMATE`] are loaded at once,
+/// and the lines of larger ones are derived from their size.
+fn estimate_file(repo: &Repository, id: Oid, size: u64) -> Result<CodeStats> {
+    let odb = repo.odb()?;
+
+    let stats = match odb.reader(id) {
+        // The reader always reports full reads, even past the end of the content. libgit2 fills
+        // the buffer completely until the end though, so limiting it to the size is enough.
+        Ok((reader, size, _)) => count_lines(reader.take(size as u64)),
+        Err(_) if size <= MAX_PACKED_ESTIMATE => count_lines(retry(|| odb.read(id))?.data()),
+        Err(_) => Ok(estimate_lines(size)),
+    };
+
+    stats.map_err(Into::into)
+}
+
+/// Estimate the lines of content of the given size, all counted as code.
+fn estimate_lines(size: u64) -> CodeStats {
+    let mut stats = CodeStats::new();
+    stats.code = usize::try_from(size.div_ceil(AVERAGE_LINE_LENGTH)).unwrap_or(usize::MAX);
+    stats
+}
+
+/// Count the lines of the content, with all lines that aren't blank counted as code.
+fn count_lines(mut reader: impl Read) -> io::Result<CodeStats> {
+    let mut stats = CodeStats::new();
+    let mut buf = vec![0; 64 * 1024];
+    // Whether the current line has any content, or only whitespace so far.
+    let mut line = None;
+
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for &b in &buf[..len] {
+            if b == b'\n' {
+                match line.take() {
+                    Some(true) => stats.code += 1,
+                    Some(false) | None => stats.blanks += 1,
+                }
+            } else {
+                let content = line.unwrap_or_default() || !b.is_ascii_whitespace();
+                line = Some(content);
+            }
+        }
+    }
+
+    // The last line doesn't necessarily end with a line break.
+    match line {
+        Some(true) => stats.code += 1,
+        Some(false) => stats.blanks += 1,
+        None => {}
+    }
+
+    Ok(stats)
+}
+
+/// Whether a renamed or copied file can keep the statistics of its source. That's only the case
+/// for regular files with the same content that are still counted as the same language.
+fn keeps_statistics(
+    delta: &DiffDelta<'_>,
+    source: &EntryFile,
+    path: &Path,
+    rules: Option<&Rules>,
+    shared: &Shared<'_>,
+) -> bool {
+    let (old, new) = (delta.old_file(), delta.new_file());
+    let regular =
+        |file: &DiffFile<'_>| matches!(file.mode(), FileMode::Blob | FileMode::BlobExecutable);
+
+    old.id() == new.id()
+        && regular(&old)
+        && regular(&new)
+        && language(path, rules, shared) == Some(source.language)
+}
+
+/// Whether a file is left out by the exclusions or the attribute `rules`.
+fn is_excluded(path: &Path, rules: Option<&Rules>, shared: &Shared<'_>) -> bool {
+    shared.excludes.is_excluded(path) || rules.is_some_and(|rules| rules.is_excluded(path))
+}
+
+/// Language that a file is counted as by its path, or `None` if it isn't counted at all.
+fn language(path: &Path, rules: Option<&Rules>, shared: &Shared<'_>) -> Option<LanguageType> {
+    if is_excluded(path, rules, shared) {
+        return None;
+    }
+
+    rules.and_then(|rules| rules.language(path)).or_else(|| {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| shared.tokei.language(name))
+    })
+}
+
+/// Follow a symlink to its final target inside the same tree, together with the paths of all links
+/// on the way, ending with the target. Returns `None` if the link is dangling, points outside the
+/// repository or the chain of links is too long.
+fn resolve_symlink(
+    repo: &Repository,
+    tree: &Tree<'_>,
+    path: &Path,
+    link: &TreeEntry<'_>,
+) -> Option<(TreeEntry<'static>, Vec<PathBuf>)> {
+    let mut path = path.to_owned();
+    let mut link = link.to_owned();
+    let mut hops = Vec::new();
+
+    for _ in 0..MAX_SYMLINK_DEPTH {
+        let blob = link.to_object(repo).ok()?.into_blob().ok()?;
+        let target = std::str::from_utf8(blob.content()).ok()?;
+        let target = Path::new(target);
+
+        if target.is_absolute() {
+            return None;
+        }
+
+        let mut resolved = PathBuf::new();
+        for component in path.parent()?.join(target).components() {
+            match component {
+                Component::Normal(name) => resolved.push(name),
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return None;
+                    }
+                }
+                Component::CurDir => {}
+                Component::RootDir | Component::Prefix(_) => return None,
+            }
+        }
+
+        let entry = tree.get_path(&resolved).ok()?;
+        hops.push(resolved.clone());
+        if entry.filemode() != i32::from(FileMode::Link) {
+            return Some((entry, hops));
+        }
+
+        path = resolved;
+        link = entry;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, io::Write};
+
+    use git2::{Index, IndexEntry, IndexTime, Signature, Time};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::stats_file::StatsFile;
+
+    const README: &str = "# Title\n\nSome text.\n";
+    const SOURCE: &str = "// A comment.\nfn main() {\n}\n";
+
+    /// Commit the given files as the whole content of the repository.
+    fn commit(repo: &Repository, files: &[(&str, &str)]) {
+        commit_with_links(repo, files, &[]);
+    }
+
+    /// Like [`commit`], with symlinks from their path to their target as well.
+    fn commit_with_links(repo: &Repository, files: &[(&str, &str)], links: &[(&str, &str)]) {
+        commit_with_submodules(repo, files, links, &[]);
+    }
+
+    /// Like [`commit_with_links`], with submodules at their path that reference a commit as well.
+    fn commit_with_submodules(
+        repo: &Repository,
+        files: &[(&str, &str)],
+        links: &[(&str, &str)],
+        submodules: &[(&str, Oid)],
+    ) {
+        // An index creates the trees of nested paths on its own.
+        let mut index = Index::new().unwrap();
+        for (mode, path, id, size) in files
+            .iter()
+            .map(|file| (FileMode::Blob, file))
+            .chain(links.iter().map(|link| (FileMode::Link, link)))
+            .map(|(mode, (path, content))| {
+                let id = repo.blob(content.as_bytes()).unwrap();
+                (mode, path, id, content.len())
+            })
+            .chain(
+                submodules
+                    .iter()
+                    .map(|(path, commit)| (FileMode::Commit, path, *commit, 0)),
+            )
+        {
+            index
+                .add(&IndexEntry {
+                    ctime: IndexTime::new(0, 0),
+                    mtime: IndexTime::new(0, 0),
+                    dev: 0,
+                    ino: 0,
+                    mode: i32::from(mode) as u32,
+                    uid: 0,
+                    gid: 0,
+                    file_size: size as u32,
+                    id,
+                    flags: 0,
+                    flags_extended: 0,
+                    path: path.as_bytes().to_vec(),
+                })
+                .unwrap();
+        }
+        let tree = repo.find_tree(index.write_tree_to(repo).unwrap()).unwrap();
+
+        // Each commit is a second after its parent, so the history is in order.
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let time = parent
+            .as_ref()
+            .map_or(1_700_000_000, |parent| parent.time().seconds() + 1);
+        let sig = Signature::new("Jane Doe", "jane@example.com", &Time::new(time, 0)).unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &sig,
+            &sig,
+            "commit",
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )
+        .unwrap();
+    }
+
+    /// Scan the repository and return the files of each entry, with their code and comment lines.
+    fn scan(dir: &TempDir) -> Vec<BTreeMap<String, (usize, usize)>> {
+        scan_with(dir, &Options::default())
+    }
+
+    fn scan_with(dir: &TempDir, options: &Options) -> Vec<BTreeMap<String, (usize, usize)>> {
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            options,
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        let mut entries = Vec::new();
+        for index in 0..file.manifest().chunks.len() {
+            file.read_chunk(index, |entry| {
+                let files = entry.files.into_iter().map(|(key, file)| {
+                    let stats = file.statistics;
+                    (key, (stats.code, stats.comments))
+                });
+                entries.push(files.collect());
+                Ok(())
+            })
+            .unwrap();
+        }
+
+        entries
+    }
+
+    #[test]
+    fn case_only_rename_keeps_statistics() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("Readme.md", README)]);
+        commit(&repo, &[("README.md", README)]);
+
+        let entries = scan(&dir);
+        assert_eq!(2, entries.len());
+        assert_eq!(["Readme.md"], *entries[0].keys().collect::<Vec<_>>());
+        assert_eq!(["README.md"], *entries[1].keys().collect::<Vec<_>>());
+        assert_eq!(entries[0]["Readme.md"], entries[1]["README.md"]);
+    }
+
+    #[test]
+    fn changed_rename_is_counted_anew() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let changed = format!("{SOURCE}fn other() {{}}\n");
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("Lib.rs", &changed)]);
+
+        let entries = scan(&dir);
+        assert_eq!((2, 1), entries[0]["lib.rs"]);
+        assert_eq!((3, 1), entries[1]["Lib.rs"]);
+    }
+
+    #[test]
+    fn copy_keeps_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let changed = format!("{SOURCE}fn other() {{}}\n");
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        // Copies are only detected from files that changed in the same commit.
+        commit(&repo, &[("copy.rs", SOURCE), ("lib.rs", &changed)]);
+
+        let entries = scan(&dir);
+        assert_eq!((2, 1), entries[1]["copy.rs"]);
+        assert_eq!((3, 1), entries[1]["lib.rs"]);
+    }
+
+    #[test]
+    fn submodules_are_recursed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        // Where `git submodule update` clones the submodule to.
+        let sub = Repository::init_bare(repo.path().join("modules/lib")).unwrap();
+        commit(&sub, &[("lib.rs", SOURCE)]);
+        let first = sub.head().unwrap().target().unwrap();
+        commit(&sub, &[("lib.rs", SOURCE), ("util.rs", SOURCE)]);
+        let second = sub.head().unwrap().target().unwrap();
+
+        let modules = "[submodule \"lib\"]\n\tpath = deps/lib\n";
+        commit(&repo, &[("main.rs", SOURCE)]);
+        commit_with_submodules(
+            &repo,
+            &[("main.rs", SOURCE), (".gitmodules", modules)],
+            &[],
+            &[("deps/lib", first)],
+        );
+        commit_with_submodules(
+            &repo,
+            &[("main.rs", SOURCE), (".gitmodules", modules)],
+            &[],
+            &[("deps/lib", second)],
+        );
+        commit(&repo, &[("main.rs", SOURCE)]);
+
+        let entries = scan(&dir);
+        assert!(entries.iter().all(|files| files.len() == 1));
+
+        let options = Options {
+            recurse_submodules: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        let paths = entries
+            .iter()
+            .map(|files| files.keys().map(String::as_str).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                vec!["main.rs"],
+                vec!["deps/lib/lib.rs", "main.rs"],
+                vec!["deps/lib/lib.rs", "deps/lib/util.rs", "main.rs"],
+                vec!["main.rs"],
+            ],
+            paths
+        );
+    }
+
+    #[test]
+    fn symlink_follows_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let changed = format!("{SOURCE}fn other() {{}}\n");
+        // The target has no extension, so it's only counted through the link.
+        commit_with_links(&repo, &[("tool", SOURCE)], &[("tool.rs", "tool")]);
+        commit_with_links(&repo, &[("tool", &changed)], &[("tool.rs", "tool")]);
+        commit_with_links(&repo, &[], &[("tool.rs", "tool")]);
+
+        let options = Options {
+            follow_symlinks: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!((2, 1), entries[0]["tool.rs"]);
+        assert_eq!((3, 1), entries[1]["tool.rs"]);
+        assert!(entries[2].is_empty());
+    }
+
+    #[test]
+    fn symlink_to_counted_file_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit_with_links(&repo, &[("lib.rs", SOURCE)], &[("alias.rs", "lib.rs")]);
+
+        let options = Options {
+            follow_symlinks: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(["lib.rs"], *entries[0].keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn generated_code_is_excluded_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(
+            &repo,
+            &[("lib.rs", SOURCE), ("messages_pb2.py", "# A comment.\n")],
+        );
+
+        let entries = scan(&dir);
+        assert_eq!(["lib.rs"], *entries[0].keys().collect::<Vec<_>>());
+
+        let options = Options {
+            no_default_excludes: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(
+            ["lib.rs", "messages_pb2.py"],
+            *entries[0].keys().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn paths_are_filtered_by_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(
+            &repo,
+            &[
+                ("src/lib.rs", SOURCE),
+                ("src/gen/api.rs", SOURCE),
+                ("third_party/zlib/zlib.c", "int x;\n"),
+                ("build.rs", SOURCE),
+            ],
+        );
+
+        let options = Options {
+            exclude: vec!["third_party/**".to_owned(), "**/gen/**".to_owned()],
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        let mut paths = entries[0].keys().collect::<Vec<_>>();
+        paths.sort_unstable();
+        assert_eq!(["build.rs", "src/lib.rs"], *paths);
+
+        let options = Options {
+            include: vec!["src/*.rs".to_owned()],
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(["src/lib.rs"], *entries[0].keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn linguist_attributes_are_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let attributes = "*.inc linguist-language=C\n\
+                          *.h linguist-language=C++\n\
+                          legacy.h -linguist-language\n\
+                          gen.rs linguist-generated\n";
+        commit(
+            &repo,
+            &[
+                (".gitattributes", attributes),
+                ("table.inc", "/* Table. */\nint x;\n"),
+                ("api.h", "// API.\nint f();\n"),
+                ("legacy.h", "// Legacy.\nint g();\n"),
+                ("gen.rs", SOURCE),
+                ("lib.rs", SOURCE),
+            ],
+        );
+
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        let mut languages = BTreeMap::new();
+        file.read_chunk(0, |entry| {
+            languages.extend(entry.files.into_iter().map(|(k, f)| (k, f.language)));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            BTreeMap::from([
+                ("api.h".to_owned(), LanguageType::Cpp),
+                ("legacy.h".to_owned(), LanguageType::CHeader),
+                ("lib.rs".to_owned(), LanguageType::Rust),
+                ("table.inc".to_owned(), LanguageType::C),
+            ]),
+            languages
+        );
+    }
+
+    #[test]
+    fn commits_are_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        let head = repo.head().unwrap().target().unwrap();
+
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        file.read_chunk(0, |entry| {
+            let commit = entry.commit.unwrap();
+            assert_eq!(head.to_string(), commit.id);
+            assert_eq!(
+                ("Jane Doe", "jane@example.com", "commit"),
+                (&*commit.author, &*commit.email, &*commit.summary)
+            );
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn previews_are_rendered() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", SOURCE), ("main.rs", SOURCE)]);
+
+        let output = dir.path().join("test.stats");
+        let options = Options {
+            preview_every: Some(1),
+            ..Options::default()
+        };
+        run(
+            dir.path().join("repo"),
+            &output,
+            &options,
+            &Config::default(),
+        )
+        .unwrap();
+
+        let preview = fs::read_to_string(dir.path().join("test.preview.svg")).unwrap();
+        assert!(preview.starts_with("<svg"));
+    }
+
+    #[test]
+    fn shared_objects_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Repository::init_bare(dir.path().join("store")).unwrap();
+        commit(&store, &[("lib.rs", SOURCE)]);
+
+        // Like `git clone --shared`, with only the refs and a relative path to the objects.
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        fs::write(
+            dir.path().join("repo/objects/info/alternates"),
+            "../../store/objects\n",
+        )
+        .unwrap();
+        let head = store.head().unwrap().target().unwrap();
+        let repo = open_repository(repo.path()).unwrap();
+        repo.reference("refs/heads/master", head, true, "").unwrap();
+
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        file.read_chunk(0, |entry| {
+            assert!(entry.files.contains_key("lib.rs"));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn tokei_languages_are_followed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let languages = "[Pipeline]\n\
+                         extensions = [\"pipe\"]\n\
+                         line_comment = [\"//\"]\n\
+                         multi_line_comments = [[\"/*\", \"*/\"]]\n";
+        commit(
+            &repo,
+            &[
+                ("languages.toml", languages),
+                ("build.pipe", "// Steps.\nrun();\n"),
+                ("lib.rs", SOURCE),
+            ],
+        );
+
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        file.read_chunk(0, |entry| {
+            let stats = &entry.files["build.pipe"].statistics;
+            assert_eq!((1, 1), (stats.comments, stats.code));
+            assert!(entry.files.contains_key("lib.rs"));
+            Ok(())
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn repeated_changes_are_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let docs = "/// Docs.\n/// More docs.\nfn main() {}\n";
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", docs), ("README.md", README)]);
+        // Reverting and applying the change again diffs the same pair of trees.
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", docs), ("README.md", README)]);
+
+        let entries = scan(&dir);
+        assert_eq!(4, entries.len());
+        assert_eq!(entries[1], entries[3]);
+        assert_eq!(entries[0], entries[2]);
+        assert_eq!((1, 2), entries[3]["lib.rs"]);
+    }
+
+    #[test]
+    fn anomalies_are_noted() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let lfs = "version https://git-lfs.github.com/spec/v1\noid sha256:0\nsize 1\n";
+        let vendored = "fn f() {}\n".repeat(JUMP_LINES as usize);
+        commit(&repo, &[("lib.rs", SOURCE), ("lfs.rs", lfs)]);
+        commit(&repo, &[("lib.rs", SOURCE), ("vendored.rs", &vendored)]);
+
+        let output = dir.path().join("test.stats");
+        run(
+            dir.path().join("repo"),
+            &output,
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        let mut notes = Vec::new();
+        file.read_chunk(0, |entry| {
+            notes.push(entry.notes);
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(matches!(&*notes[0], [Note::LfsPointer { path }] if path == "lfs.rs"));
+        assert!(matches!(&*notes[1], [Note::Jump { .. }]));
+    }
+
+    #[test]
+    fn deep_tree_is_scanned() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+
+        // Nest the file far beyond the 32k characters limit of Windows paths.
+        let mut tree = repo.head().unwrap().peel_to_tree().unwrap().id();
+        let name = "d".repeat(200);
+        for _ in 0..170 {
+            let mut dir = repo.treebuilder(None).unwrap();
+            dir.insert(&name, tree, FileMode::Tree.into()).unwrap();
+            tree = dir.write().unwrap();
+        }
+        let tree = repo.find_tree(tree).unwrap();
+        let parent = repo.head().unwrap().peel_to_commit().unwrap();
+        let sig =
+            Signature::new("Jane Doe", "jane@example.com", &Time::new(1_800_000_000, 0)).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "deep", &tree, &[&parent])
+            .unwrap();
+
+        let entries = scan(&dir);
+        let path = format!("{}/lib.rs", vec![name; 170].join("/"));
+        assert!(path.len() > 32 * 1024);
+        assert_eq!((2, 1), entries[1][&path]);
+    }
+
+    #[test]
+    fn exceeded_timeout_leaves_out_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+
+        let options = Options {
+            commit_timeout: Some(0),
+            ..Options::default()
+        };
+        let entry = head_entry(&dir.path().join("repo"), &options, &Config::default()).unwrap();
+        assert!(entry.partial);
+        assert!(entry.files.is_empty());
+
+        let entry = head_entry(
+            &dir.path().join("repo"),
+            &Options::default(),
+            &Config::default(),
+        )
+        .unwrap();
+        assert!(!entry.partial);
+        assert_eq!(["lib.rs"], *entry.files.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn large_file_is_estimated() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+
+        let options = Options {
+            estimate_file_size: Some(0),
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!((3, 0), entries[0]["lib.rs"]);
+    }
+
+    #[test]
+    fn packed_file_is_estimated_from_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let id = repo.blob(SOURCE.as_bytes()).unwrap();
+
+        // Move the blob into a pack file, where it can't be streamed from.
+        let mut packer = repo.packbuilder().unwrap();
+        packer.insert_object(id, None).unwrap();
+        let mut pack = git2::Buf::new();
+        packer.write_buf(&mut pack).unwrap();
+        let odb = repo.odb().unwrap();
+        let mut writer = odb.packwriter().unwrap();
+        writer.write_all(&pack).unwrap();
+        writer.commit().unwrap();
+        let hex = id.to_string();
+        fs::remove_file(repo.path().join("objects").join(&hex[..2]).join(&hex[2..])).unwrap();
+
+        let stats = estimate_file(&repo, id, SOURCE.len() as u64).unwrap();
+        assert_eq!((3, 0), (stats.code, stats.blanks));
+
+        let size = MAX_PACKED_ESTIMATE + 1;
+        let stats = estimate_file(&repo, id, size).unwrap();
+        assert_eq!(size.div_ceil(AVERAGE_LINE_LENGTH), stats.code as u64);
+    }
+
+    #[test]
+    fn count_lines_without_trailing_break() {
+        let stats = count_lines("a\n \n\n  b".as_bytes()).unwrap();
+        assert_eq!((2, 2, 0), (stats.code, stats.blanks, stats.comments));
+
+        let stats = count_lines("".as_bytes()).unwrap();
+        assert_eq!((0, 0), (stats.code, stats.blanks));
+    }
+
+    #[test]
+    fn keep_going_records_failed_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", SOURCE), ("README.md", README)]);
+
+        // Break the first commit by removing its tree from the object database.
+        let tree = repo
+            .head()
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .parent(0)
+            .unwrap()
+            .tree_id();
+        let tree = tree.to_string();
+        fs::remove_file(
+            repo.path()
+                .join("objects")
+                .join(&tree[..2])
+                .join(&tree[2..]),
+        )
+        .unwrap();
+
+        let output = dir.path().join("test.stats");
+        let input = dir.path().join("repo");
+        assert!(run(
+            input.clone(),
+            &output,
+            &Options::default(),
+            &Config::default()
+        )
+        .is_err());
+
+        let options = Options {
+            keep_going: true,
+            ..Options::default()
+        };
+        run(input, &output, &options, &Config::default()).unwrap();
+
+        let mut entries = Vec::new();
+        StatsFile::open(output)
+            .unwrap()
+            .read_chunk(0, |entry| {
+                entries.push((entry.failed, entry.files.len()));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!([(true, 0), (false, 2)], *entries);
+    }
+
+    #[test]
+    fn profile_is_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+
+        let path = dir.path().join("profile.json");
+        let options = Options {
+            profile: Some(path.clone()),
+            ..Options::default()
+        };
+        scan_with(&dir, &options);
+
+        let profile: serde_json::Value =
+            serde_json::from_reader(fs::File::open(path).unwrap()).unwrap();
+        let frames = profile["shared"]["frames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| frame["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(["diff", "load", "parse", "encode", "Rust"]
+            .iter()
+            .all(|name| frames.contains(name)));
+    }
+
+    #[test]
+    fn update_only_scans_new_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", SOURCE), ("main.rs", SOURCE)]);
+        scan(&dir);
+
+        commit(&repo, &[("main.rs", SOURCE)]);
+        let options = Options {
+            update: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(scan(&dir), entries);
+        assert_eq!(3, entries.len());
+
+        // Options that change the content of the entries have to stay the same.
+        let options = Options {
+            update: true,
+            spdx: true,
+            ..Options::default()
+        };
+        let result = run(
+            dir.path().join("repo"),
+            &dir.path().join("test.stats"),
+            &options,
+            &Config::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn range_limits_scanned_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        commit(&repo, &[("lib.rs", SOURCE), ("main.rs", SOURCE)]);
+        commit(&repo, &[("main.rs", SOURCE)]);
+
+        let options = Options {
+            range: Some("HEAD~2..".to_owned()),
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+
+        // The first commit of the range still counts all of its files.
+        assert_eq!(scan(&dir)[1..], entries);
+
+        let options = Options {
+            range: Some("HEAD~2...HEAD".to_owned()),
+            ..Options::default()
+        };
+        let result = run(
+            dir.path().join("repo"),
+            &dir.path().join("test.stats"),
+            &options,
+            &Config::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn merged_commits_can_be_left_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE)]);
+        let base = repo.head().unwrap().peel_to_commit().unwrap();
+
+        // A side branch with a commit of its own, merged into the main line afterwards.
+        let blob = repo.blob(SOURCE.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(Some(&base.tree().unwrap())).unwrap();
+        tree.insert("side.rs", blob, FileMode::Blob.into()).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let sig =
+            Signature::new("Jane Doe", "jane@example.com", &Time::new(1_700_000_005, 0)).unwrap();
+        let side = repo
+            .commit(None, &sig, &sig, "side", &tree, &[&base])
+            .unwrap();
+        let side = repo.find_commit(side).unwrap();
+
+        let sig =
+            Signature::new("Jane Doe", "jane@example.com", &Time::new(1_700_000_010, 0)).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "merge", &tree, &[&base, &side])
+            .unwrap();
+
+        assert_eq!(3, scan(&dir).len());
+
+        let options = Options {
+            first_parent: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(2, entries.len());
+        assert!(entries[1].contains_key("side.rs"));
+
+        let options = Options {
+            no_merges: true,
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(scan(&dir)[..2], entries);
+    }
+
+    #[test]
+    fn commits_are_sampled() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let commits = [1, 1, 2, 8, 9, 9, 15]
+            .into_iter()
+            .enumerate()
+            .map(|(i, d)| (day(d), Oid::from_bytes(&[i as u8; 20]).unwrap()))
+            .collect::<Vec<_>>();
+        let indices = |options: &Options| {
+            sample(commits.clone(), options)
+                .into_iter()
+                .map(|oid| oid.as_bytes()[0])
+                .collect::<Vec<_>>()
+        };
+
+        let daily = Options {
+            snapshot_interval: Some(Interval::Daily),
+            ..Options::default()
+        };
+        assert_eq!(vec![1, 2, 3, 5, 6], indices(&daily));
+
+        // 2024-01-01 is a Monday.
+        let weekly = Options {
+            snapshot_interval: Some(Interval::Weekly),
+            ..Options::default()
+        };
+        assert_eq!(vec![2, 5, 6], indices(&weekly));
+
+        let every = Options {
+            sample_every: Some(3),
+            ..Options::default()
+        };
+        assert_eq!(vec![0, 3, 6], indices(&every));
+
+        let both = Options {
+            sample_every: Some(2),
+            snapshot_interval: Some(Interval::Daily),
+            ..Options::default()
+        };
+        assert_eq!(vec![1, 3, 6], indices(&both));
+    }
+
+    #[test]
+    fn graft_prepends_split_off_directory() {
+        let old = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(old.path().join("repo")).unwrap();
+        let blob = repo.blob(SOURCE.as_bytes()).unwrap();
+        let mut app = repo.treebuilder(None).unwrap();
+        app.insert("lib.rs", blob, FileMode::Blob.into()).unwrap();
+        let blob = repo.blob(README.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("README.md", blob, FileMode::Blob.into())
+            .unwrap();
+        tree.insert("app", app.write().unwrap(), FileMode::Tree.into())
+            .unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let sig =
+            Signature::new("Jane Doe", "jane@example.com", &Time::new(1_700_000_000, 0)).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "monorepo", &tree, &[])
+            .unwrap();
+        let graft = old.path().join("test.stats");
+        scan_with(&old, &Options::default());
+
+        // The split off repository starts well after the old history.
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let blob = repo.blob(SOURCE.as_bytes()).unwrap();
+        let mut tree = repo.treebuilder(None).unwrap();
+        tree.insert("main.rs", blob, FileMode::Blob.into()).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let sig =
+            Signature::new("Jane Doe", "jane@example.com", &Time::new(1_800_000_000, 0)).unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "split", &tree, &[])
+            .unwrap();
+
+        let options = Options {
+            graft: Some(graft),
+            graft_dir: Some("app".to_owned()),
+            ..Options::default()
+        };
+        let entries = scan_with(&dir, &options);
+        assert_eq!(2, entries.len());
+        assert_eq!(["lib.rs"], *entries[0].keys().collect::<Vec<_>>());
+        assert_eq!(["main.rs"], *entries[1].keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reduced_detail_keeps_language_totals() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        commit(&repo, &[("lib.rs", SOURCE), ("main.rs", SOURCE)]);
+
+        for detail in [Detail::PerLanguage, Detail::TotalsOnly] {
+            let output = dir.path().join("test.stats");
+            let options = Options {
+                detail,
+                ..Options::default()
+            };
+            run(
+                dir.path().join("repo"),
+                &output,
+                &options,
+                &Config::default(),
+            )
+            .unwrap();
+
+            let file = StatsFile::open(output).unwrap();
+            assert_eq!(detail, file.manifest().metadata.detail);
+
+            let entry = file.last_entry().unwrap().unwrap();
+            assert!(entry.files.is_empty());
+            assert_eq!(2, entry.total_stats().unwrap().files);
+            assert_eq!(4, entry.total_stats().unwrap().statistics.code);
+            assert_eq!(2, entry.total_stats().unwrap().statistics.comments);
+        }
+    }
+
+    #[test]
+    fn exceeded_budget_skips_remaining_files() {
+        let watchdog = Watchdog::new(Some(Duration::from_millis(50)));
+        let warnings = Warnings::quiet();
+        let mut budget = Budget::new(Oid::from_bytes(&[1; 20]).unwrap(), &watchdog);
+        let parse = || -> Result<Option<EntryFile>> {
+            Ok(Some(EntryFile {
+                language: LanguageType::Rust,
+                statistics: CodeStats::new(),
+                api_docs: None,
+                comment_kinds: None,
+                licensed: None,
+            }))
+        };
+
+        // The first file takes longer than the whole budget, but is kept as it finished.
+        let slow = budget
+            .parse(Path::new("slow.rs"), || {
+                thread::sleep(Duration::from_millis(100));
+                parse()
+            })
+            .unwrap();
+        assert!(slow.is_some());
+
+        let skipped = budget
+            .parse(Path::new("next.rs"), || panic!("parsed after the budget"))
+            .unwrap();
+        assert!(skipped.is_none());
+
+        assert!(budget.finish(&warnings));
+        assert_eq!(1, warnings.count());
+    }
 }
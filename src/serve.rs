@@ -0,0 +1,792 @@
+//! Small HTTP server that answers queries about a stats file with JSON, so other tools can use
+//! the data without reading stats files themselves.
+//!
+//! Several stats files can be served at once, each identified by its file name without the
+//! extension. Stats files are read again for each request, so a file that is replaced by a new
+//! scan is picked up without a restart. Requests that are answered while the file is replaced
+//! keep reading the previous one. Available endpoints:
+//!
+//! - `GET /api/repos`: All served repositories, ranked by their current comment ratio (comment
+//!   lines per code line). Each has its `id`, `name`, `comment_ratio`, the `change` of the ratio
+//!   over the last 90 days and the `trend` of the ratio as `rising`, `falling` or `flat`.
+//! - `GET /api/series`: Series of the chart that `render` would draw, as JSON object with the
+//!   `title`, the `unit` of the values and the `series`. Each series has a `label`, a `type` of
+//!   `line`, `dashed`, `markers` or `area` and its `points` as `[timestamp, value]` pairs (or
+//!   `[timestamp, min, max]` for areas), with timestamps in seconds. The query parameters are:
+//!   - `repo`: ID of the repository, only needed if several are served.
+//!   - `lang`: Language to include, can be repeated. Defaults to all languages.
+//!   - `metric`: Value to plot, like `lines` or `density`, same as `render --metric`.
+//!   - `group-by`: Split into separate series, same as `render --group-by`.
+//!   - `bucket`: Combine values per `week` or `month`, same as `render --bucket`.
+//!   - `from` and `to`: Only include points within these days, like `2024-01-31`.
+//! - `GET /api/contributors`: Files and directories that changed the most between two points of
+//!   a chart, for tooltips that explain a point when hovering it. Compares the latest entry on or
+//!   before `to` with the latest one on or before `from`, or with the entry right before it
+//!   without `from`. Each of the `files` and `directories` has its `path` and the change of its
+//!   `code` and `comments` lines, the largest changes first. Takes the `repo`, `lang`, `from`
+//!   and `to` parameters like above, and `limit` for the amount of files and directories, which
+//!   defaults to 10. Needs a stats file with statistics per file.
+//! - `POST /api/rescan`: Scan the commits that were added to the repository given with `--repo`
+//!   since the last scan, like `scan --update`, with the scan options given to `serve`. These
+//!   must match the ones the stats file was scanned with, including `--encrypt` and `--sign`.
+//!   The scan runs in the background and the request is answered right away with
+//!   `202 Accepted`. Requests during a running scan queue a single further one, so commits
+//!   pushed in the meantime aren't missed. Meant to be called by a webhook of the Git server
+//!   after each push. The server has no user interface of its own, so dashboards that offer a
+//!   button for it have to call the endpoint themselves. Not available in `--read-only` mode.
+//! - `GET /healthz` and `GET /readyz`: State of the server, the time of the last successful
+//!   rescan and the age of the stats files, see [`crate::daemon`].
+//!
+//! With `--basic-auth` or `--token`, all endpoints except the health checks need the matching
+//! `Authorization` header.
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{NaiveDate, NaiveTime, TimeDelta};
+use clap::{Args, ValueEnum, ValueHint};
+use serde_json::{json, Value};
+
+use crate::{
+    chart::{Chart, Shape},
+    config::Config,
+    contributors,
+    daemon::{Daemon, DaemonArgs},
+    http::{self, Request, Workers},
+    languages,
+    render::{self, NoData},
+    scan,
+    stats_file::StatsFile,
+};
+
+#[derive(Args)]
+#[group(skip)]
+pub struct Options {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    pub addr: SocketAddr,
+    /// Repository that the stats file was scanned from, which allows to scan it again with
+    /// `POST /api/rescan`.
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    pub repo: Option<PathBuf>,
+    /// Reject all requests that would change the stats file, like rescans.
+    #[arg(long)]
+    pub read_only: bool,
+    /// Require HTTP basic authentication with these credentials.
+    #[arg(long, value_name = "USER:PASSWORD")]
+    pub basic_auth: Option<String>,
+    /// Require this bearer token in the `Authorization` header.
+    #[arg(long)]
+    pub token: Option<String>,
+    #[command(flatten)]
+    pub scan: scan::Options,
+    #[command(flatten)]
+    pub daemon: DaemonArgs,
+}
+
+/// Time to wait between checks for new connections, which is also the delay until a shutdown is
+/// noticed.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Period over which the trend of the comment ratio is measured.
+const TREND_DAYS: i64 = 90;
+/// Smallest change of the comment ratio over the trend period that counts as rising or falling.
+const TREND_THRESHOLD: f64 = 0.001;
+
+/// State shared by all requests.
+struct Server<'a> {
+    repos: Vec<Repo>,
+    options: &'a Options,
+    /// Current configuration, replaced on reloads. Requests keep the one they started with.
+    config: Mutex<Arc<Config>>,
+    daemon: Daemon,
+    /// Accepted values of the `Authorization` header. Empty if authentication is disabled.
+    credentials: Vec<String>,
+    rescans: Rescans,
+}
+
+/// Rescans requested with `POST /api/rescan`, which run one at a time on a thread of their own.
+#[derive(Default)]
+struct Rescans {
+    state: Mutex<RescanState>,
+    /// Notified when a rescan is requested.
+    requested: Condvar,
+}
+
+#[derive(Default)]
+struct RescanState {
+    /// A rescan was requested and didn't start yet.
+    pending: bool,
+    running: bool,
+}
+
+impl Server<'_> {
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn rescan_state(&self) -> MutexGuard<'_, RescanState> {
+        self.rescans
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Look up the repository of a request, which can be left out if only one is served.
+    fn find(&self, id: Option<&str>) -> Result<&Repo> {
+        match id {
+            Some(id) => self
+                .repos
+                .iter()
+                .find(|repo| repo.id == id)
+                .with_context(|| format!("unknown repository `{id}`")),
+            None if self.repos.len() == 1 => Ok(&self.repos[0]),
+            None => bail!("several repositories are served, select one with `repo`"),
+        }
+    }
+
+    fn authorized(&self, authorization: Option<&str>) -> bool {
+        if self.credentials.is_empty() {
+            return true;
+        }
+
+        authorization.is_some_and(|authorization| {
+            self.credentials
+                .iter()
+                .any(|expected| constant_time_eq(expected.as_bytes(), authorization.as_bytes()))
+        })
+    }
+}
+
+/// Single served stats file.
+struct Repo {
+    /// File name of the stats file without its extension, which identifies it in requests.
+    id: String,
+    path: PathBuf,
+}
+
+/// Response to a single request.
+struct Response {
+    status: &'static str,
+    body: Value,
+    /// Ask the client for credentials, for requests without valid ones.
+    challenge: Option<&'static str>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: "200 OK",
+            body,
+            challenge: None,
+        }
+    }
+
+    fn error(status: &'static str, message: impl ToString) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+            challenge: None,
+        }
+    }
+}
+
+/// Parameters of a `/api/series` or `/api/contributors` query.
+#[derive(Default)]
+struct Query {
+    repo: Option<String>,
+    render: render::Options,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    limit: Option<usize>,
+}
+
+pub fn run(
+    inputs: &[PathBuf],
+    mut options: Options,
+    config: Config,
+    config_path: Option<PathBuf>,
+) -> Result<()> {
+    // Rescans only add the new commits to the existing stats file.
+    options.scan.update = true;
+    let options = &options;
+
+    let repos = repos(inputs)?;
+    ensure!(!repos.is_empty(), "no stats files found to serve");
+    ensure!(
+        options.repo.is_none() || repos.len() == 1,
+        "rescans are only possible when serving a single stats file"
+    );
+
+    let listener = TcpListener::bind(options.addr)
+        .with_context(|| format!("failed listening on {}", options.addr))?;
+    // Connections are polled for, so that signals are noticed in between.
+    listener.set_nonblocking(true)?;
+
+    let credentials = options
+        .basic_auth
+        .iter()
+        .map(|credentials| format!("Basic {}", base64(credentials.as_bytes())))
+        .chain(options.token.iter().map(|token| format!("Bearer {token}")))
+        .collect();
+    let files = repos.iter().map(|repo| repo.path.clone()).collect();
+    let server = Server {
+        repos,
+        options,
+        config: Mutex::new(Arc::new(config)),
+        daemon: Daemon::start(&options.daemon, config_path, files)?,
+        credentials,
+        rescans: Rescans::default(),
+    };
+
+    for repo in &server.repos {
+        println!("serving {} as {}", repo.path.display(), repo.id);
+    }
+    println!("listening on http://{}", options.addr);
+
+    server
+        .daemon
+        .ready(&format!("listening on {}", options.addr));
+    server.daemon.health("idle");
+
+    let workers = Workers::default();
+
+    // Requests that are still answered when shutting down are waited for, as well as a running
+    // rescan, which stops early and saves the commits it scanned so far.
+    thread::scope(|scope| {
+        if let (Some(repo), false) = (&options.repo, options.read_only) {
+            thread::Builder::new()
+                .spawn_scoped(scope, || rescan_worker(repo, &server))
+                .context("failed starting rescan worker")?;
+        }
+
+        while !server.daemon.stopping() {
+            if let Some(config) = server.daemon.reload() {
+                *server.config.lock().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+            }
+
+            let result = match listener.accept() {
+                Ok((stream, _)) => http::prepare(&stream).map_err(Into::into).and_then(|()| {
+                    match workers.claim() {
+                        Some(worker) => thread::Builder::new()
+                            .spawn_scoped(scope, || {
+                                let _worker = worker;
+                                // A broken connection must not stop the server.
+                                if let Err(e) = handle(stream, &server) {
+                                    eprintln!("Error: {e:?}");
+                                }
+                            })
+                            .map(drop)
+                            .context("failed starting worker"),
+                        None => http::busy(&stream).map_err(Into::into),
+                    }
+                }),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => Err(anyhow::Error::new(e).context("failed accepting connection")),
+            };
+
+            if let Err(e) = result {
+                eprintln!("Error: {e:?}");
+            }
+        }
+
+        println!("shutting down");
+        server.rescans.requested.notify_all();
+
+        Ok(())
+    })
+}
+
+fn handle(stream: TcpStream, server: &Server<'_>) -> Result<()> {
+    let Some(request) = http::read_request(&stream)? else {
+        return Ok(());
+    };
+
+    let response = match request {
+        // Health checks come from monitors that usually can't authenticate.
+        Ok(Request { method, target, .. }) if method == "GET" && is_health_check(&target) => {
+            route("GET", &target, server)
+        }
+        Ok(request) if !server.authorized(request.authorization.as_deref()) => Response {
+            challenge: Some(if server.options.basic_auth.is_some() {
+                "Basic realm=\"commentstats\""
+            } else {
+                "Bearer"
+            }),
+            ..Response::error("401 Unauthorized", "missing or invalid credentials")
+        },
+        Ok(request) => route(&request.method, &request.target, server),
+        Err(rejection) => return Ok(http::reject(&stream, &rejection)?),
+    };
+
+    let challenge = response
+        .challenge
+        .map(|challenge| format!("WWW-Authenticate: {challenge}\r\n"))
+        .unwrap_or_default();
+    let body = serde_json::to_string(&response.body)?;
+    http::respond(&stream, response.status, &challenge, &body)?;
+
+    Ok(())
+}
+
+fn route(method: &str, target: &str, server: &Server<'_>) -> Response {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    if let ("GET", Some((status, body))) = (method, server.daemon.status().check(path)) {
+        return Response {
+            status,
+            body,
+            challenge: None,
+        };
+    }
+
+    match (method, path) {
+        ("GET", "/api/repos") => ranking(&server.repos, &server.config()),
+        ("GET", "/api/series") => match parse_query(query) {
+            Ok(query) => match server.find(query.repo.as_deref()) {
+                Ok(repo) => series(&query, &repo.path, &server.config()),
+                Err(e) => Response::error("404 Not Found", format!("{e:#}")),
+            },
+            Err(e) => Response::error("400 Bad Request", format!("{e:#}")),
+        },
+        ("GET", "/api/contributors") => match parse_query(query) {
+            Ok(query) => match server.find(query.repo.as_deref()) {
+                Ok(repo) => contributors(&query, &repo.path),
+                Err(e) => Response::error("404 Not Found", format!("{e:#}")),
+            },
+            Err(e) => Response::error("400 Bad Request", format!("{e:#}")),
+        },
+        ("POST", "/api/rescan") => rescan(server),
+        (_, "/api/repos" | "/api/series" | "/api/contributors" | "/api/rescan") => Response::error(
+            "405 Method Not Allowed",
+            format!("{method} isn't supported"),
+        ),
+        _ => Response::error("404 Not Found", format!("unknown endpoint {path}")),
+    }
+}
+
+fn is_health_check(target: &str) -> bool {
+    let path = target.split_once('?').map_or(target, |(path, _)| path);
+    matches!(path, "/healthz" | "/readyz")
+}
+
+fn rescan(server: &Server<'_>) -> Response {
+    if server.options.read_only {
+        return Response::error("403 Forbidden", "the server is read-only");
+    }
+
+    if server.options.repo.is_none() {
+        return Response::error(
+            "404 Not Found",
+            "rescans need the repository, given with --repo",
+        );
+    }
+
+    let mut state = server.rescan_state();
+    let status = if state.pending || state.running {
+        "queued"
+    } else {
+        "started"
+    };
+    state.pending = true;
+    drop(state);
+    server.rescans.requested.notify_all();
+
+    Response {
+        status: "202 Accepted",
+        ..Response::ok(json!({ "status": status }))
+    }
+}
+
+/// Run the requested rescans one after the other, until the server shuts down.
+fn rescan_worker(repo: &Path, server: &Server<'_>) {
+    loop {
+        let mut state = server.rescan_state();
+        while !state.pending && !server.daemon.stopping() {
+            // Signals can't notify the condition variable, so shutdowns are polled for.
+            state = server
+                .rescans
+                .requested
+                .wait_timeout(state, POLL_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+        if server.daemon.stopping() {
+            return;
+        }
+        state.pending = false;
+        state.running = true;
+        drop(state);
+
+        println!("rescanning {}...", repo.display());
+        server.daemon.health("scanning");
+
+        // The stats file is only replaced once the scan is complete, so requests in the meantime
+        // keep answering with the previous data.
+        let input = &server.repos[0].path;
+        match scan::run(
+            repo.to_owned(),
+            input,
+            &server.options.scan,
+            &server.config(),
+        ) {
+            Ok(()) => server.daemon.succeeded(),
+            Err(e) => eprintln!("Error: rescan failed: {e:?}"),
+        }
+
+        server.daemon.health("idle");
+        server.rescan_state().running = false;
+    }
+}
+
+/// Collect the stats files to serve. Directories are searched for files with the `stats`
+/// extension, without descending into subdirectories.
+fn repos(inputs: &[PathBuf]) -> Result<Vec<Repo>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if !input.is_dir() {
+            paths.push(input.clone());
+            continue;
+        }
+
+        let mut found = fs::read_dir(input)
+            .with_context(|| format!("failed reading {}", input.display()))?
+            .map(|entry| Ok(entry?.path()))
+            .filter(|path| {
+                path.as_ref().map_or(true, |path| {
+                    path.extension().is_some_and(|ext| ext == "stats")
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        found.sort();
+        paths.extend(found);
+    }
+
+    let mut ids = HashSet::new();
+    paths
+        .into_iter()
+        .map(|path| {
+            let id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("invalid file name {}", path.display()))?
+                .to_owned();
+            ensure!(
+                ids.insert(id.clone()),
+                "more than one stats file is named `{id}`"
+            );
+
+            Ok(Repo { id, path })
+        })
+        .collect()
+}
+
+fn ranking(repos: &[Repo], config: &Config) -> Response {
+    let mut ranked = Vec::with_capacity(repos.len());
+
+    for repo in repos {
+        match trend(&repo.path) {
+            Ok((name, ratio, change)) => ranked.push((repo, name, ratio, change)),
+            Err(e) => {
+                return Response::error("500 Internal Server Error", format!("{}: {e:#}", repo.id))
+            }
+        }
+    }
+
+    ranked.sort_by(|a, b| b.2.total_cmp(&a.2));
+
+    let repos = ranked
+        .into_iter()
+        .map(|(repo, name, ratio, change)| {
+            let trend = if change >= TREND_THRESHOLD {
+                "rising"
+            } else if change <= -TREND_THRESHOLD {
+                "falling"
+            } else {
+                "flat"
+            };
+
+            json!({
+                "id": repo.id,
+                "name": config.redact(name.as_deref().unwrap_or(&repo.id)),
+                "comment_ratio": ratio,
+                "change": change,
+                "trend": trend,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Response::ok(json!({ "repos": repos }))
+}
+
+/// Determine the name of the repository, its latest comment ratio and how much the ratio changed
+/// over the last [`TREND_DAYS`], or since the first entry for shorter histories. Only the first
+/// history of the stats file is looked at.
+fn trend(path: &Path) -> Result<(Option<String>, f64, f64)> {
+    let file = StatsFile::open(path)?;
+    let name = file.manifest().metadata.name.clone();
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        return Ok((name, 0.0, 0.0));
+    };
+
+    let mut latest = None;
+    let mut earlier = None;
+
+    'chunks: for index in range.rev() {
+        let mut entries = Vec::new();
+        file.read_chunk(index, |entry| {
+            if !entry.failed {
+                let stats = entry.total_stats()?.statistics;
+                let ratio = if stats.code == 0 {
+                    0.0
+                } else {
+                    stats.comments as f64 / stats.code as f64
+                };
+                entries.push((entry.timestamp, ratio));
+            }
+            Ok(())
+        })?;
+
+        for (time, ratio) in entries.into_iter().rev() {
+            let (latest_time, _) = *latest.get_or_insert((time, ratio));
+            earlier = Some(ratio);
+
+            if time <= latest_time - TimeDelta::days(TREND_DAYS) {
+                break 'chunks;
+            }
+        }
+    }
+
+    let ratio = latest.map_or(0.0, |(_, ratio)| ratio);
+    Ok((name, ratio, ratio - earlier.unwrap_or(ratio)))
+}
+
+fn series(query: &Query, input: &Path, config: &Config) -> Response {
+    match render::chart(input.to_owned(), &query.render, config) {
+        Ok(chart) => Response::ok(series_json(&chart, query.from, query.to)),
+        Err(e) if e.is::<NoData>() => Response::error("404 Not Found", e),
+        Err(e) => Response::error("500 Internal Server Error", format!("{e:#}")),
+    }
+}
+
+fn contributors(query: &Query, input: &Path) -> Response {
+    let filter = query.render.filter.filter.iter().copied().collect();
+    let limit = query.limit.unwrap_or(contributors::DEFAULT_LIMIT);
+
+    match contributors::between(input, query.from, query.to, &filter, limit) {
+        Ok(value) => Response::ok(value),
+        Err(e) => Response::error("500 Internal Server Error", format!("{e:#}")),
+    }
+}
+
+/// Convert the chart into the response of `/api/series`, limited to the given days.
+fn series_json(chart: &Chart, from: Option<NaiveDate>, to: Option<NaiveDate>) -> Value {
+    let timestamp = |date: NaiveDate| date.and_time(NaiveTime::default()).and_utc().timestamp();
+    let from = from.map_or(i64::MIN, timestamp);
+    let to = to.map_or(i64::MAX, timestamp);
+    let within = |time: i64| (from..=to).contains(&time);
+
+    let series = chart
+        .series
+        .iter()
+        .map(|series| {
+            let (kind, points) = match &series.shape {
+                Shape::Line(points) | Shape::Dashed(points) | Shape::Markers(points) => {
+                    let points = points
+                        .iter()
+                        .filter(|(time, _)| within(*time))
+                        .map(|&(time, value)| json!([time, value]))
+                        .collect::<Vec<_>>();
+                    let kind = match series.shape {
+                        Shape::Markers(_) => "markers",
+                        Shape::Dashed(_) => "dashed",
+                        _ => "line",
+                    };
+                    (kind, points)
+                }
+                Shape::Area(ranges) => {
+                    let points = ranges
+                        .iter()
+                        .flatten()
+                        .filter(|(time, _, _)| within(*time))
+                        .map(|&(time, min, max)| json!([time, min, max]))
+                        .collect::<Vec<_>>();
+                    ("area", points)
+                }
+            };
+
+            json!({ "label": series.label, "type": kind, "points": points })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "title": chart.title,
+        "unit": chart.y_label,
+        "series": series,
+    })
+}
+
+fn parse_query(query: &str) -> Result<Query> {
+    let mut parsed = Query::default();
+
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = decode(value).with_context(|| format!("invalid value of `{key}`"))?;
+        let value = value.as_str();
+
+        match key {
+            "repo" => parsed.repo = Some(value.to_owned()),
+            "lang" => parsed
+                .render
+                .filter
+                .filter
+                .push(languages::parse(value).map_err(anyhow::Error::msg)?),
+            "metric" => parsed.render.metric = value_enum(key, value)?,
+            "group-by" => parsed.render.group_by = value_enum(key, value)?,
+            "bucket" => parsed.render.bucket = value_enum(key, value)?,
+            "from" => parsed.from = Some(date(key, value)?),
+            "to" => parsed.to = Some(date(key, value)?),
+            "limit" => {
+                parsed.limit = Some(
+                    value
+                        .parse()
+                        .with_context(|| format!("invalid value `{value}` of `{key}`"))?,
+                );
+            }
+            _ => bail!("unknown parameter `{key}`"),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn value_enum<T: ValueEnum>(key: &str, value: &str) -> Result<T> {
+    T::from_str(value, true).map_err(|_| {
+        let possible = T::value_variants()
+            .iter()
+            .filter_map(|v| v.to_possible_value())
+            .map(|v| v.get_name().to_owned())
+            .collect::<Vec<_>>();
+        anyhow::anyhow!(
+            "invalid value `{value}` of `{key}`, possible values are {}",
+            possible.join(", ")
+        )
+    })
+}
+
+fn date(key: &str, value: &str) -> Result<NaiveDate> {
+    value
+        .parse()
+        .with_context(|| format!("invalid date `{value}` of `{key}`, expected like 2024-01-31"))
+}
+
+/// Decode a percent-encoded query value, where `+` stands for a space.
+fn decode(value: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'+' => bytes.push(b' '),
+            b'%' => {
+                let hex = rest.get(..2).context("incomplete escape sequence")?;
+                // Checked up front, as parsing alone would accept a sign, like in `%+1`.
+                ensure!(
+                    hex.iter().all(u8::is_ascii_hexdigit),
+                    "invalid escape sequence"
+                );
+                let hex = std::str::from_utf8(hex)?;
+                bytes.push(u8::from_str_radix(hex, 16)?);
+                rest = &rest[2..];
+            }
+            _ => bytes.push(byte),
+        }
+    }
+
+    Ok(String::from_utf8(bytes)?)
+}
+
+/// Compare two values in a time that only depends on their length, so the time of a comparison
+/// doesn't reveal how much of a guessed token is correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Encode the data with the standard Base64 alphabet and padding, as used for basic
+/// authentication.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(group >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_is_parsed() {
+        let query =
+            parse_query("repo=core&lang=C%2B%2B&lang=rust&bucket=week&from=2024-01-31").unwrap();
+
+        assert_eq!(Some("core"), query.repo.as_deref());
+        assert_eq!(2, query.render.filter.filter.len());
+        assert!(matches!(query.render.bucket, render::Bucket::Week));
+        assert_eq!(NaiveDate::from_ymd_opt(2024, 1, 31), query.from);
+        assert!(query.to.is_none());
+
+        assert!(parse_query("bucket=year").is_err());
+        assert!(parse_query("unknown=1").is_err());
+        assert!(parse_query("lang=%2").is_err());
+    }
+
+    #[test]
+    fn values_are_decoded() {
+        assert_eq!("C++ code", decode("C%2B%2B+code").unwrap());
+        assert_eq!("\u{e4}", decode("%c3%A4").unwrap());
+        assert!(decode("%+1").is_err());
+        assert!(decode("%-1").is_err());
+        assert!(decode("%zz").is_err());
+        assert!(decode("%2").is_err());
+        assert!(decode("%ff").is_err());
+    }
+
+    #[test]
+    fn credentials_are_encoded() {
+        assert_eq!("", base64(b""));
+        assert_eq!("Zg==", base64(b"f"));
+        assert_eq!("Zm8=", base64(b"fo"));
+        assert_eq!("Zm9v", base64(b"foo"));
+        assert_eq!("dXNlcjpwYXNz", base64(b"user:pass"));
+    }
+}
@@ -0,0 +1,96 @@
+//! Signatures of stats files, created and verified with `ssh-keygen -Y`. The signature is stored
+//! next to the stats file, with an additional `.sig` extension.
+//!
+//! Verification is enabled by pointing the `COMMENTSTATS_ALLOWED_SIGNERS` environment variable to
+//! an allowed signers file (see `ssh-keygen(1)`). From then on, stats files are only opened if
+//! they carry a valid signature of one of the listed keys.
+
+use std::{
+    env,
+    ffi::OsString,
+    fs::File,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{ensure, Context, Result};
+
+/// Environment variable with the path of the allowed signers file used for verification.
+const ALLOWED_SIGNERS_VAR: &str = "COMMENTSTATS_ALLOWED_SIGNERS";
+/// Namespace of the signatures, which keeps them from being valid for other purposes.
+const NAMESPACE: &str = "commentstats";
+
+/// Location of the signature of a stats file.
+pub fn path(file: &Path) -> PathBuf {
+    let mut path = OsString::from(file.as_os_str());
+    path.push(".sig");
+    path.into()
+}
+
+/// Sign the file with an SSH private key, and write the signature to `signature`. Usually that's
+/// the [`path`] of the file, but signatures may be written elsewhere first, to move them in place
+/// together with the file.
+pub fn sign(file: &Path, key: &Path, signature: &Path) -> Result<()> {
+    // The data is signed from stdin, so the signature is written to stdout.
+    let output = Command::new("ssh-keygen")
+        .args(["-q", "-Y", "sign", "-n", NAMESPACE, "-f"])
+        .arg(key)
+        .stdin(File::open(file)?)
+        .stdout(File::create(signature)?)
+        .output()
+        .context("failed running ssh-keygen, is it installed?")?;
+    ensure!(
+        output.status.success(),
+        "failed signing {}: {}",
+        file.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(())
+}
+
+/// Verify the signature of the file, if verification is enabled. The `content` is an open handle
+/// of the file, positioned at its start, so the verified bytes are the ones read from that handle
+/// afterwards, even if the file is replaced in the meantime.
+pub fn verify(file: &Path, content: File) -> Result<()> {
+    let Some(allowed) = env::var_os(ALLOWED_SIGNERS_VAR) else {
+        return Ok(());
+    };
+
+    let signature = path(file);
+    ensure!(
+        signature.exists(),
+        "{} isn't signed, but {ALLOWED_SIGNERS_VAR} requires signatures",
+        file.display()
+    );
+
+    let principals = Command::new("ssh-keygen")
+        .args(["-Y", "find-principals", "-s"])
+        .arg(&signature)
+        .arg("-f")
+        .arg(&allowed)
+        .output()
+        .context("failed running ssh-keygen, is it installed?")?;
+    let principals = String::from_utf8_lossy(&principals.stdout);
+    let principal = principals
+        .lines()
+        .next()
+        .with_context(|| format!("{} isn't signed by an allowed signer", file.display()))?;
+
+    let output = Command::new("ssh-keygen")
+        .args(["-Y", "verify", "-n", NAMESPACE, "-I", principal, "-s"])
+        .arg(&signature)
+        .arg("-f")
+        .arg(&allowed)
+        .stdin(content)
+        .stderr(Stdio::null())
+        .output()
+        .context("failed running ssh-keygen, is it installed?")?;
+    ensure!(
+        output.status.success(),
+        "the signature of {} is invalid",
+        file.display()
+    );
+
+    Ok(())
+}
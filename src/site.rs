@@ -0,0 +1,245 @@
+//! Static website with the charts of a stats file, that can be published as is, for example with
+//! GitHub Pages.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use clap::{Args, ValueHint};
+
+use crate::{
+    chart::Format,
+    config::Config,
+    languages::FilterArgs,
+    legend::escape,
+    provenance,
+    render::{self, NoData},
+    stats_file::StatsFile,
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Directory to write the website to. Existing files of earlier runs are overwritten.
+    #[arg(short, long, default_value = "site", value_hint = ValueHint::DirPath)]
+    pub output: PathBuf,
+    /// Write a `manifest.json` into the site, with the tool version, a hash of the stats file
+    /// and the time of generation, so the site can be traced back to its data.
+    #[arg(long)]
+    pub manifest: bool,
+}
+
+/// Single page of the site, besides the index.
+struct Page {
+    title: String,
+    file: String,
+}
+
+pub fn run(input: &Path, options: &Options, config: &Config) -> Result<()> {
+    let stats = StatsFile::open(input)?;
+    let name = stats
+        .manifest()
+        .metadata
+        .name
+        .as_deref()
+        .unwrap_or("Repository");
+    let name = config.redact(name).into_owned();
+    let Some(latest) = stats.last_entry()? else {
+        bail!("the stats file contains no entries");
+    };
+
+    let mut languages = latest.language_stats()?.into_keys().collect::<Vec<_>>();
+    languages.sort_by_key(|lang| lang.name());
+
+    // Statistics summed up per language have no paths.
+    let dirs = latest
+        .files
+        .keys()
+        .filter_map(|path| path.split_once('/'))
+        .map(|(dir, _)| dir.to_owned())
+        .collect::<BTreeSet<_>>();
+
+    let out = &options.output;
+    fs::create_dir_all(out.join("charts"))
+        .with_context(|| format!("failed creating {}", out.display()))?;
+
+    chart(input, out, "index", render::Options::default(), config)?;
+
+    let mut language_pages = Vec::new();
+    for lang in languages {
+        let file = format!("language-{}", slug(lang.name()));
+        let chart_options = render::Options {
+            filter: FilterArgs {
+                filter: vec![lang],
+                ..FilterArgs::default()
+            },
+            ..render::Options::default()
+        };
+
+        if chart(input, out, &file, chart_options, config)? {
+            language_pages.push(Page {
+                title: lang.name().to_owned(),
+                file,
+            });
+        }
+    }
+
+    let mut dir_pages = Vec::new();
+    let mut files = HashSet::new();
+    for dir in &dirs {
+        // Page names are derived from the redacted directory, so they don't leak it either.
+        // Directories that end up with the same name are numbered.
+        let redacted = config.redact(dir);
+        let base = format!("dir-{}", slug(&redacted));
+        let file = (1..)
+            .map(|i| match i {
+                1 => base.clone(),
+                _ => format!("{base}-{i}"),
+            })
+            .find(|file| files.insert(file.clone()))
+            .unwrap_or(base);
+        let title = format!("{redacted}/");
+
+        let chart_options = render::Options {
+            path: Some(dir.clone()),
+            ..render::Options::default()
+        };
+
+        if chart(input, out, &file, chart_options, config)? {
+            dir_pages.push(Page { title, file });
+        }
+    }
+
+    // The data page offers the raw stats file and the overall chart data for other tools. The
+    // stats file contains all paths as they are, so it's left out if any are redacted.
+    let raw = config.redact.is_empty();
+    if raw {
+        fs::copy(input, out.join("stats.stats"))
+            .with_context(|| format!("failed copying {}", input.display()))?;
+    }
+    render::run(
+        input.to_owned(),
+        &out.join("data.vl.json"),
+        &render::Options {
+            format: Format::VegaLite,
+            ..render::Options::default()
+        },
+        config,
+    )?;
+
+    for (pages, kind) in [(&language_pages, "Language"), (&dir_pages, "Directory")] {
+        for page in pages {
+            let body = format!("<img src=\"charts/{}.svg\" alt=\"Chart\">", page.file);
+            let title = format!("{kind} {}", page.title);
+            write_page(out, &page.file, &name, &title, &body)?;
+        }
+    }
+
+    let mut index = String::from("<img src=\"charts/index.svg\" alt=\"Chart\">");
+    for (pages, heading) in [(&language_pages, "Languages"), (&dir_pages, "Directories")] {
+        if pages.is_empty() {
+            continue;
+        }
+
+        write!(index, "<h2>{heading}</h2><ul>")?;
+        for page in pages {
+            write!(
+                index,
+                "<li><a href=\"{}.html\">{}</a></li>",
+                page.file,
+                escape(&page.title)
+            )?;
+        }
+        index.push_str("</ul>");
+    }
+    write_page(out, "index", &name, "Overview", &index)?;
+
+    let mut data = String::from("<ul>");
+    if raw {
+        data.push_str(
+            "<li><a href=\"stats.stats\">stats.stats</a>: Full statistics, to be used with \
+             <code>commentstats render</code>.</li>",
+        );
+    }
+    data.push_str(
+        "<li><a href=\"data.vl.json\">data.vl.json</a>: Vega-Lite spec of the overview chart, \
+         with its data inlined.</li></ul>",
+    );
+    write_page(out, "data", &name, "Data", &data)?;
+
+    if options.manifest {
+        provenance::write(
+            &out.join("manifest.json"),
+            out,
+            input,
+            render::filters(&FilterArgs::default(), None),
+        )?;
+    }
+
+    println!("site written to {}", out.display());
+
+    Ok(())
+}
+
+/// Render the chart of a page into the `charts` directory. Returns `false` if there was nothing
+/// to render, in which case the page is left out.
+fn chart(
+    input: &Path,
+    out: &Path,
+    file: &str,
+    options: render::Options,
+    config: &Config,
+) -> Result<bool> {
+    let output = out.join("charts").join(format!("{file}.svg"));
+
+    match render::run(input.to_owned(), &output, &options, config) {
+        Ok(()) => Ok(true),
+        Err(e) if e.is::<NoData>() => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn write_page(out: &Path, file: &str, name: &str, title: &str, body: &str) -> Result<()> {
+    let name = escape(name);
+    let title = escape(title);
+    let html = format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title} · {name}</title>
+<style>
+body {{ font-family: sans-serif; max-width: 1200px; margin: 0 auto; padding: 1em; }}
+nav a {{ margin-right: 1em; }}
+img {{ width: 100%; height: auto; }}
+</style>
+</head>
+<body>
+<nav><a href=\"index.html\">Overview</a><a href=\"data.html\">Data</a></nav>
+<h1>{name} — {title}</h1>
+{body}
+</body>
+</html>
+"
+    );
+
+    let path = out.join(format!("{file}.html"));
+    fs::write(&path, html).with_context(|| format!("failed writing {}", path.display()))
+}
+
+/// Turn a language or directory name into a file name, like `c--` for `C++`.
+fn slug(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
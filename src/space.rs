@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+
+/// Ensure the file systems of the given paths have enough free space to write `size` bytes to
+/// each of them. Paths on the same file system need space for all their writes combined.
+/// File systems whose free space can't be determined are assumed to have enough.
+pub fn check(size: u64, paths: &[&Path]) -> Result<()> {
+    for path in paths {
+        let Some(available) = available(path)
+            .with_context(|| format!("failed checking free space of {}", path.display()))?
+        else {
+            continue;
+        };
+
+        let device = device(path);
+        let writes = match device {
+            Some(_) => paths
+                .iter()
+                .filter(|other| self::device(other) == device)
+                .count(),
+            None => 1,
+        };
+        let needed = size.saturating_mul(writes as u64);
+
+        ensure!(
+            needed <= available,
+            "not enough free space at {}: about {} needed, but only {} available (use \
+             --skip-space-check to scan anyway)",
+            path.display(),
+            format_size(needed),
+            format_size(available),
+        );
+    }
+
+    Ok(())
+}
+
+/// Format the size in bytes with a binary unit, for display.
+fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Free space on the file system of the path that is available to unprivileged users.
+#[cfg(unix)]
+fn available(path: &Path) -> Result<Option<u64>> {
+    use std::{ffi::CString, io, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: The path is a valid C string and `stat` is only read after the call succeeded and
+    // initialized it.
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        stat.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(Some(
+        (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64),
+    ))
+}
+
+/// Free space can't be determined on other platforms yet.
+#[cfg(not(unix))]
+fn available(_: &Path) -> Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Identifier of the device that holds the path, to find paths on the same file system.
+#[cfg(unix)]
+fn device(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+
+    path.metadata().ok().map(|meta| meta.dev())
+}
+
+#[cfg(not(unix))]
+fn device(_: &Path) -> Option<u64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn shared_file_system_needs_combined_space() {
+        let dir = tempfile::tempdir().unwrap();
+        let available = available(dir.path()).unwrap().unwrap();
+
+        check(available / 4, &[dir.path(), dir.path()]).unwrap();
+        assert!(check(available / 2 + 1, &[dir.path(), dir.path()]).is_err());
+    }
+
+    #[test]
+    fn size_is_formatted_with_unit() {
+        assert_eq!("512.0 B", format_size(512));
+        assert_eq!("1.5 KiB", format_size(1536));
+        assert_eq!("2.0 GiB", format_size(2 * 1024 * 1024 * 1024));
+    }
+}
@@ -0,0 +1,192 @@
+//! Report of files whose documentation likely fell behind: their code changed recently, while
+//! their comment lines stayed the same for months.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, ensure, Result};
+use chrono::{DateTime, FixedOffset, Months};
+use clap::Args;
+
+use crate::{models::Detail, stats_file::StatsFile};
+
+#[derive(Args)]
+pub struct Options {
+    /// Amount of months without any change of the comment lines, after which a file counts as
+    /// stale.
+    #[arg(long, value_name = "MONTHS", default_value_t = 6)]
+    pub stale: u32,
+    /// Only list files whose code changed within this amount of months, as the documentation of
+    /// untouched files can't fall behind.
+    #[arg(long, value_name = "MONTHS", default_value_t = 3)]
+    pub recent: u32,
+    /// Maximum amount of files to list, the longest stale first.
+    #[arg(long, default_value_t = 50)]
+    pub limit: usize,
+}
+
+/// Line counts of a file and when they last changed.
+#[derive(Clone, Copy)]
+struct Age {
+    code: usize,
+    comments: usize,
+    code_changed: DateTime<FixedOffset>,
+    comments_changed: DateTime<FixedOffset>,
+}
+
+/// Ages of the files that exist at the latest entry.
+#[derive(Default)]
+struct Ages {
+    files: HashMap<String, Age>,
+    latest: Option<DateTime<FixedOffset>>,
+}
+
+impl Ages {
+    /// Take the code and comment lines of all files at the next entry. Files that are missing
+    /// were deleted, and start over if they come back.
+    fn update(&mut self, time: DateTime<FixedOffset>, files: HashMap<String, (usize, usize)>) {
+        self.files.retain(|path, _| files.contains_key(path));
+
+        for (path, (code, comments)) in files {
+            let age = self.files.entry(path).or_insert(Age {
+                code,
+                comments,
+                code_changed: time,
+                comments_changed: time,
+            });
+
+            if age.code != code {
+                age.code = code;
+                age.code_changed = time;
+            }
+            if age.comments != comments {
+                age.comments = comments;
+                age.comments_changed = time;
+            }
+        }
+
+        self.latest = Some(time);
+    }
+
+    /// Files with recent code changes but stale comments, the longest stale first.
+    fn stale(&self, options: &Options) -> Vec<(&str, Age)> {
+        let Some(latest) = self.latest else {
+            return Vec::new();
+        };
+        let stale = latest - Months::new(options.stale);
+        let recent = latest - Months::new(options.recent);
+
+        let mut files = self
+            .files
+            .iter()
+            .filter(|(_, age)| {
+                age.code_changed >= recent
+                    && age.comments_changed <= stale
+                    && age.code_changed > age.comments_changed
+            })
+            .map(|(path, &age)| (path.as_str(), age))
+            .collect::<Vec<_>>();
+
+        files.sort_by(|a, b| (a.1.comments_changed, a.0).cmp(&(b.1.comments_changed, b.0)));
+        files.truncate(options.limit);
+        files
+    }
+}
+
+pub fn run(input: &Path, options: &Options) -> Result<()> {
+    let file = StatsFile::open(input)?;
+    ensure!(
+        file.manifest().metadata.detail == Detail::PerFile,
+        "the stats file doesn't contain statistics per file, which are needed to find stale files"
+    );
+
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        bail!("{} contains no entries", input.display());
+    };
+
+    let mut ages = Ages::default();
+    for index in range {
+        file.read_chunk(index, |entry| {
+            // Failed commits have no files, which would look like everything was deleted.
+            if !entry.failed {
+                let files = entry
+                    .files
+                    .into_iter()
+                    .map(|(path, file)| {
+                        let stats = file.statistics;
+                        (path, (stats.code, stats.comments))
+                    })
+                    .collect();
+                ages.update(entry.timestamp, files);
+            }
+            Ok(())
+        })?;
+    }
+
+    let stale = ages.stale(options);
+    if stale.is_empty() {
+        println!("no stale files");
+        return Ok(());
+    }
+
+    println!(
+        "{:<18}{:<14}{:>8}{:>10}  path",
+        "comments changed", "code changed", "code", "comments"
+    );
+    for (path, age) in stale {
+        println!(
+            "{:<18}{:<14}{:>8}{:>10}  {path}",
+            age.comments_changed.format("%Y-%m-%d"),
+            age.code_changed.format("%Y-%m-%d"),
+            age.code,
+            age.comments,
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_files_are_found() {
+        let time = |date: &str| DateTime::parse_from_rfc3339(&format!("{date}T12:00:00Z")).unwrap();
+        let files = |list: &[(&str, usize, usize)]| {
+            list.iter()
+                .map(|&(path, code, comments)| (path.to_owned(), (code, comments)))
+                .collect()
+        };
+
+        let mut ages = Ages::default();
+        ages.update(
+            time("2023-01-01"),
+            files(&[("stale.rs", 10, 5), ("fresh.rs", 10, 5), ("idle.rs", 10, 5)]),
+        );
+        ages.update(
+            time("2023-06-01"),
+            files(&[("stale.rs", 10, 5), ("fresh.rs", 20, 8), ("idle.rs", 10, 5)]),
+        );
+        ages.update(
+            time("2024-01-01"),
+            files(&[("stale.rs", 30, 5), ("fresh.rs", 20, 8), ("idle.rs", 10, 5)]),
+        );
+
+        let options = Options {
+            stale: 6,
+            recent: 3,
+            limit: 10,
+        };
+        let stale = ages.stale(&options);
+
+        // The code of `fresh.rs` changed too long ago and `idle.rs` didn't change at all.
+        assert_eq!(1, stale.len());
+        assert_eq!("stale.rs", stale[0].0);
+        assert_eq!(time("2023-01-01"), stale[0].1.comments_changed);
+        assert_eq!(time("2024-01-01"), stale[0].1.code_changed);
+
+        // Deleted files are forgotten.
+        ages.update(time("2024-01-02"), files(&[("fresh.rs", 20, 8)]));
+        assert!(ages.stale(&options).is_empty());
+    }
+}
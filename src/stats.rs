@@ -0,0 +1,202 @@
+//! Summary of a stats file, optionally with the commits whose changes stand out from the rest of
+//! the history, to explain the sudden jumps and drops in the charts.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset};
+use clap::{Args, ValueEnum};
+
+use crate::{commit_type::CommitType, models::CommitInfo, stats_file::StatsFile};
+
+#[derive(Args)]
+pub struct Options {
+    /// List the commits whose change of the code or comment lines is unusually large compared to
+    /// the other commits.
+    #[arg(long)]
+    pub detect_anomalies: bool,
+    /// How to tell unusual changes apart.
+    #[arg(long, value_enum, default_value_t = Method::ZScore, requires = "detect_anomalies")]
+    pub method: Method,
+    /// Sensitivity of the detection. Defaults to 3 standard deviations for `z-score`, and 1.5
+    /// times the interquartile range for `iqr`. Lower values flag more commits.
+    #[arg(long, requires = "detect_anomalies")]
+    pub threshold: Option<f64>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Method {
+    /// Changes that are more than the threshold of standard deviations away from the mean.
+    ZScore,
+    /// Changes that are more than the threshold of interquartile ranges outside of the middle
+    /// half of all changes. Robust against the outliers themselves, but flags every change in
+    /// histories where most commits don't change the lines at all.
+    Iqr,
+}
+
+impl Method {
+    fn default_threshold(self) -> f64 {
+        match self {
+            Self::ZScore => 3.0,
+            Self::Iqr => 1.5,
+        }
+    }
+}
+
+/// Line counts of a single commit.
+struct Point {
+    timestamp: DateTime<FixedOffset>,
+    code: i64,
+    comments: i64,
+    blanks: i64,
+    commit_type: Option<CommitType>,
+    commit: Option<CommitInfo>,
+}
+
+pub fn run(input: &Path, options: &Options) -> Result<()> {
+    let file = StatsFile::open(input)?;
+    let Some((_, range)) = file.manifest().histories().into_iter().next() else {
+        bail!("{} contains no entries", input.display());
+    };
+
+    let mut points = Vec::new();
+    for index in range {
+        file.read_chunk(index, |entry| {
+            // Failed commits have no data, which would look like the largest drop of all.
+            if !entry.failed {
+                let stats = entry.total_stats()?.statistics;
+                points.push(Point {
+                    timestamp: entry.timestamp,
+                    code: stats.code as i64,
+                    comments: stats.comments as i64,
+                    blanks: stats.blanks as i64,
+                    commit_type: entry.commit_type,
+                    commit: entry.commit,
+                });
+            }
+            Ok(())
+        })?;
+    }
+
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        bail!("{} contains no entries", input.display());
+    };
+
+    println!("entries:  {}", file.manifest().entries);
+    println!(
+        "period:   {} to {}",
+        first.timestamp.date_naive(),
+        last.timestamp.date_naive()
+    );
+    println!("code:     {}", last.code);
+    println!("comments: {}", last.comments);
+    println!("blanks:   {}", last.blanks);
+    println!("total:    {}", last.code + last.comments + last.blanks);
+    if last.code > 0 {
+        println!("ratio:    {:.4}", last.comments as f64 / last.code as f64);
+    }
+
+    if !options.detect_anomalies {
+        return Ok(());
+    }
+
+    let threshold = options
+        .threshold
+        .unwrap_or_else(|| options.method.default_threshold());
+    let deltas = |value: fn(&Point) -> i64| {
+        points
+            .windows(2)
+            .map(|pair| value(&pair[1]) - value(&pair[0]))
+            .collect::<Vec<_>>()
+    };
+    let code = deltas(|p| p.code);
+    let comments = deltas(|p| p.comments);
+    let mut flagged = detect(&code, options.method, threshold);
+    flagged.extend(detect(&comments, options.method, threshold));
+    flagged.sort_unstable();
+    flagged.dedup();
+
+    println!();
+    if flagged.is_empty() {
+        println!("no anomalies found");
+        return Ok(());
+    }
+
+    println!(
+        "{:<28}{:>10}{:>10}  {:<10}{:<10}summary",
+        "commit time", "code", "comments", "type", "commit"
+    );
+    for i in flagged {
+        let point = &points[i + 1];
+        // Legacy files and failed commits have no commit details.
+        let (id, summary) = point
+            .commit
+            .as_ref()
+            .map_or(("-", "-"), |c| (&c.id[..c.id.len().min(8)], &c.summary));
+        println!(
+            "{:<28}{:>+10}{:>+10}  {:<10}{:<10}{}",
+            point.timestamp.to_rfc3339(),
+            code[i],
+            comments[i],
+            point.commit_type.map_or("-", CommitType::name),
+            id,
+            summary,
+        );
+    }
+
+    Ok(())
+}
+
+/// Indices of the values that are unusual, according to the method.
+fn detect(values: &[i64], method: Method, threshold: f64) -> Vec<usize> {
+    if values.len() < 2 {
+        return Vec::new();
+    }
+
+    let (low, high) = match method {
+        Method::ZScore => {
+            let n = values.len() as f64;
+            let mean = values.iter().sum::<i64>() as f64 / n;
+            let variance = values
+                .iter()
+                .map(|&v| (v as f64 - mean).powi(2))
+                .sum::<f64>()
+                / n;
+            let deviation = variance.sqrt() * threshold;
+            (mean - deviation, mean + deviation)
+        }
+        Method::Iqr => {
+            let mut sorted = values.to_vec();
+            sorted.sort_unstable();
+            let quartile = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize] as f64;
+            let (q1, q3) = (quartile(0.25), quartile(0.75));
+            let range = (q3 - q1) * threshold;
+            (q1 - range, q3 + range)
+        }
+    };
+
+    values
+        .iter()
+        .enumerate()
+        .filter(|&(_, &v)| (v as f64) < low || (v as f64) > high)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outliers_are_detected() {
+        let mut values = vec![2, -1, 3, 0, 1, 4, -2, 2, 1, 3, 0, 2];
+        values.insert(5, 500);
+        values.push(-300);
+
+        assert_eq!(vec![5], detect(&values, Method::ZScore, 3.0));
+        assert_eq!(vec![5, 13], detect(&values, Method::ZScore, 1.5));
+        assert_eq!(vec![5, 13], detect(&values, Method::Iqr, 1.5));
+        assert!(detect(&[7], Method::ZScore, 3.0).is_empty());
+        assert!(detect(&[0, 0, 0], Method::Iqr, 1.5).is_empty());
+    }
+}
@@ -0,0 +1,1099 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempPath};
+use tokei::{CodeStats, LanguageType};
+use twox_hash::XxHash3_64;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+use zstd::{Decoder as ZstdDecoder, Encoder as ZstdEncoder};
+
+use crate::{
+    crypt,
+    models::{Detail, Entry, EntryFile},
+    signature,
+};
+
+/// Version of the stats file layout, stored in the [`Manifest`]. It must be increased with every
+/// change to the encoding of the manifest or the entries, together with a way to read the
+/// previous version, or a clear error if that's not possible.
+pub const FORMAT_VERSION: u32 = 3;
+/// Version of files written by development builds, whose layout of the manifest and the entries
+/// changed several times without increasing the version. As they can't be told apart, they're
+/// rejected instead of risking to decode them wrongly.
+const DEVELOPMENT_VERSION: u32 = 2;
+/// Version assigned to files from before the manifest was introduced. These only contain an
+/// `info` file with the total entry count, followed by the chunks, and have no checksums. Files
+/// scanned on Windows may contain `\` as path separator, which is converted when reading them.
+pub const LEGACY_VERSION: u32 = 1;
+const MANIFEST_NAME: &str = "manifest";
+const LEGACY_INFO_NAME: &str = "info";
+/// Default zstd level of the chunks and the manifest.
+pub const ZSTD_COMPRESSION_DEFAULT: i32 = 11;
+
+/// Compression of a single chunk. Each chunk records its own, so files that are combined from
+/// others or updated can mix them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Compression {
+    /// Zstandard, at the level given with `--compression-level`.
+    #[default]
+    Zstd,
+    /// No compression at all, which is the fastest to write but makes the file several times
+    /// larger.
+    None,
+}
+
+/// Table of contents of a stats file, stored as the first file in the archive.
+#[derive(Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Total amount of entries over all chunks.
+    pub entries: u64,
+    pub chunks: Vec<ChunkInfo>,
+    pub metadata: Metadata,
+}
+
+/// Information about the scanned repository. All fields are optional, as they are missing for
+/// older files and can't always be determined.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Metadata {
+    /// Name of the repository, derived from its directory.
+    pub name: Option<String>,
+    /// The scanned histories, in the order of their chunks. Empty for older files, which means
+    /// all chunks form a single history.
+    pub histories: Vec<History>,
+    /// Granularity of the statistics in the entries.
+    pub detail: Detail,
+    /// Whether the documentation of public API items was analyzed for Rust files.
+    pub api_docs: bool,
+    /// Whether comment lines were split into natural language and commented-out code.
+    pub comment_quality: bool,
+    /// Whether files were checked for SPDX license tags.
+    pub spdx: bool,
+}
+
+/// History of a single scanned revision.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct History {
+    /// Short name of the scanned reference, like `main`.
+    pub reference: Option<String>,
+    /// Full ID of the scanned commit.
+    pub commit: Option<String>,
+    /// Amount of chunks that belong to this history. They directly follow the chunks of the
+    /// previous history.
+    pub chunks: usize,
+}
+
+impl Manifest {
+    /// Get the histories contained in the file, together with the indices of their chunks.
+    pub fn histories(&self) -> Vec<(Option<&History>, Range<usize>)> {
+        if self.metadata.histories.is_empty() {
+            return vec![(None, 0..self.chunks.len())];
+        }
+
+        let mut start = 0;
+        self.metadata
+            .histories
+            .iter()
+            .map(|history| {
+                let range = start..start + history.chunks;
+                start = range.end;
+                (Some(history), range)
+            })
+            .collect()
+    }
+}
+
+/// Layout of entries in [`LEGACY_VERSION`] files, which only stored the files.
+#[derive(Deserialize)]
+struct LegacyEntry {
+    timestamp: DateTime<FixedOffset>,
+    files: HashMap<String, LegacyFile>,
+}
+
+#[derive(Deserialize)]
+struct LegacyFile {
+    language: LanguageType,
+    statistics: CodeStats,
+}
+
+impl From<LegacyEntry> for Entry {
+    fn from(entry: LegacyEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            files: entry
+                .files
+                .into_iter()
+                .map(|(key, file)| {
+                    let file = EntryFile {
+                        language: file.language,
+                        statistics: file.statistics,
+                        api_docs: None,
+                        comment_kinds: None,
+                        licensed: None,
+                    };
+                    (key, file)
+                })
+                .collect(),
+            languages: HashMap::new(),
+            totals: None,
+            bytes: 0,
+            partial: false,
+            failed: false,
+            notes: Vec::new(),
+            commit_type: None,
+            commit: None,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ChunkInfo {
+    /// File name of the chunk inside the archive.
+    pub name: String,
+    pub entries: u64,
+    /// XXH3 checksum of the uncompressed chunk content.
+    pub checksum: u64,
+    pub compression: Compression,
+}
+
+/// Writer for a single chunk of entries, that is later bundled into the stats file with
+/// [`write`].
+pub struct ChunkWriter<'a> {
+    name: String,
+    entries: u64,
+    compression: Compression,
+    file: HashingWriter<Encoder<'a, BufWriter<File>>>,
+}
+
+impl<'a> ChunkWriter<'a> {
+    /// Create a chunk with the default compression.
+    pub fn create(dir: &Path, index: usize, entries: u64) -> Result<Self> {
+        Self::create_with(
+            dir,
+            index,
+            entries,
+            Compression::Zstd,
+            ZSTD_COMPRESSION_DEFAULT,
+        )
+    }
+
+    /// Create a chunk with the given compression, using the level for zstd.
+    pub fn create_with(
+        dir: &Path,
+        index: usize,
+        entries: u64,
+        compression: Compression,
+        level: i32,
+    ) -> Result<Self> {
+        let name = format!("stats-{index:05}");
+        let file = BufWriter::new(File::create(dir.join(&name))?);
+        let mut file = HashingWriter::new(Encoder::new(file, compression, level)?);
+        bincode::encode_into_std_write(entries, &mut file, bincode::config::standard())?;
+
+        Ok(Self {
+            name,
+            entries,
+            compression,
+            file,
+        })
+    }
+
+    pub fn write(&mut self, entry: &Entry) -> Result<()> {
+        bincode::serde::encode_into_std_write(entry, &mut self.file, bincode::config::standard())?;
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<ChunkInfo> {
+        let (file, checksum) = self.file.finish();
+        file.finish()?.flush()?;
+
+        Ok(ChunkInfo {
+            name: self.name,
+            entries: self.entries,
+            checksum,
+            compression: self.compression,
+        })
+    }
+}
+
+/// Counter for the size that entries take up in a chunk, without writing them anywhere. Used to
+/// estimate the size of a stats file before it's written.
+pub struct SizeCounter<'a> {
+    file: Encoder<'a, CountingWriter>,
+}
+
+impl<'a> SizeCounter<'a> {
+    pub fn new(compression: Compression, level: i32) -> Result<Self> {
+        Ok(Self {
+            file: Encoder::new(CountingWriter::default(), compression, level)?,
+        })
+    }
+
+    pub fn add(&mut self, entry: &Entry) -> Result<()> {
+        bincode::serde::encode_into_std_write(entry, &mut self.file, bincode::config::standard())?;
+        Ok(())
+    }
+
+    /// Get the compressed size of all added entries.
+    pub fn finish(self) -> Result<u64> {
+        Ok(self.file.finish()?.0)
+    }
+}
+
+/// Writer that compresses the data as chosen for a chunk.
+enum Encoder<'a, W: Write> {
+    Zstd(ZstdEncoder<'a, W>),
+    None(W),
+}
+
+impl<W: Write> Encoder<'_, W> {
+    fn new(inner: W, compression: Compression, level: i32) -> Result<Self> {
+        Ok(match compression {
+            Compression::Zstd => Self::Zstd(ZstdEncoder::new(inner, level)?),
+            Compression::None => Self::None(inner),
+        })
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Self::Zstd(encoder) => encoder.finish(),
+            Self::None(inner) => Ok(inner),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Zstd(encoder) => encoder.write(buf),
+            Self::None(inner) => inner.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Zstd(encoder) => encoder.flush(),
+            Self::None(inner) => inner.flush(),
+        }
+    }
+}
+
+/// Writer that discards all data, only counting its length.
+#[derive(Default)]
+struct CountingWriter(u64);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Bundle the manifest and all chunks, previously written to `dir`, into the final stats file.
+pub fn write(
+    output: &Path,
+    dir: &Path,
+    manifest: &Manifest,
+    mut progress: impl FnMut(),
+) -> Result<()> {
+    let mut zip_file = ZipWriter::new(BufWriter::new(File::create(output)?));
+
+    zip_file.start_file(MANIFEST_NAME, FileOptions::default())?;
+    let mut encoder = ZstdEncoder::new(&mut zip_file, ZSTD_COMPRESSION_DEFAULT)?;
+    bincode::serde::encode_into_std_write(manifest, &mut encoder, bincode::config::standard())?;
+    encoder.finish()?;
+
+    for chunk in &manifest.chunks {
+        let mut file = File::open(dir.join(&chunk.name))?;
+
+        zip_file.start_file(&chunk.name, FileOptions::default())?;
+        io::copy(&mut file, &mut zip_file)?;
+
+        progress();
+    }
+
+    zip_file.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Output that is written under a temporary name next to its final location, and only replaces
+/// the file there once it's complete. A failed or interrupted write leaves the previous stats file
+/// and its signature untouched.
+pub struct Staged {
+    output: PathBuf,
+    file: TempPath,
+    signature: Option<TempPath>,
+}
+
+impl Staged {
+    pub fn new(output: &Path) -> Result<Self> {
+        Ok(Self {
+            output: output.to_owned(),
+            file: temp_path_next_to(output)?,
+            signature: None,
+        })
+    }
+
+    /// Location to write the stats file to, possibly encrypted.
+    pub fn path(&self) -> &Path {
+        &self.file
+    }
+
+    /// Sign the staged file. The signature replaces the one of the previous file on commit.
+    pub fn sign(&mut self, key: &Path) -> Result<()> {
+        let signature = temp_path_next_to(&self.output)?;
+        signature::sign(&self.file, key, &signature)?;
+        self.signature = Some(signature);
+
+        Ok(())
+    }
+
+    /// Move the stats file and its signature to their final location. If the file wasn't
+    /// signed, a signature of the previous file is removed, as it doesn't match anymore.
+    ///
+    /// The file is moved first and the signature second, so the signature in place never claims
+    /// more than the file next to it.
+    pub fn commit(self) -> Result<()> {
+        adopt_permissions(&self.file, &self.output)?;
+        self.file
+            .persist(&self.output)
+            .with_context(|| format!("failed writing {}", self.output.display()))?;
+
+        let signature_path = signature::path(&self.output);
+        match self.signature {
+            Some(signature) => {
+                adopt_permissions(&signature, &signature_path)?;
+                signature
+                    .persist(&signature_path)
+                    .with_context(|| format!("failed writing {}", signature_path.display()))?;
+            }
+            None => match fs::remove_file(&signature_path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    return Err(e)
+                        .with_context(|| format!("failed removing {}", signature_path.display()));
+                }
+                _ => {}
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Create an empty temporary file in the directory of `path`, so it can be renamed over it.
+fn temp_path_next_to(path: &Path) -> Result<TempPath> {
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    tempfile::Builder::new()
+        .prefix(".commentstats-")
+        .tempfile_in(dir)
+        .map(NamedTempFile::into_temp_path)
+        .with_context(|| format!("failed creating a temporary file in {}", dir.display()))
+}
+
+/// Give a staged file the permissions of the one it replaces, or the usual ones of new files,
+/// instead of the private ones of temporary files.
+fn adopt_permissions(staged: &Path, replaced: &Path) -> Result<()> {
+    let permissions = match fs::metadata(replaced) {
+        Ok(metadata) => metadata.permissions(),
+        #[cfg(unix)]
+        Err(_) => std::os::unix::fs::PermissionsExt::from_mode(0o644),
+        #[cfg(not(unix))]
+        Err(_) => return Ok(()),
+    };
+
+    fs::set_permissions(staged, permissions)
+        .with_context(|| format!("failed setting permissions of {}", staged.display()))
+}
+
+/// Read access to a stats file. Chunks are opened independently, so they can be decoded in
+/// parallel.
+pub struct StatsFile {
+    archive: ZipArchive<Snapshot>,
+    manifest: Manifest,
+}
+
+impl StatsFile {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        // The file is opened only once, and verified, decrypted and parsed through that handle,
+        // so all of them see the same content, even if a rescan replaces the file meanwhile.
+        let file =
+            File::open(&path).with_context(|| format!("failed opening {}", path.display()))?;
+        signature::verify(&path, rewound(&file)?)?;
+
+        let source = if crypt::is_encrypted(rewound(&file)?)
+            .with_context(|| format!("failed reading {}", path.display()))?
+        {
+            Source::Memory(crypt::decrypt(&path, rewound(&file)?)?)
+        } else {
+            Source::File(file)
+        };
+
+        Self::read(source).with_context(|| format!("failed reading manifest of {}", path.display()))
+    }
+
+    fn read(source: Source) -> Result<Self> {
+        let mut archive = ZipArchive::new(Snapshot::new(source)?)?;
+
+        let manifest = if archive.file_names().any(|name| name == MANIFEST_NAME) {
+            read_manifest(&mut archive)
+        } else if archive.file_names().any(|name| name == LEGACY_INFO_NAME) {
+            read_legacy_manifest(&mut archive)
+        } else {
+            Err(anyhow!("not a stats file"))
+        }?;
+
+        Ok(Self { archive, manifest })
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Decode the most recent entry, if the file contains any. Entries of failed commits are
+    /// skipped, as they have no data.
+    pub fn last_entry(&self) -> Result<Option<Entry>> {
+        let Some(index) = self.manifest.chunks.iter().rposition(|c| c.entries > 0) else {
+            return Ok(None);
+        };
+
+        let mut last = None;
+        self.read_chunk(index, |entry| {
+            if !entry.failed {
+                last = Some(entry);
+            }
+            Ok(())
+        })?;
+
+        Ok(last)
+    }
+
+    /// Decode all entries of the chunk at the given index, verifying its checksum.
+    pub fn read_chunk(&self, index: usize, mut f: impl FnMut(Entry) -> Result<()>) -> Result<()> {
+        let chunk = &self.manifest.chunks[index];
+
+        self.decode_chunk(chunk, &mut f)
+            .with_context(|| format!("stats file corrupted at chunk {index}"))
+    }
+
+    fn decode_chunk(
+        &self,
+        chunk: &ChunkInfo,
+        f: &mut impl FnMut(Entry) -> Result<()>,
+    ) -> Result<()> {
+        let config = bincode::config::standard();
+        let mut archive = self.archive.clone();
+        let file = archive.by_name(&chunk.name)?;
+        let file: Box<dyn Read> = match chunk.compression {
+            Compression::Zstd => Box::new(ZstdDecoder::new(file)?),
+            Compression::None => Box::new(BufReader::new(file)),
+        };
+        let mut reader = HashingReader::new(file);
+
+        let count = bincode::decode_from_std_read::<u64, _, _>(&mut reader, config)?;
+        ensure!(
+            count == chunk.entries,
+            "expected {} entries, but found {count}",
+            chunk.entries
+        );
+
+        for _ in 0..count {
+            let entry = if self.manifest.version == LEGACY_VERSION {
+                let mut entry =
+                    bincode::serde::decode_from_std_read::<LegacyEntry, _, _>(&mut reader, config)?
+                        .into();
+                normalize_paths(&mut entry);
+                entry
+            } else {
+                bincode::serde::decode_from_std_read(&mut reader, config)?
+            };
+
+            f(entry)?;
+        }
+
+        // Include any trailing data in the checksum, so it covers the full content.
+        io::copy(&mut reader, &mut io::sink())?;
+
+        let checksum = reader.finish();
+        if self.manifest.version != LEGACY_VERSION && checksum != chunk.checksum {
+            bail!(
+                "checksum mismatch (expected {:016x}, got {checksum:016x})",
+                chunk.checksum
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn read_manifest(archive: &mut ZipArchive<Snapshot>) -> Result<Manifest> {
+    let config = bincode::config::standard();
+    let mut file = ZstdDecoder::new(archive.by_name(MANIFEST_NAME)?)?;
+
+    // The version is the first field of every manifest, so it can be checked on its own before
+    // decoding the remaining fields, whose layout may differ in other versions.
+    let version = bincode::decode_from_std_read::<u32, _, _>(&mut file, config)?;
+
+    ensure!(
+        version != DEVELOPMENT_VERSION,
+        "the stats file was written by a development build with an unstable format, scan the \
+         repository again"
+    );
+    ensure!(
+        version <= FORMAT_VERSION,
+        "the stats file has version {version}, which needs a newer release of commentstats"
+    );
+    ensure!(
+        version == FORMAT_VERSION,
+        "unsupported stats file version {version}"
+    );
+    let (entries, chunks, metadata) = bincode::serde::decode_from_std_read(&mut file, config)?;
+
+    Ok(Manifest {
+        version,
+        entries,
+        chunks,
+        metadata,
+    })
+}
+
+/// Build a manifest for stats files from before the format was versioned, by collecting the
+/// entry counts from the `info` file and the header of each chunk.
+fn read_legacy_manifest(archive: &mut ZipArchive<Snapshot>) -> Result<Manifest> {
+    let config = bincode::config::standard();
+
+    let mut file = ZstdDecoder::new(archive.by_name(LEGACY_INFO_NAME)?)?;
+    let entries = bincode::decode_from_std_read::<u64, _, _>(&mut file, config)?;
+    drop(file);
+
+    // Chunk numbers are zero padded to only three digits, so sort by their numeric value to get
+    // the correct order for files with a thousand chunks or more.
+    let mut names = archive
+        .file_names()
+        .filter_map(|name| {
+            let index = name.strip_prefix("stats-")?.parse::<usize>().ok()?;
+            Some((index, name.to_owned()))
+        })
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+
+    let chunks = names
+        .into_iter()
+        .map(|(_, name)| {
+            let mut file = ZstdDecoder::new(archive.by_name(&name)?)?;
+            let entries = bincode::decode_from_std_read::<u64, _, _>(&mut file, config)?;
+
+            Ok(ChunkInfo {
+                name,
+                entries,
+                checksum: 0,
+                compression: Compression::Zstd,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Manifest {
+        version: LEGACY_VERSION,
+        entries,
+        chunks,
+        metadata: Metadata::default(),
+    })
+}
+
+/// Convert the Windows path separators of [`LEGACY_VERSION`] files.
+fn normalize_paths(entry: &mut Entry) {
+    if entry.files.keys().any(|path| path.contains('\\')) {
+        entry.files = std::mem::take(&mut entry.files)
+            .into_iter()
+            .map(|(path, file)| (path.replace('\\', "/"), file))
+            .collect();
+    }
+}
+
+/// Another handle of the file, positioned at its start, to pass its content to other programs.
+/// Handles share their position, which [`Snapshot`] doesn't rely on, as it reads at offsets.
+fn rewound(file: &File) -> Result<File> {
+    let mut file = file.try_clone()?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+/// Reader of a stats file that keeps the file open, so its content stays the same even if the
+/// file is replaced in the meantime, like by a rescan of `serve`. Clones read the same file, each
+/// at its own position, so chunks can be decoded in parallel.
+#[derive(Clone)]
+struct Snapshot {
+    source: Arc<Source>,
+    len: u64,
+    position: u64,
+}
+
+/// Where the content of a stats file is read from.
+enum Source {
+    File(File),
+    /// Decrypted content of an encrypted file.
+    Memory(Vec<u8>),
+}
+
+impl Snapshot {
+    fn new(source: Source) -> io::Result<Self> {
+        let len = match &source {
+            Source::File(file) => file.metadata()?.len(),
+            Source::Memory(data) => data.len() as u64,
+        };
+
+        Ok(Self {
+            source: Arc::new(source),
+            len,
+            position: 0,
+        })
+    }
+}
+
+impl Read for Snapshot {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = match &*self.source {
+            // Reads at an offset leave the shared position of the file alone.
+            #[cfg(unix)]
+            Source::File(file) => std::os::unix::fs::FileExt::read_at(file, buf, self.position)?,
+            #[cfg(windows)]
+            Source::File(file) => {
+                std::os::windows::fs::FileExt::seek_read(file, buf, self.position)?
+            }
+            Source::Memory(data) => {
+                let start = usize::try_from(self.position)
+                    .map_or(data.len(), |position| position.min(data.len()));
+                let mut rest = &data[start..];
+                rest.read(buf)?
+            }
+        };
+
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for Snapshot {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => (position, 0),
+            SeekFrom::End(offset) => (self.len, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+
+        self.position = base.checked_add_signed(offset).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek before the start of the file",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+struct HashingWriter<W> {
+    inner: W,
+    hasher: XxHash3_64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: XxHash3_64::default(),
+        }
+    }
+
+    fn finish(self) -> (W, u64) {
+        let checksum = self.hasher.finish();
+        (self.inner, checksum)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.write(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: XxHash3_64,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            hasher: XxHash3_64::default(),
+        }
+    }
+
+    fn finish(self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.write(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use tempfile::TempDir;
+    use tokei::{CodeStats, LanguageType};
+
+    use super::*;
+
+    /// Layout of the entries in [`LEGACY_VERSION`] files, frozen as it was written back then.
+    #[derive(Serialize)]
+    pub(crate) struct EntryV1 {
+        pub timestamp: DateTime<FixedOffset>,
+        pub files: HashMap<String, EntryFileV1>,
+    }
+
+    #[derive(Serialize)]
+    pub(crate) struct EntryFileV1 {
+        pub language: LanguageType,
+        pub statistics: CodeStats,
+    }
+
+    pub(crate) fn timestamp() -> DateTime<FixedOffset> {
+        DateTime::parse_from_rfc3339("2023-11-20T12:30:00+01:00").unwrap()
+    }
+
+    pub(crate) fn statistics(code: usize, comments: usize) -> CodeStats {
+        let mut stats = CodeStats::new();
+        stats.code = code;
+        stats.comments = comments;
+        stats.blanks = 1;
+        stats
+    }
+
+    fn entry() -> Entry {
+        Entry {
+            timestamp: timestamp(),
+            files: HashMap::from([(
+                "src/main.rs".into(),
+                EntryFile {
+                    language: LanguageType::Rust,
+                    statistics: statistics(10, 4),
+                    api_docs: None,
+                    comment_kinds: None,
+                    licensed: None,
+                },
+            )]),
+            languages: HashMap::new(),
+            totals: None,
+            bytes: 512,
+            partial: false,
+            failed: false,
+            notes: Vec::new(),
+            commit_type: None,
+            commit: None,
+        }
+    }
+
+    /// Write a stats file with a single chunk of the given entries, letting `tamper` change the
+    /// chunk information before it ends up in the manifest.
+    fn write_file(
+        dir: &TempDir,
+        entries: &[Entry],
+        tamper: impl FnOnce(&mut ChunkInfo),
+    ) -> PathBuf {
+        let mut writer = ChunkWriter::create(dir.path(), 0, entries.len() as u64).unwrap();
+        for entry in entries {
+            writer.write(entry).unwrap();
+        }
+        let mut chunk = writer.finish().unwrap();
+        tamper(&mut chunk);
+
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            entries: entries.len() as u64,
+            chunks: vec![chunk],
+            metadata: Metadata::default(),
+        };
+        let output = dir.path().join("test.stats");
+        write(&output, dir.path(), &manifest, || {}).unwrap();
+
+        output
+    }
+
+    /// Write a stats file like the versions before the manifest did.
+    pub(crate) fn write_legacy_file(dir: &TempDir, entries: &[EntryV1]) -> PathBuf {
+        let config = bincode::config::standard();
+        let output = dir.path().join("legacy.stats");
+        let mut zip_file = ZipWriter::new(File::create(&output).unwrap());
+
+        zip_file
+            .start_file(LEGACY_INFO_NAME, FileOptions::default())
+            .unwrap();
+        let mut encoder = ZstdEncoder::new(&mut zip_file, 3).unwrap();
+        bincode::encode_into_std_write(entries.len() as u64, &mut encoder, config).unwrap();
+        encoder.finish().unwrap();
+
+        zip_file
+            .start_file("stats-000", FileOptions::default())
+            .unwrap();
+        let mut encoder = ZstdEncoder::new(&mut zip_file, 3).unwrap();
+        bincode::encode_into_std_write(entries.len() as u64, &mut encoder, config).unwrap();
+        for entry in entries {
+            bincode::serde::encode_into_std_write(entry, &mut encoder, config).unwrap();
+        }
+        encoder.finish().unwrap();
+
+        zip_file.finish().unwrap();
+
+        output
+    }
+
+    pub(crate) fn read_entries(file: &StatsFile) -> Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        for index in 0..file.manifest().chunks.len() {
+            file.read_chunk(index, |entry| {
+                entries.push(entry);
+                Ok(())
+            })?;
+        }
+
+        Ok(entries)
+    }
+
+    fn assert_entries_eq(actual: &[Entry], expected: &[Entry]) {
+        let config = bincode::config::standard();
+        assert_eq!(
+            bincode::serde::encode_to_vec(actual, config).unwrap(),
+            bincode::serde::encode_to_vec(expected, config).unwrap()
+        );
+    }
+
+    #[test]
+    fn uncompressed_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let expected = [entry(), entry()];
+
+        let mut writer = ChunkWriter::create_with(dir.path(), 0, 2, Compression::None, 0).unwrap();
+        for entry in &expected {
+            writer.write(entry).unwrap();
+        }
+        let plain = writer.finish().unwrap();
+        let mut writer = ChunkWriter::create_with(dir.path(), 1, 2, Compression::Zstd, 1).unwrap();
+        for entry in &expected {
+            writer.write(entry).unwrap();
+        }
+        let compressed = writer.finish().unwrap();
+        assert_eq!(plain.checksum, compressed.checksum);
+
+        let manifest = Manifest {
+            version: FORMAT_VERSION,
+            entries: 4,
+            chunks: vec![plain, compressed],
+            metadata: Metadata::default(),
+        };
+        let output = dir.path().join("test.stats");
+        write(&output, dir.path(), &manifest, || {}).unwrap();
+
+        let file = StatsFile::open(output).unwrap();
+        assert_entries_eq(
+            &read_entries(&file).unwrap(),
+            &[entry(), entry(), entry(), entry()],
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new().unwrap();
+        let empty = Entry {
+            files: HashMap::new(),
+            partial: true,
+            ..entry()
+        };
+        let expected = [entry(), empty];
+        let path = write_file(&dir, &expected, |_| {});
+
+        let file = StatsFile::open(&path).unwrap();
+        assert_eq!(file.manifest().version, FORMAT_VERSION);
+        assert_eq!(file.manifest().entries, 2);
+        assert_entries_eq(&read_entries(&file).unwrap(), &expected);
+
+        // Decrypted files are read from memory instead.
+        let file = StatsFile::read(Source::Memory(fs::read(&path).unwrap())).unwrap();
+        assert_eq!(file.manifest().entries, 2);
+        assert_entries_eq(&read_entries(&file).unwrap(), &expected);
+    }
+
+    #[test]
+    fn round_trip_legacy() {
+        let dir = TempDir::new().unwrap();
+        let path = write_legacy_file(
+            &dir,
+            &[EntryV1 {
+                timestamp: timestamp(),
+                files: HashMap::from([(
+                    "src/main.rs".to_owned(),
+                    EntryFileV1 {
+                        language: LanguageType::Rust,
+                        statistics: statistics(10, 4),
+                    },
+                )]),
+            }],
+        );
+
+        let file = StatsFile::open(path).unwrap();
+        assert_eq!(file.manifest().version, LEGACY_VERSION);
+        assert_eq!(file.manifest().entries, 1);
+
+        let expected = Entry {
+            timestamp: timestamp(),
+            files: HashMap::from([(
+                "src/main.rs".into(),
+                EntryFile {
+                    language: LanguageType::Rust,
+                    statistics: statistics(10, 4),
+                    api_docs: None,
+                    comment_kinds: None,
+                    licensed: None,
+                },
+            )]),
+            languages: HashMap::new(),
+            totals: None,
+            bytes: 0,
+            partial: false,
+            failed: false,
+            notes: Vec::new(),
+            commit_type: None,
+            commit: None,
+        };
+        assert_entries_eq(&read_entries(&file).unwrap(), &[expected]);
+    }
+
+    #[test]
+    fn legacy_windows_paths() {
+        let dir = TempDir::new().unwrap();
+        let path = write_legacy_file(
+            &dir,
+            &[EntryV1 {
+                timestamp: timestamp(),
+                files: HashMap::from([(
+                    "src\\bin\\main.rs".to_owned(),
+                    EntryFileV1 {
+                        language: LanguageType::Rust,
+                        statistics: statistics(1, 0),
+                    },
+                )]),
+            }],
+        );
+
+        let entries = read_entries(&StatsFile::open(path).unwrap()).unwrap();
+        let paths = entries[0].files.keys().collect::<Vec<_>>();
+        assert_eq!(paths, ["src/bin/main.rs"]);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let path = write_file(&dir, &[entry()], |chunk| {
+            chunk.checksum ^= 1;
+        });
+
+        let Err(error) = read_entries(&StatsFile::open(path).unwrap()) else {
+            panic!("tampered chunk was read without error");
+        };
+        assert!(
+            format!("{error:#}").contains("checksum mismatch"),
+            "unexpected error: {error:#}"
+        );
+    }
+
+    #[test]
+    fn staged_file_replaces_output_on_commit() {
+        let dir = TempDir::new().unwrap();
+        let output = dir.path().join("test.stats");
+        let signature = signature::path(&output);
+        fs::write(&output, "previous").unwrap();
+        fs::write(&signature, "previous signature").unwrap();
+
+        // Abandoned writes leave the previous file alone, and don't leave anything behind.
+        let staged = Staged::new(&output).unwrap();
+        fs::write(staged.path(), "abandoned").unwrap();
+        drop(staged);
+        assert_eq!("previous", fs::read_to_string(&output).unwrap());
+        assert_eq!(2, fs::read_dir(dir.path()).unwrap().count());
+
+        let staged = Staged::new(&output).unwrap();
+        fs::write(staged.path(), "current").unwrap();
+        assert_eq!("previous", fs::read_to_string(&output).unwrap());
+        staged.commit().unwrap();
+
+        assert_eq!("current", fs::read_to_string(&output).unwrap());
+        assert!(!signature.exists(), "outdated signature was kept");
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn development_version_is_rejected() {
+        let dir = TempDir::new().unwrap();
+        let manifest = Manifest {
+            version: DEVELOPMENT_VERSION,
+            entries: 0,
+            chunks: Vec::new(),
+            metadata: Metadata::default(),
+        };
+        let output = dir.path().join("test.stats");
+        write(&output, dir.path(), &manifest, || {}).unwrap();
+
+        let Err(error) = StatsFile::open(output) else {
+            panic!("development version was opened");
+        };
+        assert!(
+            format!("{error:#}").contains("scan the repository again"),
+            "unexpected error: {error:#}"
+        );
+    }
+
+    /// Guard against changing the encoding of entries without increasing [`FORMAT_VERSION`].
+    #[test]
+    fn entry_layout_matches_version() {
+        let bytes = bincode::serde::encode_to_vec(entry(), bincode::config::standard()).unwrap();
+        let mut hasher = XxHash3_64::default();
+        hasher.write(&bytes);
+
+        // Both are updated together, so the checksum always belongs to the current version.
+        assert_eq!(
+            (3, 0xa9e6_007f_a86e_e2b4),
+            (FORMAT_VERSION, hasher.finish()),
+            "the encoding of entries changed, increase FORMAT_VERSION and update this test"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Repositories of submodules, to count their files as part of the superproject with
+//! `--recurse-submodules`.
+//!
+//! Submodules are cloned by `git submodule` into the `modules` directory of the superproject's
+//! repository, named after their entry in `.gitmodules`. Submodules that are checked out with a
+//! repository of their own are found at their path in the work tree as well.
+
+use std::path::Path;
+
+use git2::{Repository, Tree};
+
+use crate::scan;
+
+/// Open the repository of the submodule at `path` in the tree, if it can be found.
+pub fn open(repo: &Repository, tree: &Tree<'_>, path: &Path) -> Option<Repository> {
+    let modules = repo.path().join("modules");
+
+    name(repo, tree, path)
+        .map(|name| modules.join(name))
+        .into_iter()
+        .chain([modules.join(path)])
+        .chain(repo.workdir().map(|dir| dir.join(path)))
+        .find_map(|dir| scan::open_repository(&dir).ok())
+}
+
+/// Name of the submodule at `path`, from the `.gitmodules` file of the tree.
+fn name(repo: &Repository, tree: &Tree<'_>, path: &Path) -> Option<String> {
+    let blob = tree
+        .get_path(Path::new(".gitmodules"))
+        .ok()?
+        .to_object(repo)
+        .ok()?
+        .peel_to_blob()
+        .ok()?;
+
+    find_name(&String::from_utf8_lossy(blob.content()), path)
+}
+
+/// Find the name of the submodule at `path` in the content of a `.gitmodules` file.
+fn find_name(content: &str, path: &Path) -> Option<String> {
+    let mut name = None;
+
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('[') {
+            name = line
+                .strip_prefix("[submodule \"")
+                .and_then(|line| line.strip_suffix("\"]"));
+        } else if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == "path" && Path::new(value.trim()) == path {
+                return name.map(str::to_owned);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_found_by_path() {
+        let content = "[submodule \"vendor\"]\n\
+                       \tpath = third_party/vendor\n\
+                       \turl = https://example.com/vendor.git\n\
+                       [core]\n\
+                       \tpath = docs\n\
+                       [submodule \"docs\"]\n\
+                       \turl = https://example.com/docs.git\n\
+                       \tpath = site/docs\n";
+
+        assert_eq!(
+            Some("vendor"),
+            find_name(content, Path::new("third_party/vendor")).as_deref()
+        );
+        assert_eq!(
+            Some("docs"),
+            find_name(content, Path::new("site/docs")).as_deref()
+        );
+        assert_eq!(None, find_name(content, Path::new("docs")));
+    }
+}
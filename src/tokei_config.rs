@@ -0,0 +1,276 @@
+//! Configuration of tokei from the scanned repository, so projects that count their lines with
+//! tokei get the same languages here.
+//!
+//! The files are read from the root of the scanned tip, or from the file given with
+//! `--tokei-config`:
+//!
+//! - `tokei.toml` (or `.tokeirc`) with tokei's own settings, of which
+//!   `treat_doc_strings_as_comments` affects the counts, and additional languages in
+//!   `[languages.<name>]` tables.
+//! - `languages.toml` with only the language tables, in the format of tokei's `languages.json`.
+//!
+//! The bundled tokei can't learn new languages, so extensions of built-in languages are mapped to
+//! them, and new languages are counted as the built-in language with the same comment syntax.
+//! Extensions given with `--map-ext` take precedence over both.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+use serde::Deserialize;
+use tokei::{Config as TokeiConfig, LanguageType};
+
+use crate::{
+    languages,
+    warnings::{Category, Warnings},
+};
+
+/// Files with tokei settings in the repository root, in order of precedence.
+const CONFIG_FILES: &[&str] = &["tokei.toml", ".tokeirc"];
+
+/// File with only language definitions in the repository root.
+const LANGUAGES_FILE: &str = "languages.toml";
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct File {
+    treat_doc_strings_as_comments: Option<bool>,
+    languages: BTreeMap<String, Language>,
+}
+
+/// Definition of a language, with the fields of tokei's `languages.json` that matter for
+/// counting.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Language {
+    extensions: Vec<String>,
+    line_comment: Vec<String>,
+    multi_line_comments: Vec<(String, String)>,
+}
+
+/// Settings for tokei together with the additional file extensions.
+#[derive(Default)]
+pub struct Tokei {
+    pub config: TokeiConfig,
+    /// Lowercase file extensions mapped to the language that they are counted as.
+    extensions: HashMap<String, LanguageType>,
+}
+
+impl Tokei {
+    /// Load the configuration from the given file, or else from the root of the commit, with the
+    /// `mapped` extensions taking precedence over the ones of the files. Languages that can't be
+    /// counted are reported as warning and left out.
+    pub fn load(
+        repo: &Repository,
+        commit: Option<Oid>,
+        path: Option<&Path>,
+        mapped: &[(String, LanguageType)],
+        warnings: &Warnings,
+    ) -> Result<Self> {
+        let mut files = Vec::new();
+
+        if let Some(path) = path {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("failed reading {}", path.display()))?;
+            files.push((path.display().to_string(), parse_file(&content, path)?));
+        } else if let Some(oid) = commit {
+            let tree = repo.find_commit(oid)?.tree()?;
+            let read = |name: &str| -> Result<Option<String>> {
+                let Some(entry) = tree.get_name(name) else {
+                    return Ok(None);
+                };
+                let blob = entry.to_object(repo)?.peel_to_blob()?;
+                Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+            };
+
+            for &name in CONFIG_FILES {
+                if let Some(content) = read(name)? {
+                    files.push((name.to_owned(), parse_file(&content, Path::new(name))?));
+                    break;
+                }
+            }
+            if let Some(content) = read(LANGUAGES_FILE)? {
+                files.push((
+                    LANGUAGES_FILE.to_owned(),
+                    parse_file(&content, Path::new(LANGUAGES_FILE))?,
+                ));
+            }
+        }
+
+        let mut tokei = Self::default();
+        for (name, file) in files {
+            if file.treat_doc_strings_as_comments.is_some() {
+                tokei.config.treat_doc_strings_as_comments = file.treat_doc_strings_as_comments;
+            }
+
+            for (language, definition) in file.languages {
+                match resolve(&language, &definition) {
+                    Ok(lang) => tokei.extensions.extend(
+                        definition
+                            .extensions
+                            .iter()
+                            .map(|ext| (ext.trim_start_matches('.').to_lowercase(), lang)),
+                    ),
+                    Err(reason) => warnings.warn(
+                        Category::Languages,
+                        format_args!("{name}: leaving out language {language}, {reason}"),
+                    ),
+                }
+            }
+        }
+
+        tokei.extensions.extend(mapped.iter().cloned());
+
+        Ok(tokei)
+    }
+
+    /// Language of a file by its name, with the configured extensions taking precedence over
+    /// the built-in ones.
+    pub fn language(&self, name: &str) -> Option<LanguageType> {
+        Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.extensions.get(&ext.to_lowercase()).copied())
+            .or_else(|| LanguageType::from_path(name, &self.config))
+    }
+}
+
+impl Clone for Tokei {
+    fn clone(&self) -> Self {
+        Self {
+            config: TokeiConfig {
+                treat_doc_strings_as_comments: self.config.treat_doc_strings_as_comments,
+                ..TokeiConfig::default()
+            },
+            extensions: self.extensions.clone(),
+        }
+    }
+}
+
+/// Parse a mapping of a file extension to a language from the command line, like `tpl=html`.
+pub fn parse_mapping(value: &str) -> Result<(String, LanguageType), String> {
+    let (extension, language) = value
+        .split_once('=')
+        .ok_or_else(|| "expected a mapping like `tpl=html`".to_owned())?;
+    let extension = extension.trim().trim_start_matches('.').to_lowercase();
+    if extension.is_empty() {
+        return Err("the file extension is empty".to_owned());
+    }
+
+    Ok((extension, languages::parse(language.trim())?))
+}
+
+/// Parse a configuration file, where `languages.toml` only holds the language tables.
+fn parse_file(content: &str, path: &Path) -> Result<File> {
+    let context = || format!("invalid tokei configuration in {}", path.display());
+
+    if path.file_name().is_some_and(|name| name == LANGUAGES_FILE) {
+        Ok(File {
+            languages: toml::from_str(content).with_context(context)?,
+            ..File::default()
+        })
+    } else {
+        toml::from_str(content).with_context(context)
+    }
+}
+
+/// Built-in language to count the files of a language definition as. Known languages stay
+/// themselves, and new ones take the first built-in language with exactly their comment syntax.
+fn resolve(name: &str, definition: &Language) -> Result<LanguageType, String> {
+    let same_syntax = |lang: &LanguageType| {
+        lang.line_comments().iter().eq(&definition.line_comment)
+            && lang
+                .multi_line_comments()
+                .iter()
+                .map(|&(start, end)| (start.to_owned(), end.to_owned()))
+                .eq(definition.multi_line_comments.iter().cloned())
+    };
+
+    if let Ok(lang) = languages::parse(name) {
+        let custom =
+            !definition.line_comment.is_empty() || !definition.multi_line_comments.is_empty();
+        return if custom && !same_syntax(&lang) {
+            Err(format!(
+                "the comment syntax of the built-in language {lang} can't be changed"
+            ))
+        } else {
+            Ok(lang)
+        };
+    }
+
+    LanguageType::list()
+        .iter()
+        .copied()
+        .filter(|lang| !lang.is_literate())
+        .find(same_syntax)
+        .ok_or_else(|| "no built-in language has the same comment syntax".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_mapped() {
+        let file = parse_file(
+            r#"
+                treat_doc_strings_as_comments = true
+
+                [languages.Python]
+                extensions = ["py3"]
+            "#,
+            Path::new("tokei.toml"),
+        )
+        .unwrap();
+        assert_eq!(Some(true), file.treat_doc_strings_as_comments);
+        assert_eq!(
+            Ok(LanguageType::Python),
+            resolve("Python", &file.languages["Python"])
+        );
+
+        let file = parse_file(
+            r#"
+                [Pipeline]
+                extensions = ["pipe"]
+                line_comment = ["//"]
+                multi_line_comments = [["/*", "*/"]]
+
+                [Weird]
+                extensions = ["weird"]
+                line_comment = ["~~~"]
+            "#,
+            Path::new(LANGUAGES_FILE),
+        )
+        .unwrap();
+        let pipeline = resolve("Pipeline", &file.languages["Pipeline"]).unwrap();
+        assert_eq!(&["//"], pipeline.line_comments());
+        assert_eq!(&[("/*", "*/")], pipeline.multi_line_comments());
+        assert!(resolve("Weird", &file.languages["Weird"]).is_err());
+    }
+
+    #[test]
+    fn mappings_are_parsed() {
+        assert_eq!(
+            Ok(("tpl".to_owned(), LanguageType::Html)),
+            parse_mapping("tpl=HTML")
+        );
+        assert_eq!(
+            Ok(("inc".to_owned(), LanguageType::Php)),
+            parse_mapping(".INC=php")
+        );
+        assert!(parse_mapping("tpl").is_err());
+        assert!(parse_mapping("=html").is_err());
+        assert!(parse_mapping("tpl=nonsense").is_err());
+
+        let tokei = Tokei {
+            extensions: HashMap::from([("tpl".to_owned(), LanguageType::Html)]),
+            ..Tokei::default()
+        };
+        assert_eq!(Some(LanguageType::Html), tokei.language("page.TPL"));
+        assert_eq!(Some(LanguageType::Rust), tokei.language("lib.rs"));
+    }
+}
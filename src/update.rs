@@ -0,0 +1,78 @@
+use std::{ops::Range, path::Path};
+
+use anyhow::{ensure, Context, Result};
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    scan::Options,
+    stats_file::{ChunkInfo, ChunkWriter, StatsFile},
+};
+
+/// Stats file of an earlier scan of the same repository. Its entries are kept as they are, so
+/// only the commits that were added since then have to be scanned.
+pub struct Previous {
+    file: StatsFile,
+}
+
+impl Previous {
+    pub fn open(path: &Path, options: &Options) -> Result<Self> {
+        let file = StatsFile::open(path)
+            .with_context(|| format!("failed opening previous scan {}", path.display()))?;
+
+        let metadata = &file.manifest().metadata;
+        ensure!(
+            metadata.detail == options.detail
+                && metadata.api_docs == options.api_docs
+                && metadata.comment_quality == options.comment_quality
+                && metadata.spdx == options.spdx,
+            "the previous scan used different options, scan the repository again without --update"
+        );
+
+        Ok(Self { file })
+    }
+
+    /// Find the chunks of the history that was recorded for the revision, together with the
+    /// time of its latest entry. Files from before multiple revisions were supported only
+    /// contain a single unnamed history, which is matched by any revision.
+    pub fn history(
+        &self,
+        reference: Option<&str>,
+    ) -> Result<Option<(Range<usize>, DateTime<FixedOffset>)>> {
+        let histories = self.file.manifest().histories();
+        let range = histories.into_iter().find_map(|(history, range)| {
+            let matches = history.is_none_or(|h| h.reference.as_deref() == reference);
+            matches.then_some(range)
+        });
+
+        let Some(range) = range else {
+            return Ok(None);
+        };
+        let Some(last) = range.clone().rev().find(|&i| self.chunk_entries(i) > 0) else {
+            return Ok(None);
+        };
+
+        let mut latest = None;
+        self.file.read_chunk(last, |entry| {
+            latest = Some(entry.timestamp);
+            Ok(())
+        })?;
+
+        Ok(latest.map(|latest| (range, latest)))
+    }
+
+    /// Copy the chunks of a history into new chunks, numbered starting at `offset`.
+    pub fn write(&self, dir: &Path, offset: usize, range: Range<usize>) -> Result<Vec<ChunkInfo>> {
+        range
+            .enumerate()
+            .map(|(i, index)| {
+                let mut writer = ChunkWriter::create(dir, offset + i, self.chunk_entries(index))?;
+                self.file.read_chunk(index, |entry| writer.write(&entry))?;
+                writer.finish()
+            })
+            .collect()
+    }
+
+    fn chunk_entries(&self, index: usize) -> u64 {
+        self.file.manifest().chunks[index].entries
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::exit::Failure;
+
+/// Warnings that were printed by all collectors of the process.
+static TOTAL: AtomicU64 = AtomicU64::new(0);
+
+/// Amount of warnings that were printed so far, for `--fail-on warning`.
+pub fn total() -> u64 {
+    TOTAL.load(Ordering::Relaxed)
+}
+
+/// Fail the run if any warnings were printed and `strict` is set, like by `--fail-on warning`.
+pub fn check(strict: bool) -> Result<()> {
+    verdict(strict, total())
+}
+
+fn verdict(strict: bool, warnings: u64) -> Result<()> {
+    if strict && warnings > 0 {
+        return Err(
+            anyhow!("{warnings} warnings were reported, which fail the run")
+                .context(Failure::Partial),
+        );
+    }
+
+    Ok(())
+}
+
+/// Kind of issue that a warning is about, to group them in the summary.
+#[derive(Clone, Copy)]
+pub enum Category {
+    /// Files that were left out of the statistics on purpose, like large or unparseable ones.
+    Skipped,
+    /// Files that couldn't be looked up or loaded from the repository.
+    Unreadable,
+    /// Renames and copies from sources of unknown language, which are counted as added files.
+    Renames,
+    /// Changes of a kind that a diff between two commits shouldn't have.
+    Changes,
+    /// Commits that couldn't be scanned at all.
+    Failed,
+    /// Commits that took long to scan or exceeded their time budget.
+    Slow,
+    /// Languages of the tokei configuration that were left out.
+    Languages,
+    /// Submodules whose repository or commit couldn't be found.
+    Submodules,
+    /// Results that only cover part of the data, like interrupted renders.
+    Partial,
+    /// Optional steps that failed without affecting the results, like writing a commit-graph.
+    Other,
+}
+
+impl Category {
+    const ALL: [Self; 10] = [
+        Self::Skipped,
+        Self::Unreadable,
+        Self::Renames,
+        Self::Changes,
+        Self::Failed,
+        Self::Slow,
+        Self::Languages,
+        Self::Submodules,
+        Self::Partial,
+        Self::Other,
+    ];
+
+    /// Description of the issues in the summary, for one and for multiple of them.
+    fn label(self) -> (&'static str, &'static str) {
+        match self {
+            Self::Skipped => ("skipped file", "skipped files"),
+            Self::Unreadable => ("unreadable file", "unreadable files"),
+            Self::Renames => (
+                "rename from an unknown source",
+                "renames from unknown sources",
+            ),
+            Self::Changes => ("unexpected change", "unexpected changes"),
+            Self::Failed => ("failed commit", "failed commits"),
+            Self::Slow => ("slow commit", "slow commits"),
+            Self::Languages => ("left out language", "left out languages"),
+            Self::Submodules => ("unavailable submodule", "unavailable submodules"),
+            Self::Partial => ("partial result", "partial results"),
+            Self::Other => ("other warning", "other warnings"),
+        }
+    }
+}
+
+/// Collector for non-fatal issues that are reported during a run. Each warning is printed right
+/// away and counted by its category, so a summary can be shown at the end.
+#[derive(Default)]
+pub struct Warnings {
+    /// Only count warnings, without printing them.
+    quiet: bool,
+    counts: [AtomicU64; Category::ALL.len()],
+}
+
+impl Warnings {
+    /// Create a collector that doesn't print any warnings, for work that is repeated later on
+    /// and would report the same issues again.
+    pub fn quiet() -> Self {
+        Self {
+            quiet: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn warn(&self, category: Category, message: impl Display) {
+        if !self.quiet {
+            eprintln!("warning: {message}");
+            TOTAL.fetch_add(1, Ordering::Relaxed);
+        }
+        self.counts[category as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Report a file that was left out of the statistics.
+    pub fn skip(&self, message: impl Display) {
+        self.warn(Category::Skipped, message);
+    }
+
+    /// Report a commit that couldn't be scanned.
+    pub fn fail(&self, message: impl Display) {
+        self.warn(Category::Failed, message);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Print the amount of warnings of each category that had any.
+    pub fn print_summary(&self) {
+        match self.count() {
+            0 => return,
+            1 => println!("finished with 1 warning:"),
+            count => println!("finished with {count} warnings:"),
+        }
+
+        for category in Category::ALL {
+            let (one, many) = category.label();
+            match self.counts[category as usize].load(Ordering::Relaxed) {
+                0 => {}
+                1 => println!("  1 {one}"),
+                count => println!("  {count} {many}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::ExitCode;
+
+    use super::*;
+    use crate::exit;
+
+    #[test]
+    fn warnings_are_counted() {
+        let warnings = Warnings::quiet();
+        warnings.skip("large.bin: too large");
+        warnings.warn(Category::Slow, "slow commit");
+        assert_eq!(2, warnings.count());
+
+        // Other tests print warnings at the same time, so the total only ever grows.
+        let before = total();
+        Warnings::default().fail("broken commit");
+        assert!(total() > before);
+    }
+
+    #[test]
+    fn warnings_only_fail_strict_runs() {
+        assert!(verdict(false, 0).is_ok());
+        assert!(verdict(false, 3).is_ok());
+        assert!(verdict(true, 0).is_ok());
+
+        let error = verdict(true, 3).unwrap_err();
+        assert_eq!(ExitCode::from(10), exit::code(&error));
+        assert_eq!(
+            "3 warnings were reported, which fail the run",
+            error.root_cause().to_string()
+        );
+    }
+}
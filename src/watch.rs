@@ -0,0 +1,150 @@
+//! Long-running updates of a stats file, scanning the new commits of a repository on a schedule.
+//! This allows running it as a service, without cron or systemd timers.
+
+use std::{net::SocketAddr, path::PathBuf, thread, time::Duration};
+
+use anyhow::Result;
+use chrono::{Local, NaiveDateTime, NaiveTime};
+use clap::{Args, ValueHint};
+use rand::Rng;
+
+use crate::{
+    config::Config,
+    daemon::{Daemon, DaemonArgs, Interval},
+    scan,
+};
+
+/// Longest time to sleep at once while waiting for the next scan, which is also the delay until
+/// a shutdown or reload is noticed.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Args)]
+#[group(skip)]
+pub struct Options {
+    /// Stats file to keep up to date. It's created by the first scan if it doesn't exist yet.
+    #[arg(short, long, default_value = "stats.stats", value_hint = ValueHint::FilePath)]
+    pub output: PathBuf,
+    /// Scan again after this amount of time, like `30m`, `6h` or `1d12h`.
+    #[arg(long, value_name = "INTERVAL", required_unless_present = "at")]
+    pub every: Option<Interval>,
+    /// Scan every day at this local time, like `02:00`. Can be given multiple times. Together
+    /// with `--every`, whichever comes first starts the next scan.
+    #[arg(long, value_name = "HH:MM", value_parser = parse_time)]
+    pub at: Vec<NaiveTime>,
+    /// Delay each scheduled scan by a random amount of time up to this one, so that several
+    /// instances don't all scan at the same moment.
+    #[arg(long, value_name = "INTERVAL")]
+    pub jitter: Option<Interval>,
+    /// Address to answer health checks on, with `GET /healthz` and `GET /readyz`.
+    #[arg(long)]
+    pub addr: Option<SocketAddr>,
+    #[command(flatten)]
+    pub scan: scan::Options,
+    #[command(flatten)]
+    pub daemon: DaemonArgs,
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|e| format!("invalid time: {e}"))
+}
+
+pub fn run(
+    input: PathBuf,
+    mut options: Options,
+    mut config: Config,
+    config_path: Option<PathBuf>,
+) -> Result<()> {
+    // Only the first scan may start from scratch, all later ones build on it.
+    options.scan.update = true;
+
+    let daemon = Daemon::start(&options.daemon, config_path, vec![options.output.clone()])?;
+    if let Some(addr) = options.addr {
+        daemon.listen(addr)?;
+    }
+    daemon.ready(&format!("watching {}", input.display()));
+
+    while !daemon.stopping() {
+        println!("scanning {}...", input.display());
+        daemon.health("scanning");
+
+        // A failed scan leaves the previous stats file in place, so the next one can try again.
+        // Shutdowns end the scan early, saving the commits that were done so far.
+        match scan::run(input.clone(), &options.output, &options.scan, &config) {
+            Ok(()) => daemon.succeeded(),
+            Err(e) => eprintln!("Error: {e:?}"),
+        }
+        daemon.health("idle");
+
+        let now = Local::now().naive_local();
+        let next = next_run(now, options.every.map(|every| every.0), &options.at);
+        let mut wait = (next - now).to_std().unwrap_or_default();
+
+        if let Some(jitter) = options.jitter {
+            wait += Duration::from_secs(rand::thread_rng().gen_range(0..=jitter.0.as_secs()));
+        }
+
+        println!("next scan in {}s", wait.as_secs());
+
+        while !wait.is_zero() && !daemon.stopping() {
+            if let Some(loaded) = daemon.reload() {
+                config = loaded;
+            }
+
+            let step = wait.min(POLL_INTERVAL);
+            thread::sleep(step);
+            wait -= step;
+        }
+    }
+
+    println!("shutting down");
+
+    Ok(())
+}
+
+/// Find the time of the next scan, which is the earliest of the interval since `now` and the
+/// daily times. Times are local, so daily scans follow daylight saving time changes.
+fn next_run(now: NaiveDateTime, every: Option<Duration>, at: &[NaiveTime]) -> NaiveDateTime {
+    let interval = every
+        .and_then(|every| chrono::Duration::from_std(every).ok())
+        .and_then(|every| now.checked_add_signed(every));
+
+    let daily = at.iter().map(|&time| {
+        let today = now.date().and_time(time);
+        if today > now {
+            today
+        } else {
+            today + chrono::Duration::days(1)
+        }
+    });
+
+    interval
+        .into_iter()
+        .chain(daily)
+        .min()
+        .unwrap_or(NaiveDateTime::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn next_run_is_earliest() {
+        let now = NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        let next = next_run(now, Some(Duration::from_secs(6 * 3600)), &[]);
+        assert_eq!(now + chrono::Duration::hours(6), next);
+
+        let next = next_run(now, None, &[time(2, 0), time(14, 30)]);
+        assert_eq!(now + chrono::Duration::minutes(150), next);
+
+        let next = next_run(now, Some(Duration::from_secs(24 * 3600)), &[time(2, 0)]);
+        assert_eq!(now + chrono::Duration::hours(14), next);
+    }
+}
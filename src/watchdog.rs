@@ -0,0 +1,207 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex, PoisonError},
+    thread::Scope,
+    time::{Duration, Instant},
+};
+
+use git2::Oid;
+
+use crate::warnings::{Category, Warnings};
+
+/// Interval in which the watchdog checks for commits that exceeded their time budget.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reporter for commits that take longer than their time budget while they are still being
+/// processed. A single huge file can keep a worker busy for a long time, and without it there
+/// would be no feedback until that file is done.
+pub struct Watchdog {
+    limit: Option<Duration>,
+    active: Mutex<HashMap<Oid, Activity>>,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// A commit that is currently being processed.
+struct Activity {
+    started: Instant,
+    /// The file that is currently being parsed.
+    file: Option<PathBuf>,
+    reported: bool,
+}
+
+impl Watchdog {
+    /// Create a new watchdog for the given time budget. Without a budget, it does nothing.
+    pub fn new(limit: Option<Duration>) -> Self {
+        Self {
+            limit,
+            active: Mutex::default(),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        }
+    }
+
+    pub fn limit(&self) -> Option<Duration> {
+        self.limit
+    }
+
+    /// Run the watchdog in a background thread of the scope, until the returned guard is
+    /// dropped.
+    pub fn spawn<'scope, 'env>(
+        &'env self,
+        scope: &'scope Scope<'scope, 'env>,
+        warnings: &'env Warnings,
+    ) -> Guard<'env> {
+        if let Some(limit) = self.limit {
+            scope.spawn(move || self.run(limit, warnings));
+        }
+
+        Guard(self)
+    }
+
+    fn run(&self, limit: Duration, warnings: &Warnings) {
+        let mut stopped = self.stopped.lock().unwrap_or_else(PoisonError::into_inner);
+
+        while !*stopped {
+            stopped = self
+                .wake
+                .wait_timeout(stopped, CHECK_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+
+            let mut active = self.active.lock().unwrap_or_else(PoisonError::into_inner);
+            for (oid, activity) in active.iter_mut() {
+                let elapsed = activity.started.elapsed();
+                if activity.reported || elapsed < limit {
+                    continue;
+                }
+
+                activity.reported = true;
+                warnings.warn(
+                    Category::Slow,
+                    format_args!(
+                        "{oid}: still processing after {}s{}",
+                        elapsed.as_secs(),
+                        activity
+                            .file
+                            .as_ref()
+                            .map(|file| format!(", currently parsing {}", file.display()))
+                            .unwrap_or_default(),
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Mark the commit as being processed, until [`Self::end`] is called.
+    pub fn begin(&self, oid: Oid) {
+        if self.limit.is_none() {
+            return;
+        }
+
+        self.active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                oid,
+                Activity {
+                    started: Instant::now(),
+                    file: None,
+                    reported: false,
+                },
+            );
+    }
+
+    /// Record the file that is currently parsed for the commit.
+    pub fn file(&self, oid: Oid, path: &Path) {
+        if self.limit.is_none() {
+            return;
+        }
+
+        if let Some(activity) = self
+            .active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(&oid)
+        {
+            activity.file = Some(path.to_owned());
+        }
+    }
+
+    pub fn end(&self, oid: Oid) {
+        if self.limit.is_none() {
+            return;
+        }
+
+        self.active
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&oid);
+    }
+}
+
+/// Stops the watchdog thread when dropped, so the surrounding scope can finish, even if the
+/// scan failed.
+pub struct Guard<'a>(&'a Watchdog);
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        *self
+            .0
+            .stopped
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = true;
+        self.0.wake.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn slow_commits_are_reported_once() {
+        let watchdog = Watchdog::new(Some(Duration::from_millis(50)));
+        let warnings = Warnings::quiet();
+        let oid = Oid::from_bytes(&[1; 20]).unwrap();
+
+        // The scope only ends if the watchdog thread stops when its guard is dropped.
+        thread::scope(|scope| {
+            let _guard = watchdog.spawn(scope, &warnings);
+            watchdog.begin(oid);
+            watchdog.file(oid, Path::new("src/huge.rs"));
+
+            let started = Instant::now();
+            while warnings.count() == 0 {
+                assert!(started.elapsed() < CHECK_INTERVAL * 5, "never reported");
+                thread::sleep(Duration::from_millis(10));
+            }
+
+            // Another check passes without reporting the same commit again.
+            thread::sleep(CHECK_INTERVAL * 2);
+            watchdog.end(oid);
+        });
+
+        assert_eq!(1, warnings.count());
+        assert!(watchdog.active.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn without_budget_nothing_is_tracked() {
+        let watchdog = Watchdog::new(None);
+        let warnings = Warnings::quiet();
+        let oid = Oid::from_bytes(&[1; 20]).unwrap();
+
+        thread::scope(|scope| {
+            let _guard = watchdog.spawn(scope, &warnings);
+            watchdog.begin(oid);
+            watchdog.file(oid, Path::new("src/huge.rs"));
+            assert!(watchdog.active.lock().unwrap().is_empty());
+        });
+
+        assert_eq!(0, warnings.count());
+    }
+}